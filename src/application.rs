@@ -1,15 +1,260 @@
 // src/application.rs
 
-use crate::configuration_manager::{self, ApplicationSettings, EventActionSetting, DeviceAction, DeviceActionType};
-use crate::game_event_processor::{self, GameStateSnapshot};
-use crate::message_passing::{CommandToAsyncTasks, UpdateFromAsyncTasks, ClonableButtplugClientDevice}; // Добавили ClonableButtplugClientDevice
-use crate::war_thunder_connector::WarThunderIndicators;
+use crate::buttplug_connector;
+use crate::clock::{Clock, SystemClock};
+use crate::connection_metrics::{DeviceCommandMetrics, WarThunderPollMetrics};
+use crate::configuration_manager::{self, ActionTarget, ApplicationSettings, AsyncRuntimeKind, BasicAuthSetting, ButtplugConnectorKind, ConcurrencyLimitPolicy, ContinuousMappingSetting, DeviceAction, DeviceActionType, DeviceGroupSetting, DeviceMinimumOnTimeSetting, EffectPreset, EventActionSetting, HttpHeaderSetting, IndicatorSmoothingSetting, IndicatorsCache, MappingTerm, MissingDeviceBehavior, PatternPoint, ProfileFile, ProfileImportMode, RuleEvaluationMode, RuleStatistics, SimultaneousVibrationPolicy, TermCombineMode, VehicleClass};
+use crate::game_event_processor::{self, GameStateSnapshot, TriggeredEvent};
+use crate::telemetry_normalization;
+use crate::message_passing::{BackgroundTaskKind, CommandToAsyncTasks, UpdateFromAsyncTasks, LogCategory, TriggeredEventBus, TriggeredEventNotification};
+use crate::war_thunder_api::WarThunderIndicators;
 use eframe::egui;
 use tokio::sync::mpsc;
-use buttplug::client::ButtplugClientDevice; 
+use buttplug::client::ButtplugClientDevice;
+use buttplug::core::message::Endpoint;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// Имитируемые изменения индикаторов для панели разработчика
+#[derive(Clone, Copy)]
+enum SimulatedGameEvent {
+    Damage,
+    MainGunShot,
+    HardImpact,
+    RepairComplete,
+}
+
+// Подбор кривой непрерывного сопоставления: пользователь задает значение основного поля
+// (например, здоровье) и видит - а по желанию и чувствует - итоговую интенсивность
+#[derive(Default)]
+struct MappingCurveSimulation {
+    mapping_index: usize,
+    field_value: f64,
+    is_device_output_enabled: bool, // Отправлять интенсивность на устройство по умолчанию
+    last_sent_intensity: Option<f64>,
+}
+
+// Точек на кривой при отрисовке
+const MAPPING_CURVE_SAMPLE_COUNT: usize = 64;
+
+impl SimulatedGameEvent {
+    const ALL: [SimulatedGameEvent; 4] = [
+        SimulatedGameEvent::Damage,
+        SimulatedGameEvent::MainGunShot,
+        SimulatedGameEvent::HardImpact,
+        SimulatedGameEvent::RepairComplete,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            SimulatedGameEvent::Damage => "Урон -20%",
+            SimulatedGameEvent::MainGunShot => "Выстрел",
+            SimulatedGameEvent::HardImpact => "Жесткий удар",
+            SimulatedGameEvent::RepairComplete => "Ремонт завершен",
+        }
+    }
+
+    // Меняет копию последних индикаторов так, как это сделала бы игра при событии
+    fn apply(self, indicators: &mut WarThunderIndicators, settings: &ApplicationSettings) {
+        match self {
+            SimulatedGameEvent::Damage => {
+                indicators.health_percentage = Some((indicators.health_percentage.unwrap_or(100.0) - 20.0).max(0.0));
+            }
+            SimulatedGameEvent::MainGunShot => {
+                indicators.first_stage_ammo = Some((indicators.first_stage_ammo.unwrap_or(30.0) - 1.0).max(0.0));
+            }
+            SimulatedGameEvent::HardImpact => {
+                indicators.g_load = Some(settings.hard_impact.g_threshold as f32 + 1.0);
+                indicators.altitude_10k = Some(0.0);
+            }
+            SimulatedGameEvent::RepairComplete => {
+                indicators.health_percentage = Some(100.0);
+            }
+        }
+    }
+}
+
+struct LogEntry {
+    category: LogCategory,
+    device_address: Option<String>,
+    message: String,
+}
+
+// Что показывать в панели логов
+#[derive(Clone, PartialEq)]
+enum LogFilter {
+    All,
+    Category(LogCategory),
+    Device(String),
+}
+
+impl LogFilter {
+    fn label(&self) -> String {
+        match self {
+            LogFilter::All => "Все записи".to_string(),
+            LogFilter::Category(category) => category.label().to_string(),
+            LogFilter::Device(address) => format!("Устройство: {}", address),
+        }
+    }
+
+    fn matches(&self, log_entry: &LogEntry) -> bool {
+        match self {
+            LogFilter::All => true,
+            LogFilter::Category(category) => log_entry.category == *category,
+            LogFilter::Device(address) => log_entry.device_address.as_ref() == Some(address),
+        }
+    }
+}
+
+// Сколько команд может ждать повторной отправки, пока очередь фоновых задач переполнена
+const PENDING_COMMAND_LIMIT: usize = 64;
+const PENDING_COMMAND_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+// Отправка команд из GUI без блокировки интерфейса. Вместо молчаливого `let _ = try_send` переполненная
+// очередь не теряет команду, а откладывает ее до следующих кадров; закрытый канал (фоновые задачи
+// завершились) сообщается один раз. Метод принимает &self, чтобы вызываться из замыканий egui рядом
+// с изменяемыми полями приложения; сообщения о сбоях забирает update() в лог.
+struct GuiCommandSender {
+    sender: mpsc::Sender<CommandToAsyncTasks>,
+    pending_commands: RefCell<VecDeque<CommandToAsyncTasks>>,
+    failure_messages: RefCell<Vec<String>>,
+    is_closed_reported: Cell<bool>,
+    // Последние настройки, отправленные фоновым задачам (для "есть непримененные изменения")
+    last_sent_settings: RefCell<Option<ApplicationSettings>>,
+}
+
+impl GuiCommandSender {
+    fn new(sender: mpsc::Sender<CommandToAsyncTasks>) -> Self {
+        Self {
+            sender,
+            pending_commands: RefCell::new(VecDeque::new()),
+            failure_messages: RefCell::new(Vec::new()),
+            is_closed_reported: Cell::new(false),
+            last_sent_settings: RefCell::new(None),
+        }
+    }
+
+    fn send(&self, command: CommandToAsyncTasks) {
+        if let CommandToAsyncTasks::UpdateApplicationSettings(settings) = &command {
+            *self.last_sent_settings.borrow_mut() = Some((**settings).clone());
+        }
+        // Остановка не ждет очереди: отложенные вибрации, которые она отменяет, выбрасываем,
+        // поэтому порядок "вибрация, затем стоп" не нарушится
+        if is_stop_command(&command) {
+            self.pending_commands.borrow_mut().retain(|pending_command| !is_cancelled_by_stop(pending_command, &command));
+            match self.sender.try_send(command) {
+                Ok(()) => {}
+                Err(mpsc::error::TrySendError::Full(command)) => self.defer(command),
+                Err(mpsc::error::TrySendError::Closed(_)) => self.report_closed(),
+            }
+            return;
+        }
+        // Пока есть отложенные команды, новые встают за ними, чтобы не нарушить порядок (вибрация, затем стоп)
+        if !self.pending_commands.borrow().is_empty() {
+            self.defer(command);
+            return;
+        }
+        match self.sender.try_send(command) {
+            Ok(()) => {}
+            Err(mpsc::error::TrySendError::Full(command)) => {
+                self.failure_messages.borrow_mut().push("Очередь команд переполнена, команда будет отправлена повторно.".to_string());
+                self.defer(command);
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => self.report_closed(),
+        }
+    }
+
+    fn defer(&self, command: CommandToAsyncTasks) {
+        let mut pending_commands = self.pending_commands.borrow_mut();
+        // Остановки уходят первыми (в порядке поступления) и не отбрасываются по лимиту
+        if is_stop_command(&command) {
+            let stop_count = pending_commands.iter().take_while(|pending_command| is_stop_command(pending_command)).count();
+            pending_commands.insert(stop_count, command);
+            return;
+        }
+        if pending_commands.len() >= PENDING_COMMAND_LIMIT {
+            self.failure_messages.borrow_mut().push(format!(
+                "Очередь команд переполнена, команда {} отброшена. Попробуйте еще раз.",
+                command_label(&command)
+            ));
+            return;
+        }
+        pending_commands.push_back(command);
+    }
+
+    fn report_closed(&self) {
+        if !self.is_closed_reported.replace(true) {
+            self.failure_messages.borrow_mut().push("Фоновые задачи не отвечают (канал команд закрыт). Команды не выполняются - перезапустите приложение.".to_string());
+        }
+    }
+
+    // Повторная отправка отложенных команд; true - еще остались отложенные
+    fn retry_pending(&self) -> bool {
+        let mut pending_commands = self.pending_commands.borrow_mut();
+        while let Some(command) = pending_commands.pop_front() {
+            match self.sender.try_send(command) {
+                Ok(()) => {}
+                Err(mpsc::error::TrySendError::Full(command)) => {
+                    pending_commands.push_front(command);
+                    return true;
+                }
+                Err(mpsc::error::TrySendError::Closed(_)) => {
+                    pending_commands.clear();
+                    drop(pending_commands);
+                    self.report_closed();
+                    return false;
+                }
+            }
+        }
+        false
+    }
+
+    fn take_failure_messages(&self) -> Vec<String> {
+        std::mem::take(&mut *self.failure_messages.borrow_mut())
+    }
+}
+
+// Команды остановки: в очереди отложенных идут раньше остальных
+fn is_stop_command(command: &CommandToAsyncTasks) -> bool {
+    matches!(
+        command,
+        CommandToAsyncTasks::StopAllDevices
+            | CommandToAsyncTasks::StopDevice(_)
+            | CommandToAsyncTasks::StopGroup(_)
+            | CommandToAsyncTasks::SetOutputMuted(true)
+    )
+}
+
+// Отложенная вибрация или последовательность, которую остановка делает ненужной
+fn is_cancelled_by_stop(pending_command: &CommandToAsyncTasks, stop_command: &CommandToAsyncTasks) -> bool {
+    match (pending_command, stop_command) {
+        (
+            CommandToAsyncTasks::VibrateDevice { .. } | CommandToAsyncTasks::VibrateGroup { .. } | CommandToAsyncTasks::PlayTestSequence { .. },
+            CommandToAsyncTasks::StopAllDevices | CommandToAsyncTasks::SetOutputMuted(true),
+        ) => true,
+        (
+            CommandToAsyncTasks::VibrateDevice { device_index, .. } | CommandToAsyncTasks::PlayTestSequence { device_index, .. },
+            CommandToAsyncTasks::StopDevice(stopped_device_index),
+        ) => device_index == stopped_device_index,
+        (CommandToAsyncTasks::VibrateGroup { member_addresses, .. }, CommandToAsyncTasks::StopGroup(stopped_member_addresses)) => {
+            member_addresses == stopped_member_addresses
+        }
+        _ => false,
+    }
+}
+
+// Имя варианта команды для сообщений (без данных: в них могут быть длинные списки шагов и байты)
+fn command_label(command: &CommandToAsyncTasks) -> String {
+    let debug_text = format!("{:?}", command);
+    debug_text.split([' ', '(', '{']).next().unwrap_or_default().to_string()
+}
 
 pub struct WarThunderHapticsApplication {
-    command_sender: mpsc::Sender<CommandToAsyncTasks>,
+    command_sender: GuiCommandSender,
+    clock: Arc<dyn Clock>, // Время для обработки событий и таймеров интерфейса (усиление, мягкий старт, тихие часы); в работе всегда SystemClock
     update_receiver: mpsc::Receiver<UpdateFromAsyncTasks>,
     settings: ApplicationSettings,
     current_wt_indicators: Option<WarThunderIndicators>,
@@ -19,15 +264,296 @@ pub struct WarThunderHapticsApplication {
     // Либо храним ButtplugClientDevice и конвертируем при отправке/получении, если Clone для enum не нужен.
     // Пока оставим Vec<ButtplugClientDevice>, а Clonable используется только в канале.
     // Это значит, что при получении ClonableButtplugClientDevice мы будем извлекать .0
-    buttplug_devices: Vec<ButtplugClientDevice>, 
+    buttplug_devices: Vec<Arc<ButtplugClientDevice>>,
     selected_device_index_in_vec: Option<usize>,
     is_buttplug_connected: bool,
     is_war_thunder_connected: bool,
-    log_messages: Vec<String>,
+    is_war_thunder_in_battle: bool,
+    is_connection_metrics_visible: bool,
+    // Настройки с диска, изменившиеся вне приложения при несохраненных правках: ждут решения пользователя
+    externally_changed_settings: Option<ApplicationSettings>,
+    processing_started_at: Option<Instant>, // Начало сессии обработки (для мягкого старта)
+    // Проверка адреса сервера Buttplug: адрес в проверке и последний результат (адрес, итог)
+    buttplug_address_under_test: Option<String>,
+    buttplug_address_test_result: Option<(String, Result<(), String>)>,
+    war_thunder_poll_metrics: Option<WarThunderPollMetrics>,
+    device_command_metrics: Option<DeviceCommandMetrics>,
+    log_messages: Vec<LogEntry>,
+    log_filter: LogFilter,
     is_processing_enabled: bool,
     config_editor_new_event_name: String,
     config_editor_new_event_intensity: f64,
     config_editor_new_event_duration: u64,
+    config_editor_selected_preset_name: Option<String>, // Пресет, значения которого сейчас в форме добавления
+    last_continuous_intensity: Option<f64>, // Последняя отправленная интенсивность непрерывных сопоставлений
+    burst_expires_at: Option<Instant>, // Временное усиление активно до этого момента (см. BurstModifierSettings)
+    are_indicator_updates_requested: bool, // Что мы последним сообщили WT задаче про отправку индикаторов
+    config_editor_new_group_name: String,
+    is_raw_json_capture_enabled: bool,
+    last_raw_indicators_json: Option<String>,
+    last_raw_state_json: Option<String>,
+    rule_flash_started_at: HashMap<String, Instant>, // Имя правила -> когда началась подсветка
+    // Каждое срабатывание публикуется в шину; счетчики срабатываний - такой же подписчик, как внешние потребители
+    triggered_event_bus: TriggeredEventBus,
+    rule_statistics_receiver: tokio::sync::broadcast::Receiver<TriggeredEventNotification>,
+    rule_statistics: RuleStatistics,
+    // Панель сырых команд (расширенный режим)
+    raw_command_device_index: Option<u32>, // Индекс BP
+    raw_command_endpoint: Option<Endpoint>,
+    raw_command_hex_text: String,
+    raw_command_write_with_response: bool,
+    raw_read_expected_length: u32,
+    rule_statistics_save_due_at: Option<Instant>, // Есть несохраненные срабатывания; запись не чаще RULE_STATISTICS_SAVE_INTERVAL
+    // Последние боевые индикаторы с прошлых сессий - примеры значений для редактора условий без игры
+    indicators_cache: IndicatorsCache,
+    indicators_cache_save_due_at: Option<Instant>, // Кэш обновлялся; запись не чаще INDICATORS_CACHE_SAVE_INTERVAL
+    profile_import_path_text: String,
+    pending_profile_import: Option<ProfileFile>, // Загружен, но имя занято - ждет решения пользователя
+    profile_import_rename_text: String,
+    active_config_path_label: String,
+    ready_device_indices: HashSet<u32>, // Индексы BP устройств, прошедших прогрев
+    // Что сейчас запущено игровыми событиями (а не ручными тестами) - это останавливаем при потере WT
+    game_driven_device_indices: HashSet<u32>, // Индексы BP устройств, запущенных игровыми событиями
+    game_driven_member_addresses: HashSet<String>,
+    last_saved_settings: ApplicationSettings, // Состояние файла конфигурации (для "есть несохраненные изменения")
+    last_observed_settings: ApplicationSettings, // Для отложенного автосохранения: что было в прошлом кадре
+    autosave_due_at: Option<Instant>,
+    sensor_subscribed_device_indices: HashSet<u32>, // Индексы BP устройств с включенной подпиской на датчики
+    latest_sensor_readings: HashMap<u32, Vec<(String, Vec<i32>)>>, // Индекс BP -> последние показания по типам датчиков
+    device_commanded_speeds: HashMap<u32, f64>, // Индекс BP -> скорость, отправленная на устройство
+    device_output_intensities: HashMap<u32, f64>, // Индекс BP -> что устройство фактически получило (см. ButtplugDeviceOutput)
+    was_window_focused: bool,
+    was_window_minimized: bool,
+    // Разрешен ли вывод игровых событий на устройства. Ручное включение обработки разрешает его сразу,
+    // автозапуск - нет: пользователь подтверждает вывод кнопкой, чтобы ничего не сработало неожиданно.
+    is_output_armed: bool,
+    last_sent_output_armed: Option<bool>, // Что последним отправлено Buttplug задаче (SetOutputArmed)
+    is_quiet_hours_active: bool, // Сейчас тихие часы: игровые события не выводятся на устройства
+    quiet_hours_checked_at: Option<Instant>, // None - проверить на ближайшем кадре (запуск, смена настроек)
+    applied_mini_mode: Option<bool>, // Режим, под который уже настроено окно (None - еще не настраивалось)
+    select_pulse_due_at: Option<Instant>, // Пользователь сменил выбор; импульс - если выбор не менялся SELECT_PULSE_DEBOUNCE
+    last_quiet_hours_error: Option<String>, // Ошибка в настройках тихих часов сообщается один раз
+    // "Без вибрации": правила продолжают срабатывать и логироваться, но на устройства ничего не уходит
+    is_muted: bool,
+    is_no_devices_hint_visible: bool, // Подсказка после сканирования без результатов (закрывается пользователем)
+    is_awaiting_battle_return: bool, // Данные WT пропадали; следующие данные боя начинают новую сессию
+    mapping_curve_simulation: MappingCurveSimulation,
+}
+
+// Автосохранение выполняется, когда настройки не менялись столько времени
+const AUTOSAVE_DEBOUNCE: Duration = Duration::from_secs(2);
+const RULE_STATISTICS_SAVE_INTERVAL: Duration = Duration::from_secs(10);
+const QUIET_HOURS_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+const INDICATORS_CACHE_SAVE_INTERVAL: Duration = Duration::from_secs(10);
+// Размеры окна: обычный режим (как при запуске, см. main.rs) и мини-режим
+const FULL_MODE_WINDOW_SIZE: egui::Vec2 = egui::vec2(800.0, 600.0);
+const FULL_MODE_MIN_WINDOW_SIZE: egui::Vec2 = egui::vec2(600.0, 400.0);
+const MINI_MODE_WINDOW_SIZE: egui::Vec2 = egui::vec2(340.0, 90.0);
+// При быстром переборе устройств в списке импульс получает только то, на котором остановились
+const SELECT_PULSE_DEBOUNCE: Duration = Duration::from_millis(400);
+
+// Сколько длится подсветка правила после срабатывания или теста
+const RULE_FLASH_DURATION: Duration = Duration::from_millis(600);
+
+// Сила подсветки 1.0 -> 0.0 по мере угасания
+fn rule_flash_strength(flash_started_at: Option<&Instant>, now: Instant) -> f32 {
+    flash_started_at
+        .map(|started_at| 1.0 - now.saturating_duration_since(*started_at).as_secs_f32() / RULE_FLASH_DURATION.as_secs_f32())
+        .unwrap_or(0.0)
+        .max(0.0)
+}
+
+#[cfg(feature = "sound_feedback")]
+fn play_rule_trigger_beep() {
+    // Воспроизводим в отдельном потоке, чтобы не блокировать GUI
+    std::thread::spawn(|| {
+        use rodio::Source;
+        let Ok((_output_stream, stream_handle)) = rodio::OutputStream::try_default() else {
+            tracing::warn!("Не удалось открыть аудиоустройство для звукового сигнала.");
+            return;
+        };
+        let beep = rodio::source::SineWave::new(880.0)
+            .take_duration(Duration::from_millis(120))
+            .amplify(0.2);
+        if stream_handle.play_raw(beep.convert_samples()).is_ok() {
+            std::thread::sleep(Duration::from_millis(150)); // Поток должен жить, пока звук играет
+        }
+    });
+}
+
+#[cfg(not(feature = "sound_feedback"))]
+fn play_rule_trigger_beep() {}
+
+// Сколько символов сырого JSON показывать в отладочной панели
+const RAW_JSON_DISPLAY_LIMIT_CHARS: usize = 20_000;
+
+// Редактор паттерна: график интенсивности по времени. Точки перетаскиваются мышью,
+// двойной клик по пустому месту добавляет точку, правый клик по точке удаляет ее.
+// Размер области меняется за правый нижний угол.
+fn show_pattern_timeline_editor(ui: &mut egui::Ui, device_action: &mut DeviceAction, editor_id: egui::Id) {
+    egui::Resize::default()
+        .id_salt(editor_id)
+        .default_size([320.0, 90.0])
+        .min_size([120.0, 50.0])
+        .show(ui, |ui| {
+            let (graph_response, painter) = ui.allocate_painter(ui.available_size(), egui::Sense::click());
+            let graph_rect = graph_response.rect;
+            painter.rect_filled(graph_rect, 2.0, ui.visuals().extreme_bg_color);
+            let to_screen = |pattern_point: &PatternPoint| egui::pos2(
+                graph_rect.left() + pattern_point.time_fraction as f32 * graph_rect.width(),
+                graph_rect.bottom() - pattern_point.intensity as f32 * graph_rect.height(),
+            );
+
+            let line_points: Vec<egui::Pos2> = device_action.sorted_pattern_points().iter().map(to_screen).collect();
+            if line_points.len() >= 2 {
+                painter.add(egui::Shape::line(line_points, egui::Stroke::new(2.0, egui::Color32::LIGHT_BLUE)));
+            }
+
+            let mut point_to_remove_index: Option<usize> = None;
+            for (point_index, pattern_point) in device_action.pattern_points.iter_mut().enumerate() {
+                let point_center = to_screen(pattern_point);
+                let point_response = ui.interact(
+                    egui::Rect::from_center_size(point_center, egui::vec2(12.0, 12.0)),
+                    editor_id.with(point_index),
+                    egui::Sense::click_and_drag(),
+                );
+                if point_response.dragged() {
+                    let drag_delta = point_response.drag_delta();
+                    pattern_point.time_fraction = (pattern_point.time_fraction + f64::from(drag_delta.x / graph_rect.width())).clamp(0.0, 1.0);
+                    pattern_point.intensity = (pattern_point.intensity - f64::from(drag_delta.y / graph_rect.height())).clamp(0.0, 1.0);
+                }
+                if point_response.secondary_clicked() {
+                    point_to_remove_index = Some(point_index);
+                }
+                let point_radius = if point_response.hovered() || point_response.dragged() { 6.0 } else { 4.0 };
+                painter.circle_filled(point_center, point_radius, egui::Color32::WHITE);
+            }
+            if let Some(point_index) = point_to_remove_index {
+                device_action.pattern_points.remove(point_index);
+            }
+            if graph_response.double_clicked()
+                && let Some(pointer_position) = graph_response.interact_pointer_pos()
+            {
+                device_action.pattern_points.push(PatternPoint {
+                    time_fraction: f64::from((pointer_position.x - graph_rect.left()) / graph_rect.width()).clamp(0.0, 1.0),
+                    intensity: f64::from((graph_rect.bottom() - pointer_position.y) / graph_rect.height()).clamp(0.0, 1.0),
+                });
+            }
+
+            let axis_text_color = ui.visuals().weak_text_color();
+            painter.text(graph_rect.left_bottom(), egui::Align2::LEFT_BOTTOM, "0", egui::FontId::monospace(10.0), axis_text_color);
+            painter.text(
+                graph_rect.right_bottom(),
+                egui::Align2::RIGHT_BOTTOM,
+                format!("{} мс", device_action.duration_milliseconds),
+                egui::FontId::monospace(10.0),
+                axis_text_color,
+            );
+        });
+}
+
+// График кривой сопоставления: по X - значение поля в value_range, по Y - интенсивность 0..1.
+// Синяя линия - результат сопоставления, оранжевая - что уйдет на устройство (усиление, порог интенсивности).
+// Клик или перетаскивание по графику задает значение поля; возвращается новое значение.
+fn show_mapping_curve_plot(
+    ui: &mut egui::Ui,
+    mapping_curve: &[(f64, f64)],
+    output_curve: &[(f64, f64)],
+    value_range: (f64, f64),
+    operating_point: Option<(f64, f64)>,
+) -> Option<f64> {
+    let (value_min, value_max) = value_range;
+    let desired_size = egui::vec2(ui.available_width().min(420.0), 120.0);
+    let (graph_response, painter) = ui.allocate_painter(desired_size, egui::Sense::click_and_drag());
+    let graph_rect = graph_response.rect;
+    painter.rect_filled(graph_rect, 2.0, ui.visuals().extreme_bg_color);
+    let to_screen = |&(field_value, intensity): &(f64, f64)| egui::pos2(
+        graph_rect.left() + ((field_value - value_min) / (value_max - value_min)) as f32 * graph_rect.width(),
+        graph_rect.bottom() - intensity.clamp(0.0, 1.0) as f32 * graph_rect.height(),
+    );
+    for (curve, curve_color) in [(output_curve, egui::Color32::from_rgb(255, 165, 0)), (mapping_curve, egui::Color32::LIGHT_BLUE)] {
+        if curve.len() >= 2 {
+            painter.add(egui::Shape::line(curve.iter().map(to_screen).collect(), egui::Stroke::new(2.0, curve_color)));
+        }
+    }
+    if let Some(operating_point) = operating_point {
+        let point_center = to_screen(&operating_point);
+        painter.line_segment(
+            [egui::pos2(point_center.x, graph_rect.top()), egui::pos2(point_center.x, graph_rect.bottom())],
+            egui::Stroke::new(1.0, ui.visuals().weak_text_color()),
+        );
+        painter.circle_filled(point_center, 5.0, egui::Color32::WHITE);
+    }
+
+    let axis_text_color = ui.visuals().weak_text_color();
+    painter.text(graph_rect.left_bottom(), egui::Align2::LEFT_BOTTOM, format!("{:.1}", value_min), egui::FontId::monospace(10.0), axis_text_color);
+    painter.text(graph_rect.right_bottom(), egui::Align2::RIGHT_BOTTOM, format!("{:.1}", value_max), egui::FontId::monospace(10.0), axis_text_color);
+    painter.text(graph_rect.left_top(), egui::Align2::LEFT_TOP, "1.0", egui::FontId::monospace(10.0), axis_text_color);
+
+    let pointer_position = graph_response.interact_pointer_pos().filter(|_| graph_response.clicked() || graph_response.dragged())?;
+    let pointer_fraction = f64::from((pointer_position.x - graph_rect.left()) / graph_rect.width()).clamp(0.0, 1.0);
+    Some(value_min + pointer_fraction * (value_max - value_min))
+}
+
+fn truncate_for_display(text: &str, limit_chars: usize) -> String {
+    match text.char_indices().nth(limit_chars) {
+        Some((cut_position, _)) => format!("{}\n... (обрезано, всего {} байт)", &text[..cut_position], text.len()),
+        None => text.to_string(),
+    }
+}
+
+fn action_target_label(target: &ActionTarget) -> String {
+    match target {
+        ActionTarget::SelectedDevice => "Выбранное устройство".to_string(),
+        ActionTarget::Device(address) => format!("Устройство: {}", address),
+        ActionTarget::Group(group_name) => format!("Группа: {}", group_name),
+    }
+}
+
+fn rule_evaluation_mode_label(evaluation_mode: RuleEvaluationMode) -> &'static str {
+    match evaluation_mode {
+        RuleEvaluationMode::AllMatching => "Все подходящие правила",
+        RuleEvaluationMode::FirstMatch => "Только первое по приоритету",
+    }
+}
+
+fn simultaneous_vibration_policy_label(policy: SimultaneousVibrationPolicy) -> &'static str {
+    match policy {
+        SimultaneousVibrationPolicy::Max => "Самая сильная",
+        SimultaneousVibrationPolicy::SumClamped => "Сумма (до 1.0)",
+        SimultaneousVibrationPolicy::Last => "Последняя",
+    }
+}
+
+// Редактор списка шаблонов фильтра устройств. Возвращает true, когда правка завершена и ее пора отправить.
+fn show_device_pattern_list(ui: &mut egui::Ui, list_label: &str, patterns: &mut Vec<String>) -> bool {
+    let mut is_edit_finished = false;
+    let mut pattern_to_delete_index: Option<usize> = None;
+    ui.label(list_label);
+    for (pattern_index, pattern) in patterns.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            is_edit_finished |= ui.add(egui::TextEdit::singleline(pattern).hint_text("часть имени устройства")).lost_focus();
+            if ui.small_button("x").clicked() {
+                pattern_to_delete_index = Some(pattern_index);
+            }
+        });
+    }
+    if let Some(pattern_index) = pattern_to_delete_index {
+        patterns.remove(pattern_index);
+        is_edit_finished = true;
+    }
+    if ui.small_button("Добавить шаблон").clicked() {
+        patterns.push(String::new());
+    }
+    is_edit_finished
+}
+
+fn missing_device_behavior_label(behavior: MissingDeviceBehavior) -> &'static str {
+    match behavior {
+        MissingDeviceBehavior::SkipSilently => "Пропустить молча",
+        MissingDeviceBehavior::SkipWithWarning => "Пропустить с предупреждением",
+        MissingDeviceBehavior::FallBackToDefaultDevice => "Отправить на устройство по умолчанию",
+    }
 }
 
 impl WarThunderHapticsApplication {
@@ -35,285 +561,2667 @@ impl WarThunderHapticsApplication {
         _creation_context: &eframe::CreationContext<'_>,
         command_sender: mpsc::Sender<CommandToAsyncTasks>,
         update_receiver: mpsc::Receiver<UpdateFromAsyncTasks>,
+        triggered_event_bus: TriggeredEventBus,
     ) -> Self {
-        let initial_settings = match configuration_manager::load_configuration() {
+        let settings_store = configuration_manager::SettingsStore::resolve();
+        let initial_settings = match settings_store.as_ref().map_err(String::clone).and_then(|settings_store| settings_store.load()) {
             Ok(settings) => settings,
             Err(err_msg) => {
                 tracing::error!("Ошибка загрузки конфигурации: {}. Используются настройки по умолчанию.", err_msg);
                 ApplicationSettings::default()
             }
         };
-        let _ = command_sender.try_send(CommandToAsyncTasks::UpdateApplicationSettings(initial_settings.clone()));
+        let command_sender = GuiCommandSender::new(command_sender);
+        command_sender.send(CommandToAsyncTasks::UpdateApplicationSettings(Box::new(initial_settings.clone())));
 
-        Self {
+        let mut application = Self {
             command_sender,
+            clock: Arc::new(SystemClock),
             update_receiver,
+            last_saved_settings: initial_settings.clone(),
+            last_observed_settings: initial_settings.clone(),
+            autosave_due_at: None,
+            sensor_subscribed_device_indices: HashSet::new(),
+            latest_sensor_readings: HashMap::new(),
+            device_commanded_speeds: HashMap::new(),
+            device_output_intensities: HashMap::new(),
+            was_window_focused: true,
+            was_window_minimized: false,
+            is_output_armed: false,
+            last_sent_output_armed: None,
+            is_quiet_hours_active: false,
+            quiet_hours_checked_at: None,
+            applied_mini_mode: None,
+            select_pulse_due_at: None,
+            last_quiet_hours_error: None,
+            is_muted: false,
+            is_no_devices_hint_visible: false,
+            is_awaiting_battle_return: false,
             settings: initial_settings,
             current_wt_indicators: None,
             game_state_snapshot: GameStateSnapshot::default(),
-            buttplug_devices: Vec::new(), // Здесь храним оригинальный ButtplugClientDevice
+            buttplug_devices: Vec::new(),
             selected_device_index_in_vec: None,
             is_buttplug_connected: false,
             is_war_thunder_connected: false,
-            log_messages: vec!["Приложение запущено.".to_string()],
+            is_war_thunder_in_battle: false,
+            is_connection_metrics_visible: false,
+            externally_changed_settings: None,
+            processing_started_at: None,
+            buttplug_address_under_test: None,
+            buttplug_address_test_result: None,
+            war_thunder_poll_metrics: None,
+            device_command_metrics: None,
+            log_messages: vec![LogEntry {
+                category: LogCategory::General,
+                device_address: None,
+                message: "Приложение запущено.".to_string(),
+            }],
+            log_filter: LogFilter::All,
             is_processing_enabled: false,
             config_editor_new_event_name: "Новое событие".to_string(),
             config_editor_new_event_intensity: 0.5,
             config_editor_new_event_duration: 500,
-        }
+            config_editor_selected_preset_name: None,
+            last_continuous_intensity: None,
+            burst_expires_at: None,
+            are_indicator_updates_requested: true,
+            config_editor_new_group_name: "Новая группа".to_string(),
+            is_raw_json_capture_enabled: false,
+            last_raw_indicators_json: None,
+            last_raw_state_json: None,
+            rule_flash_started_at: HashMap::new(),
+            rule_statistics_receiver: triggered_event_bus.subscribe(),
+            triggered_event_bus,
+            rule_statistics: configuration_manager::load_rule_statistics().unwrap_or_else(|e| {
+                tracing::error!("{}. Счетчики срабатываний начнутся с нуля.", e);
+                RuleStatistics::default()
+            }),
+            rule_statistics_save_due_at: None,
+            indicators_cache: configuration_manager::load_indicators_cache().unwrap_or_else(|e| {
+                tracing::error!("{}. Примеры значений появятся после следующего боя.", e);
+                IndicatorsCache::default()
+            }),
+            indicators_cache_save_due_at: None,
+            profile_import_path_text: String::new(),
+            mapping_curve_simulation: MappingCurveSimulation::default(),
+            pending_profile_import: None,
+            profile_import_rename_text: String::new(),
+            raw_command_device_index: None,
+            raw_command_endpoint: None,
+            raw_command_hex_text: String::new(),
+            raw_command_write_with_response: false,
+            raw_read_expected_length: 1,
+            active_config_path_label: settings_store
+                .map(|settings_store| settings_store.config_file_path().display().to_string())
+                .unwrap_or_else(|e| e),
+            ready_device_indices: HashSet::new(),
+            game_driven_device_indices: HashSet::new(),
+            game_driven_member_addresses: HashSet::new(),
+        };
+        application.report_settings_validation();
+        application.report_unknown_configuration_keys();
+        application.run_auto_start_actions();
+        application
     }
 
-    fn add_log_message(&mut self, message: String) {
-        tracing::info!("{}", message);
-        self.log_messages.insert(0, message);
-        if self.log_messages.len() > 100 {
-            self.log_messages.pop();
+    fn run_auto_start_actions(&mut self) {
+        if self.settings.auto_connect_buttplug {
+            self.add_log_message("Автозапуск: подключение к Buttplug.".to_string());
+            self.command_sender.send(CommandToAsyncTasks::ScanForButtplugDevices);
+        }
+        if self.settings.auto_start_processing {
+            self.is_processing_enabled = true;
+            self.processing_started_at = Some(self.clock.now());
+            self.add_log_message("Автозапуск: обработка событий War Thunder включена. Вывод на устройства ожидает подтверждения.".to_string());
+            self.command_sender.send(CommandToAsyncTasks::StartProcessing);
         }
     }
 
-    fn handle_incoming_updates(&mut self) {
-        while let Ok(update) = self.update_receiver.try_recv() {
-            match update {
-                UpdateFromAsyncTasks::LogMessage(msg) => self.add_log_message(msg),
-                UpdateFromAsyncTasks::WarThunderIndicatorsUpdate(indicators) => {
-                    self.current_wt_indicators = Some(indicators.clone());
-                    if self.is_processing_enabled {
-                        let actions_to_take = game_event_processor::process_war_thunder_data(
-                            &indicators,
-                            &self.settings,
-                            &mut self.game_state_snapshot,
-                        );
-                        for device_action in actions_to_take {
-                            if let Some(device_idx_in_vec) = self.selected_device_index_in_vec.or_else(|| if !self.buttplug_devices.is_empty() { Some(0)} else {None} ) {
-                                if let Some(device) = self.buttplug_devices.get(device_idx_in_vec) { 
-                                    match device_action.action_type {
-                                        DeviceActionType::Vibrate => {
-                                            self.add_log_message(format!(
-                                                "Игровое событие: вибрация устр-ва '{}' (индекс {}) инт. {} на {} мс",
-                                                device.name(), 
-                                                device.index(),
-                                                device_action.intensity,
-                                                device_action.duration_milliseconds
-                                            ));
-                                            let _ = self.command_sender.try_send(CommandToAsyncTasks::VibrateDevice {
-                                                device_index: device_idx_in_vec, 
-                                                speed: device_action.intensity,
-                                            });
-                                        }
-                                        DeviceActionType::Stop => {
-                                            let _ = self.command_sender.try_send(CommandToAsyncTasks::StopDevice(device_idx_in_vec));
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-                UpdateFromAsyncTasks::WarThunderConnectionStatus(is_connected) => {
-                    self.is_war_thunder_connected = is_connected;
-                    if !is_connected { self.current_wt_indicators = None; }
-                }
-                UpdateFromAsyncTasks::ButtplugConnected => {
-                    self.is_buttplug_connected = true;
-                    self.add_log_message("Успешно подключено к Buttplug серверу.".to_string());
+    // Строгая проверка конфигурации: только предупреждения, загруженные настройки остаются в силе
+    fn report_unknown_configuration_keys(&mut self) {
+        if !self.settings.strict_config_validation {
+            return;
+        }
+        match configuration_manager::find_unknown_keys_in_active_configuration() {
+            Ok(unknown_keys) => {
+                for unknown_key in unknown_keys {
+                    self.add_log_message(format!("Предупреждение конфигурации: неизвестный ключ '{}' проигнорирован (опечатка?)", unknown_key));
                 }
-                UpdateFromAsyncTasks::ButtplugDisconnected => {
-                    self.is_buttplug_connected = false;
-                    self.buttplug_devices.clear();
-                    self.selected_device_index_in_vec = None;
-                    self.add_log_message("Отключено от Buttplug сервера.".to_string());
+            }
+            Err(e) => self.add_log_message(format!("Строгая проверка конфигурации не выполнена: {}", e)),
+        }
+    }
+
+    fn report_settings_validation(&mut self) {
+        let settings_validation = configuration_manager::validate_settings(&self.settings);
+        if settings_validation.is_valid() {
+            return;
+        }
+        for validation_error in settings_validation.rule_errors {
+            self.add_log_message(format!("Ошибка в условии: {}", validation_error));
+        }
+        self.report_war_thunder_api_errors(settings_validation.war_thunder_api_errors);
+    }
+
+    fn report_war_thunder_api_errors(&mut self, war_thunder_api_errors: Vec<String>) {
+        for validation_error in war_thunder_api_errors {
+            self.add_tagged_log_message(LogCategory::WarThunder, None, format!("Ошибка в настройках War Thunder API: {}", validation_error));
+        }
+    }
+
+    fn show_war_thunder_api_editor(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Адрес API:");
+            ui.text_edit_singleline(&mut self.settings.war_thunder_api.base_url)
+                .on_hover_text("По умолчанию http://localhost:8111 - сервер самой игры");
+        });
+        ui.label("Дополнительные заголовки:");
+        let mut header_to_delete_index: Option<usize> = None;
+        for (header_index, header_setting) in self.settings.war_thunder_api.request_headers.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut header_setting.name).hint_text("Имя").desired_width(120.0));
+                ui.add(egui::TextEdit::singleline(&mut header_setting.value).hint_text("Значение").password(true).desired_width(160.0));
+                if ui.small_button("Удалить").clicked() {
+                    header_to_delete_index = Some(header_index);
                 }
-                UpdateFromAsyncTasks::ButtplugDeviceFound(clonable_device) => { 
-                    let device = clonable_device.0; // Извлекаем внутренний ButtplugClientDevice
-                    if !self.buttplug_devices.iter().any(|d_arc| d_arc.index() == device.index()) {
-                        self.add_log_message(format!(
-                            "Найдено устройство Buttplug: {} (Индекс: {}, Атрибуты: {:?})",
-                            device.name(),
-                            device.index(),
-                            device.message_attributes()
-                        ));
-                        self.buttplug_devices.push(device); // Храним оригинальный ButtplugClientDevice
-                        if self.selected_device_index_in_vec.is_none() && !self.buttplug_devices.is_empty() {
-                            self.selected_device_index_in_vec = Some(0);
-                        }
+            });
+        }
+        if let Some(header_index) = header_to_delete_index {
+            self.settings.war_thunder_api.request_headers.remove(header_index);
+        }
+        if ui.button("Добавить заголовок").clicked() {
+            self.settings.war_thunder_api.request_headers.push(HttpHeaderSetting { name: String::new(), value: String::new() });
+        }
+        let mut is_basic_auth_enabled = self.settings.war_thunder_api.basic_auth.is_some();
+        if ui.checkbox(&mut is_basic_auth_enabled, "Basic-авторизация").changed() {
+            self.settings.war_thunder_api.basic_auth = is_basic_auth_enabled.then(|| BasicAuthSetting { username: String::new(), password: String::new() });
+        }
+        if let Some(basic_auth) = &mut self.settings.war_thunder_api.basic_auth {
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut basic_auth.username).hint_text("Пользователь").desired_width(120.0));
+                ui.add(egui::TextEdit::singleline(&mut basic_auth.password).hint_text("Пароль").password(true).desired_width(160.0));
+            });
+        }
+        ui.label(egui::RichText::new("Секреты хранятся в файле настроек открытым текстом.").small());
+        if ui.button("Применить").clicked() {
+            self.command_sender.send(CommandToAsyncTasks::UpdateApplicationSettings(Box::new(self.settings.clone())));
+            self.report_war_thunder_api_errors(configuration_manager::validate_settings(&self.settings).war_thunder_api_errors);
+        }
+    }
+
+    fn show_continuous_mappings_editor(&mut self, ui: &mut egui::Ui) {
+        let mut mapping_to_delete_index: Option<usize> = None;
+        for (mapping_index, mapping) in self.settings.continuous_mappings.iter_mut().enumerate() {
+            ui.group(|ui| {
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut mapping.enabled, "");
+                    ui.text_edit_singleline(&mut mapping.name);
+                    if ui.small_button("Удалить").clicked() {
+                        mapping_to_delete_index = Some(mapping_index);
                     }
-                }
-                UpdateFromAsyncTasks::ButtplugDeviceLost(clonable_device) => { 
-                    let device = clonable_device.0; // Извлекаем внутренний ButtplugClientDevice
-                    self.add_log_message(format!("Устройство Buttplug потеряно: {} (Индекс: {})", device.name(), device.index()));
-                    self.buttplug_devices.retain(|d_arc| d_arc.index() != device.index());
-                    if let Some(selected_idx) = self.selected_device_index_in_vec {
-                        if selected_idx >= self.buttplug_devices.len() {
-                            self.selected_device_index_in_vec = if self.buttplug_devices.is_empty() { None } else { Some(0) };
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Поле:");
+                    ui.text_edit_singleline(&mut mapping.source_field);
+                    ui.label("от");
+                    ui.add(egui::DragValue::new(&mut mapping.input_min).speed(0.1));
+                    ui.label("до");
+                    ui.add(egui::DragValue::new(&mut mapping.input_max).speed(0.1));
+                });
+                let mut term_to_delete_index: Option<usize> = None;
+                for (term_index, term) in mapping.additional_terms.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.label("+ Поле:");
+                        ui.text_edit_singleline(&mut term.field);
+                        ui.label("от");
+                        ui.add(egui::DragValue::new(&mut term.input_min).speed(0.1));
+                        ui.label("до");
+                        ui.add(egui::DragValue::new(&mut term.input_max).speed(0.1));
+                        ui.label("вес");
+                        ui.add(egui::DragValue::new(&mut term.weight).speed(0.01).range(0.0..=1.0));
+                        if ui.small_button("x").clicked() {
+                            term_to_delete_index = Some(term_index);
                         }
-                    }
-                }
-                UpdateFromAsyncTasks::ButtplugError(err_msg) => {
-                    self.add_log_message(format!("Ошибка Buttplug: {}", err_msg));
+                    });
                 }
-                 UpdateFromAsyncTasks::ApplicationSettingsLoaded(loaded_settings) => {
-                    self.settings = loaded_settings;
-                    self.add_log_message("Настройки успешно загружены.".to_string());
+                if let Some(term_index) = term_to_delete_index {
+                    mapping.additional_terms.remove(term_index);
                 }
+                ui.horizontal(|ui| {
+                    if ui.small_button("Добавить поле").clicked() {
+                        mapping.additional_terms.push(MappingTerm {
+                            field: "speed".to_string(),
+                            input_min: 0.0,
+                            input_max: 1.0,
+                            weight: 0.5,
+                        });
+                    }
+                    egui::ComboBox::from_id_salt(("mapping_combine_mode", mapping_index))
+                        .selected_text(match mapping.combine_mode {
+                            TermCombineMode::WeightedSum => "Взвешенная сумма",
+                            TermCombineMode::Max => "Максимум",
+                        })
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut mapping.combine_mode, TermCombineMode::WeightedSum, "Взвешенная сумма");
+                            ui.selectable_value(&mut mapping.combine_mode, TermCombineMode::Max, "Максимум");
+                        });
+                    ui.label("Макс. интенсивность:");
+                    ui.add(egui::Slider::new(&mut mapping.max_intensity, 0.0..=1.0));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Мертвая зона:");
+                    ui.add(egui::Slider::new(&mut mapping.deadzone, 0.0..=0.5))
+                        .on_hover_text("Интенсивность ниже порога останавливает устройство");
+                });
+            });
+        }
+        if let Some(mapping_index) = mapping_to_delete_index {
+            self.settings.continuous_mappings.remove(mapping_index);
+            self.add_log_message("Сопоставление удалено. Не забудьте сохранить конфигурацию.".to_string());
+        }
+        if ui.button("Добавить сопоставление").clicked() {
+            self.settings.continuous_mappings.push(ContinuousMappingSetting {
+                name: "Новое сопоставление".to_string(),
+                enabled: false,
+                source_field: "speed".to_string(),
+                input_min: 0.0,
+                input_max: 100.0,
+                max_intensity: 1.0,
+                additional_terms: Vec::new(),
+                combine_mode: TermCombineMode::WeightedSum,
+                deadzone: 0.05,
+            });
+        }
+    }
+
+    // Подбор кривой: значение поля подставляется в последние индикаторы (или в пустые без игры),
+    // результат проходит те же усиление и порог интенсивности, что и живой вывод
+    fn show_mapping_curve_simulation(&mut self, ui: &mut egui::Ui) {
+        if self.settings.continuous_mappings.is_empty() {
+            ui.label("Сопоставлений нет - добавьте хотя бы одно выше.");
+            return;
+        }
+        let last_mapping_index = self.settings.continuous_mappings.len() - 1;
+        self.mapping_curve_simulation.mapping_index = self.mapping_curve_simulation.mapping_index.min(last_mapping_index);
+        ui.horizontal(|ui| {
+            ui.label("Сопоставление:");
+            egui::ComboBox::from_id_salt("mapping_curve_simulation_mapping")
+                .selected_text(self.settings.continuous_mappings[self.mapping_curve_simulation.mapping_index].name.clone())
+                .show_ui(ui, |ui| {
+                    for (mapping_index, mapping) in self.settings.continuous_mappings.iter().enumerate() {
+                        ui.selectable_value(&mut self.mapping_curve_simulation.mapping_index, mapping_index, &mapping.name);
+                    }
+                });
+        });
+
+        let mapping = self.settings.continuous_mappings[self.mapping_curve_simulation.mapping_index].clone();
+        if !game_event_processor::is_simulatable_indicator_field(&mapping.source_field) {
+            ui.label(format!("Поле '{}' нельзя подставить: подбор работает для полей игры (health_percentage, speed и т.п.).", mapping.source_field));
+            return;
+        }
+        // Немного за пределами входного диапазона видно, где кривая упирается в ограничения
+        let (range_start, range_end) = (mapping.input_min.min(mapping.input_max), mapping.input_min.max(mapping.input_max));
+        let range_margin = (range_end - range_start) * 0.1;
+        let (mut value_min, mut value_max) = (range_start - range_margin, range_end + range_margin);
+        if let Some((field_min, field_max)) = game_event_processor::indicator_field_value_range(&mapping.source_field) {
+            value_min = value_min.max(field_min);
+            value_max = value_max.min(field_max);
+        }
+        if value_max - value_min <= f64::EPSILON {
+            ui.label("Пустой входной диапазон: задайте разные значения \"от\" и \"до\".");
+            return;
+        }
+
+        let base_indicators = self.example_indicators().cloned().unwrap_or_default();
+        let intensity_factor = self.game_intensity_factor();
+        let intensity_floor = self.settings.intensity_floor;
+        let mapped_intensity_at = |field_value: f64| game_event_processor::simulate_continuous_mapping(
+            &base_indicators,
+            &self.settings,
+            &self.game_state_snapshot,
+            &mapping,
+            field_value,
+        );
+        // Усиление применяет GUI, порог - задача Buttplug; здесь оба повторяются, чтобы показать итог
+        let output_intensity_for = |mapped_intensity: f64| {
+            buttplug_connector::lift_to_intensity_floor((mapped_intensity * intensity_factor).min(1.0), intensity_floor)
+        };
+        let mut mapping_curve = Vec::with_capacity(MAPPING_CURVE_SAMPLE_COUNT);
+        let mut output_curve = Vec::with_capacity(MAPPING_CURVE_SAMPLE_COUNT);
+        for sample_index in 0..MAPPING_CURVE_SAMPLE_COUNT {
+            let field_value = value_min + (value_max - value_min) * sample_index as f64 / (MAPPING_CURVE_SAMPLE_COUNT - 1) as f64;
+            if let Some(mapped_intensity) = mapped_intensity_at(field_value) {
+                mapping_curve.push((field_value, mapped_intensity));
+                output_curve.push((field_value, output_intensity_for(mapped_intensity)));
             }
         }
+
+        let simulation = &mut self.mapping_curve_simulation;
+        simulation.field_value = simulation.field_value.clamp(value_min, value_max);
+        ui.add(egui::Slider::new(&mut simulation.field_value, value_min..=value_max).text(mapping.source_field.as_str()));
+        let mapped_intensity = mapped_intensity_at(simulation.field_value);
+        let output_intensity = mapped_intensity.map(output_intensity_for);
+        let operating_point = output_intensity.map(|output_intensity| (simulation.field_value, output_intensity));
+        if let Some(dragged_value) = show_mapping_curve_plot(ui, &mapping_curve, &output_curve, (value_min, value_max), operating_point) {
+            simulation.field_value = dragged_value;
+        }
+        match (mapped_intensity, output_intensity) {
+            (Some(mapped_intensity), Some(output_intensity)) => ui.label(format!(
+                "Сопоставление: {:.2}, на устройство: {:.2} (усиление x{:.2}, порог {:.2})",
+                mapped_intensity,
+                output_intensity,
+                intensity_factor,
+                intensity_floor
+            )),
+            _ => ui.label("Сопоставление не дает значения (поле игнорируется?)"),
+        };
+        if mapping.enabled {
+            ui.label("Сопоставление включено: во время боя устройством управляют и живые данные.");
+        }
+
+        let output_checkbox_response = ui.checkbox(&mut simulation.is_device_output_enabled, "Чувствовать на устройстве по умолчанию");
+        if output_checkbox_response.changed() && !simulation.is_device_output_enabled {
+            simulation.last_sent_intensity = None;
+            if let Some(device_index) = self.default_target_device_bp_index() {
+                self.command_sender.send(CommandToAsyncTasks::StopDevice(device_index));
+            }
+            return;
+        }
+        // Отправляем итог без порога: его применит задача Buttplug
+        let sent_intensity = mapped_intensity.map(|mapped_intensity| (mapped_intensity * intensity_factor).min(1.0)).unwrap_or(0.0);
+        self.send_mapping_curve_simulation_output(sent_intensity);
     }
-}
 
-impl eframe::App for WarThunderHapticsApplication {
-    fn update(&mut self, context: &egui::Context, _frame: &mut eframe::Frame) {
-        self.handle_incoming_updates();
+    fn send_mapping_curve_simulation_output(&mut self, intensity: f64) {
+        if !self.mapping_curve_simulation.is_device_output_enabled || self.is_muted || !self.is_output_armed {
+            return;
+        }
+        let intensity_changed = self.mapping_curve_simulation.last_sent_intensity
+            .is_none_or(|last_intensity| (last_intensity - intensity).abs() > 0.01);
+        let Some(device_index) = self.default_target_device_bp_index().filter(|_| intensity_changed) else {
+            return;
+        };
+        let simulation_command = if intensity <= 0.0 {
+            CommandToAsyncTasks::StopDevice(device_index)
+        } else {
+            CommandToAsyncTasks::VibrateDevice { device_index, speed: intensity, actuator_speeds: Vec::new(), duration_milliseconds: None }
+        };
+        self.command_sender.send(simulation_command);
+        self.mapping_curve_simulation.last_sent_intensity = Some(intensity);
+    }
 
-        egui::TopBottomPanel::top("top_panel").show(context, |ui| {
-            egui::menu::bar(ui, |ui| {
-                // ... (меню Файл и Управление без изменений) ...
-                 ui.menu_button("Файл", |ui| {
-                    if ui.button("Сохранить конфигурацию").clicked() {
-                        match configuration_manager::save_configuration(&self.settings) {
-                            Ok(_) => self.add_log_message("Конфигурация успешно сохранена.".to_string()),
-                            Err(e) => self.add_log_message(format!("Ошибка сохранения конфигурации: {}", e)),
-                        }
-                        ui.close_menu();
+    // Свернутый инструмент подбора не должен оставлять устройство включенным
+    fn stop_mapping_curve_simulation_output(&mut self) {
+        if !self.mapping_curve_simulation.is_device_output_enabled {
+            return;
+        }
+        self.mapping_curve_simulation.is_device_output_enabled = false;
+        self.mapping_curve_simulation.last_sent_intensity = None;
+        if let Some(device_index) = self.default_target_device_bp_index() {
+            self.command_sender.send(CommandToAsyncTasks::StopDevice(device_index));
+        }
+    }
+
+    // Загруженные с диска настройки становятся и рабочими, и "сохраненными"
+    fn apply_loaded_settings(&mut self, loaded_settings: ApplicationSettings, success_message: &str) {
+        self.settings = loaded_settings.clone();
+        self.last_saved_settings = loaded_settings.clone();
+        self.last_observed_settings = loaded_settings.clone();
+        self.externally_changed_settings = None;
+        self.quiet_hours_checked_at = None;
+        self.command_sender.send(CommandToAsyncTasks::UpdateApplicationSettings(Box::new(loaded_settings)));
+        self.add_log_message(success_message.to_string());
+        self.report_settings_validation();
+        self.report_unknown_configuration_keys();
+    }
+
+    // Файл изменили вне приложения (см. configuration_watcher). Рабочие настройки принадлежат GUI:
+    // без несохраненных правок новые применяются сразу, иначе решает пользователь.
+    fn reconcile_externally_loaded_settings(&mut self, loaded_settings: ApplicationSettings) {
+        if loaded_settings == self.last_saved_settings {
+            return; // Наше же сохранение или изменение без последствий
+        }
+        if !self.has_unsaved_changes() {
+            self.apply_loaded_settings(loaded_settings, "Файл конфигурации изменен вне приложения - настройки перезагружены.");
+            return;
+        }
+        self.add_log_message("Файл конфигурации изменен вне приложения, но есть несохраненные правки - нужно выбрать, что оставить.".to_string());
+        self.externally_changed_settings = Some(loaded_settings);
+    }
+
+    fn show_external_settings_change_prompt(&mut self, context: &egui::Context) {
+        let Some(loaded_settings) = self.externally_changed_settings.clone() else {
+            return;
+        };
+        egui::Window::new("Конфигурация изменена вне приложения")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(context, |ui| {
+                ui.label("Файл конфигурации изменился на диске, а в приложении есть несохраненные правки.");
+                ui.horizontal(|ui| {
+                    if ui.button("Загрузить с диска").on_hover_text("Несохраненные правки будут потеряны").clicked() {
+                        self.apply_loaded_settings(loaded_settings.clone(), "Настройки загружены с диска, несохраненные правки отброшены.");
+                    }
+                    if ui.button("Оставить мои правки").on_hover_text("Следующее сохранение перезапишет файл").clicked() {
+                        // Дальше правки сравниваются с тем, что теперь на диске
+                        self.last_saved_settings = loaded_settings.clone();
+                        self.externally_changed_settings = None;
+                        self.add_log_message("Оставлены правки приложения; изменения файла будут перезаписаны при сохранении.".to_string());
+                    }
+                });
+            });
+    }
+
+    // Правило с ошибками не сохраняется включенным: оно бы молча никогда не срабатывало
+    fn disable_invalid_rules(&mut self) {
+        let mut disabled_rule_messages = Vec::new();
+        for event_action in self.settings.event_actions.iter_mut().filter(|event_action| event_action.enabled) {
+            let rule_errors = game_event_processor::validate_event_action(event_action);
+            if !rule_errors.is_empty() {
+                event_action.enabled = false;
+                disabled_rule_messages.push(format!("Правило '{}' выключено: {}", event_action.name, rule_errors.join("; ")));
+            }
+        }
+        if !disabled_rule_messages.is_empty() {
+            self.command_sender.send(CommandToAsyncTasks::UpdateApplicationSettings(Box::new(self.settings.clone())));
+        }
+        for disabled_rule_message in disabled_rule_messages {
+            self.add_log_message(disabled_rule_message);
+        }
+    }
+
+    fn has_unsaved_changes(&self) -> bool {
+        self.settings != self.last_saved_settings
+    }
+
+    // Часть правок уходит фоновым задачам только по окончании ввода (или не уходит вовсе, если
+    // нужна только GUI) - тогда настройки в окне отличаются от тех, с которыми работают задачи
+    fn has_unapplied_changes(&self) -> bool {
+        self.command_sender.last_sent_settings.borrow().as_ref() != Some(&self.settings)
+    }
+
+    // Кнопка "Применить": отправить текущие настройки фоновым задачам и сохранить в файл
+    fn apply_settings(&mut self) {
+        self.command_sender.send(CommandToAsyncTasks::UpdateApplicationSettings(Box::new(self.settings.clone())));
+        self.save_settings_to_disk("Настройки применены и сохранены.");
+    }
+
+    fn save_settings_to_disk(&mut self, success_message: &str) {
+        self.disable_invalid_rules();
+        match configuration_manager::save_configuration(&self.settings) {
+            Ok(_) => {
+                self.last_saved_settings = self.settings.clone();
+                self.add_log_message(success_message.to_string());
+            }
+            Err(e) => self.add_log_message(format!("Ошибка сохранения конфигурации: {}", e)),
+        }
+    }
+
+    // Отложенное автосохранение: каждое изменение сдвигает срок, запись - после паузы в правках
+    fn handle_autosave(&mut self) {
+        if !self.settings.autosave_on_change || !self.has_unsaved_changes() {
+            self.autosave_due_at = None;
+            return;
+        }
+        if self.settings != self.last_observed_settings {
+            self.last_observed_settings = self.settings.clone();
+            self.autosave_due_at = Some(self.clock.now() + AUTOSAVE_DEBOUNCE);
+        }
+        if self.autosave_due_at.is_some_and(|due_at| self.clock.now() >= due_at) {
+            self.autosave_due_at = None;
+            self.save_settings_to_disk("Конфигурация автоматически сохранена.");
+        }
+    }
+
+    // Данные WT пропали (отключение или выход из боя): сбрасываем состояние и по настройке гасим эффекты
+    fn handle_war_thunder_data_lost(&mut self) {
+        self.current_wt_indicators = None;
+        self.is_awaiting_battle_return = true;
+        let was_in_combat = self.game_state_snapshot.is_in_combat;
+        let combat_ended_events = game_event_processor::end_combat(&self.settings, &mut self.game_state_snapshot);
+        self.last_continuous_intensity = None;
+        if was_in_combat {
+            self.stop_effects_on_combat_end();
+        } else if self.settings.stop_effects_on_wt_disconnect {
+            self.stop_game_driven_effects();
+        }
+        // Без включенной обработки правила не выполняются, в том числе CombatEnded
+        if self.is_processing_enabled {
+            self.run_triggered_events(combat_ended_events, false);
+        }
+    }
+
+    // WT снова отдает данные боя после отключения или ангара: сессия начинается с чистого состояния
+    fn start_battle_session(&mut self) {
+        self.is_awaiting_battle_return = false;
+        game_event_processor::reset_combat_state(&mut self.game_state_snapshot);
+        self.last_continuous_intensity = None;
+        if self.settings.battle_return.announce_ready {
+            self.add_tagged_log_message(LogCategory::WarThunder, None, "War Thunder снова в бою: состояние сессии сброшено, эффекты готовы.".to_string());
+        }
+        let is_output_allowed = self.is_processing_enabled && self.is_game_output_allowed() && !self.is_muted;
+        if !self.settings.battle_return.confirmation_pulse || !is_output_allowed {
+            return;
+        }
+        let connect_pulse = &self.settings.connect_pulse;
+        if let Some(device_index) = self.default_target_device_bp_index().filter(|device_index| self.ready_device_indices.contains(device_index)) {
+            self.command_sender.send(CommandToAsyncTasks::PlayTestSequence {
+                device_index,
+                steps: vec![(connect_pulse.intensity, connect_pulse.duration_milliseconds)],
+            });
+        }
+    }
+
+    fn stop_game_driven_effects(&mut self) {
+        if self.game_driven_device_indices.is_empty() && self.game_driven_member_addresses.is_empty() {
+            return;
+        }
+        self.add_tagged_log_message(LogCategory::WarThunder, None, "Останавливаем эффекты игровых событий.".to_string());
+        for device_index in std::mem::take(&mut self.game_driven_device_indices) {
+            self.command_sender.send(CommandToAsyncTasks::StopDevice(device_index));
+        }
+        let member_addresses: Vec<String> = std::mem::take(&mut self.game_driven_member_addresses).into_iter().collect();
+        if !member_addresses.is_empty() {
+            self.command_sender.send(CommandToAsyncTasks::StopGroup(member_addresses));
+        }
+    }
+
+    // Останавливает все устройства при потере фокуса или сворачивании окна (если включено).
+    // Само ничего не возобновляет.
+    fn handle_window_focus_change(&mut self, context: &egui::Context) {
+        let (is_window_focused, is_window_minimized) = context.input(|input| {
+            (input.viewport().focused.unwrap_or(true), input.viewport().minimized.unwrap_or(false))
+        });
+        if self.was_window_focused && !is_window_focused && self.settings.stop_devices_on_focus_loss {
+            self.stop_all_devices_for_window("Окно потеряло фокус: все устройства остановлены.");
+        } else if !self.was_window_minimized && is_window_minimized && self.settings.stop_devices_on_minimize {
+            self.stop_all_devices_for_window("Окно свернуто: все устройства остановлены.");
+        }
+        self.was_window_focused = is_window_focused;
+        self.was_window_minimized = is_window_minimized;
+    }
+
+    fn stop_all_devices_for_window(&mut self, log_message: &str) {
+        self.add_log_message(log_message.to_string());
+        self.command_sender.send(CommandToAsyncTasks::StopAllDevices);
+        self.game_driven_device_indices.clear();
+        self.game_driven_member_addresses.clear();
+    }
+
+    // Игровые события доходят до устройств: вывод разрешен и сейчас не тихие часы
+    fn is_game_output_allowed(&self) -> bool {
+        self.is_output_armed && !self.is_quiet_hours_active
+    }
+
+    fn handle_quiet_hours(&mut self) {
+        let now = self.clock.now();
+        if self.quiet_hours_checked_at.is_some_and(|checked_at| now.saturating_duration_since(checked_at) < QUIET_HOURS_CHECK_INTERVAL) {
+            return;
+        }
+        self.quiet_hours_checked_at = Some(now);
+        let quiet_hours = &self.settings.quiet_hours;
+        let quiet_hours_check = if quiet_hours.enabled { quiet_hours.contains(self.clock.local_time()) } else { Ok(false) };
+        let is_quiet_now = match quiet_hours_check {
+            Ok(is_quiet_now) => {
+                self.last_quiet_hours_error = None;
+                is_quiet_now
+            }
+            Err(quiet_hours_error) => {
+                if self.last_quiet_hours_error.as_ref() != Some(&quiet_hours_error) {
+                    self.add_log_message(format!("{}. Тихие часы не действуют.", quiet_hours_error));
+                    self.last_quiet_hours_error = Some(quiet_hours_error);
+                }
+                false
+            }
+        };
+        if is_quiet_now == self.is_quiet_hours_active {
+            return;
+        }
+        self.is_quiet_hours_active = is_quiet_now;
+        self.last_continuous_intensity = None;
+        if is_quiet_now {
+            self.add_log_message(format!(
+                "Тихие часы ({} - {}): вывод игровых событий на устройства отключен.",
+                self.settings.quiet_hours.start, self.settings.quiet_hours.end
+            ));
+            self.stop_game_driven_effects();
+        } else {
+            self.add_log_message("Тихие часы закончились: вывод игровых событий снова разрешен.".to_string());
+        }
+    }
+
+    // Импульс на выбранное пользователем в списке устройство (connect_pulse.on_device_select).
+    // Автоматический выбор при подключении не в счет: там уже был импульс подключения.
+    fn handle_device_select_pulse(&mut self, context: &egui::Context) {
+        let Some(due_at) = self.select_pulse_due_at else {
+            return;
+        };
+        let now = self.clock.now();
+        if now < due_at {
+            context.request_repaint_after(due_at - now);
+            return;
+        }
+        self.select_pulse_due_at = None;
+        let selected_device_index = self.default_target_device_bp_index();
+        let connect_pulse = &self.settings.connect_pulse;
+        // Тот же запрет, что у Buttplug задачи: без вибрации, в тихие часы и до подтверждения вывода импульса нет
+        let is_output_allowed = (self.is_output_armed || !self.is_processing_enabled) && !self.is_quiet_hours_active && !self.is_muted;
+        if !connect_pulse.on_device_select || !is_output_allowed {
+            return;
+        }
+        if let Some(device_index) = selected_device_index.filter(|device_index| self.ready_device_indices.contains(device_index)) {
+            self.command_sender.send(CommandToAsyncTasks::PlayTestSequence {
+                device_index,
+                steps: vec![(connect_pulse.intensity, connect_pulse.duration_milliseconds)],
+            });
+        }
+    }
+
+    // Buttplug задаче важно только, ждет ли вывод подтверждения после автозапуска
+    fn sync_output_armed_state(&mut self) {
+        let is_armed = (self.is_output_armed || !self.is_processing_enabled) && !self.is_quiet_hours_active;
+        if self.last_sent_output_armed != Some(is_armed) {
+            self.last_sent_output_armed = Some(is_armed);
+            self.command_sender.send(CommandToAsyncTasks::SetOutputArmed(is_armed));
+        }
+    }
+
+    fn set_muted(&mut self, is_muted: bool) {
+        self.is_muted = is_muted;
+        self.command_sender.send(CommandToAsyncTasks::SetOutputMuted(is_muted));
+        if is_muted {
+            self.command_sender.send(CommandToAsyncTasks::StopAllDevices);
+            self.game_driven_device_indices.clear();
+            self.game_driven_member_addresses.clear();
+            self.add_log_message("Вибрация временно отключена: правила работают, команды на устройства не отправляются.".to_string());
+        } else {
+            // Непрерывные сопоставления заново отправят текущую интенсивность
+            self.last_continuous_intensity = None;
+            self.add_log_message("Вибрация снова включена.".to_string());
+        }
+    }
+
+    // Визуальная (и по желанию звуковая) обратная связь о срабатывании правила - работает и без устройств
+    fn show_rule_trigger_feedback(&mut self, rule_name: &str) {
+        if self.settings.rule_trigger_flash_enabled {
+            self.rule_flash_started_at.insert(rule_name.to_string(), self.clock.now());
+        }
+        if self.settings.rule_trigger_beep_enabled {
+            play_rule_trigger_beep();
+        }
+    }
+
+    // Имитация не попадает в счетчики срабатываний - они должны отражать реальные бои
+    fn collect_rule_statistics(&mut self) {
+        loop {
+            match self.rule_statistics_receiver.try_recv() {
+                Ok(notification) if notification.is_simulated => {}
+                Ok(notification) => self.count_rule_trigger(&notification.triggered_event.rule_name),
+                Err(tokio::sync::broadcast::error::TryRecvError::Lagged(skipped_count)) => {
+                    tracing::warn!("Счетчики срабатываний пропустили {} событий: шина переполнена.", skipped_count);
+                }
+                Err(_) => break, // Пусто (или шина закрыта)
+            }
+        }
+    }
+
+    fn count_rule_trigger(&mut self, rule_name: &str) {
+        *self.rule_statistics.trigger_counts.entry(rule_name.to_string()).or_insert(0) += 1;
+        if self.rule_statistics_save_due_at.is_none() {
+            self.rule_statistics_save_due_at = Some(self.clock.now() + RULE_STATISTICS_SAVE_INTERVAL);
+        }
+    }
+
+    fn save_rule_statistics_to_disk(&mut self) {
+        self.rule_statistics_save_due_at = None;
+        if let Err(e) = configuration_manager::save_rule_statistics(&self.rule_statistics) {
+            self.add_log_message(format!("Ошибка сохранения счетчиков срабатываний: {}", e));
+        }
+    }
+
+    // Срабатывания копятся в памяти и пишутся на диск пачкой, а не на каждый выстрел
+    fn handle_rule_statistics_save(&mut self) {
+        if self.rule_statistics_save_due_at.is_some_and(|due_at| self.clock.now() >= due_at) {
+            self.save_rule_statistics_to_disk();
+        }
+    }
+
+    // В кэш попадают только боевые данные: в ангаре WT отдает нули, как примеры они бесполезны
+    fn export_rule_layer_profile(&mut self, layer_name: &str) {
+        match configuration_manager::build_profile_file(&self.settings, layer_name)
+            .and_then(|profile_file| configuration_manager::export_profile(&profile_file))
+        {
+            Ok(profile_path) => self.add_log_message(format!("Профиль '{}' экспортирован в {:?}", layer_name, profile_path)),
+            Err(e) => self.add_log_message(format!("Ошибка экспорта профиля: {}", e)),
+        }
+    }
+
+    fn start_profile_import(&mut self) {
+        let profile_path = PathBuf::from(self.profile_import_path_text.trim());
+        let profile_file = match configuration_manager::load_profile_file(&profile_path) {
+            Ok(profile_file) => profile_file,
+            Err(e) => {
+                self.add_log_message(format!("Ошибка импорта профиля: {}", e));
+                return;
+            }
+        };
+        if self.settings.rule_layers.iter().any(|rule_layer| rule_layer.name == profile_file.profile.name) {
+            // Имя занято: спрашиваем, перезаписать или переименовать
+            self.profile_import_rename_text = format!("{} (импорт)", profile_file.profile.name);
+            self.pending_profile_import = Some(profile_file);
+        } else {
+            self.finish_profile_import(profile_file, ProfileImportMode::Overwrite);
+        }
+    }
+
+    fn finish_profile_import(&mut self, profile_file: ProfileFile, import_mode: ProfileImportMode) {
+        let profile_name = match &import_mode {
+            ProfileImportMode::Rename(new_name) => new_name.clone(),
+            ProfileImportMode::Overwrite => profile_file.profile.name.clone(),
+        };
+        configuration_manager::apply_imported_profile(&mut self.settings, profile_file, import_mode);
+        self.add_log_message(format!("Профиль '{}' импортирован как слой правил. Не забудьте сохранить конфигурацию.", profile_name));
+        self.command_sender.send(CommandToAsyncTasks::UpdateApplicationSettings(Box::new(self.settings.clone())));
+    }
+
+    fn update_indicators_cache(&mut self, indicators: &WarThunderIndicators) {
+        if indicators.vehicle_type.is_none() || indicators.valid == Some(false) {
+            return;
+        }
+        self.indicators_cache.indicators = Some(indicators.clone());
+        self.schedule_indicators_cache_save();
+    }
+
+    fn schedule_indicators_cache_save(&mut self) {
+        if self.indicators_cache_save_due_at.is_none() {
+            self.indicators_cache_save_due_at = Some(self.clock.now() + INDICATORS_CACHE_SAVE_INTERVAL);
+        }
+    }
+
+    fn save_indicators_cache_to_disk(&mut self) {
+        self.indicators_cache_save_due_at = None;
+        if let Err(e) = configuration_manager::save_indicators_cache(&self.indicators_cache) {
+            self.add_log_message(format!("Ошибка сохранения кэша индикаторов: {}", e));
+        }
+    }
+
+    fn handle_indicators_cache_save(&mut self) {
+        if self.indicators_cache_save_due_at.is_some_and(|due_at| self.clock.now() >= due_at) {
+            self.save_indicators_cache_to_disk();
+        }
+    }
+
+    // Индикаторы для примеров в редакторе: живые, а без игры - последние сохраненные
+    fn example_indicators(&self) -> Option<&WarThunderIndicators> {
+        self.current_wt_indicators.as_ref().or(self.indicators_cache.indicators.as_ref())
+    }
+
+    fn indicator_example_value_text(&self, field_name: &str) -> String {
+        self.example_indicators()
+            .and_then(|indicators| game_event_processor::indicator_field_value(indicators, &self.game_state_snapshot, field_name))
+            .map_or_else(|| "-".to_string(), |field_value| format!("{:.2}", field_value))
+    }
+
+    fn condition_editor_hint_text(&self) -> String {
+        let mut hint_text = "Например: health_percentage < 30 AND speed > 0. Пусто - проверка по имени правила.".to_string();
+        if self.example_indicators().is_some() {
+            let values_source = if self.current_wt_indicators.is_some() { "текущие" } else { "из последнего боя" };
+            hint_text.push_str(&format!("\n\nДоступные поля ({}):", values_source));
+            for &field_name in game_event_processor::INDICATOR_FIELD_NAMES {
+                hint_text.push_str(&format!("\n{} = {}", field_name, self.indicator_example_value_text(field_name)));
+            }
+        }
+        hint_text.push_str(&format!(
+            "\n\nСтроковые поля ({}) проверяются только на смену: например, vehicle_type CHANGED - новая техника.",
+            game_event_processor::STRING_INDICATOR_FIELD_NAMES.join(", ")
+        ));
+        hint_text.push_str(&format!(
+            "\n\nСкорость изменения (в секунду): <поле>{} со знаком, <поле>{} - только падение. Например: altitude_10k{} < -50.",
+            game_event_processor::RATE_FIELD_SUFFIX,
+            game_event_processor::DECREASE_RATE_FIELD_SUFFIX,
+            game_event_processor::RATE_FIELD_SUFFIX
+        ));
+        if let Some(example_indicators) = self.example_indicators().filter(|indicators| !indicators.raw_fields.is_empty()) {
+            let raw_field_names: Vec<String> = example_indicators.raw_fields.keys()
+                .map(|raw_field_name| format!("{}{}", telemetry_normalization::RAW_FIELD_PREFIX, raw_field_name))
+                .collect();
+            hint_text.push_str(&format!("\n\nСырые поля WT (зависят от техники): {}", raw_field_names.join(", ")));
+        }
+        hint_text
+    }
+
+    fn reset_rule_trigger_counts(&mut self) {
+        self.rule_statistics.trigger_counts.clear();
+        self.save_rule_statistics_to_disk();
+        self.add_log_message("Счетчики срабатываний правил сброшены.".to_string());
+    }
+
+    // Множитель временного усиления: burst_modifier.multiplier, пока усиление активно, иначе 1.0
+    fn burst_factor(&self) -> f64 {
+        if self.burst_expires_at.is_some() { self.settings.burst_modifier.multiplier } else { 1.0 }
+    }
+
+    // Общий множитель игровых эффектов: мягкий старт обработки и временное усиление
+    fn game_intensity_factor(&self) -> f64 {
+        self.processing_ramp_factor() * self.burst_factor()
+    }
+
+    fn start_burst(&mut self) {
+        let burst_modifier = &self.settings.burst_modifier;
+        self.burst_expires_at = Some(self.clock.now() + Duration::from_millis(burst_modifier.duration_milliseconds));
+        self.add_log_message(format!(
+            "Временное усиление x{:.2} на {:.1} с.",
+            burst_modifier.multiplier,
+            burst_modifier.duration_milliseconds as f64 / 1000.0
+        ));
+        // Непрерывные сопоставления заново отправят интенсивность уже с усилением
+        self.last_continuous_intensity = None;
+    }
+
+    fn handle_burst_modifier(&mut self, context: &egui::Context) {
+        if let Some(hotkey) = egui::Key::from_name(&self.settings.burst_modifier.hotkey)
+            && context.input(|input| input.key_pressed(hotkey))
+        {
+            self.start_burst();
+        }
+        if let Some(burst_expires_at) = self.burst_expires_at {
+            if self.clock.now() >= burst_expires_at {
+                self.burst_expires_at = None;
+                self.last_continuous_intensity = None;
+                self.add_log_message("Временное усиление закончилось.".to_string());
+            } else {
+                context.request_repaint_after(Duration::from_millis(100)); // Обратный отсчет в верхней панели
+            }
+        }
+    }
+
+    // Множитель мягкого старта: 0.0 в момент включения обработки, 1.0 по истечении processing_ramp_milliseconds
+    fn processing_ramp_factor(&self) -> f64 {
+        match (self.processing_started_at, self.settings.processing_ramp_milliseconds) {
+            (Some(processing_started_at), ramp_milliseconds) if ramp_milliseconds > 0 => {
+                (self.clock.now().saturating_duration_since(processing_started_at).as_secs_f64() * 1000.0 / ramp_milliseconds as f64).min(1.0)
+            }
+            _ => 1.0,
+        }
+    }
+
+    // Отправляет действие правила его цели: выбранному устройству, устройству по адресу или группе
+    // is_driven_by_game: действие пришло от игрового события (а не от ручного теста)
+    fn dispatch_device_action(&mut self, device_action: &DeviceAction, is_driven_by_game: bool) {
+        if is_driven_by_game && !self.is_game_output_allowed() {
+            return;
+        }
+        let intensity_factor = if is_driven_by_game { self.game_intensity_factor() } else { 1.0 };
+        let ramped_device_action;
+        let device_action = if intensity_factor != 1.0 {
+            ramped_device_action = device_action.with_scaled_intensity(intensity_factor);
+            &ramped_device_action
+        } else {
+            device_action
+        };
+        if self.is_muted {
+            self.add_tagged_log_message(LogCategory::Events, None, format!(
+                "Без вибрации: пропущено {:?} инт. {} на {} мс ({})",
+                device_action.action_type,
+                device_action.intensity,
+                device_action.duration_milliseconds,
+                action_target_label(&device_action.target)
+            ));
+            return;
+        }
+        let device_idx_in_vec = match &device_action.target {
+            ActionTarget::Group(group_name) => {
+                let Some(device_group) = self.settings.device_groups.iter().find(|group| group.name == *group_name) else {
+                    self.add_log_message(format!("Группа устройств '{}' не найдена, действие пропущено.", group_name));
+                    return;
+                };
+                let member_addresses = device_group.member_addresses.clone();
+                let group_command = match device_action.action_type {
+                    DeviceActionType::Vibrate => {
+                        self.add_tagged_log_message(LogCategory::Events, None, format!(
+                            "Игровое событие: вибрация группы '{}' инт. {} на {} мс",
+                            group_name,
+                            device_action.intensity,
+                            device_action.duration_milliseconds
+                        ));
+                        if is_driven_by_game {
+                            self.game_driven_member_addresses.extend(member_addresses.iter().cloned());
+                        }
+                        if !device_action.pattern_points.is_empty() {
+                            // Паттерн проигрывается на каждом подключенном участнике отдельно
+                            let pattern_steps = device_action.pattern_steps();
+                            for device in self.buttplug_devices.iter()
+                                .filter(|device| member_addresses.contains(&buttplug_connector::device_address(device)))
+                            {
+                                self.command_sender.send(CommandToAsyncTasks::PlayTestSequence {
+                                    device_index: device.index(),
+                                    steps: pattern_steps.clone(),
+                                });
+                            }
+                            return;
+                        }
+                        CommandToAsyncTasks::VibrateGroup {
+                            member_addresses,
+                            speed: device_action.intensity,
+                            actuator_speeds: device_action.actuator_speeds(),
+                            duration_milliseconds: Some(device_action.duration_milliseconds),
+                        }
+                    }
+                    DeviceActionType::Stop => CommandToAsyncTasks::StopGroup(member_addresses),
+                };
+                self.command_sender.send(group_command);
+                return;
+            }
+            ActionTarget::Device(address) => {
+                let device_position = self.buttplug_devices.iter()
+                    .position(|device| buttplug_connector::device_address(device) == *address);
+                if device_position.is_some() {
+                    device_position
+                } else {
+                    match self.settings.missing_device_behavior {
+                        MissingDeviceBehavior::SkipSilently => return,
+                        MissingDeviceBehavior::SkipWithWarning => {
+                            self.add_tagged_log_message(LogCategory::Events, Some(address.clone()), format!(
+                                "Устройство '{}' не подключено, действие пропущено.", address
+                            ));
+                            return;
+                        }
+                        MissingDeviceBehavior::FallBackToDefaultDevice => {
+                            let fallback_position = self.default_target_device_index();
+                            if fallback_position.is_some() {
+                                self.add_tagged_log_message(LogCategory::Events, Some(address.clone()), format!(
+                                    "Устройство '{}' не подключено, действие отправлено на устройство по умолчанию.", address
+                                ));
+                            }
+                            fallback_position
+                        }
+                    }
+                }
+            }
+            ActionTarget::SelectedDevice => self.default_target_device_index(),
+        };
+
+        if let Some(device_idx_in_vec) = device_idx_in_vec {
+            // Данные устройства копируются: запись в журнал ниже требует &mut self
+            let target_device = self.buttplug_devices.get(device_idx_in_vec)
+                .map(|device| (device.index(), device.name().clone(), buttplug_connector::device_address(device)));
+            if let Some((device_index, device_name, device_address)) = target_device {
+                match device_action.action_type {
+                    DeviceActionType::Vibrate => {
+                        self.add_tagged_log_message(LogCategory::Events, Some(device_address), format!(
+                            "Игровое событие: вибрация устр-ва '{}' (индекс {}) инт. {} на {} мс",
+                            device_name,
+                            device_index,
+                            device_action.intensity,
+                            device_action.duration_milliseconds
+                        ));
+                        let vibration_command = if device_action.pattern_points.is_empty() {
+                            CommandToAsyncTasks::VibrateDevice {
+                                device_index,
+                                speed: device_action.intensity,
+                                actuator_speeds: device_action.actuator_speeds(),
+                                duration_milliseconds: Some(device_action.duration_milliseconds),
+                            }
+                        } else {
+                            CommandToAsyncTasks::PlayTestSequence { device_index, steps: device_action.pattern_steps() }
+                        };
+                        self.command_sender.send(vibration_command);
+                        if is_driven_by_game {
+                            self.game_driven_device_indices.insert(device_index);
+                        }
+                    }
+                    DeviceActionType::Stop => {
+                        self.command_sender.send(CommandToAsyncTasks::StopDevice(device_index));
+                    }
+                }
+            }
+        }
+    }
+
+    // Сырые команды идут мимо всей логики Buttplug: байты уходят на конечную точку устройства как есть
+    fn show_raw_device_command_panel(&mut self, ui: &mut egui::Ui) {
+        ui.label(egui::RichText::new("Байты отправляются устройству без проверок. Используйте только с документированным протоколом устройства.")
+            .color(egui::Color32::RED));
+        let raw_capable_devices: Vec<&Arc<ButtplugClientDevice>> = self.buttplug_devices.iter()
+            .filter(|device| {
+                !buttplug_connector::raw_write_endpoints(device).is_empty() || !buttplug_connector::raw_read_endpoints(device).is_empty()
+            })
+            .collect();
+        if raw_capable_devices.is_empty() {
+            ui.label("Нет устройств с конечными точками для сырых команд. Сырые сообщения должен разрешить сервер (в Intiface Central - отдельная настройка).");
+            return;
+        }
+
+        let Some(selected_device) = raw_capable_devices.iter()
+            .find(|device| Some(device.index()) == self.raw_command_device_index)
+            .or(raw_capable_devices.first())
+            .map(|device| Arc::clone(device))
+        else {
+            return;
+        };
+        ui.horizontal(|ui| {
+            ui.label("Устройство:");
+            egui::ComboBox::from_id_salt("raw_command_device")
+                .selected_text(selected_device.name())
+                .show_ui(ui, |ui| {
+                    for device in &raw_capable_devices {
+                        ui.selectable_value(&mut self.raw_command_device_index, Some(device.index()), device.name());
+                    }
+                });
+        });
+        self.raw_command_device_index.get_or_insert(selected_device.index());
+
+        let write_endpoints = buttplug_connector::raw_write_endpoints(&selected_device);
+        let read_endpoints = buttplug_connector::raw_read_endpoints(&selected_device);
+        let mut available_endpoints = write_endpoints.clone();
+        available_endpoints.extend(read_endpoints.iter().filter(|endpoint| !write_endpoints.contains(endpoint)).cloned());
+        if !self.raw_command_endpoint.as_ref().is_some_and(|endpoint| available_endpoints.contains(endpoint)) {
+            self.raw_command_endpoint = available_endpoints.first().cloned();
+        }
+        ui.horizontal(|ui| {
+            ui.label("Конечная точка:");
+            egui::ComboBox::from_id_salt("raw_command_endpoint")
+                .selected_text(self.raw_command_endpoint.as_ref().map(|endpoint| format!("{:?}", endpoint)).unwrap_or_default())
+                .show_ui(ui, |ui| {
+                    for endpoint in &available_endpoints {
+                        ui.selectable_value(&mut self.raw_command_endpoint, Some(*endpoint), format!("{:?}", endpoint));
+                    }
+                });
+        });
+        let Some(selected_endpoint) = self.raw_command_endpoint else {
+            return;
+        };
+
+        ui.horizontal(|ui| {
+            ui.label("Байты (hex):");
+            ui.add(egui::TextEdit::singleline(&mut self.raw_command_hex_text).hint_text("01 FF 2A").desired_width(200.0));
+        });
+        let parsed_bytes = buttplug_connector::parse_hex_bytes(&self.raw_command_hex_text);
+        if let Err(parse_error) = &parsed_bytes
+            && !self.raw_command_hex_text.trim().is_empty()
+        {
+            ui.colored_label(egui::Color32::RED, parse_error);
+        }
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.raw_command_write_with_response, "С подтверждением записи");
+            let can_write = write_endpoints.contains(&selected_endpoint) && parsed_bytes.is_ok();
+            if ui.add_enabled(can_write, egui::Button::new("Записать")).clicked()
+                && let Ok(data) = parsed_bytes
+            {
+                self.command_sender.send(CommandToAsyncTasks::RawWriteDevice {
+                    device_index: selected_device.index(),
+                    endpoint: selected_endpoint,
+                    data,
+                    write_with_response: self.raw_command_write_with_response,
+                });
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Прочитать байт:");
+            ui.add(egui::DragValue::new(&mut self.raw_read_expected_length).range(1..=512));
+            if ui.add_enabled(read_endpoints.contains(&selected_endpoint), egui::Button::new("Прочитать")).clicked() {
+                self.command_sender.send(CommandToAsyncTasks::RawReadDevice {
+                    device_index: selected_device.index(),
+                    endpoint: selected_endpoint,
+                    expected_length: self.raw_read_expected_length,
+                    timeout_milliseconds: 1000,
+                });
+            }
+        });
+        ui.label("Результат команды появится в логе (категория BP).");
+    }
+
+    fn run_triggered_events(&mut self, triggered_events: Vec<TriggeredEvent>, is_simulated: bool) {
+        let device_actions = triggered_events.iter()
+            .map(|triggered_event| triggered_event.device_action.clone())
+            .collect();
+        let selected_device_address = self.default_target_device_index()
+            .and_then(|idx_in_vec| self.buttplug_devices.get(idx_in_vec))
+            .map(|device| buttplug_connector::device_address(device));
+        let merged_device_actions = game_event_processor::merge_simultaneous_vibrations(
+            device_actions,
+            self.settings.simultaneous_vibration_policy,
+            |target| match (target, &selected_device_address) {
+                (ActionTarget::SelectedDevice, Some(address)) => ActionTarget::Device(address.clone()),
+                _ => target.clone(),
+            },
+        );
+        for device_action in &merged_device_actions {
+            self.dispatch_device_action(device_action, true);
+        }
+        for triggered_event in triggered_events {
+            self.show_rule_trigger_feedback(&triggered_event.rule_name);
+            // Ошибка значит только, что подписчиков сейчас нет
+            let _ = self.triggered_event_bus.send(TriggeredEventNotification { triggered_event, is_simulated });
+        }
+    }
+
+    // Конец боя всегда гасит эффекты игровых событий, независимо от stop_effects_on_wt_disconnect:
+    // вибрация, запущенная в бою, не должна продолжаться в ангаре. Правила CombatEnded выполняются уже после.
+    fn stop_effects_on_combat_end(&mut self) {
+        self.add_tagged_log_message(LogCategory::Events, None, "Бой закончился.".to_string());
+        self.stop_game_driven_effects();
+    }
+
+    // Общий путь обработки индикаторов: и живые данные WT, и имитация из панели разработчика.
+    // Срабатывания имитации помечаются в шине is_simulated.
+    fn process_indicators(&mut self, indicators: &WarThunderIndicators, is_simulated: bool) {
+        let was_in_combat = self.game_state_snapshot.is_in_combat;
+        let triggered_events = game_event_processor::process_war_thunder_data(
+            indicators,
+            &self.settings,
+            &mut self.game_state_snapshot,
+            self.clock.as_ref(),
+        );
+        if was_in_combat && !self.game_state_snapshot.is_in_combat {
+            self.stop_effects_on_combat_end();
+        }
+        self.run_triggered_events(triggered_events, is_simulated);
+
+        if let Some(mapped_intensity) = game_event_processor::evaluate_continuous_mappings(
+            indicators,
+            &self.settings,
+            &self.game_state_snapshot,
+        ) {
+            let mapped_intensity = (mapped_intensity * self.game_intensity_factor()).min(1.0);
+            let intensity_changed = self.last_continuous_intensity
+                .is_none_or(|last_intensity| (last_intensity - mapped_intensity).abs() > 0.01);
+            if intensity_changed && self.is_game_output_allowed() && !self.is_muted
+                && let Some(device_index) = self.default_target_device_bp_index()
+            {
+                // Ноль (в т.ч. после мертвой зоны) - полноценная остановка, а не крошечная скорость
+                let continuous_command = if mapped_intensity <= 0.0 {
+                    CommandToAsyncTasks::StopDevice(device_index)
+                } else {
+                    CommandToAsyncTasks::VibrateDevice {
+                        device_index,
+                        speed: mapped_intensity,
+                        actuator_speeds: Vec::new(),
+                        duration_milliseconds: None,
+                    }
+                };
+                self.command_sender.send(continuous_command);
+                self.last_continuous_intensity = Some(mapped_intensity);
+                self.game_driven_device_indices.insert(device_index);
+            }
+        }
+    }
+
+    // Имитация события: последние индикаторы (или заготовка без игры) меняются и проходят обычную обработку.
+    // Работает и при выключенной обработке WT, но вывод на устройства по-прежнему требует разрешения.
+    fn simulate_game_event(&mut self, simulated_event: SimulatedGameEvent) {
+        let base_indicators = match &self.current_wt_indicators {
+            Some(indicators) => indicators.clone(),
+            None => {
+                let placeholder_indicators = WarThunderIndicators {
+                    vehicle_type: Some("simulation".to_string()),
+                    health_percentage: Some(100.0),
+                    first_stage_ammo: Some(30.0),
+                    altitude_10k: Some(1000.0),
+                    ..Default::default()
+                };
+                // Первый проход задает исходное состояние, иначе изменение не с чем сравнить
+                self.process_indicators(&placeholder_indicators, true);
+                placeholder_indicators
+            }
+        };
+        let mut simulated_indicators = base_indicators;
+        simulated_event.apply(&mut simulated_indicators, &self.settings);
+        self.add_tagged_log_message(LogCategory::Events, None, format!("Имитация события: {}", simulated_event.label()));
+        self.process_indicators(&simulated_indicators, true);
+        self.current_wt_indicators = Some(simulated_indicators);
+    }
+
+    // Индикаторы нужны GUI, пока открыта живая панель или включена обработка событий
+    // (обработка работает по индикаторам, поэтому без них правила не сработают).
+    fn sync_indicator_updates_request(&mut self, is_live_panel_open: bool) {
+        let should_request_updates = is_live_panel_open || self.is_processing_enabled;
+        if should_request_updates != self.are_indicator_updates_requested {
+            self.command_sender.send(CommandToAsyncTasks::SetIndicatorUpdatesEnabled(should_request_updates));
+            self.are_indicator_updates_requested = should_request_updates;
+        }
+    }
+
+    // Выбранное устройство, а если ничего не выбрано - первое из списка
+    fn default_target_device_index(&self) -> Option<usize> {
+        self.selected_device_index_in_vec.or((!self.buttplug_devices.is_empty()).then_some(0))
+    }
+
+    // Команды устройствам адресуются индексом Buttplug; позиция в списке GUI - только для выбора в интерфейсе
+    fn default_target_device_bp_index(&self) -> Option<u32> {
+        buttplug_connector::buttplug_index_at(&self.buttplug_devices, self.default_target_device_index())
+    }
+
+    fn handle_pending_commands(&mut self, context: &egui::Context) {
+        if self.command_sender.retry_pending() {
+            context.request_repaint_after(PENDING_COMMAND_RETRY_INTERVAL);
+        }
+        for failure_message in self.command_sender.take_failure_messages() {
+            self.add_log_message(failure_message);
+        }
+    }
+
+    fn add_log_message(&mut self, message: String) {
+        self.add_tagged_log_message(LogCategory::General, None, message);
+    }
+
+    fn add_tagged_log_message(&mut self, category: LogCategory, device_address: Option<String>, message: String) {
+        tracing::info!("[{}] {}", category.label(), message);
+        self.log_messages.insert(0, LogEntry { category, device_address, message });
+        if self.log_messages.len() > 100 {
+            self.log_messages.pop();
+        }
+    }
+
+    fn handle_incoming_updates(&mut self) {
+        while let Ok(update) = self.update_receiver.try_recv() {
+            match update {
+                UpdateFromAsyncTasks::LogMessage { category, device_address, message } => {
+                    self.add_tagged_log_message(category, device_address, message);
+                }
+                UpdateFromAsyncTasks::WarThunderIndicatorsUpdate(indicators) => {
+                    if self.is_awaiting_battle_return && game_event_processor::is_battle_data(&indicators) {
+                        self.start_battle_session();
+                    }
+                    self.current_wt_indicators = Some(indicators.clone());
+                    self.update_indicators_cache(&indicators);
+                    if self.is_processing_enabled {
+                        self.process_indicators(&indicators, false);
+                    }
+                }
+                UpdateFromAsyncTasks::WarThunderConnectionStatus(is_connected) => {
+                    self.is_war_thunder_connected = is_connected;
+                    if !is_connected {
+                        self.is_war_thunder_in_battle = false;
+                        self.handle_war_thunder_data_lost();
+                    }
+                }
+                UpdateFromAsyncTasks::WarThunderHudDamageMessages(hud_messages) => {
+                    let triggered_events = game_event_processor::process_hud_damage_messages(&hud_messages, &self.settings, &mut self.game_state_snapshot);
+                    if self.is_processing_enabled {
+                        self.run_triggered_events(triggered_events, false);
+                    }
+                }
+                UpdateFromAsyncTasks::WarThunderBattleStatus(is_in_battle) => {
+                    let was_in_battle = std::mem::replace(&mut self.is_war_thunder_in_battle, is_in_battle);
+                    if was_in_battle && !is_in_battle {
+                        self.add_tagged_log_message(LogCategory::WarThunder, None, "War Thunder: не в бою.".to_string());
+                        self.handle_war_thunder_data_lost();
+                    }
+                }
+                UpdateFromAsyncTasks::WarThunderRawJson { source_url, pretty_json } => {
+                    let truncated_json = truncate_for_display(&pretty_json, RAW_JSON_DISPLAY_LIMIT_CHARS);
+                    if source_url.ends_with("/state") {
+                        self.last_raw_state_json = Some(truncated_json);
+                    } else {
+                        if self.is_war_thunder_in_battle {
+                            self.indicators_cache.raw_json = Some(truncated_json.clone());
+                            self.schedule_indicators_cache_save();
+                        }
+                        self.last_raw_indicators_json = Some(truncated_json);
+                    }
+                }
+                UpdateFromAsyncTasks::ButtplugScanFinished { found_device_count } => {
+                    self.add_tagged_log_message(LogCategory::Buttplug, None, format!("Сканирование завершено, устройств: {}", found_device_count));
+                    self.is_no_devices_hint_visible = found_device_count == 0;
+                }
+                UpdateFromAsyncTasks::ButtplugConnected => {
+                    self.is_buttplug_connected = true;
+                    self.add_tagged_log_message(LogCategory::Buttplug, None, "Успешно подключено к Buttplug серверу.".to_string());
+                }
+                UpdateFromAsyncTasks::ButtplugDisconnected => {
+                    self.clear_buttplug_connection_state();
+                    self.add_tagged_log_message(LogCategory::Buttplug, None, "Отключено от Buttplug сервера.".to_string());
+                }
+                UpdateFromAsyncTasks::ButtplugDeviceFound(clonable_device) => { 
+                    let device = clonable_device.0; // Извлекаем внутренний ButtplugClientDevice
+                    if !self.buttplug_devices.iter().any(|d_arc| d_arc.index() == device.index()) {
+                        self.add_tagged_log_message(LogCategory::Buttplug, Some(buttplug_connector::device_address(&device)), format!(
+                            "Найдено устройство Buttplug: {} (Индекс: {}, Атрибуты: {:?})",
+                            device.name(),
+                            device.index(),
+                            device.message_attributes()
+                        ));
+                        self.buttplug_devices.push(device);
+                        self.is_no_devices_hint_visible = false;
+                        if self.selected_device_index_in_vec.is_none() && !self.buttplug_devices.is_empty() {
+                            self.selected_device_index_in_vec = Some(0);
+                        }
+                    }
+                }
+                UpdateFromAsyncTasks::ButtplugDeviceLost(clonable_device) => { 
+                    let device = clonable_device.0; // Извлекаем внутренний ButtplugClientDevice
+                    self.add_tagged_log_message(
+                        LogCategory::Buttplug,
+                        Some(buttplug_connector::device_address(&device)),
+                        format!("Устройство Buttplug потеряно: {} (Индекс: {})", device.name(), device.index()),
+                    );
+                    // Выбор запоминаем по индексу BP: после удаления позиции в списке сдвигаются
+                    let selected_bp_index = buttplug_connector::buttplug_index_at(&self.buttplug_devices, self.selected_device_index_in_vec);
+                    self.buttplug_devices.retain(|d_arc| d_arc.index() != device.index());
+                    self.game_driven_device_indices.remove(&device.index());
+                    self.ready_device_indices.remove(&device.index());
+                    self.sensor_subscribed_device_indices.remove(&device.index());
+                    self.latest_sensor_readings.remove(&device.index());
+                    self.device_commanded_speeds.remove(&device.index());
+                    self.device_output_intensities.remove(&device.index());
+                    self.selected_device_index_in_vec = selected_bp_index
+                        .and_then(|bp_index| buttplug_connector::position_of_buttplug_index(&self.buttplug_devices, bp_index))
+                        .or(if self.buttplug_devices.is_empty() { None } else { Some(0) });
+                }
+                UpdateFromAsyncTasks::ButtplugDeviceReady(device_index) => {
+                    if self.buttplug_devices.iter().any(|device| device.index() == device_index) {
+                        self.ready_device_indices.insert(device_index);
+                    }
+                }
+                UpdateFromAsyncTasks::ButtplugDeviceSpeeds(commanded_speeds) => {
+                    self.device_commanded_speeds.extend(commanded_speeds);
+                }
+                UpdateFromAsyncTasks::ButtplugDeviceOutput { device_index, output_intensity } => {
+                    self.device_output_intensities.insert(device_index, output_intensity);
+                }
+                UpdateFromAsyncTasks::ButtplugSensorReading { device_index, sensor_type, values } => {
+                    let device_readings = self.latest_sensor_readings.entry(device_index).or_default();
+                    match device_readings.iter_mut().find(|(known_type, _)| *known_type == sensor_type) {
+                        Some((_, latest_values)) => *latest_values = values,
+                        None => device_readings.push((sensor_type, values)),
+                    }
+                }
+                UpdateFromAsyncTasks::ButtplugError(err_msg) => {
+                    self.add_tagged_log_message(LogCategory::Buttplug, None, format!("Ошибка Buttplug: {}", err_msg));
+                }
+                UpdateFromAsyncTasks::ButtplugDeviceError { device_address, message } => {
+                    self.add_tagged_log_message(LogCategory::Buttplug, Some(device_address), format!("Ошибка Buttplug: {}", message));
+                }
+                UpdateFromAsyncTasks::ApplicationSettingsLoaded(loaded_settings) => {
+                    self.reconcile_externally_loaded_settings(*loaded_settings);
+                }
+                UpdateFromAsyncTasks::ButtplugServerAddressTestResult { server_address, result } => {
+                    if self.buttplug_address_under_test.as_ref() == Some(&server_address) {
+                        self.buttplug_address_under_test = None;
+                    }
+                    self.buttplug_address_test_result = Some((server_address, result));
+                }
+                UpdateFromAsyncTasks::WarThunderPollMetrics(poll_metrics) => {
+                    self.war_thunder_poll_metrics = Some(poll_metrics);
+                }
+                UpdateFromAsyncTasks::DeviceCommandMetrics(command_metrics) => {
+                    self.device_command_metrics = Some(command_metrics);
+                }
+                UpdateFromAsyncTasks::BackgroundTaskRestarted(task_kind) => {
+                    self.restore_background_task_state(task_kind);
+                }
+            }
+        }
+    }
+
+    fn clear_buttplug_connection_state(&mut self) {
+        self.is_buttplug_connected = false;
+        self.buttplug_devices.clear();
+        self.ready_device_indices.clear();
+        self.game_driven_device_indices.clear();
+        self.game_driven_member_addresses.clear();
+        self.sensor_subscribed_device_indices.clear();
+        self.latest_sensor_readings.clear();
+        self.device_commanded_speeds.clear();
+        self.device_output_intensities.clear();
+        self.selected_device_index_in_vec = None;
+    }
+
+    // Перезапущенная фоновая задача ничего не знает о состоянии GUI: отправляем его заново.
+    // Настройки и метрики уходят обеим задачам - для второй это повтор того, что она уже знает.
+    fn restore_background_task_state(&mut self, task_kind: BackgroundTaskKind) {
+        self.command_sender.send(CommandToAsyncTasks::UpdateApplicationSettings(Box::new(self.settings.clone())));
+        self.command_sender.send(CommandToAsyncTasks::SetConnectionMetricsEnabled(self.is_connection_metrics_visible));
+        match task_kind {
+            BackgroundTaskKind::WarThunder => {
+                self.command_sender.send(CommandToAsyncTasks::SetIndicatorUpdatesEnabled(self.are_indicator_updates_requested));
+                self.command_sender.send(CommandToAsyncTasks::SetRawJsonCaptureEnabled(self.is_raw_json_capture_enabled));
+                if !self.is_processing_enabled {
+                    self.command_sender.send(CommandToAsyncTasks::StopProcessing);
+                }
+            }
+            BackgroundTaskKind::Buttplug => {
+                // Соединение с сервером ушло вместе с задачей
+                self.clear_buttplug_connection_state();
+                self.last_sent_output_armed = None; // sync_output_armed_state отправит заново
+                if self.is_muted {
+                    self.command_sender.send(CommandToAsyncTasks::SetOutputMuted(true));
+                }
+                self.add_tagged_log_message(LogCategory::Buttplug, None, "Подключитесь к Buttplug заново.".to_string());
+            }
+        }
+    }
+
+    fn set_connection_metrics_visible(&mut self, is_visible: bool) {
+        self.is_connection_metrics_visible = is_visible;
+        self.command_sender.send(CommandToAsyncTasks::SetConnectionMetricsEnabled(is_visible));
+        if !is_visible {
+            self.war_thunder_poll_metrics = None;
+            self.device_command_metrics = None;
+        }
+    }
+
+    // Окно с метриками соединений за последнюю минуту (для сообщений "все тормозит")
+    fn show_connection_metrics_window(&mut self, context: &egui::Context) {
+        let mut is_window_open = self.is_connection_metrics_visible;
+        egui::Window::new("Диагностика соединений")
+            .open(&mut is_window_open)
+            .resizable(false)
+            .show(context, |ui| {
+                ui.label("За последнюю минуту:");
+                egui::Grid::new("connection_metrics_grid").num_columns(2).striped(true).show(ui, |ui| {
+                    match &self.war_thunder_poll_metrics {
+                        Some(poll_metrics) => {
+                            ui.label("Опросов WT:"); ui.label(poll_metrics.poll_count.to_string()); ui.end_row();
+                            ui.label("Успешных опросов:");
+                            ui.label(poll_metrics.success_rate()
+                                .map(|success_rate| format!("{:.1}%", success_rate * 100.0))
+                                .unwrap_or_else(|| "—".to_string()));
+                            ui.end_row();
+                            ui.label("Ошибок разбора JSON:"); ui.label(poll_metrics.parse_error_count.to_string()); ui.end_row();
+                            ui.label("Выброшено обновлений:"); ui.label(poll_metrics.dropped_update_count.to_string()); ui.end_row();
+                        }
+                        None => {
+                            ui.label("War Thunder:"); ui.label("ожидание данных..."); ui.end_row();
+                        }
+                    }
+                    match &self.device_command_metrics {
+                        Some(command_metrics) => {
+                            ui.label("Команд устройствам:"); ui.label(command_metrics.command_count.to_string()); ui.end_row();
+                            ui.label("Ошибок команд:"); ui.label(command_metrics.failed_command_count.to_string()); ui.end_row();
+                            ui.label("Средняя задержка команды:");
+                            ui.label(command_metrics.average_latency_milliseconds
+                                .map(|latency| format!("{:.1} мс", latency))
+                                .unwrap_or_else(|| "—".to_string()));
+                            ui.end_row();
+                            ui.label("Максимальная задержка команды:");
+                            ui.label(command_metrics.max_latency_milliseconds
+                                .map(|latency| format!("{:.1} мс", latency))
+                                .unwrap_or_else(|| "—".to_string()));
+                            ui.end_row();
+                        }
+                        None => {
+                            ui.label("Buttplug:"); ui.label("ожидание данных..."); ui.end_row();
+                        }
+                    }
+                });
+                if ui.checkbox(&mut self.settings.verbose_device_command_logging, "Подробный журнал команд устройствам (debug)")
+                    .on_hover_text("Каждая команда пишется в лог с приводами, скоростями и временем выполнения. Нужен уровень логирования debug.")
+                    .changed()
+                {
+                    self.command_sender.send(CommandToAsyncTasks::UpdateApplicationSettings(Box::new(self.settings.clone())));
+                }
+            });
+        if !is_window_open {
+            self.set_connection_metrics_visible(false);
+        }
+    }
+
+    // Мини-режим: маленькое окно поверх остальных; обычный режим - прежний размер и уровень окна
+    fn sync_mini_mode_viewport(&mut self, context: &egui::Context) {
+        if self.applied_mini_mode == Some(self.settings.mini_mode_enabled) {
+            return;
+        }
+        self.applied_mini_mode = Some(self.settings.mini_mode_enabled);
+        let (min_inner_size, inner_size, window_level) = if self.settings.mini_mode_enabled {
+            (MINI_MODE_WINDOW_SIZE, MINI_MODE_WINDOW_SIZE, egui::WindowLevel::AlwaysOnTop)
+        } else {
+            (FULL_MODE_MIN_WINDOW_SIZE, FULL_MODE_WINDOW_SIZE, egui::WindowLevel::Normal)
+        };
+        context.send_viewport_cmd(egui::ViewportCommand::MinInnerSize(min_inner_size));
+        context.send_viewport_cmd(egui::ViewportCommand::InnerSize(inner_size));
+        context.send_viewport_cmd(egui::ViewportCommand::WindowLevel(window_level));
+    }
+
+    // Только то, что нужно во время боя: связь, разрешен ли вывод, сила эффектов сейчас и аварийная остановка
+    fn show_mini_mode_layout(&mut self, context: &egui::Context) {
+        egui::CentralPanel::default().show(context, |ui| {
+            ui.horizontal(|ui| {
+                let (war_thunder_status_text, war_thunder_status_color) = match (self.is_war_thunder_connected, self.is_war_thunder_in_battle) {
+                    (false, _) => ("WT: нет", egui::Color32::RED),
+                    (true, false) => ("WT: ангар", egui::Color32::YELLOW),
+                    (true, true) => ("WT: бой", egui::Color32::GREEN),
+                };
+                ui.label(egui::RichText::new(war_thunder_status_text).color(war_thunder_status_color));
+                ui.label(egui::RichText::new(format!("BP: {}", self.buttplug_devices.len()))
+                    .color(if self.is_buttplug_connected { egui::Color32::GREEN } else { egui::Color32::RED }))
+                    .on_hover_text("Подключенных устройств Buttplug");
+                let (output_state_text, output_state_color) = if self.is_muted {
+                    ("без вибрации", egui::Color32::RED)
+                } else if self.is_quiet_hours_active {
+                    ("тихие часы", egui::Color32::LIGHT_BLUE)
+                } else if !self.is_processing_enabled {
+                    ("обработка выкл.", egui::Color32::GRAY)
+                } else if !self.is_output_armed {
+                    ("вывод не разрешен", egui::Color32::YELLOW)
+                } else {
+                    ("вывод разрешен", egui::Color32::GREEN)
+                };
+                ui.label(egui::RichText::new(output_state_text).color(output_state_color));
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    if ui.small_button("⛶").on_hover_text("Обычный режим").clicked() {
+                        self.settings.mini_mode_enabled = false;
+                    }
+                });
+            });
+            let strongest_output_intensity = self.device_output_intensities.values().copied().fold(0.0, f64::max);
+            ui.add(egui::ProgressBar::new(strongest_output_intensity as f32)
+                .fill(egui::Color32::from_rgb(200, 120, 40))
+                .text(format!("эффекты {:.0}%", strongest_output_intensity * 100.0)))
+                .on_hover_text("Самый сильный выход среди устройств");
+            let stop_all_button = egui::Button::new(egui::RichText::new("СТОП ВСЕ").strong().color(egui::Color32::WHITE))
+                .fill(egui::Color32::DARK_RED);
+            if ui.add_sized([ui.available_width(), 28.0], stop_all_button).clicked() {
+                self.stop_all_devices_for_window("Все устройства остановлены (мини-режим).");
+            }
+        });
+    }
+
+    // Обычный интерфейс. Возвращает, открыта ли живая панель индикаторов.
+    fn show_full_layout(&mut self, context: &egui::Context) -> bool {
+        egui::TopBottomPanel::top("top_panel").show(context, |ui| {
+            egui::menu::bar(ui, |ui| {
+                // ... (меню Файл и Управление без изменений) ...
+                 ui.menu_button("Файл", |ui| {
+                    if ui.button("Сохранить конфигурацию").clicked() {
+                        self.save_settings_to_disk("Конфигурация успешно сохранена.");
+                        ui.close_menu();
+                    }
+                    if ui.button("Загрузить конфигурацию").clicked() {
+                         match configuration_manager::load_configuration() {
+                            Ok(loaded_settings) => self.apply_loaded_settings(loaded_settings, "Конфигурация успешно загружена."),
+                            Err(e) => self.add_log_message(format!("Ошибка загрузки конфигурации: {}", e)),
+                        }
+                        ui.close_menu();
+                    }
+                    ui.checkbox(&mut self.settings.autosave_on_change, "Автосохранение при изменениях");
+                    ui.checkbox(&mut self.settings.strict_config_validation, "Строгая проверка конфигурации")
+                        .on_hover_text("При загрузке сообщать в логе о неизвестных ключах (опечатках) в файле настроек");
+                    if ui.button("Выход").clicked() {
+                        context.send_viewport_cmd(egui::ViewportCommand::Close);
+                        ui.close_menu();
+                    }
+                });
+                 ui.menu_button("Управление", |ui| {
+                    if ui.checkbox(&mut self.is_processing_enabled, "Включить обработку событий WT").changed() {
+                        if self.is_processing_enabled {
+                            self.is_output_armed = true;
+                            self.processing_started_at = Some(self.clock.now());
+                            self.add_log_message("Обработка событий War Thunder включена.".to_string());
+                            self.command_sender.send(CommandToAsyncTasks::StartProcessing);
+                        } else {
+                            self.add_log_message("Обработка событий War Thunder выключена.".to_string());
+                            self.command_sender.send(CommandToAsyncTasks::StopProcessing);
+                        }
+                        ui.close_menu();
+                    }
+                    ui.checkbox(&mut self.settings.auto_start_processing, "Включать обработку при запуске");
+                    ui.checkbox(&mut self.settings.auto_connect_buttplug, "Подключаться к Buttplug при запуске");
+                    ui.checkbox(&mut self.settings.developer_mode_enabled, "Режим разработчика (имитация событий)");
+                    ui.checkbox(&mut self.settings.advanced_mode_enabled, "Расширенный режим (сырые команды устройствам)");
+                    ui.separator();
+                     if ui.button("Подключиться/Сканировать Buttplug").clicked() {
+                        self.command_sender.send(CommandToAsyncTasks::ScanForButtplugDevices);
+                        ui.close_menu();
+                    }
+                    if ui.add_enabled(self.is_buttplug_connected, egui::Button::new("Восстановить устройства"))
+                        .on_hover_text("Заново получить список устройств от сервера без сканирования - после сбоя, когда соединение живо, а устройства пропали")
+                        .clicked()
+                    {
+                        self.command_sender.send(CommandToAsyncTasks::ReacquireButtplugDevices);
+                        ui.close_menu();
+                    }
+                    if ui.button("Отключиться от Buttplug").clicked() {
+                        self.command_sender.send(CommandToAsyncTasks::DisconnectButtplug);
+                        ui.close_menu();
+                    }
+                    if ui.button("Экспорт отчета об устройствах").clicked() {
+                        match configuration_manager::device_report_path() {
+                            Ok(report_path) => {
+                                self.command_sender.send(CommandToAsyncTasks::ExportDeviceReport(report_path));
+                            }
+                            Err(e) => self.add_log_message(format!("Не удалось определить путь отчета: {}", e)),
+                        }
+                        ui.close_menu();
+                    }
+                });
+                ui.menu_button("Отладка", |ui| {
+                    let mut is_metrics_visible = self.is_connection_metrics_visible;
+                    if ui.checkbox(&mut is_metrics_visible, "Диагностика соединений").changed() {
+                        self.set_connection_metrics_visible(is_metrics_visible);
+                        ui.close_menu();
+                    }
+                });
+                let mute_button_text = if self.is_muted {
+                    egui::RichText::new("🔇 БЕЗ ВИБРАЦИИ").strong().color(egui::Color32::RED)
+                } else {
+                    egui::RichText::new("🔊 Вибрация").strong()
+                };
+                if ui.button(mute_button_text).on_hover_text("Временно отключить вывод на устройства, не выключая обработку").clicked() {
+                    self.set_muted(!self.is_muted);
+                }
+                if ui.button("▭ Мини").on_hover_text("Компактное окно поверх игры: статус, сила эффектов и остановка всех устройств").clicked() {
+                    self.settings.mini_mode_enabled = true;
+                }
+                match self.burst_expires_at {
+                    Some(burst_expires_at) => {
+                        let remaining_seconds = burst_expires_at.saturating_duration_since(self.clock.now()).as_secs_f64();
+                        ui.label(egui::RichText::new(format!("⚡ Усиление x{:.2}: {:.1} с", self.settings.burst_modifier.multiplier, remaining_seconds))
+                            .strong()
+                            .color(egui::Color32::ORANGE));
+                        if ui.small_button("Отменить").clicked() {
+                            self.burst_expires_at = Some(self.clock.now()); // Завершится в следующем кадре с записью в лог
+                        }
+                    }
+                    None => {
+                        if ui.button("⚡ Усиление")
+                            .on_hover_text(format!("Временно умножить интенсивность игровых эффектов (клавиша {})", self.settings.burst_modifier.hotkey))
+                            .clicked()
+                        {
+                            self.start_burst();
+                        }
+                    }
+                }
+                if self.is_quiet_hours_active {
+                    ui.label(egui::RichText::new("🌙 Тихие часы").color(egui::Color32::LIGHT_BLUE))
+                        .on_hover_text(format!(
+                            "До {} игровые события не выводятся на устройства (ручные тесты работают)",
+                            self.settings.quiet_hours.end
+                        ));
+                }
+                if self.is_processing_enabled && !self.is_output_armed
+                    && ui.button(egui::RichText::new("Разрешить вывод на устройства").color(egui::Color32::YELLOW)).clicked()
+                {
+                    self.is_output_armed = true;
+                    self.add_log_message("Вывод игровых событий на устройства разрешен.".to_string());
+                }
+                if !self.settings.autosave_on_change && self.has_unsaved_changes() {
+                    ui.label(egui::RichText::new("● Есть несохраненные изменения").color(egui::Color32::YELLOW));
+                }
+                let has_unapplied_changes = self.has_unapplied_changes();
+                let has_pending_changes = has_unapplied_changes || self.has_unsaved_changes();
+                if has_unapplied_changes {
+                    ui.label(egui::RichText::new("● Не применено").color(egui::Color32::YELLOW))
+                        .on_hover_text("Фоновые задачи работают с настройками до последних правок");
+                } else if !has_pending_changes {
+                    ui.label(egui::RichText::new("✔ Применено").color(egui::Color32::GREEN));
+                }
+                if ui.add_enabled(has_pending_changes, egui::Button::new("Применить"))
+                    .on_hover_text("Отправить текущие настройки фоновым задачам и сохранить конфигурацию")
+                    .on_disabled_hover_text("Нет непримененных или несохраненных изменений")
+                    .clicked()
+                {
+                    self.apply_settings();
+                }
+            });
+        });
+
+        if self.is_connection_metrics_visible {
+            self.show_connection_metrics_window(context);
+        }
+        self.show_external_settings_change_prompt(context);
+
+        let mut is_live_panel_open = false;
+        egui::CentralPanel::default().show(context, |ui| {
+            ui.heading(&self.settings.application_name);
+            ui.separator();
+
+            ui.collapsing("Статус", |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Файл конфигурации:");
+                    ui.label(&self.active_config_path_label);
+                });
+                // ... (статус WT и Buttplug сервера без изменений) ...
+                ui.horizontal(|ui| {
+                    ui.label("War Thunder API:");
+                    let (war_thunder_status_text, war_thunder_status_color) = match (self.is_war_thunder_connected, self.is_war_thunder_in_battle) {
+                        (false, _) => ("ОТКЛЮЧЕНО", egui::Color32::RED),
+                        (true, false) => ("ПОДКЛЮЧЕНО (не в бою)", egui::Color32::YELLOW),
+                        (true, true) => ("ПОДКЛЮЧЕНО (в бою)", egui::Color32::GREEN),
+                    };
+                    ui.label(egui::RichText::new(war_thunder_status_text).color(war_thunder_status_color));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Бой:");
+                    if self.game_state_snapshot.is_in_combat {
+                        let vehicle_class_label = self.game_state_snapshot.combat_vehicle_class.map_or("класс не опознан", VehicleClass::label);
+                        match &self.game_state_snapshot.combat_army {
+                            Some(combat_army) => ui.label(format!("{} (army: {})", vehicle_class_label, combat_army)),
+                            None => ui.label(format!("{} (WT не передал army)", vehicle_class_label)),
+                        };
+                    } else {
+                        ui.label(egui::RichText::new("нет данных вне боя").weak());
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Свои исходы:");
+                    if self.settings.player_name.trim().is_empty() {
+                        ui.label(egui::RichText::new("ник не задан - журнал боя не читается").weak());
+                    } else if self.game_state_snapshot.is_in_combat {
+                        ui.label(format!(
+                            "{}: уничтожено {}, потеряно {}",
+                            self.settings.player_name.trim(),
+                            self.game_state_snapshot.own_kill_count,
+                            self.game_state_snapshot.own_death_count
+                        ));
+                    } else {
+                        ui.label(egui::RichText::new("нет данных вне боя").weak());
+                    }
+                });
+                 ui.horizontal(|ui| {
+                    ui.label("Buttplug сервер:");
+                    ui.label(egui::RichText::new(if self.is_buttplug_connected { "ПОДКЛЮЧЕНО" } else { "ОТКЛЮЧЕНО" })
+                        .color(if self.is_buttplug_connected { egui::Color32::GREEN } else { egui::Color32::RED }));
+                });
+
+                if self.is_buttplug_connected && !self.buttplug_devices.is_empty() {
+                    ui.label("Подключенные устройства Buttplug:");
+                    egui::ScrollArea::vertical().max_height(100.0).show(ui, |ui| {
+                        for (idx_in_vec, device) in self.buttplug_devices.iter().enumerate() {
+                            let readiness_label = if self.ready_device_indices.contains(&device.index()) { "готово" } else { "прогрев..." };
+                            let commanded_speed = self.device_commanded_speeds.get(&device.index()).copied().unwrap_or(0.0);
+                            let output_intensity = self.device_output_intensities.get(&device.index()).copied().unwrap_or(0.0);
+                            let vibrate_step_count = buttplug_connector::vibrate_step_count(device);
+                            // Плавный переход к новому значению; egui сам перерисовывает кадры, пока идет анимация
+                            let animated_output_intensity = ui.ctx().animate_value_with_time(
+                                egui::Id::new(("device_output_meter", device.index())),
+                                output_intensity as f32,
+                                0.15,
+                            );
+                            ui.horizontal(|ui| {
+                                let device_row_response = ui.selectable_value(
+                                    &mut self.selected_device_index_in_vec,
+                                    Some(idx_in_vec),
+                                    format!("{}: {} (Индекс: {}, {})", idx_in_vec, device.name(), device.index(), readiness_label)
+                                ).on_hover_text("Правый клик - действия с устройством");
+                                if device_row_response.changed() {
+                                    self.select_pulse_due_at = Some(self.clock.now() + SELECT_PULSE_DEBOUNCE);
+                                }
+                                device_row_response.context_menu(|ui| {
+                                    let device_address = buttplug_connector::device_address(device);
+                                    if ui.button("Копировать адрес").on_hover_text(&device_address).clicked() {
+                                        // Адрес вставляется в цель правила или в группу устройств
+                                        ui.ctx().copy_text(device_address);
+                                        ui.close_menu();
+                                    }
+                                    let is_device_ready = self.ready_device_indices.contains(&device.index());
+                                    if ui.add_enabled(is_device_ready, egui::Button::new("Тест (0,5 с)"))
+                                        .on_disabled_hover_text("Устройство еще прогревается")
+                                        .clicked()
+                                    {
+                                        self.command_sender.send(CommandToAsyncTasks::PlayTestSequence {
+                                            device_index: device.index(),
+                                            steps: vec![(0.5, 500)],
+                                        });
+                                        ui.close_menu();
+                                    }
+                                    if ui.button("Стоп").clicked() {
+                                        self.command_sender.send(CommandToAsyncTasks::StopDevice(device.index()));
+                                        ui.close_menu();
+                                    }
+                                    if ui.add_enabled(self.selected_device_index_in_vec != Some(idx_in_vec), egui::Button::new("Сделать устройством по умолчанию"))
+                                        .on_hover_text("Получает действия правил с целью \"Выбранное устройство\" и непрерывные сопоставления")
+                                        .clicked()
+                                    {
+                                        self.selected_device_index_in_vec = Some(idx_in_vec);
+                                        self.select_pulse_due_at = Some(self.clock.now() + SELECT_PULSE_DEBOUNCE);
+                                        ui.close_menu();
+                                    }
+                                });
+                                ui.add(egui::ProgressBar::new(commanded_speed as f32)
+                                    .desired_width(80.0)
+                                    .text(format!("{:.0}%", commanded_speed * 100.0)))
+                                    .on_hover_text("Скорость из последней команды");
+                                ui.add(egui::ProgressBar::new(animated_output_intensity)
+                                    .desired_width(80.0)
+                                    .fill(egui::Color32::from_rgb(200, 120, 40))
+                                    .text(format!("выход {:.0}%", output_intensity * 100.0)))
+                                    .on_hover_text(format!(
+                                        "Что устройство фактически получает: после масштабирования, интенсивностей приводов, паттернов и затухания, \
+                                         с округлением до ступеней мотора. Ступень {:.0} из {}.",
+                                        output_intensity * f64::from(vibrate_step_count),
+                                        vibrate_step_count
+                                    ));
+                            });
+                        }
+                    });
+
+                    let is_selected_device_ready = self.selected_device_index_in_vec
+                        .and_then(|idx_in_vec| self.buttplug_devices.get(idx_in_vec))
+                        .is_some_and(|device| self.ready_device_indices.contains(&device.index()));
+                    if !is_selected_device_ready {
+                        ui.label("Выбранное устройство еще не готово, подождите окончания прогрева.");
+                    } else if let Some(selected_bp_index) = self.default_target_device_bp_index() {
+                         if ui.button("Тест вибрации выбранного").clicked() {
+                             self.command_sender.send(CommandToAsyncTasks::VibrateDevice{device_index: selected_bp_index, speed: 0.5, actuator_speeds: Vec::new(), duration_milliseconds: None});
+                         }
+                         if ui.button("Стоп выбранного").clicked() {
+                             self.command_sender.send(CommandToAsyncTasks::StopDevice(selected_bp_index));
+                         }
+                    }
+
+                    if let Some(selected_device) = self.selected_device_index_in_vec.and_then(|idx_in_vec| self.buttplug_devices.get(idx_in_vec)) {
+                        let selected_bp_index = selected_device.index();
+                        let mut is_sensor_subscription_enabled = self.sensor_subscribed_device_indices.contains(&selected_bp_index);
+                        if ui.checkbox(&mut is_sensor_subscription_enabled, "Получать показания датчиков").changed() {
+                            if is_sensor_subscription_enabled {
+                                self.sensor_subscribed_device_indices.insert(selected_bp_index);
+                            } else {
+                                self.sensor_subscribed_device_indices.remove(&selected_bp_index);
+                                self.latest_sensor_readings.remove(&selected_bp_index);
+                            }
+                            self.command_sender.send(CommandToAsyncTasks::SetDeviceSensorSubscription {
+                                device_index: selected_bp_index,
+                                is_enabled: is_sensor_subscription_enabled,
+                            });
+                        }
+                        for (sensor_type, values) in self.latest_sensor_readings.get(&selected_bp_index).into_iter().flatten() {
+                            ui.label(format!("  {}: {:?}", sensor_type, values));
+                        }
+                        let selected_device_address = buttplug_connector::device_address(selected_device);
+                        let mut minimum_on_milliseconds = self.settings.device_minimum_on_times.iter()
+                            .find(|minimum_on_time| minimum_on_time.device_address == selected_device_address)
+                            .map_or(0, |minimum_on_time| minimum_on_time.minimum_on_milliseconds);
+                        ui.horizontal(|ui| {
+                            ui.label("Минимальное время включения (мс, 0 - без ограничения):");
+                            let minimum_on_response = ui.add(egui::DragValue::new(&mut minimum_on_milliseconds).speed(5.0).range(0..=1000))
+                                .on_hover_text("Короткие импульсы паттернов продлеваются до этого времени, чтобы мотор успел раскрутиться");
+                            if minimum_on_response.changed() {
+                                self.settings.device_minimum_on_times.retain(|minimum_on_time| minimum_on_time.device_address != selected_device_address);
+                                if minimum_on_milliseconds > 0 {
+                                    self.settings.device_minimum_on_times.push(DeviceMinimumOnTimeSetting {
+                                        device_address: selected_device_address.clone(),
+                                        minimum_on_milliseconds,
+                                    });
+                                }
+                            }
+                            if minimum_on_response.drag_stopped() || minimum_on_response.lost_focus() {
+                                self.command_sender.send(CommandToAsyncTasks::UpdateApplicationSettings(Box::new(self.settings.clone())));
+                            }
+                        });
+                        let mut is_explicit_zero_stop = self.settings.explicit_zero_stop_device_addresses.contains(&selected_device_address);
+                        let explicit_zero_stop_response = ui.checkbox(&mut is_explicit_zero_stop, "Останавливать явным нулем на каждый привод")
+                            .on_hover_text("Для устройств, которые после обычной остановки продолжают работать одним из моторов");
+                        if explicit_zero_stop_response.changed() {
+                            self.settings.explicit_zero_stop_device_addresses.retain(|device_address| *device_address != selected_device_address);
+                            if is_explicit_zero_stop {
+                                self.settings.explicit_zero_stop_device_addresses.push(selected_device_address.clone());
+                            }
+                            self.command_sender.send(CommandToAsyncTasks::UpdateApplicationSettings(Box::new(self.settings.clone())));
+                        }
+                    }
+                } else if self.is_buttplug_connected {
+                     ui.label("Устройства Buttplug не найдены. Попробуйте сканировать.");
+                }
+                if self.is_no_devices_hint_visible {
+                    egui::Frame::group(ui.style()).fill(egui::Color32::from_rgb(60, 50, 20)).show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.strong("Сканирование не нашло ни одного устройства");
+                            if ui.small_button("✖").on_hover_text("Скрыть подсказку").clicked() {
+                                self.is_no_devices_hint_visible = false;
+                            }
+                        });
+                        ui.label("• Включено ли устройство и заряжено ли оно?");
+                        ui.label("• Устройство не подключено к другому приложению или телефону?");
+                        ui.label("• Для Bluetooth: включен ли Bluetooth на компьютере, сопряжено ли устройство (если требуется)?");
+                        ui.label("• При подключении через Intiface: запущен ли сервер и видит ли Intiface устройство в своем окне?");
+                        ui.label("После проверки нажмите \"Подключиться/Сканировать Buttplug\" еще раз.");
+                    });
+                }
+            });
+            ui.separator();
+            // ... (остальные секции UI без изменений: Данные WT, Конфигурация, Логи) ...
+            let live_panel_response = ui.collapsing("Данные War Thunder (Live)", |ui| {
+                if let Some(indicators) = &self.current_wt_indicators {
+                    egui::Grid::new("wt_indicators_grid")
+                        .num_columns(2)
+                        .spacing([40.0, 4.0])
+                        .striped(true)
+                        .show(ui, |ui| {
+                            ui.label("Тип техники:"); ui.label(format!("{:?}", indicators.vehicle_type.as_deref().unwrap_or("N/A"))); ui.end_row();
+                            ui.label("Класс техники:");
+                            ui.label(game_event_processor::classify_vehicle(indicators).map_or("не опознан", VehicleClass::label));
+                            ui.end_row();
+                            ui.label("Скорость:"); ui.label(format!("{:.2}", indicators.speed.unwrap_or(0.0))); ui.end_row();
+                            // Для самолетов с подходящим профилем speed - приборная скорость, запас считаем от порога сваливания
+                            if let (Some(profile), Some(indicated_airspeed)) =
+                                (game_event_processor::flight_envelope_profile(indicators, &self.settings), indicators.speed)
+                            {
+                                ui.label("Приборная скорость:"); ui.label(format!("{:.0} км/ч", indicated_airspeed)); ui.end_row();
+                                ui.label("Запас до сваливания:");
+                                ui.label(format!("{:.0} км/ч (профиль \"{}\")", indicated_airspeed - profile.stall_speed, profile.name));
+                                ui.end_row();
+                            }
+                            ui.label("Здоровье:"); ui.label(format!("{:.2}%", indicators.health_percentage.unwrap_or(0.0))); ui.end_row();
+                            ui.label("Интенсивность боя:"); ui.label(format!("{:.2}", self.game_state_snapshot.combat_intensity)); ui.end_row();
+                        });
+                } else {
+                    ui.label("Нет данных от War Thunder.");
+                }
+            });
+            is_live_panel_open = live_panel_response.openness > 0.0;
+            ui.separator();
+
+            ui.collapsing("Поля индикаторов", |ui| {
+                ui.label("Игнорируемые поля: правила и сопоставления, которые на них ссылаются, не срабатывают.");
+                ui.label("Сглаживание (EMA): меньше alpha - плавнее, но с большей задержкой.");
+                if self.current_wt_indicators.is_none() && self.indicators_cache.indicators.is_some() {
+                    ui.label("Значения - из последнего боя (игра сейчас не передает данные).");
+                }
+                egui::Grid::new("indicator_fields_grid").num_columns(4).striped(true).show(ui, |ui| {
+                    for &field_name in game_event_processor::INDICATOR_FIELD_NAMES {
+                        ui.label(field_name);
+                        ui.label(self.indicator_example_value_text(field_name));
+                        let mut is_ignored = game_event_processor::is_indicator_field_ignored(&self.settings, field_name);
+                        if ui.checkbox(&mut is_ignored, "игнорировать").changed() {
+                            if is_ignored {
+                                self.settings.ignored_indicator_fields.push(field_name.to_string());
+                            } else {
+                                self.settings.ignored_indicator_fields.retain(|ignored_field| ignored_field != field_name);
+                            }
+                        }
+                        if game_event_processor::is_virtual_indicator_field(field_name) {
+                            ui.label("");
+                        } else {
+                            ui.horizontal(|ui| {
+                                let smoothing_position = self.settings.indicator_smoothing.iter().position(|smoothing| smoothing.field == field_name);
+                                let mut is_smoothed = smoothing_position.is_some();
+                                if ui.checkbox(&mut is_smoothed, "сглаживать").changed() {
+                                    if is_smoothed {
+                                        self.settings.indicator_smoothing.push(IndicatorSmoothingSetting {
+                                            field: field_name.to_string(),
+                                            alpha: configuration_manager::default_smoothing_alpha(),
+                                        });
+                                    } else {
+                                        self.settings.indicator_smoothing.retain(|smoothing| smoothing.field != field_name);
+                                    }
+                                }
+                                if let Some(smoothing) = smoothing_position.and_then(|position| self.settings.indicator_smoothing.get_mut(position)) {
+                                    ui.add(egui::DragValue::new(&mut smoothing.alpha).speed(0.01).range(0.01..=1.0).prefix("alpha "));
+                                }
+                            });
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+            ui.separator();
+
+            if self.settings.developer_mode_enabled {
+                ui.collapsing("Разработчик: имитация событий", |ui| {
+                    ui.label("Изменяет последние данные WT и прогоняет их через обычную обработку правил.");
+                    if !self.is_game_output_allowed() {
+                        ui.label(egui::RichText::new("Вывод на устройства не разрешен: правила подсветятся, но команды на устройства не уйдут.").color(egui::Color32::YELLOW));
+                    }
+                    ui.horizontal_wrapped(|ui| {
+                        for simulated_event in SimulatedGameEvent::ALL {
+                            if ui.button(simulated_event.label()).clicked() {
+                                self.simulate_game_event(simulated_event);
+                            }
+                        }
+                    });
+                });
+                ui.separator();
+            }
+
+            if self.settings.advanced_mode_enabled {
+                ui.collapsing("Расширенный режим: сырые команды устройствам", |ui| {
+                    self.show_raw_device_command_panel(ui);
+                });
+                ui.separator();
+            }
+
+            ui.collapsing("Отладка: сырые данные WT", |ui| {
+                if ui.checkbox(&mut self.is_raw_json_capture_enabled, "Получать сырой JSON (/indicators и /state)").changed() {
+                    self.command_sender.send(CommandToAsyncTasks::SetRawJsonCaptureEnabled(self.is_raw_json_capture_enabled));
+                }
+                // Без живых данных показываем сохраненный JSON из последнего боя
+                let (indicators_title, indicators_raw_json) = match (&self.last_raw_indicators_json, &self.indicators_cache.raw_json) {
+                    (None, Some(cached_raw_json)) => ("/indicators (последний бой)", Some(cached_raw_json)),
+                    (live_raw_json, _) => ("/indicators", live_raw_json.as_ref()),
+                };
+                for (title, raw_json) in [(indicators_title, indicators_raw_json), ("/state", self.last_raw_state_json.as_ref())] {
+                    ui.label(title);
+                    egui::ScrollArea::vertical().id_salt(title).max_height(200.0).show(ui, |ui| {
+                        ui.label(egui::RichText::new(raw_json.map_or("Нет данных.", String::as_str)).monospace());
+                    });
+                }
+            });
+            ui.separator();
+
+            ui.collapsing("Конфигурация действий", |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Интервал опроса WT (мс):");
+                    let polling_response = ui.add(egui::DragValue::new(&mut self.settings.polling_interval_milliseconds).speed(5.0).range(50..=5000));
+                    // Отправляем по окончании правки, а не на каждый шаг перетаскивания
+                    if polling_response.drag_stopped() || polling_response.lost_focus() {
+                        self.command_sender.send(CommandToAsyncTasks::UpdateApplicationSettings(Box::new(self.settings.clone())));
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Таймаут запроса WT (мс):");
+                    let timeout_response = ui.add(egui::DragValue::new(&mut self.settings.war_thunder_request_timeout_milliseconds).speed(10.0).range(100..=10000))
+                        .on_hover_text("Если игра не ответила за это время, считаем ее отключенной. Лучше держать ниже интервала опроса.");
+                    if timeout_response.drag_stopped() || timeout_response.lost_focus() {
+                        self.command_sender.send(CommandToAsyncTasks::UpdateApplicationSettings(Box::new(self.settings.clone())));
+                    }
+                });
+                ui.collapsing("War Thunder API: адрес, заголовки, авторизация", |ui| {
+                    self.show_war_thunder_api_editor(ui);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Подключение Buttplug:");
+                    let previous_connector_kind = self.settings.buttplug_connector_kind;
+                    egui::ComboBox::from_id_salt("buttplug_connector_kind")
+                        .selected_text(match self.settings.buttplug_connector_kind {
+                            ButtplugConnectorKind::InProcess => "Встроенный сервер",
+                            ButtplugConnectorKind::WebSocket => "Intiface (WebSocket)",
+                        })
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.settings.buttplug_connector_kind, ButtplugConnectorKind::InProcess, "Встроенный сервер");
+                            ui.selectable_value(&mut self.settings.buttplug_connector_kind, ButtplugConnectorKind::WebSocket, "Intiface (WebSocket)");
+                        });
+                    if self.settings.buttplug_connector_kind != previous_connector_kind {
+                        self.command_sender.send(CommandToAsyncTasks::UpdateApplicationSettings(Box::new(self.settings.clone())));
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Фоновые задачи:");
+                    egui::ComboBox::from_id_salt("async_runtime_kind")
+                        .selected_text(match self.settings.async_runtime_kind {
+                            AsyncRuntimeKind::MultiThread => "Многопоточный рантайм",
+                            AsyncRuntimeKind::DedicatedThread => "Один отдельный поток",
+                        })
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.settings.async_runtime_kind, AsyncRuntimeKind::MultiThread, "Многопоточный рантайм");
+                            ui.selectable_value(&mut self.settings.async_runtime_kind, AsyncRuntimeKind::DedicatedThread, "Один отдельный поток");
+                        })
+                        .response
+                        .on_hover_text("Один поток легче для слабых ПК, но медленная операция Buttplug может задержать опрос WT. Применяется после сохранения и перезапуска.");
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Адрес сервера Buttplug (для WebSocket):");
+                    let address_response = ui.text_edit_singleline(&mut self.settings.buttplug_server_address);
+                    let is_address_valid = buttplug_connector::websocket_host_and_port(&self.settings.buttplug_server_address).is_some();
+                    if !is_address_valid {
+                        ui.colored_label(egui::Color32::RED, "Ожидается ws://хост:порт");
+                    }
+                    if address_response.lost_focus() && is_address_valid {
+                        self.command_sender.send(CommandToAsyncTasks::UpdateApplicationSettings(Box::new(self.settings.clone())));
+                    }
+                    let is_test_running = self.buttplug_address_under_test.is_some();
+                    if ui.add_enabled(is_address_valid && !is_test_running, egui::Button::new("Проверить"))
+                        .on_hover_text("Разовое подключение и отключение. Intiface принимает одного клиента: при активном подключении к этому же серверу проверка не пройдет.")
+                        .clicked()
+                    {
+                        let server_address = self.settings.buttplug_server_address.clone();
+                        self.buttplug_address_under_test = Some(server_address.clone());
+                        self.buttplug_address_test_result = None;
+                        self.command_sender.send(CommandToAsyncTasks::TestButtplugServerAddress(server_address));
+                    }
+                    if is_test_running {
+                        ui.spinner();
+                    } else if let Some((tested_address, test_result)) = &self.buttplug_address_test_result {
+                        // Результат показываем, только пока адрес в поле не изменился
+                        if *tested_address == self.settings.buttplug_server_address {
+                            match test_result {
+                                Ok(()) => { ui.colored_label(egui::Color32::GREEN, "Сервер доступен"); }
+                                Err(test_error) => { ui.colored_label(egui::Color32::RED, test_error); }
+                            }
+                        }
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.settings.rule_trigger_flash_enabled, "Подсвечивать сработавшие правила");
+                    ui.add_enabled(
+                        cfg!(feature = "sound_feedback"),
+                        egui::Checkbox::new(&mut self.settings.rule_trigger_beep_enabled, "Звуковой сигнал"),
+                    ).on_disabled_hover_text("Доступно в сборке с feature \"sound_feedback\"");
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Прогрев устройства после подключения (мс):");
+                    let warmup_response = ui.add(egui::DragValue::new(&mut self.settings.device_warmup_milliseconds).speed(10.0).range(0..=10000));
+                    if warmup_response.drag_stopped() || warmup_response.lost_focus() {
+                        self.command_sender.send(CommandToAsyncTasks::UpdateApplicationSettings(Box::new(self.settings.clone())));
+                    }
+                });
+                ui.horizontal(|ui| {
+                    let pulse_checkbox_response = ui.checkbox(&mut self.settings.connect_pulse.enabled, "Импульс при подключении устройства")
+                        .on_hover_text("Короткая слабая вибрация после прогрева - подтверждение, что устройство отвечает.");
+                    let select_pulse_checkbox_response = ui.checkbox(&mut self.settings.connect_pulse.on_device_select, "и при выборе")
+                        .on_hover_text("Тот же импульс на устройство, выбранное в списке: видно, какое из них выбрано");
+                    let is_pulse_used = self.settings.connect_pulse.enabled || self.settings.connect_pulse.on_device_select;
+                    let pulse_intensity_response = ui.add_enabled(
+                        is_pulse_used,
+                        egui::Slider::new(&mut self.settings.connect_pulse.intensity, 0.0..=1.0).text("инт."),
+                    );
+                    let pulse_duration_response = ui.add_enabled(
+                        is_pulse_used,
+                        egui::DragValue::new(&mut self.settings.connect_pulse.duration_milliseconds).speed(10.0).range(20..=2000).suffix(" мс"),
+                    );
+                    if pulse_checkbox_response.changed()
+                        || select_pulse_checkbox_response.changed()
+                        || pulse_intensity_response.drag_stopped()
+                        || pulse_duration_response.drag_stopped()
+                        || pulse_duration_response.lost_focus()
+                    {
+                        self.command_sender.send(CommandToAsyncTasks::UpdateApplicationSettings(Box::new(self.settings.clone())));
                     }
-                    if ui.button("Загрузить конфигурацию").clicked() {
-                         match configuration_manager::load_configuration() {
-                            Ok(loaded_settings) => {
-                                self.settings = loaded_settings.clone();
-                                let _ = self.command_sender.try_send(CommandToAsyncTasks::UpdateApplicationSettings(loaded_settings));
-                                self.add_log_message("Конфигурация успешно загружена.".to_string());
-                            },
-                            Err(e) => self.add_log_message(format!("Ошибка загрузки конфигурации: {}", e)),
-                        }
-                        ui.close_menu();
+                });
+                ui.collapsing("Фильтр устройств", |ui| {
+                    ui.label("Шаблон - часть имени устройства без учета регистра. Черный список важнее белого, пустой белый список пропускает все.");
+                    ui.label("Фильтр применяется к вновь найденным устройствам; уже подключенные остаются.");
+                    let is_allowed_list_edited = show_device_pattern_list(ui, "Белый список (принимать только эти):", &mut self.settings.device_filter.allowed_patterns);
+                    let is_blocked_list_edited = show_device_pattern_list(ui, "Черный список (никогда не принимать):", &mut self.settings.device_filter.blocked_patterns);
+                    if is_allowed_list_edited || is_blocked_list_edited {
+                        self.command_sender.send(CommandToAsyncTasks::UpdateApplicationSettings(Box::new(self.settings.clone())));
                     }
-                    if ui.button("Выход").clicked() {
-                        context.send_viewport_cmd(egui::ViewportCommand::Close);
-                        ui.close_menu();
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Временное усиление:");
+                    ui.add(egui::DragValue::new(&mut self.settings.burst_modifier.multiplier).speed(0.05).range(0.0..=4.0).prefix("x"));
+                    ui.add(egui::DragValue::new(&mut self.settings.burst_modifier.duration_milliseconds).speed(100.0).range(500..=60000).suffix(" мс"));
+                    ui.label("Клавиша:");
+                    ui.add(egui::TextEdit::singleline(&mut self.settings.burst_modifier.hotkey).desired_width(60.0));
+                    if egui::Key::from_name(&self.settings.burst_modifier.hotkey).is_none() {
+                        ui.colored_label(egui::Color32::RED, "Неизвестная клавиша").on_hover_text("Имя клавиши egui: F1..F12, A..Z, Space и т.п.");
                     }
                 });
-                 ui.menu_button("Управление", |ui| {
-                    if ui.checkbox(&mut self.is_processing_enabled, "Включить обработку событий WT").changed() {
-                        if self.is_processing_enabled {
-                            self.add_log_message("Обработка событий War Thunder включена.".to_string());
-                            let _ = self.command_sender.try_send(CommandToAsyncTasks::StartProcessing);
-                        } else {
-                            self.add_log_message("Обработка событий War Thunder выключена.".to_string());
-                            let _ = self.command_sender.try_send(CommandToAsyncTasks::StopProcessing);
-                        }
-                        ui.close_menu();
+                ui.horizontal(|ui| {
+                    let mut is_quiet_hours_changed = ui.checkbox(&mut self.settings.quiet_hours.enabled, "Тихие часы с").changed();
+                    is_quiet_hours_changed |= ui.add(egui::TextEdit::singleline(&mut self.settings.quiet_hours.start).desired_width(50.0)).lost_focus();
+                    ui.label("до");
+                    is_quiet_hours_changed |= ui.add(egui::TextEdit::singleline(&mut self.settings.quiet_hours.end).desired_width(50.0)).lost_focus();
+                    ui.label("(ЧЧ:ММ, местное время)")
+                        .on_hover_text("В это время игровые события не выводятся на устройства; логи и подсветка правил работают");
+                    if is_quiet_hours_changed {
+                        self.quiet_hours_checked_at = None; // Проверить сразу, не дожидаясь интервала
                     }
-                    ui.separator();
-                     if ui.button("Подключиться/Сканировать Buttplug").clicked() {
-                        let _ = self.command_sender.try_send(CommandToAsyncTasks::ScanForButtplugDevices);
-                        ui.close_menu();
+                });
+                ui.checkbox(&mut self.settings.stop_effects_on_wt_disconnect, "Останавливать эффекты игровых событий при отключении War Thunder или выходе из боя");
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.settings.battle_return.announce_ready, "Сообщать о готовности при возвращении в бой")
+                        .on_hover_text("После отключения или ангара состояние сессии (лимиты эффектов, кулдауны) сбрасывается в любом случае");
+                    ui.checkbox(&mut self.settings.battle_return.confirmation_pulse, "и импульсом")
+                        .on_hover_text("Импульс на устройство по умолчанию с силой и длительностью импульса подключения");
+                });
+                ui.checkbox(&mut self.settings.stop_devices_on_focus_loss, "Останавливать все устройства, когда окно теряет фокус")
+                    .on_hover_text("Пока вы в игре, окно приложения обычно не в фокусе - тогда эффекты будут остановлены при каждом переключении.");
+                ui.add_enabled_ui(!self.settings.stop_devices_on_focus_loss, |ui| {
+                    ui.checkbox(&mut self.settings.stop_devices_on_minimize, "Останавливать все устройства при сворачивании окна")
+                        .on_hover_text("Выключено: эффекты продолжаются в свернутом окне.")
+                        .on_disabled_hover_text("Сворачивание снимает фокус, поэтому при остановке по потере фокуса устройства остановятся и при сворачивании.");
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Плавная остановка устройств (мс, 0 - мгновенно):");
+                    let fade_response = ui.add(egui::DragValue::new(&mut self.settings.stop_fade_milliseconds).speed(10.0).range(0..=5000));
+                    if fade_response.drag_stopped() || fade_response.lost_focus() {
+                        self.command_sender.send(CommandToAsyncTasks::UpdateApplicationSettings(Box::new(self.settings.clone())));
                     }
-                    if ui.button("Отключиться от Buttplug").clicked() {
-                        let _ = self.command_sender.try_send(CommandToAsyncTasks::DisconnectButtplug);
-                        ui.close_menu();
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Минимальная интенсивность:");
+                    let floor_response = ui.add(egui::Slider::new(&mut self.settings.intensity_floor, 0.0..=0.5))
+                        .on_hover_text("Ненулевая интенсивность поднимается хотя бы до этого значения, чтобы слабые эффекты ощущались. \
+                            Ноль остается нулем: мертвая зона сопоставлений по-прежнему выключает слабый сигнал.");
+                    if floor_response.drag_stopped() || floor_response.lost_focus() {
+                        self.command_sender.send(CommandToAsyncTasks::UpdateApplicationSettings(Box::new(self.settings.clone())));
                     }
                 });
-            });
-        });
-
-        egui::CentralPanel::default().show(context, |ui| {
-            ui.heading(&self.settings.application_name);
-            ui.separator();
-
-            ui.collapsing("Статус", |ui| {
-                // ... (статус WT и Buttplug сервера без изменений) ...
                 ui.horizontal(|ui| {
-                    ui.label("War Thunder API:");
-                    ui.label(egui::RichText::new(if self.is_war_thunder_connected { "ПОДКЛЮЧЕНО" } else { "ОТКЛЮЧЕНО" })
-                        .color(if self.is_war_thunder_connected { egui::Color32::GREEN } else { egui::Color32::RED }));
+                    ui.label("Повторы команды при сбое связи с устройством:");
+                    let retry_response = ui.add(egui::DragValue::new(&mut self.settings.device_command_retry_count).range(0..=5))
+                        .on_hover_text("Повторяются только временные ошибки обмена (BLE); при отключении устройства повтора нет.");
+                    if retry_response.drag_stopped() || retry_response.lost_focus() {
+                        self.command_sender.send(CommandToAsyncTasks::UpdateApplicationSettings(Box::new(self.settings.clone())));
+                    }
                 });
-                 ui.horizontal(|ui| {
-                    ui.label("Buttplug сервер:");
-                    ui.label(egui::RichText::new(if self.is_buttplug_connected { "ПОДКЛЮЧЕНО" } else { "ОТКЛЮЧЕНО" })
-                        .color(if self.is_buttplug_connected { egui::Color32::GREEN } else { egui::Color32::RED }));
+                ui.horizontal(|ui| {
+                    ui.label("Отключаться от устройств после простоя (мин, 0 - никогда):");
+                    let idle_response = ui.add(egui::DragValue::new(&mut self.settings.buttplug_idle_disconnect_minutes).range(0..=240))
+                        .on_hover_text("Простой - нет команд устройствам и обработка событий выключена. Следующая команда устройству подключит заново.");
+                    if idle_response.drag_stopped() || idle_response.lost_focus() {
+                        self.command_sender.send(CommandToAsyncTasks::UpdateApplicationSettings(Box::new(self.settings.clone())));
+                    }
                 });
-
-                if self.is_buttplug_connected && !self.buttplug_devices.is_empty() {
-                    ui.label("Подключенные устройства Buttplug:");
-                    egui::ScrollArea::vertical().max_height(100.0).show(ui, |ui| {
-                        for (idx_in_vec, device) in self.buttplug_devices.iter().enumerate() { 
-                            ui.selectable_value(
-                                &mut self.selected_device_index_in_vec,
-                                Some(idx_in_vec),
-                                format!("{}: {} (Индекс: {})", idx_in_vec, device.name(), device.index())
-                            );
-                        }
+                ui.horizontal(|ui| {
+                    ui.label("Мягкий старт обработки (мс, 0 - сразу):");
+                    ui.add(egui::DragValue::new(&mut self.settings.processing_ramp_milliseconds).speed(10.0).range(0..=30000))
+                        .on_hover_text("После включения обработки интенсивность игровых эффектов плавно растет от нуля до полной.");
+                });
+                ui.add_enabled_ui(cfg!(feature = "control_api"), |ui| {
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.settings.control_api_enabled, "API управления (после перезапуска)")
+                            .on_disabled_hover_text("Доступно только в сборке с feature \"control_api\"");
+                        ui.label(format!("{}:", self.settings.control_api_bind_address));
+                        ui.add(egui::DragValue::new(&mut self.settings.control_api_port));
+                        ui.label("Токен:");
+                        ui.add(egui::TextEdit::singleline(&mut self.settings.control_api_token).password(true).desired_width(120.0));
+                    });
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Правила урона работают при здоровье от (%):");
+                    ui.add(egui::DragValue::new(&mut self.settings.damage_rules_min_health_percentage).speed(1.0).range(0.0..=100.0));
+                });
+                let mut is_critical_signal_enabled = self.settings.critical_health_action.is_some();
+                if ui.checkbox(&mut is_critical_signal_enabled, "Однократный сигнал при здоровье ниже порога").changed() {
+                    self.settings.critical_health_action = is_critical_signal_enabled.then(|| DeviceAction {
+                        action_type: DeviceActionType::Vibrate,
+                        intensity: 1.0,
+                        duration_milliseconds: 1000,
+                        target: ActionTarget::SelectedDevice,
+                        actuator_intensities: Vec::new(),
+                        pattern_points: Vec::new(),
                     });
-
-                    if let Some(selected_idx_in_vec) = self.selected_device_index_in_vec {
-                         if ui.button("Тест вибрации выбранного").clicked() {
-                             let _ = self.command_sender.try_send(CommandToAsyncTasks::VibrateDevice{device_index: selected_idx_in_vec, speed: 0.5});
-                         }
-                         if ui.button("Стоп выбранного").clicked() {
-                             let _ = self.command_sender.try_send(CommandToAsyncTasks::StopDevice(selected_idx_in_vec));
-                         }
-                    }
-                } else if self.is_buttplug_connected {
-                     ui.label("Устройства Buttplug не найдены. Попробуйте сканировать.");
                 }
-            });
-            ui.separator();
-            // ... (остальные секции UI без изменений: Данные WT, Конфигурация, Логи) ...
-            ui.collapsing("Данные War Thunder (Live)", |ui| {
-                if let Some(indicators) = &self.current_wt_indicators {
-                    egui::Grid::new("wt_indicators_grid")
-                        .num_columns(2)
-                        .spacing([40.0, 4.0])
-                        .striped(true)
-                        .show(ui, |ui| {
-                            ui.label("Тип техники:"); ui.label(format!("{:?}", indicators.vehicle_type.as_deref().unwrap_or("N/A"))); ui.end_row();
-                            ui.label("Скорость:"); ui.label(format!("{:.2}", indicators.speed.unwrap_or(0.0))); ui.end_row();
-                            ui.label("Здоровье:"); ui.label(format!("{:.2}%", indicators.health_percentage.unwrap_or(0.0))); ui.end_row();
+                ui.horizontal(|ui| {
+                    ui.label("Ник в War Thunder:");
+                    ui.add(egui::TextEdit::singleline(&mut self.settings.player_name).desired_width(140.0))
+                        .on_hover_text("Журнал боя общий для всех игроков: свои уничтожения и гибель узнаются по нику. Пусто - журнал не читается");
+                    let mut is_own_kill_enabled = self.settings.own_kill_action.is_some();
+                    if ui.checkbox(&mut is_own_kill_enabled, "Сигнал за уничтожение").changed() {
+                        self.settings.own_kill_action = is_own_kill_enabled.then(|| DeviceAction {
+                            action_type: DeviceActionType::Vibrate,
+                            intensity: 0.8,
+                            duration_milliseconds: 600,
+                            target: ActionTarget::SelectedDevice,
+                            actuator_intensities: Vec::new(),
+                            pattern_points: Vec::new(),
                         });
-                } else {
-                    ui.label("Нет данных от War Thunder.");
-                }
-            });
-            ui.separator();
-
-            ui.collapsing("Конфигурация действий", |ui| {
+                    }
+                    let mut is_own_death_enabled = self.settings.own_death_action.is_some();
+                    if ui.checkbox(&mut is_own_death_enabled, "Сигнал при гибели").changed() {
+                        self.settings.own_death_action = is_own_death_enabled.then(|| DeviceAction {
+                            action_type: DeviceActionType::Vibrate,
+                            intensity: 1.0,
+                            duration_milliseconds: 1200,
+                            target: ActionTarget::SelectedDevice,
+                            actuator_intensities: Vec::new(),
+                            pattern_points: Vec::new(),
+                        });
+                    }
+                });
                 ui.horizontal(|ui| {
-                    ui.label("Интервал опроса WT (мс):");
-                    ui.label(self.settings.polling_interval_milliseconds.to_string());
+                    let mut is_hard_impact_enabled = self.settings.hard_impact_action.is_some();
+                    if ui.checkbox(&mut is_hard_impact_enabled, "Сильный толчок при крушении, порог перегрузки (G):").changed() {
+                        self.settings.hard_impact_action = is_hard_impact_enabled.then(|| DeviceAction {
+                            action_type: DeviceActionType::Vibrate,
+                            intensity: 1.0,
+                            duration_milliseconds: 800,
+                            target: ActionTarget::SelectedDevice,
+                            actuator_intensities: Vec::new(),
+                            pattern_points: Vec::new(),
+                        });
+                    }
+                    ui.add(egui::DragValue::new(&mut self.settings.hard_impact.g_threshold).speed(0.1).range(1.0..=30.0));
                 });
                 ui.horizontal(|ui| {
-                    ui.label("Адрес сервера Buttplug (для WebSocket):");
-                    ui.text_edit_singleline(&mut self.settings.buttplug_server_address);
+                    let mut is_repair_signal_enabled = self.settings.repair_complete_action.is_some();
+                    if ui.checkbox(&mut is_repair_signal_enabled, "Сигнал о завершении ремонта, порог здоровья (%):").changed() {
+                        self.settings.repair_complete_action = is_repair_signal_enabled.then(|| DeviceAction {
+                            action_type: DeviceActionType::Vibrate,
+                            intensity: 0.4,
+                            duration_milliseconds: 300,
+                            target: ActionTarget::SelectedDevice,
+                            actuator_intensities: Vec::new(),
+                            pattern_points: Vec::new(),
+                        });
+                    }
+                    ui.add(egui::DragValue::new(&mut self.settings.repair_complete.health_threshold).speed(1.0).range(1.0..=100.0));
+                });
+                ui.horizontal(|ui| {
+                    let mut is_reload_signal_enabled = self.settings.reload_complete_action.is_some();
+                    if ui.checkbox(&mut is_reload_signal_enabled, "Импульс по окончании перезарядки").changed() {
+                        self.settings.reload_complete_action = is_reload_signal_enabled.then(|| DeviceAction {
+                            action_type: DeviceActionType::Vibrate,
+                            intensity: 0.6,
+                            duration_milliseconds: 150,
+                            target: ActionTarget::SelectedDevice,
+                            actuator_intensities: Vec::new(),
+                            pattern_points: Vec::new(),
+                        });
+                    }
+                    ui.label("длительность (с, 0 - по выстрелам):");
+                    ui.add(egui::DragValue::new(&mut self.settings.reload_feedback.reload_seconds).speed(0.1).range(0.0..=120.0))
+                        .on_hover_text("WT не передает таймер перезарядки: без значения она оценивается по самому короткому интервалу между выстрелами");
+                    ui.label("кривая:");
+                    ui.add(egui::DragValue::new(&mut self.settings.reload_feedback.curve_exponent).speed(0.05).range(0.1..=5.0))
+                        .on_hover_text("Форма поля reload_progress для сопоставлений: 1 - линейно, больше - рост ближе к готовности");
+                });
+                ui.horizontal(|ui| {
+                    let mut is_stall_warning_enabled = self.settings.stall_warning_action.is_some();
+                    if ui.checkbox(&mut is_stall_warning_enabled, "Пульс при приближении к сваливанию (самолеты)").changed() {
+                        self.settings.stall_warning_action = is_stall_warning_enabled.then(|| DeviceAction {
+                            action_type: DeviceActionType::Vibrate,
+                            intensity: 0.3,
+                            duration_milliseconds: 900,
+                            target: ActionTarget::SelectedDevice,
+                            actuator_intensities: Vec::new(),
+                            // Три мягких толчка
+                            pattern_points: [0.0, 0.15, 0.35, 0.5, 0.7, 0.85]
+                                .iter()
+                                .enumerate()
+                                .map(|(point_index, time_fraction)| PatternPoint {
+                                    time_fraction: *time_fraction,
+                                    intensity: if point_index % 2 == 0 { 1.0 } else { 0.0 },
+                                })
+                                .collect(),
+                        });
+                    }
+                    let mut is_overspeed_enabled = self.settings.overspeed_action.is_some();
+                    if ui.checkbox(&mut is_overspeed_enabled, "Сильная вибрация при превышении скорости").changed() {
+                        self.settings.overspeed_action = is_overspeed_enabled.then(|| DeviceAction {
+                            action_type: DeviceActionType::Vibrate,
+                            intensity: 1.0,
+                            duration_milliseconds: 1000,
+                            target: ActionTarget::SelectedDevice,
+                            actuator_intensities: Vec::new(),
+                            pattern_points: Vec::new(),
+                        });
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Не проверять сваливание ниже высоты:");
+                    ui.add(egui::DragValue::new(&mut self.settings.flight_envelope.min_altitude).speed(5.0).range(0.0..=5000.0));
+                });
+                egui::Grid::new("flight_envelope_profiles_grid").num_columns(6).striped(true).show(ui, |ui| {
+                    ui.label("Профиль");
+                    ui.label("Подстрока type");
+                    ui.label("Сваливание");
+                    ui.label("Запас");
+                    ui.label("Предел скорости");
+                    ui.label("Гистерезис");
+                    ui.end_row();
+                    for profile in &mut self.settings.flight_envelope.profiles {
+                        ui.add(egui::TextEdit::singleline(&mut profile.name).desired_width(110.0));
+                        ui.add(egui::TextEdit::singleline(&mut profile.vehicle_type_pattern).desired_width(90.0));
+                        ui.add(egui::DragValue::new(&mut profile.stall_speed).speed(1.0).range(0.0..=1000.0));
+                        ui.add(egui::DragValue::new(&mut profile.stall_warning_margin).speed(1.0).range(0.0..=300.0));
+                        ui.add(egui::DragValue::new(&mut profile.overspeed_speed).speed(1.0).range(0.0..=3000.0));
+                        ui.add(egui::DragValue::new(&mut profile.hysteresis).speed(1.0).range(0.0..=200.0));
+                        ui.end_row();
+                    }
                 });
 
                 ui.separator();
-                ui.label("Действия на события:");
+                ui.horizontal(|ui| {
+                    ui.label("Срабатывание правил:");
+                    egui::ComboBox::from_id_salt("rule_evaluation_mode")
+                        .selected_text(rule_evaluation_mode_label(self.settings.rule_evaluation_mode))
+                        .show_ui(ui, |ui| {
+                            for evaluation_mode in [RuleEvaluationMode::AllMatching, RuleEvaluationMode::FirstMatch] {
+                                ui.selectable_value(&mut self.settings.rule_evaluation_mode, evaluation_mode, rule_evaluation_mode_label(evaluation_mode));
+                            }
+                        })
+                        .response
+                        .on_hover_text("Касается правил из списка ниже; встроенные сигналы (критическое здоровье, удар, сваливание) срабатывают всегда.");
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Одновременные вибрации:");
+                    egui::ComboBox::from_id_salt("simultaneous_vibration_policy")
+                        .selected_text(simultaneous_vibration_policy_label(self.settings.simultaneous_vibration_policy))
+                        .show_ui(ui, |ui| {
+                            for policy in [SimultaneousVibrationPolicy::Max, SimultaneousVibrationPolicy::SumClamped, SimultaneousVibrationPolicy::Last] {
+                                ui.selectable_value(&mut self.settings.simultaneous_vibration_policy, policy, simultaneous_vibration_policy_label(policy));
+                            }
+                        })
+                        .response
+                        .on_hover_text("Если в одном обновлении несколько правил вибрируют одним устройством, им уходит одна команда.");
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Действия на события:");
+                    if ui.button("Сбросить счетчики срабатываний")
+                        .on_hover_text("Обнулить статистику всех правил (файл rule_statistics.toml рядом с конфигурацией)")
+                        .clicked()
+                    {
+                        self.reset_rule_trigger_counts();
+                    }
+                });
+                let mut target_options = vec![ActionTarget::SelectedDevice];
+                for device in &self.buttplug_devices {
+                    let device_target = ActionTarget::Device(buttplug_connector::device_address(device));
+                    if !target_options.contains(&device_target) {
+                        target_options.push(device_target);
+                    }
+                }
+                target_options.extend(self.settings.device_groups.iter().map(|group| ActionTarget::Group(group.name.clone())));
+                let condition_editor_hint_text = self.condition_editor_hint_text();
+                if !self.settings.event_actions.is_empty() && self.settings.event_actions.iter().all(|event_action| !event_action.enabled) {
+                    ui.label("Все правила выключены - игровые события не вызовут вибрацию. Включите нужные галочкой слева от имени; \
+                              вибрация начнется только после включения обработки событий WT.");
+                }
+
                 egui::ScrollArea::vertical().max_height(150.0).show(ui, |ui| {
                     let mut action_to_delete_index: Option<usize> = None;
+                    let mut is_any_rule_setting_changed = false;
+                    let mut action_to_test_index: Option<usize> = None;
+                    let mut action_to_duplicate_index: Option<usize> = None;
                     for (index, event_action) in self.settings.event_actions.iter_mut().enumerate() {
-                        ui.group(|ui| {
+                        let flash_strength = rule_flash_strength(self.rule_flash_started_at.get(&event_action.name), self.clock.now());
+                        let rule_frame = egui::Frame::group(ui.style())
+                            .fill(egui::Color32::from_rgba_unmultiplied(255, 200, 0, (flash_strength * 90.0) as u8));
+                        rule_frame.show(ui, |ui| {
                             ui.horizontal(|ui| {
                                 ui.checkbox(&mut event_action.enabled, "");
-                                ui.text_edit_singleline(&mut event_action.name);
+                                ui.text_edit_singleline(&mut event_action.name)
+                                    .on_hover_text(format!(
+                                        "Правило без условия с именем {} или {} срабатывает при входе в бой и выходе из него",
+                                        game_event_processor::COMBAT_STARTED_EVENT_NAME,
+                                        game_event_processor::COMBAT_ENDED_EVENT_NAME,
+                                    ));
+                                if ui.add(egui::DragValue::new(&mut event_action.priority).range(-100..=100).prefix("приоритет "))
+                                    .on_hover_text("Правила с большим приоритетом проверяются раньше; при равном - по порядку в списке")
+                                    .changed()
+                                {
+                                    is_any_rule_setting_changed = true;
+                                }
+                                let trigger_count = self.rule_statistics.trigger_counts.get(&event_action.name).copied().unwrap_or(0);
+                                ui.label(format!("Срабатываний: {}", trigger_count))
+                                    .on_hover_text("Счетчик привязан к имени правила: после переименования он начнется с нуля");
+                            });
+                            ui.horizontal(|ui| {
+                                ui.label("Условие:");
+                                let mut condition_text = event_action.condition_expression.clone().unwrap_or_default();
+                                if ui.text_edit_singleline(&mut condition_text)
+                                    .on_hover_text(condition_editor_hint_text.as_str())
+                                    .changed()
+                                {
+                                    event_action.condition_expression = (!condition_text.trim().is_empty()).then_some(condition_text);
+                                    is_any_rule_setting_changed = true;
+                                }
                             });
+                            let rule_errors = game_event_processor::validate_event_action(event_action);
+                            for rule_error in &rule_errors {
+                                ui.colored_label(egui::Color32::RED, rule_error);
+                            }
+                            if !rule_errors.is_empty() && event_action.enabled {
+                                ui.colored_label(egui::Color32::YELLOW, "Правило с ошибками будет выключено при сохранении.");
+                            }
                             ui.label(format!("  Действие: {:?}, Интенсивность: {:.2}, Длительность: {} мс",
                                 event_action.device_action.action_type,
                                 event_action.device_action.intensity,
                                 event_action.device_action.duration_milliseconds
                             ));
-                            if ui.add(egui::Button::new("Удалить").small()).clicked() {
-                                action_to_delete_index = Some(index);
+                            if !event_action.device_action.actuator_intensities.is_empty() {
+                                let per_actuator_summary: Vec<String> = event_action.device_action.actuator_intensities.iter()
+                                    .map(|actuator_intensity| format!("#{}: {:.2}", actuator_intensity.actuator_index, actuator_intensity.intensity))
+                                    .collect();
+                                ui.label(format!("  По моторам: {}", per_actuator_summary.join(", ")));
                             }
+                            ui.horizontal(|ui| {
+                                ui.label("Техника:").on_hover_text("Ничего не отмечено - правило действует для любой техники");
+                                for vehicle_class in VehicleClass::ALL {
+                                    let mut is_class_selected = event_action.vehicle_classes.contains(&vehicle_class);
+                                    if ui.checkbox(&mut is_class_selected, vehicle_class.label()).changed() {
+                                        if is_class_selected {
+                                            event_action.vehicle_classes.push(vehicle_class);
+                                        } else {
+                                            event_action.vehicle_classes.retain(|selected_class| *selected_class != vehicle_class);
+                                        }
+                                        is_any_rule_setting_changed = true;
+                                    }
+                                }
+                            });
+                            ui.collapsing(format!("Паттерн ({} точек)", event_action.device_action.pattern_points.len()), |ui| {
+                                // Паттерн нужен только GUI (он проигрывает действия), фоновым задачам настройки не пересылаем
+                                ui.label("Двойной клик - добавить точку, перетаскивание - изменить, правый клик - удалить. Без точек - постоянная интенсивность.");
+                                show_pattern_timeline_editor(ui, &mut event_action.device_action, ui.id().with(("pattern_timeline", index)));
+                                if !event_action.device_action.pattern_points.is_empty() && ui.small_button("Очистить паттерн").clicked() {
+                                    event_action.device_action.pattern_points.clear();
+                                }
+                            });
+                            egui::ComboBox::from_id_salt(("event_action_target", index))
+                                .selected_text(action_target_label(&event_action.device_action.target))
+                                .show_ui(ui, |ui| {
+                                    for target_option in &target_options {
+                                        if ui.selectable_value(&mut event_action.device_action.target, target_option.clone(), action_target_label(target_option)).changed() {
+                                            is_any_rule_setting_changed = true;
+                                        }
+                                    }
+                                });
+                            ui.horizontal(|ui| {
+                                ui.label("Одновременно эффектов (0 - без лимита):");
+                                if ui.add(egui::DragValue::new(&mut event_action.max_concurrent_effects).range(0..=16)).changed() {
+                                    is_any_rule_setting_changed = true;
+                                }
+                                egui::ComboBox::from_id_salt(("event_action_concurrency_policy", index))
+                                    .selected_text(match event_action.concurrency_limit_policy {
+                                        ConcurrencyLimitPolicy::DropNew => "Пропускать новые",
+                                        ConcurrencyLimitPolicy::Restart => "Перезапускать",
+                                    })
+                                    .show_ui(ui, |ui| {
+                                        is_any_rule_setting_changed |= ui.selectable_value(&mut event_action.concurrency_limit_policy, ConcurrencyLimitPolicy::DropNew, "Пропускать новые").changed();
+                                        is_any_rule_setting_changed |= ui.selectable_value(&mut event_action.concurrency_limit_policy, ConcurrencyLimitPolicy::Restart, "Перезапускать").changed();
+                                    });
+                            });
+                            ui.horizontal(|ui| {
+                                if ui.add(egui::Button::new("Тест").small()).clicked() {
+                                    action_to_test_index = Some(index);
+                                }
+                                if ui.add(egui::Button::new("Дублировать").small()).clicked() {
+                                    action_to_duplicate_index = Some(index);
+                                }
+                                if ui.add(egui::Button::new("Удалить").small()).clicked() {
+                                    action_to_delete_index = Some(index);
+                                }
+                            });
                         });
                     }
-                    if let Some(index) = action_to_delete_index {
+                    if let Some(index) = action_to_test_index {
+                        let tested_action = self.settings.event_actions[index].clone();
+                        self.show_rule_trigger_feedback(&tested_action.name);
+                        self.dispatch_device_action(&tested_action.device_action, false);
+                    }
+                    if let Some(index) = action_to_duplicate_index {
+                        let mut duplicated_action = self.settings.event_actions[index].clone();
+                        duplicated_action.name.push_str(" (копия)");
+                        self.add_log_message(format!("Действие '{}' добавлено. Не забудьте сохранить конфигурацию.", duplicated_action.name));
+                        self.settings.event_actions.insert(index + 1, duplicated_action);
+                        self.command_sender.send(CommandToAsyncTasks::UpdateApplicationSettings(Box::new(self.settings.clone())));
+                    } else if let Some(index) = action_to_delete_index {
                         self.settings.event_actions.remove(index);
                         self.add_log_message(format!("Действие #{} удалено. Не забудьте сохранить конфигурацию.", index));
-                        let _ = self.command_sender.try_send(CommandToAsyncTasks::UpdateApplicationSettings(self.settings.clone()));
+                        self.command_sender.send(CommandToAsyncTasks::UpdateApplicationSettings(Box::new(self.settings.clone())));
+                    } else if is_any_rule_setting_changed {
+                        self.command_sender.send(CommandToAsyncTasks::UpdateApplicationSettings(Box::new(self.settings.clone())));
+                    }
+                });
+
+                ui.separator();
+                ui.label("Группы устройств:");
+                let mut group_to_delete_index: Option<usize> = None;
+                for (index, device_group) in self.settings.device_groups.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("{}: {}", device_group.name, device_group.member_addresses.join(", ")));
+                        if ui.small_button("Вибрация").clicked() {
+                            self.command_sender.send(CommandToAsyncTasks::VibrateGroup {
+                                member_addresses: device_group.member_addresses.clone(),
+                                speed: 0.5,
+                                actuator_speeds: Vec::new(),
+                                duration_milliseconds: None,
+                            });
+                        }
+                        if ui.small_button("Стоп").clicked() {
+                            self.command_sender.send(CommandToAsyncTasks::StopGroup(device_group.member_addresses.clone()));
+                        }
+                        if ui.small_button("Удалить").clicked() {
+                            group_to_delete_index = Some(index);
+                        }
+                    });
+                }
+                if let Some(index) = group_to_delete_index {
+                    let removed_group = self.settings.device_groups.remove(index);
+                    self.add_log_message(format!("Группа '{}' удалена. Не забудьте сохранить конфигурацию.", removed_group.name));
+                }
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut self.config_editor_new_group_name);
+                    if ui.button("Создать группу из подключенных устройств").clicked() {
+                        let group_name = self.config_editor_new_group_name.trim().to_string();
+                        let mut member_addresses: Vec<String> = self.buttplug_devices.iter()
+                            .map(|device| buttplug_connector::device_address(device))
+                            .collect();
+                        member_addresses.dedup();
+                        if group_name.is_empty() || member_addresses.is_empty() {
+                            self.add_log_message("Для группы нужны имя и хотя бы одно подключенное устройство.".to_string());
+                        } else if self.settings.device_groups.iter().any(|group| group.name == group_name) {
+                            self.add_log_message(format!("Группа '{}' уже существует.", group_name));
+                        } else {
+                            self.settings.device_groups.push(DeviceGroupSetting { name: group_name.clone(), member_addresses });
+                            self.add_log_message(format!("Группа '{}' создана. Не забудьте сохранить конфигурацию.", group_name));
+                        }
                     }
                 });
+                ui.horizontal(|ui| {
+                    ui.label("Если устройство правила не подключено:");
+                    egui::ComboBox::from_id_salt("missing_device_behavior")
+                        .selected_text(missing_device_behavior_label(self.settings.missing_device_behavior))
+                        .show_ui(ui, |ui| {
+                            for behavior in [MissingDeviceBehavior::SkipSilently, MissingDeviceBehavior::SkipWithWarning, MissingDeviceBehavior::FallBackToDefaultDevice] {
+                                ui.selectable_value(&mut self.settings.missing_device_behavior, behavior, missing_device_behavior_label(behavior));
+                            }
+                        })
+                        .response
+                        .on_hover_text("Касается правил с конкретным устройством; устройство по умолчанию - выбранное в списке или первое.");
+                });
 
                 ui.separator();
                 ui.label("Добавить новое действие (очень упрощенно):");
+                ui.horizontal(|ui| {
+                    ui.label("Пресет:");
+                    let mut chosen_preset: Option<EffectPreset> = None;
+                    egui::ComboBox::from_id_salt("new_action_effect_preset")
+                        .selected_text(self.config_editor_selected_preset_name.as_deref().unwrap_or("Свои значения"))
+                        .show_ui(ui, |ui| {
+                            for effect_preset in &self.settings.effect_presets {
+                                let is_selected = self.config_editor_selected_preset_name.as_deref() == Some(effect_preset.name.as_str());
+                                if ui.selectable_label(is_selected, format!(
+                                    "{} ({:.0}%, {} мс)", effect_preset.name, effect_preset.intensity * 100.0, effect_preset.duration_milliseconds
+                                )).clicked() {
+                                    chosen_preset = Some(effect_preset.clone());
+                                }
+                            }
+                        });
+                    if let Some(effect_preset) = chosen_preset {
+                        self.config_editor_new_event_intensity = effect_preset.intensity;
+                        self.config_editor_new_event_duration = effect_preset.duration_milliseconds;
+                        self.config_editor_selected_preset_name = Some(effect_preset.name);
+                    }
+                    if let Some(preset_name) = self.config_editor_selected_preset_name.clone()
+                        && ui.small_button("Сохранить значения в пресет")
+                            .on_hover_text("Записать текущие интенсивность и длительность в выбранный пресет")
+                            .clicked()
+                        && let Some(effect_preset) = self.settings.effect_presets.iter_mut().find(|preset| preset.name == preset_name)
+                    {
+                        effect_preset.intensity = self.config_editor_new_event_intensity;
+                        effect_preset.duration_milliseconds = self.config_editor_new_event_duration;
+                        self.add_log_message(format!("Пресет '{}' обновлен. Не забудьте сохранить конфигурацию.", preset_name));
+                    }
+                });
                  ui.horizontal(|ui| {
                     ui.label("Имя:");
                     ui.text_edit_singleline(&mut self.config_editor_new_event_name);
@@ -335,15 +3243,24 @@ impl eframe::App for WarThunderHapticsApplication {
                             action_type: DeviceActionType::Vibrate,
                             intensity: self.config_editor_new_event_intensity,
                             duration_milliseconds: self.config_editor_new_event_duration,
-                        }
+                            target: ActionTarget::SelectedDevice,
+                            actuator_intensities: Vec::new(),
+                            pattern_points: Vec::new(),
+                        },
+                        max_concurrent_effects: 0,
+                        concurrency_limit_policy: ConcurrencyLimitPolicy::DropNew,
+                        condition_expression: None,
+                        vehicle_classes: Vec::new(),
+                        priority: 0,
                     };
                     if !new_action.name.is_empty() {
                         self.settings.event_actions.push(new_action);
                         self.add_log_message("Новое действие добавлено. Не забудьте сохранить конфигурацию.".to_string());
-                        let _ = self.command_sender.try_send(CommandToAsyncTasks::UpdateApplicationSettings(self.settings.clone()));
+                        self.command_sender.send(CommandToAsyncTasks::UpdateApplicationSettings(Box::new(self.settings.clone())));
                         self.config_editor_new_event_name = "Новое событие".to_string();
                         self.config_editor_new_event_intensity = 0.5;
                         self.config_editor_new_event_duration = 500;
+                        self.config_editor_selected_preset_name = None;
                     } else {
                         self.add_log_message("Имя нового события не может быть пустым.".to_string());
                     }
@@ -351,24 +3268,265 @@ impl eframe::App for WarThunderHapticsApplication {
             });
             ui.separator();
 
+            ui.collapsing("Слои правил", |ui| {
+                if self.settings.rule_layers.is_empty() {
+                    ui.label("Слои не заданы. Их можно описать в файле конфигурации ([[rule_layers]]).");
+                }
+                let mut is_layer_set_changed = false;
+                let mut layer_to_export_name: Option<String> = None;
+                for rule_layer in &self.settings.rule_layers {
+                    let layer_position = self.settings.active_rule_layers.iter().position(|active_name| *active_name == rule_layer.name);
+                    let mut is_layer_active = layer_position.is_some();
+                    ui.horizontal(|ui| {
+                        if ui.checkbox(&mut is_layer_active, format!("{} ({} правил)", rule_layer.name, rule_layer.event_actions.len())).changed() {
+                            if is_layer_active {
+                                // Включенный слой ложится поверх уже активных
+                                self.settings.active_rule_layers.push(rule_layer.name.clone());
+                            } else {
+                                self.settings.active_rule_layers.retain(|active_name| *active_name != rule_layer.name);
+                            }
+                            is_layer_set_changed = true;
+                        }
+                        if let Some(position) = layer_position {
+                            ui.label(format!("уровень {}", position + 1));
+                        }
+                        if ui.small_button("Экспорт")
+                            .on_hover_text("Сохранить слой с его группами устройств и пресетами в файл профиля (каталог profiles рядом с конфигурацией)")
+                            .clicked()
+                        {
+                            layer_to_export_name = Some(rule_layer.name.clone());
+                        }
+                    });
+                }
+                if let Some(layer_name) = layer_to_export_name {
+                    self.export_rule_layer_profile(&layer_name);
+                }
+                if !self.settings.active_rule_layers.is_empty() {
+                    ui.label("При совпадении имен действует правило слоя с бо́льшим уровнем.");
+                }
+                if is_layer_set_changed {
+                    self.command_sender.send(CommandToAsyncTasks::UpdateApplicationSettings(Box::new(self.settings.clone())));
+                }
+
+                ui.horizontal(|ui| {
+                    ui.label("Импорт профиля:");
+                    ui.add(egui::TextEdit::singleline(&mut self.profile_import_path_text).hint_text("путь к файлу .profile.toml"));
+                    let can_import = !self.profile_import_path_text.trim().is_empty() && self.pending_profile_import.is_none();
+                    if ui.add_enabled(can_import, egui::Button::new("Импортировать")).clicked() {
+                        self.start_profile_import();
+                    }
+                });
+                if let Some(pending_profile) = &self.pending_profile_import {
+                    let pending_profile_name = pending_profile.profile.name.clone();
+                    let mut import_decision: Option<Option<ProfileImportMode>> = None;
+                    ui.colored_label(egui::Color32::YELLOW, format!("Слой '{}' уже есть.", pending_profile_name));
+                    ui.horizontal(|ui| {
+                        if ui.button("Перезаписать").clicked() {
+                            import_decision = Some(Some(ProfileImportMode::Overwrite));
+                        }
+                        ui.label("или новое имя:");
+                        ui.text_edit_singleline(&mut self.profile_import_rename_text);
+                        let new_name = self.profile_import_rename_text.trim().to_string();
+                        let is_new_name_free = !new_name.is_empty()
+                            && !self.settings.rule_layers.iter().any(|rule_layer| rule_layer.name == new_name);
+                        if ui.add_enabled(is_new_name_free, egui::Button::new("Переименовать")).clicked() {
+                            import_decision = Some(Some(ProfileImportMode::Rename(new_name)));
+                        }
+                        if ui.button("Отмена").clicked() {
+                            import_decision = Some(None);
+                        }
+                    });
+                    if let Some(import_mode) = import_decision
+                        && let (Some(profile_file), Some(import_mode)) = (self.pending_profile_import.take(), import_mode)
+                    {
+                        self.finish_profile_import(profile_file, import_mode);
+                    }
+                }
+            });
+            ui.separator();
+
+            ui.collapsing("Тестовые последовательности", |ui| {
+                let target_device_index = self.default_target_device_bp_index();
+                if target_device_index.is_none() {
+                    ui.label("Нет устройства для проигрывания.");
+                }
+                let mut sequence_to_play_index: Option<usize> = None;
+                for (sequence_index, test_sequence) in self.settings.test_sequences.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        let total_milliseconds: u64 = test_sequence.steps.iter().map(|step| step.duration_milliseconds).sum();
+                        ui.label(format!("{} ({} шагов, {} мс)", test_sequence.name, test_sequence.steps.len(), total_milliseconds));
+                        if ui.add_enabled(target_device_index.is_some(), egui::Button::new("Проиграть").small()).clicked() {
+                            sequence_to_play_index = Some(sequence_index);
+                        }
+                    });
+                }
+                if let (Some(sequence_index), Some(device_index)) = (sequence_to_play_index, target_device_index) {
+                    let test_sequence = &self.settings.test_sequences[sequence_index];
+                    let steps = test_sequence.steps.iter().map(|step| (step.intensity, step.duration_milliseconds)).collect();
+                    let sequence_name = test_sequence.name.clone();
+                    self.command_sender.send(CommandToAsyncTasks::PlayTestSequence { device_index, steps });
+                    self.add_log_message(format!("Тестовая последовательность '{}' запущена.", sequence_name));
+                }
+                ui.label("Последовательности задаются в файле конфигурации ([[test_sequences]]).");
+            });
+            ui.separator();
+
+            ui.collapsing("Непрерывные сопоставления", |ui| {
+                self.show_continuous_mappings_editor(ui);
+            });
+            let curve_simulation_section = ui.collapsing("Подбор кривой сопоставления", |ui| {
+                self.show_mapping_curve_simulation(ui);
+            });
+            if curve_simulation_section.body_returned.is_none() {
+                self.stop_mapping_curve_simulation_output();
+            }
+            ui.separator();
+
             ui.collapsing("Логи", |ui| {
+                let mut filter_options = vec![LogFilter::All];
+                filter_options.extend(LogCategory::ALL.into_iter().map(LogFilter::Category));
+                for log_entry in &self.log_messages {
+                    if let Some(address) = &log_entry.device_address {
+                        let device_filter = LogFilter::Device(address.clone());
+                        if !filter_options.contains(&device_filter) {
+                            filter_options.push(device_filter);
+                        }
+                    }
+                }
+                egui::ComboBox::from_id_salt("log_filter")
+                    .selected_text(self.log_filter.label())
+                    .show_ui(ui, |ui| {
+                        for filter_option in filter_options {
+                            let option_label = filter_option.label();
+                            ui.selectable_value(&mut self.log_filter, filter_option, option_label);
+                        }
+                    });
                 egui::ScrollArea::vertical().max_height(200.0).auto_shrink([false, false]).show(ui, |ui| {
-                    for msg in self.log_messages.iter() {
-                        ui.label(msg);
+                    for log_entry in self.log_messages.iter().filter(|log_entry| self.log_filter.matches(log_entry)) {
+                        ui.label(format!("[{}] {}", log_entry.category.label(), log_entry.message));
                     }
                 });
             });
         });
+        is_live_panel_open
+    }
+}
+
+impl eframe::App for WarThunderHapticsApplication {
+    fn update(&mut self, context: &egui::Context, _frame: &mut eframe::Frame) {
+        self.handle_incoming_updates();
+        self.handle_pending_commands(context);
+        self.handle_quiet_hours();
+        self.sync_output_armed_state();
+        self.handle_device_select_pulse(context);
+        self.handle_burst_modifier(context);
+        self.collect_rule_statistics();
+        self.handle_window_focus_change(context);
+
+        self.sync_mini_mode_viewport(context);
+        let is_live_panel_open = if self.settings.mini_mode_enabled {
+            self.show_mini_mode_layout(context);
+            false
+        } else {
+            self.show_full_layout(context)
+        };
 
+        self.sync_indicator_updates_request(is_live_panel_open);
+
+        self.handle_autosave();
+        self.handle_rule_statistics_save();
+        self.handle_indicators_cache_save();
+
+        let now = self.clock.now();
+        if self.rule_flash_started_at.values().any(|started_at| now.saturating_duration_since(*started_at) < RULE_FLASH_DURATION) {
+            context.request_repaint(); // Плавное угасание подсветки
+        } else {
+            self.rule_flash_started_at.clear();
+        }
         context.request_repaint_after(std::time::Duration::from_millis(100));
     }
 
     fn save(&mut self, _storage: &mut dyn eframe::Storage) {
-        match configuration_manager::save_configuration(&self.settings) {
-            Ok(_) => self.add_log_message("Конфигурация автоматически сохранена при выходе.".to_string()),
-            Err(e) => self.add_log_message(format!("Ошибка автосохранения конфигурации: {}", e)),
+        self.save_settings_to_disk("Конфигурация автоматически сохранена при выходе.");
+        self.collect_rule_statistics();
+        if self.rule_statistics_save_due_at.is_some() {
+            self.save_rule_statistics_to_disk();
+        }
+        if self.indicators_cache_save_due_at.is_some() {
+            self.save_indicators_cache_to_disk();
+        }
+        self.command_sender.send(CommandToAsyncTasks::StopProcessing);
+        self.command_sender.send(CommandToAsyncTasks::DisconnectButtplug);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vibrate_device(device_index: u32) -> CommandToAsyncTasks {
+        CommandToAsyncTasks::VibrateDevice { device_index, speed: 0.5, actuator_speeds: Vec::new(), duration_milliseconds: Some(200) }
+    }
+
+    fn pending_labels(command_sender: &GuiCommandSender) -> Vec<String> {
+        command_sender.pending_commands.borrow().iter().map(command_label).collect()
+    }
+
+    // Канал на одну команду, уже занятый: все новые команды откладываются
+    fn sender_with_full_channel() -> (GuiCommandSender, mpsc::Receiver<CommandToAsyncTasks>) {
+        let (sender, receiver) = mpsc::channel(1);
+        sender.try_send(CommandToAsyncTasks::StartProcessing).unwrap();
+        (GuiCommandSender::new(sender), receiver)
+    }
+
+    #[test]
+    fn stop_purges_pending_vibrations_and_goes_first() {
+        let (command_sender, mut receiver) = sender_with_full_channel();
+        command_sender.send(vibrate_device(1));
+        command_sender.send(CommandToAsyncTasks::SetIndicatorUpdatesEnabled(true));
+        command_sender.send(CommandToAsyncTasks::PlayTestSequence { device_index: 2, steps: vec![(1.0, 100)] });
+        command_sender.send(CommandToAsyncTasks::StopAllDevices);
+        assert_eq!(pending_labels(&command_sender), vec!["StopAllDevices", "SetIndicatorUpdatesEnabled"]);
+
+        // Освободилось место - первой уходит остановка
+        assert!(matches!(receiver.try_recv(), Ok(CommandToAsyncTasks::StartProcessing)));
+        assert!(command_sender.retry_pending());
+        assert!(matches!(receiver.try_recv(), Ok(CommandToAsyncTasks::StopAllDevices)));
+    }
+
+    #[test]
+    fn device_stop_purges_only_that_device() {
+        let (command_sender, _receiver) = sender_with_full_channel();
+        command_sender.send(vibrate_device(1));
+        command_sender.send(vibrate_device(2));
+        command_sender.send(CommandToAsyncTasks::StopDevice(1));
+        command_sender.send(CommandToAsyncTasks::SetOutputMuted(false));
+        command_sender.send(CommandToAsyncTasks::StopGroup(vec!["Lovense Edge".to_string()]));
+        let pending_commands = command_sender.pending_commands.borrow();
+        assert!(matches!(
+            pending_commands.iter().collect::<Vec<_>>().as_slice(),
+            [
+                CommandToAsyncTasks::StopDevice(1),
+                CommandToAsyncTasks::StopGroup(_),
+                CommandToAsyncTasks::VibrateDevice { device_index: 2, .. },
+                CommandToAsyncTasks::SetOutputMuted(false),
+            ]
+        ));
+    }
+
+    #[test]
+    fn stop_is_never_dropped_by_pending_limit() {
+        let (command_sender, _receiver) = sender_with_full_channel();
+        for _ in 0..PENDING_COMMAND_LIMIT {
+            command_sender.send(CommandToAsyncTasks::SetIndicatorUpdatesEnabled(true));
         }
-        let _ = self.command_sender.try_send(CommandToAsyncTasks::StopProcessing);
-        let _ = self.command_sender.try_send(CommandToAsyncTasks::DisconnectButtplug);
+        command_sender.send(CommandToAsyncTasks::SetRawJsonCaptureEnabled(true));
+        assert_eq!(command_sender.pending_commands.borrow().len(), PENDING_COMMAND_LIMIT);
+        assert!(command_sender.take_failure_messages().iter().any(|message| message.contains("SetRawJsonCaptureEnabled")));
+
+        command_sender.send(CommandToAsyncTasks::SetOutputMuted(true));
+        assert_eq!(pending_labels(&command_sender)[0], "SetOutputMuted");
+        assert_eq!(command_sender.pending_commands.borrow().len(), PENDING_COMMAND_LIMIT + 1);
     }
-}
\ No newline at end of file
+}
+
@@ -1,16 +1,963 @@
 // src/buttplug_connector.rs
 
-use tracing::info;
 
-use crate::message_passing::{CommandToAsyncTasks, UpdateFromAsyncTasks, ClonableButtplugClientDevice};
+use crate::configuration_manager::{ApplicationSettings, ButtplugConnectorKind, DeviceMinimumOnTimeSetting};
+use crate::connection_metrics::{DeviceCommandMetrics, RollingWindow, METRICS_REPORT_INTERVAL};
+use crate::message_passing::{CommandToAsyncTasks, UpdateFromAsyncTasks, ClonableButtplugClientDevice, LogCategory};
 use buttplug::client::{
-    ButtplugClient, ButtplugClientDevice, ButtplugClientEvent,
+    ButtplugClient, ButtplugClientDevice, ButtplugClientDeviceEvent, ButtplugClientError, ButtplugClientEvent, RotateCommand, ScalarCommand,
 };
-use buttplug::core::connector::ButtplugInProcessClientConnector;
-use buttplug::core::message::{ActuatorType, ScalarCmdV3, ScalarSubcommandV3};
-use futures::{StreamExt, FutureExt}; // Добавлен FutureExt для now_or_never
+use buttplug::core::errors::{ButtplugDeviceError, ButtplugError};
+use buttplug::core::connector::{new_json_ws_client_connector, ButtplugInProcessClientConnector, ButtplugInProcessClientConnectorBuilder};
+use buttplug::server::ButtplugServerBuilder;
+use buttplug::core::message::{ActuatorType, ButtplugServerMessageV3, Endpoint};
+use futures::StreamExt;
+use futures::stream::BoxStream;
 use tokio::sync::mpsc;
-use std::sync::Arc;
+use tokio::task::JoinHandle;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Задержки команд устройствам (None - команда завершилась ошибкой); пишут задачи отправки команд
+type CommandLatencyWindow = Arc<Mutex<RollingWindow<Option<Duration>>>>;
+
+// Команда участнику группы: устройство, вибрация, итоговая интенсивность и приводы для подробного журнала
+type GroupMemberCommand = (Arc<ButtplugClientDevice>, ScalarCommand, f64, Option<Vec<(u32, f64)>>);
+
+fn record_command_latency(command_latencies: &CommandLatencyWindow, command_started_at: Instant, is_successful: bool) {
+    if let Ok(mut latency_window) = command_latencies.lock() {
+        let command_finished_at = Instant::now();
+        latency_window.record(is_successful.then(|| command_finished_at.duration_since(command_started_at)), command_finished_at);
+    }
+}
+
+// Подробная запись одной команды устройству (настройка verbose_device_command_logging).
+// Время - от получения команды задачей Buttplug до ответа сервера, включая повторы.
+fn log_device_command_timing(
+    device: &ButtplugClientDevice,
+    command_name: &str,
+    actuator_speeds: &[(u32, f64)],
+    command_received_at: Instant,
+    is_successful: bool,
+) {
+    tracing::debug!(
+        device = %device.name(),
+        device_index = device.index(),
+        command = command_name,
+        actuator_speeds = ?actuator_speeds,
+        elapsed_milliseconds = command_received_at.elapsed().as_secs_f64() * 1000.0,
+        is_successful,
+        "Команда устройству выполнена"
+    );
+}
+
+// Сколько ждем TCP-соединения с сервером перед полным рукопожатием Buttplug
+const SERVER_PROBE_TIMEOUT: Duration = Duration::from_millis(700);
+
+// Адрес устройства для настроек (группы, цели правил). Клиент Buttplug не отдает физический адрес,
+// поэтому используем имя устройства: одинаковые устройства получают одинаковый адрес,
+// что для групп означает "все устройства с таким именем".
+pub fn device_address(device: &ButtplugClientDevice) -> String {
+    device.name().to_string()
+}
+
+// Конечные точки для сырых команд. Сервер сообщает их, только если разрешены сырые сообщения
+// (в Intiface Central это отдельная настройка; встроенный сервер их не разрешает).
+pub fn raw_write_endpoints(device: &ButtplugClientDevice) -> Vec<Endpoint> {
+    device.message_attributes().raw_write_cmd().as_ref()
+        .map(|raw_attributes| raw_attributes.endpoints().clone())
+        .unwrap_or_default()
+}
+
+pub fn raw_read_endpoints(device: &ButtplugClientDevice) -> Vec<Endpoint> {
+    device.message_attributes().raw_read_cmd().as_ref()
+        .map(|raw_attributes| raw_attributes.endpoints().clone())
+        .unwrap_or_default()
+}
+
+// "01 ff 0x2A" или "01,FF,2a" -> [0x01, 0xFF, 0x2A]
+pub fn parse_hex_bytes(hex_text: &str) -> Result<Vec<u8>, String> {
+    let bytes = hex_text
+        .split(|separator: char| separator.is_whitespace() || separator == ',')
+        .filter(|token| !token.is_empty())
+        .map(|token| {
+            let hex_digits = token.trim_start_matches("0x").trim_start_matches("0X");
+            u8::from_str_radix(hex_digits, 16).map_err(|_| format!("'{}' - не байт в шестнадцатеричной записи", token))
+        })
+        .collect::<Result<Vec<u8>, String>>()?;
+    if bytes.is_empty() {
+        return Err("Нет байтов для отправки.".to_string());
+    }
+    Ok(bytes)
+}
+
+pub fn format_hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02X}", byte)).collect::<Vec<_>>().join(" ")
+}
+
+// Скалярный привод устройства, как его описывают message_attributes()
+#[derive(Debug, Clone)]
+pub struct ActuatorCapability {
+    pub index: u32,
+    pub actuator_type: ActuatorType,
+    pub step_count: u32,
+}
+
+// Каким путем устройство получает вибрацию. Клиент говорит на протоколе v3, где отдельного VibrateCmd нет:
+// сервер сам представляет вибромоторы старых устройств (с устаревшим VibrateCmd) скалярными приводами Vibrate.
+// Не покрыты этим устройства без вибромоторов - им вибрация передается на другие скалярные приводы.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VibrationProtocol {
+    ScalarVibrate, // ScalarCmd на вибромоторы
+    ScalarFallback, // Вибромоторов нет: ScalarCmd на осцилляцию, сжатие или накачку
+    Unsupported, // Подходящих скалярных приводов нет (например, только вращение или линейное движение)
+}
+
+impl VibrationProtocol {
+    pub fn label(self) -> &'static str {
+        match self {
+            VibrationProtocol::ScalarVibrate => "ScalarCmd (вибромоторы)",
+            VibrationProtocol::ScalarFallback => "ScalarCmd (другие скалярные приводы вместо вибрации)",
+            VibrationProtocol::Unsupported => "нет подходящих приводов - вибрация не отправляется",
+        }
+    }
+}
+
+// Скалярные приводы, которым можно передать интенсивность вибрации, если вибромоторов нет.
+// Position не подходит: это положение, а не сила.
+const VIBRATION_FALLBACK_ACTUATOR_TYPES: [ActuatorType; 3] = [ActuatorType::Oscillate, ActuatorType::Constrict, ActuatorType::Inflate];
+
+// Раскладка приводов устройства. Считывается один раз при обнаружении, чтобы путь команд
+// не разбирал message_attributes() на каждую вибрацию.
+#[derive(Debug, Clone, Default)]
+pub struct DeviceCapabilities {
+    pub scalar_actuators: Vec<ActuatorCapability>,
+}
+
+impl DeviceCapabilities {
+    pub fn introspect(device: &ButtplugClientDevice) -> Self {
+        let scalar_actuators = device.message_attributes().scalar_cmd().iter()
+            .flatten()
+            .map(|feature_actuator| ActuatorCapability {
+                index: *feature_actuator.index(),
+                actuator_type: *feature_actuator.actuator_type(),
+                step_count: *feature_actuator.step_count(),
+            })
+            .collect();
+        Self { scalar_actuators }
+    }
+
+    pub fn vibration_protocol(&self) -> VibrationProtocol {
+        if self.scalar_actuators.iter().any(|actuator| actuator.actuator_type == ActuatorType::Vibrate) {
+            VibrationProtocol::ScalarVibrate
+        } else if self.scalar_actuators.iter().any(|actuator| VIBRATION_FALLBACK_ACTUATOR_TYPES.contains(&actuator.actuator_type)) {
+            VibrationProtocol::ScalarFallback
+        } else {
+            VibrationProtocol::Unsupported
+        }
+    }
+
+    // Приводы, на которые идут команды вибрации (см. VibrationProtocol)
+    pub fn vibrate_actuators(&self) -> impl Iterator<Item = &ActuatorCapability> {
+        let vibration_protocol = self.vibration_protocol();
+        self.scalar_actuators.iter().filter(move |actuator| match vibration_protocol {
+            VibrationProtocol::ScalarVibrate => actuator.actuator_type == ActuatorType::Vibrate,
+            VibrationProtocol::ScalarFallback => VIBRATION_FALLBACK_ACTUATOR_TYPES.contains(&actuator.actuator_type),
+            VibrationProtocol::Unsupported => false,
+        })
+    }
+}
+
+// Число ступеней самого точного привода вибрации - для подписи индикатора выхода в GUI (0 - таких приводов нет)
+pub fn vibrate_step_count(device: &ButtplugClientDevice) -> u32 {
+    DeviceCapabilities::introspect(device).vibrate_actuators().map(|actuator| actuator.step_count).max().unwrap_or(0)
+}
+
+// Запись для лога: каким путем устройство будет получать вибрацию
+fn vibration_protocol_log_message(device: &ButtplugClientDevice, capabilities: &DeviceCapabilities) -> String {
+    format!("Устройство {}: вибрация через {}.", device.name(), capabilities.vibration_protocol().label())
+}
+
+// Раскладки приводов подключенных устройств по индексу Buttplug. Не по адресу (имени): у двух одинаковых
+// устройств имена совпадают, а переподключенное устройство получает новый индекс и опрашивается заново.
+// Запись устройства удаляется при его пропаже (DeviceRemoved, восстановление устройств), весь кэш - при отключении.
+#[derive(Debug, Default)]
+struct CapabilitiesCache {
+    capabilities_by_device_index: HashMap<u32, DeviceCapabilities>,
+}
+
+impl CapabilitiesCache {
+    fn get_or_insert_with(&mut self, device_index: u32, introspect: impl FnOnce() -> DeviceCapabilities) -> &DeviceCapabilities {
+        self.capabilities_by_device_index.entry(device_index).or_insert_with(introspect)
+    }
+
+    fn insert(&mut self, device_index: u32, capabilities: DeviceCapabilities) {
+        self.capabilities_by_device_index.insert(device_index, capabilities);
+    }
+
+    fn invalidate(&mut self, device_index: u32) {
+        self.capabilities_by_device_index.remove(&device_index);
+    }
+
+    fn clear(&mut self) {
+        self.capabilities_by_device_index.clear();
+    }
+}
+
+// Запланированная работа устройств по индексу BP: задачи (плавная остановка, тестовая последовательность)
+// и моменты остановки ограниченных по времени вибраций. Любая новая команда устройству отменяет и то, и другое.
+// Остановка по времени выполняется циклом как обычная StopDevice (см. next_command), поэтому учитывает
+// способ остановки, плавное затухание и обновляет последние отправленные скорости.
+#[derive(Debug, Default)]
+struct ScheduledDeviceTasks {
+    tasks_by_device_index: HashMap<u32, JoinHandle<()>>,
+    timed_stops_by_device_index: HashMap<u32, tokio::time::Instant>,
+}
+
+impl ScheduledDeviceTasks {
+    fn insert(&mut self, device_index: u32, scheduled_task: JoinHandle<()>) {
+        if let Some(replaced_task) = self.tasks_by_device_index.insert(device_index, scheduled_task) {
+            replaced_task.abort();
+        }
+    }
+
+    fn stop_at(&mut self, device_index: u32, stop_deadline: tokio::time::Instant) {
+        self.timed_stops_by_device_index.insert(device_index, stop_deadline);
+    }
+
+    fn cancel(&mut self, device_index: u32) {
+        if let Some(scheduled_task) = self.tasks_by_device_index.remove(&device_index) {
+            scheduled_task.abort();
+        }
+        self.timed_stops_by_device_index.remove(&device_index);
+    }
+
+    fn cancel_all(&mut self) {
+        self.tasks_by_device_index.drain().for_each(|(_, scheduled_task)| scheduled_task.abort());
+        self.timed_stops_by_device_index.clear();
+    }
+
+    // Ближайшая остановка по времени: (индекс BP, момент)
+    fn next_timed_stop(&self) -> Option<(u32, tokio::time::Instant)> {
+        self.timed_stops_by_device_index.iter()
+            .min_by_key(|(_, stop_deadline)| **stop_deadline)
+            .map(|(device_index, stop_deadline)| (*device_index, *stop_deadline))
+    }
+
+    fn take_timed_stop(&mut self, device_index: u32) -> Option<tokio::time::Instant> {
+        self.timed_stops_by_device_index.remove(&device_index)
+    }
+
+    fn is_effect_running(&self) -> bool {
+        !self.timed_stops_by_device_index.is_empty()
+            || self.tasks_by_device_index.values().any(|scheduled_task| !scheduled_task.is_finished())
+    }
+}
+
+// Возможности устройства из кэша; при промахе устройство опрашивается и кэшируется
+fn cached_capabilities<'a>(capabilities_cache: &'a mut CapabilitiesCache, device: &ButtplugClientDevice) -> &'a DeviceCapabilities {
+    capabilities_cache.get_or_insert_with(device.index(), || DeviceCapabilities::introspect(device))
+}
+
+// Привод различает только step_count ступеней; произвольное значение устройство округлит по-своему.
+// Округляем сами до ближайшей ступени, чтобы индикатор выхода показывал то, что реально играет.
+// Ненулевая скорость не опускается до нуля: слабый эффект должен ощущаться хотя бы первой ступенью.
+fn quantize_to_actuator_steps(speed: f64, step_count: u32) -> f64 {
+    if step_count == 0 || speed <= 0.0 {
+        return speed.clamp(0.0, 1.0);
+    }
+    let step = (speed * f64::from(step_count)).round().clamp(1.0, f64::from(step_count));
+    step / f64::from(step_count)
+}
+
+// Порог интенсивности (intensity_floor): любая ненулевая скорость поднимается хотя бы до порога, ноль остается нулем.
+// Применяется к командам вибрации и шагам тестовых последовательностей при получении, то есть после мертвой зоны
+// сопоставлений (обнуленное ею так и остается нулем) и до округления до ступеней привода.
+// Плавная остановка порог не использует - она доводит скорость до нуля.
+pub fn lift_to_intensity_floor(speed: f64, intensity_floor: f64) -> f64 {
+    if speed > 0.0 { speed.max(intensity_floor.clamp(0.0, 1.0)) } else { speed }
+}
+
+// Итоговая скорость каждого вибромотора: из actuator_speeds, если привод там есть, иначе speed;
+// округлена до ступеней привода
+fn vibrate_actuator_speeds(capabilities: &DeviceCapabilities, speed: f64, actuator_speeds: &[(u32, f64)]) -> Vec<(u32, f64)> {
+    capabilities.vibrate_actuators()
+        .map(|actuator| {
+            let actuator_speed = actuator_speeds.iter()
+                .find(|(actuator_index, _)| *actuator_index == actuator.index)
+                .map_or(speed, |(_, actuator_speed)| actuator_speed.clamp(0.0, 1.0));
+            (actuator.index, quantize_to_actuator_steps(actuator_speed, actuator.step_count))
+        })
+        .collect()
+}
+
+// Что фактически получает устройство от команды вибрации: самый сильный из его вибромоторов
+fn vibration_output_intensity(capabilities: &DeviceCapabilities, speed: f64, actuator_speeds: &[(u32, f64)]) -> f64 {
+    vibrate_actuator_speeds(capabilities, speed, actuator_speeds)
+        .into_iter()
+        .map(|(_, actuator_speed)| actuator_speed)
+        .fold(0.0, f64::max)
+}
+
+// Индикатор выхода в GUI обновляется только по успешно выполненным командам.
+// try_send: индикатор не должен задерживать команды, пропущенное значение перекроет следующее.
+fn report_device_output(output_sender: &mpsc::Sender<UpdateFromAsyncTasks>, device_index: u32, output_intensity: f64) {
+    let _ = output_sender.try_send(UpdateFromAsyncTasks::ButtplugDeviceOutput { device_index, output_intensity });
+}
+
+// Собирает ScalarCmd для всех приводов вибрации устройства. None - если устройство не умеет вибрировать.
+// actuator_speeds: скорость для отдельных приводов; приводы без записи получают speed
+fn build_vibration_command(
+    device: &ButtplugClientDevice,
+    capabilities: &DeviceCapabilities,
+    speed: f64,
+    actuator_speeds: &[(u32, f64)],
+) -> Option<ScalarCommand> {
+    let scalar_speeds: HashMap<u32, (f64, ActuatorType)> = vibrate_actuator_speeds(capabilities, speed, actuator_speeds)
+        .into_iter()
+        .filter_map(|(actuator_index, actuator_speed)| {
+            let actuator = capabilities.scalar_actuators.iter().find(|actuator| actuator.index == actuator_index)?;
+            Some((actuator_index, (actuator_speed, actuator.actuator_type)))
+        })
+        .collect();
+
+    if scalar_speeds.is_empty() {
+        tracing::warn!("Устройство {}: {}.", device.name(), VibrationProtocol::Unsupported.label());
+        return None;
+    }
+    Some(ScalarCommand::ScalarMap(scalar_speeds))
+}
+
+// Как останавливать устройство. stop() на части устройств обнуляет не все приводы,
+// для них (explicit_zero_stop_device_addresses) каждому приводу явно отправляется ноль.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceStopStrategy {
+    StopCommand, // StopDeviceCmd - device.stop()
+    ExplicitZero, // Нулевой ScalarCmd на все скалярные приводы и нулевое вращение
+}
+
+impl DeviceStopStrategy {
+    pub fn label(self) -> &'static str {
+        match self {
+            DeviceStopStrategy::StopCommand => "StopDeviceCmd",
+            DeviceStopStrategy::ExplicitZero => "явный ноль на каждый привод",
+        }
+    }
+}
+
+fn device_stop_strategy_for(explicit_zero_stop_device_addresses: &[String], device: &ButtplugClientDevice) -> DeviceStopStrategy {
+    let address = device_address(device);
+    if explicit_zero_stop_device_addresses.contains(&address) {
+        DeviceStopStrategy::ExplicitZero
+    } else {
+        DeviceStopStrategy::StopCommand
+    }
+}
+
+// Останавливает устройство выбранным способом. У линейных приводов нулевой скорости нет
+// (команда задает положение), поэтому при явном обнулении для них все равно отправляется stop().
+async fn stop_device_with_strategy(
+    device: &ButtplugClientDevice,
+    capabilities: &DeviceCapabilities,
+    stop_strategy: DeviceStopStrategy,
+) -> Result<(), ButtplugClientError> {
+    if stop_strategy == DeviceStopStrategy::StopCommand {
+        return device.stop().await;
+    }
+    if !capabilities.scalar_actuators.is_empty() {
+        let zero_speeds = capabilities.scalar_actuators.iter()
+            .map(|actuator| (actuator.index, (0.0, actuator.actuator_type)))
+            .collect();
+        device.scalar(&ScalarCommand::ScalarMap(zero_speeds)).await?;
+    }
+    let rotate_actuator_count = device.message_attributes().rotate_cmd().as_ref().map_or(0, |rotate_attributes| rotate_attributes.len());
+    if rotate_actuator_count > 0 {
+        device.rotate(&RotateCommand::RotateVec(vec![(0.0, true); rotate_actuator_count])).await?;
+    }
+    if device.message_attributes().linear_cmd().is_some() {
+        device.stop().await?;
+    }
+    Ok(())
+}
+
+// Шаг плавной остановки: как часто снижаем скорость
+const FADE_OUT_STEP_INTERVAL: Duration = Duration::from_millis(50);
+
+// Плавная остановка: скорость ступенями снижается от start_speed до нуля за fade_duration, затем остановка.
+// Задачу прерывают (abort), если на устройство пришла новая команда.
+fn spawn_fade_out_stop(
+    device: Arc<ButtplugClientDevice>,
+    capabilities: DeviceCapabilities,
+    stop_strategy: DeviceStopStrategy,
+    start_speed: f64,
+    fade_duration: Duration,
+    error_sender: mpsc::Sender<UpdateFromAsyncTasks>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let step_count = (fade_duration.as_millis() / FADE_OUT_STEP_INTERVAL.as_millis()).max(1) as u32;
+        let step_interval = fade_duration / step_count;
+        for step in 1..step_count {
+            let faded_speed = start_speed * (1.0 - step as f64 / step_count as f64);
+            if let Some(fade_command) = build_vibration_command(&device, &capabilities, faded_speed, &[]) {
+                if let Err(fade_error) = device.scalar(&fade_command).await {
+                    tracing::error!("Ошибка ScalarCmd при плавной остановке {}: {:?}", device.name(), fade_error);
+                    break; // Ниже все равно пробуем остановить
+                }
+                report_device_output(&error_sender, device.index(), vibration_output_intensity(&capabilities, faded_speed, &[]));
+            }
+            tokio::time::sleep(step_interval).await;
+        }
+        match stop_device_with_strategy(&device, &capabilities, stop_strategy).await {
+            Ok(()) => report_device_output(&error_sender, device.index(), 0.0),
+            Err(stop_error) => {
+                tracing::error!("Ошибка при остановке {}: {:?}", device.name(), stop_error);
+                report_device_command_error(&error_sender, &device, "Stop", stop_error).await;
+            }
+        }
+    })
+}
+
+// Версия библиотеки buttplug для отчетов (держать в соответствии с Cargo.toml)
+const BUTTPLUG_LIBRARY_VERSION: &str = "9.0.8";
+
+// Отчет о подключенных устройствах для обращений в поддержку: имена, адреса, приводы
+fn build_device_report(
+    connected_devices: &[Arc<ButtplugClientDevice>],
+    capabilities_cache: &mut CapabilitiesCache,
+) -> serde_json::Value {
+    let devices: Vec<serde_json::Value> = connected_devices.iter()
+        .map(|device| {
+            let capabilities = cached_capabilities(capabilities_cache, device);
+            let vibration_protocol = capabilities.vibration_protocol();
+            let actuators: Vec<serde_json::Value> = capabilities.scalar_actuators.iter()
+                .map(|actuator| serde_json::json!({
+                    "index": actuator.index,
+                    "actuator_type": format!("{:?}", actuator.actuator_type),
+                    "step_count": actuator.step_count,
+                }))
+                .collect();
+            serde_json::json!({
+                "name": device.name(),
+                "address": device_address(device),
+                "buttplug_index": device.index(),
+                "scalar_actuators": actuators,
+                "vibration_protocol": format!("{:?}", vibration_protocol),
+            })
+        })
+        .collect();
+    let generated_at_unix_seconds = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    serde_json::json!({
+        "application": format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+        "buttplug_library_version": BUTTPLUG_LIBRARY_VERSION,
+        "generated_at_unix_seconds": generated_at_unix_seconds,
+        "devices": devices,
+    })
+}
+
+// Шаги последовательности выполняются по очереди; в конце устройство останавливается
+// Серия подряд идущих ненулевых шагов короче minimum_on_milliseconds продлевается за счет последнего шага серии.
+// Паузы не сокращаются: короткий сигнал важнее точного ритма.
+fn apply_minimum_on_time(mut steps: Vec<(f64, u64)>, minimum_on_milliseconds: u64) -> Vec<(f64, u64)> {
+    if minimum_on_milliseconds == 0 {
+        return steps;
+    }
+    let mut on_run_duration_milliseconds: Option<u64> = None;
+    for step_index in 0..=steps.len() {
+        match steps.get(step_index) {
+            Some((intensity, duration_milliseconds)) if *intensity > 0.0 => {
+                *on_run_duration_milliseconds.get_or_insert(0) += duration_milliseconds;
+            }
+            _ => {
+                if let Some(run_duration_milliseconds) = on_run_duration_milliseconds.take()
+                    && run_duration_milliseconds < minimum_on_milliseconds
+                {
+                    steps[step_index - 1].1 += minimum_on_milliseconds - run_duration_milliseconds;
+                }
+            }
+        }
+    }
+    steps
+}
+
+fn minimum_on_milliseconds_for(device_minimum_on_times: &[DeviceMinimumOnTimeSetting], device: &ButtplugClientDevice) -> u64 {
+    let address = device_address(device);
+    device_minimum_on_times.iter()
+        .find(|minimum_on_time| minimum_on_time.device_address == address)
+        .map_or(0, |minimum_on_time| minimum_on_time.minimum_on_milliseconds)
+}
+
+// Сколько держать ограниченную по времени вибрацию до остановки: короткий эффект
+// продлевается до минимального времени включения устройства, как шаги последовательности
+fn timed_vibration_hold_duration(duration_milliseconds: u64, minimum_on_milliseconds: u64) -> Duration {
+    Duration::from_millis(duration_milliseconds.max(minimum_on_milliseconds))
+}
+
+fn spawn_test_sequence(
+    device: Arc<ButtplugClientDevice>,
+    capabilities: DeviceCapabilities,
+    stop_strategy: DeviceStopStrategy,
+    steps: Vec<(f64, u64)>,
+    error_sender: mpsc::Sender<UpdateFromAsyncTasks>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        for (intensity, duration_milliseconds) in steps {
+            let step_intensity = intensity.clamp(0.0, 1.0);
+            let step_result = if step_intensity <= 0.0 {
+                stop_device_with_strategy(&device, &capabilities, stop_strategy).await
+            } else if let Some(step_command) = build_vibration_command(&device, &capabilities, step_intensity, &[]) {
+                device.scalar(&step_command).await
+            } else {
+                return; // Вибраторов нет - build_vibration_command уже предупредил
+            };
+            if let Err(step_error) = step_result {
+                tracing::error!("Ошибка тестовой последовательности для {}: {:?}", device.name(), step_error);
+                report_device_command_error(&error_sender, &device, "TestSequence", step_error).await;
+                return;
+            }
+            report_device_output(&error_sender, device.index(), vibration_output_intensity(&capabilities, step_intensity, &[]));
+            tokio::time::sleep(Duration::from_millis(duration_milliseconds)).await;
+        }
+        match stop_device_with_strategy(&device, &capabilities, stop_strategy).await {
+            Ok(()) => report_device_output(&error_sender, device.index(), 0.0),
+            Err(stop_error) => report_device_command_error(&error_sender, &device, "Stop", stop_error).await,
+        }
+    })
+}
+
+// Прогрев: нулевая команда и пауза, после чего устройство считается готовым (ButtplugDeviceReady).
+// connect_pulse_step - импульс подтверждения (интенсивность, мс) в конце прогрева, если разрешен.
+fn spawn_device_warmup(
+    warming_device: Arc<ButtplugClientDevice>,
+    capabilities: DeviceCapabilities,
+    connect_pulse_step: Option<(f64, u64)>,
+    warmup_duration: Duration,
+    ready_sender: mpsc::Sender<UpdateFromAsyncTasks>,
+) {
+    tokio::spawn(async move {
+        if let Err(probe_error) = warming_device.stop().await {
+            tracing::warn!("Пробная команда для {} не прошла: {:?}", warming_device.name(), probe_error);
+        }
+        tokio::time::sleep(warmup_duration).await;
+        if let Some((pulse_intensity, pulse_duration_milliseconds)) = connect_pulse_step
+            && let Some(pulse_command) = build_vibration_command(&warming_device, &capabilities, pulse_intensity, &[])
+        {
+            if let Err(pulse_error) = warming_device.scalar(&pulse_command).await {
+                tracing::warn!("Импульс подключения для {} не прошел: {:?}", warming_device.name(), pulse_error);
+            }
+            tokio::time::sleep(Duration::from_millis(pulse_duration_milliseconds)).await;
+            let _ = warming_device.stop().await;
+        }
+        let _ = ready_sender.send(UpdateFromAsyncTasks::ButtplugDeviceReady(warming_device.index())).await;
+    });
+}
+
+// Индекс устройства Buttplug. Поиск по индексу написан через этот трейт, чтобы его можно было
+// проверить без живого клиента.
+pub trait ButtplugDeviceIndex {
+    fn buttplug_index(&self) -> u32;
+}
+
+impl ButtplugDeviceIndex for ButtplugClientDevice {
+    fn buttplug_index(&self) -> u32 {
+        self.index()
+    }
+}
+
+impl<D: ButtplugDeviceIndex> ButtplugDeviceIndex for Arc<D> {
+    fn buttplug_index(&self) -> u32 {
+        (**self).buttplug_index()
+    }
+}
+
+// Находит устройство по индексу Buttplug. Позиция в connected_devices для адресации не используется:
+// список пополняется в порядке событий DeviceAdded и не обязан совпадать со списком в GUI.
+fn find_connected_device<D: ButtplugDeviceIndex + Clone>(connected_devices: &[D], device_index: u32) -> Option<D> {
+    connected_devices.iter().find(|device| device.buttplug_index() == device_index).cloned()
+}
+
+// Индекс Buttplug устройства на позиции списка GUI
+pub fn buttplug_index_at<D: ButtplugDeviceIndex>(devices: &[D], position: Option<usize>) -> Option<u32> {
+    position.and_then(|position| devices.get(position)).map(ButtplugDeviceIndex::buttplug_index)
+}
+
+// Позиция устройства в списке GUI по индексу Buttplug (после удаления или перестановки позиции сдвигаются)
+pub fn position_of_buttplug_index<D: ButtplugDeviceIndex>(devices: &[D], device_index: u32) -> Option<usize> {
+    devices.iter().position(|device| device.buttplug_index() == device_index)
+}
+
+// Находит подключенные устройства группы. Отключившиеся участники пропускаются с предупреждением.
+fn resolve_group_members(
+    connected_devices: &[Arc<ButtplugClientDevice>],
+    member_addresses: &[String],
+) -> Vec<Arc<ButtplugClientDevice>> {
+    let mut group_devices = Vec::new();
+    for member_address in member_addresses {
+        let mut matching_devices = connected_devices.iter()
+            .filter(|device| device_address(device) == *member_address)
+            .peekable();
+        if matching_devices.peek().is_none() {
+            tracing::warn!("Участник группы '{}' не подключен, пропускаем.", member_address);
+        }
+        group_devices.extend(matching_devices.cloned());
+    }
+    group_devices
+}
+
+// Ошибки команд, выполняемых в отдельных задачах, иначе видны только в tracing-логе
+// Пауза перед повтором команды после временной ошибки
+const COMMAND_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+// Временная ошибка - сбой обмена с устройством (BLE). Потеря соединения с сервером
+// или с устройством не повторяется: повтор все равно не пройдет.
+fn is_transient_command_error(command_error: &ButtplugClientError) -> bool {
+    matches!(
+        command_error,
+        ButtplugClientError::ButtplugError(ButtplugError::ButtplugDeviceError(ButtplugDeviceError::DeviceCommunicationError(_)))
+    )
+}
+
+// Выполняет команду устройству с ограниченным числом повторов при временных ошибках
+async fn run_device_command_with_retry<CommandFuture>(
+    device_name: &str,
+    retry_count: u32,
+    mut send_command: impl FnMut() -> CommandFuture,
+) -> Result<(), ButtplugClientError>
+where
+    CommandFuture: std::future::Future<Output = Result<(), ButtplugClientError>>,
+{
+    let mut attempt_number = 0;
+    loop {
+        match send_command().await {
+            Err(command_error) if attempt_number < retry_count && is_transient_command_error(&command_error) => {
+                attempt_number += 1;
+                tracing::warn!("Временная ошибка команды для {} ({:?}), повтор {}/{}", device_name, command_error, attempt_number, retry_count);
+                tokio::time::sleep(COMMAND_RETRY_DELAY).await;
+            }
+            command_result => return command_result,
+        }
+    }
+}
+
+async fn report_device_command_error(
+    error_sender: &mpsc::Sender<UpdateFromAsyncTasks>,
+    device: &ButtplugClientDevice,
+    command_name: &str,
+    command_error: impl std::fmt::Display,
+) {
+    let _ = error_sender.send(UpdateFromAsyncTasks::ButtplugDeviceError {
+        device_address: device_address(device),
+        message: format!("Команда {} для '{}' не выполнена: {}", command_name, device.name(), command_error),
+    }).await;
+}
+
+// Подписывается на все датчики устройства с поддержкой подписки и пересылает показания в GUI.
+// Возвращает задачу пересылки (None - подписываться не на что).
+async fn subscribe_device_sensors(
+    device: Arc<ButtplugClientDevice>,
+    to_gui_sender: mpsc::Sender<UpdateFromAsyncTasks>,
+) -> Option<JoinHandle<()>> {
+    let subscribable_sensors = device.message_attributes().sensor_subscribe_cmd().clone().unwrap_or_default();
+    if subscribable_sensors.is_empty() {
+        if device.message_attributes().sensor_read_cmd().as_ref().is_some_and(|sensors| !sensors.is_empty()) {
+            tracing::info!("Устройство {} поддерживает только чтение датчиков, подписка невозможна.", device.name());
+        }
+        let _ = to_gui_sender.send(UpdateFromAsyncTasks::LogMessage { category: LogCategory::Buttplug, device_address: Some(device_address(&device)), message: format!("Устройство '{}' не поддерживает подписку на датчики.", device.name()) }).await;
+        return None;
+    }
+
+    // Поток событий берем до подписки, чтобы не пропустить первые показания
+    let mut device_event_stream = device.event_stream();
+    for sensor in &subscribable_sensors {
+        if let Err(subscribe_error) = device.subscribe_sensor(*sensor.index(), *sensor.sensor_type()).await {
+            report_device_command_error(&to_gui_sender, &device, "SensorSubscribe", subscribe_error).await;
+        }
+    }
+
+    let device_index = device.index();
+    Some(tokio::spawn(async move {
+        while let Some(device_event) = device_event_stream.next().await {
+            match device_event {
+                ButtplugClientDeviceEvent::Message(ButtplugServerMessageV3::SensorReading(sensor_reading)) => {
+                    let _ = to_gui_sender.send(UpdateFromAsyncTasks::ButtplugSensorReading {
+                        device_index,
+                        sensor_type: format!("{:?}", sensor_reading.sensor_type()),
+                        values: sensor_reading.data().clone(),
+                    }).await;
+                }
+                ButtplugClientDeviceEvent::DeviceRemoved | ButtplugClientDeviceEvent::ClientDisconnect => break,
+                _ => {}
+            }
+        }
+    }))
+}
+
+async fn unsubscribe_device_sensors(device: &ButtplugClientDevice) {
+    for sensor in device.message_attributes().sensor_subscribe_cmd().iter().flatten() {
+        if let Err(unsubscribe_error) = device.unsubscribe_sensor(*sensor.index(), *sensor.sensor_type()).await {
+            tracing::warn!("Не удалось отписаться от датчика {} ({}): {:?}", sensor.index(), device.name(), unsubscribe_error);
+        }
+    }
+}
+
+// Следующее событие клиента. Без потока событий (клиент не подключен) - ждем вечно,
+// чтобы tokio::select! просто обслуживал команды.
+async fn next_client_event(
+    optional_event_stream: &mut Option<BoxStream<'static, ButtplugClientEvent>>,
+) -> Option<ButtplugClientEvent> {
+    match optional_event_stream.as_mut() {
+        Some(event_stream) => event_stream.next().await,
+        None => std::future::pending().await,
+    }
+}
+
+// Следующая команда от GUI. Истекшая длительность вибрации приходит отсюда же как StopDevice,
+// чтобы остановка шла общим путем. None - канал команд закрыт.
+async fn next_command(
+    from_gui_receiver: &mut mpsc::Receiver<CommandToAsyncTasks>,
+    scheduled_device_tasks: &mut ScheduledDeviceTasks,
+) -> Option<CommandToAsyncTasks> {
+    let next_timed_stop = scheduled_device_tasks.next_timed_stop();
+    tokio::select! {
+        biased;
+
+        optional_command_from_gui = from_gui_receiver.recv() => optional_command_from_gui,
+        device_index = wait_for_timed_stop(next_timed_stop) => {
+            scheduled_device_tasks.take_timed_stop(device_index);
+            Some(CommandToAsyncTasks::StopDevice(device_index))
+        }
+    }
+}
+
+// Ожидание ближайшей остановки по времени. Без них - ждем вечно.
+async fn wait_for_timed_stop(next_timed_stop: Option<(u32, tokio::time::Instant)>) -> u32 {
+    match next_timed_stop {
+        Some((device_index, stop_deadline)) => {
+            tokio::time::sleep_until(stop_deadline).await;
+            device_index
+        }
+        None => std::future::pending().await,
+    }
+}
+
+// Ожидание конца сканирования по таймауту. Без активного сканирования - ждем вечно.
+async fn wait_for_scan_deadline(scan_deadline: Option<tokio::time::Instant>) {
+    match scan_deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+// "ws://127.0.0.1:12345/path" -> "127.0.0.1:12345". None - адрес некорректен.
+pub fn websocket_host_and_port(server_address: &str) -> Option<String> {
+    let without_scheme = server_address
+        .strip_prefix("ws://")
+        .or_else(|| server_address.strip_prefix("wss://"))?;
+    let host_and_port = without_scheme.split('/').next()?.trim();
+    if host_and_port.is_empty() {
+        return None;
+    }
+    if let Some((host, port)) = host_and_port.rsplit_once(':') {
+        if host.is_empty() || port.parse::<u16>().is_err() {
+            return None;
+        }
+        Some(host_and_port.to_string())
+    } else {
+        let default_port = if server_address.starts_with("wss://") { 443 } else { 80 };
+        Some(format!("{}:{}", host_and_port, default_port))
+    }
+}
+
+// Коннектор встроенного сервера. ButtplugInProcessClientConnector::default() в buttplug 9
+// создается без сервера и паникует, поэтому сервер собираем сами.
+fn new_in_process_connector() -> Result<ButtplugInProcessClientConnector, String> {
+    let server = ButtplugServerBuilder::default()
+        .finish()
+        .map_err(|server_error| format!("Не удалось создать встроенный сервер Buttplug: {}", server_error))?;
+    Ok(ButtplugInProcessClientConnectorBuilder::default().server(server).finish())
+}
+
+// Быстрая проверка, что на адресе сервера кто-то слушает. Без нее мертвый адрес дает
+// малопонятную ошибку рукопожатия WebSocket.
+async fn probe_server_reachable(server_address: &str) -> Result<(), String> {
+    let host_and_port = websocket_host_and_port(server_address)
+        .ok_or_else(|| format!("Некорректный адрес сервера Buttplug: '{}'. Ожидается вида ws://127.0.0.1:12345", server_address))?;
+    match tokio::time::timeout(SERVER_PROBE_TIMEOUT, tokio::net::TcpStream::connect(&host_and_port)).await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(_)) | Err(_) => Err(format!(
+            "Intiface не обнаружен на {}. Запустите Intiface Central и нажмите \"Start Server\", затем повторите сканирование.",
+            host_and_port
+        )),
+    }
+}
+
+// Сколько ждем рукопожатия при проверке адреса сервера
+const SERVER_ADDRESS_TEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Проверка адреса: отдельный клиент подключается и сразу отключается.
+// Intiface обычно принимает только одного клиента, поэтому при активном основном подключении
+// к тому же серверу проверка закончится ошибкой.
+async fn test_server_address(server_address: &str) -> Result<(), String> {
+    probe_server_reachable(server_address).await?;
+    let test_client = ButtplugClient::new("WarThunder Haptics GUI (проверка адреса)");
+    match tokio::time::timeout(SERVER_ADDRESS_TEST_TIMEOUT, test_client.connect(new_json_ws_client_connector(server_address))).await {
+        Ok(Ok(_)) => {
+            if let Err(disconnect_error) = test_client.disconnect().await {
+                tracing::warn!("Ошибка отключения проверочного клиента: {:?}", disconnect_error);
+            }
+            Ok(())
+        }
+        Ok(Err(connection_error)) => Err(format!("Сервер отклонил подключение: {}", connection_error)),
+        Err(_) => Err(format!("Нет ответа Buttplug за {} с", SERVER_ADDRESS_TEST_TIMEOUT.as_secs())),
+    }
+}
+
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+// Сколько при завершении ждать места в канале GUI для ButtplugDisconnected.
+// GUI при выходе может уже не читать канал - тогда не зависаем на нем.
+const SHUTDOWN_NOTIFICATION_TIMEOUT: Duration = Duration::from_secs(1);
+
+// Отключение клиента (завершение задачи, простой). Ошибка только в журнал: клиент все равно отбрасывается.
+async fn disconnect_client(client_instance: &ButtplugClient) {
+    if client_instance.connected()
+        && let Err(disconnect_error) = client_instance.disconnect().await
+    {
+        tracing::error!("Ошибка при отключении от Buttplug: {:?}", disconnect_error);
+    }
+}
+
+// Команды, которые считаются использованием устройств: сбрасывают отсчет простоя и будят соединение,
+// отключенное по простою. Остановки не в счет - после них устройства и так простаивают.
+fn is_device_activity_command(command: &CommandToAsyncTasks) -> bool {
+    matches!(
+        command,
+        CommandToAsyncTasks::ScanForButtplugDevices
+            | CommandToAsyncTasks::StartProcessing
+            | CommandToAsyncTasks::VibrateDevice { .. }
+            | CommandToAsyncTasks::VibrateGroup { .. }
+            | CommandToAsyncTasks::PlayTestSequence { .. }
+            | CommandToAsyncTasks::SetDeviceSensorSubscription { .. }
+            | CommandToAsyncTasks::RawWriteDevice { .. }
+            | CommandToAsyncTasks::RawReadDevice { .. }
+    )
+}
+
+// Подключение (если клиента еще нет) и запуск сканирования: команда сканирования и переподключение после простоя
+async fn connect_and_start_scanning(
+    optional_client: &mut Option<ButtplugClient>,
+    optional_event_stream: &mut Option<BoxStream<'static, ButtplugClientEvent>>,
+    scan_deadline: &mut Option<tokio::time::Instant>,
+    buttplug_connector_kind: ButtplugConnectorKind,
+    buttplug_server_address: &str,
+    scan_timeout_seconds: u64,
+    to_gui_sender: &mpsc::Sender<UpdateFromAsyncTasks>,
+) {
+    if optional_client.is_none() {
+        tracing::info!("Клиент Buttplug не инициализирован. Попытка создания и подключения ({:?})...", buttplug_connector_kind);
+        if buttplug_connector_kind == ButtplugConnectorKind::WebSocket
+            && let Err(probe_error) = probe_server_reachable(buttplug_server_address).await
+        {
+            tracing::warn!("{}", probe_error);
+            let _ = to_gui_sender.send(UpdateFromAsyncTasks::ButtplugError(probe_error)).await;
+            let _ = to_gui_sender.send(UpdateFromAsyncTasks::ButtplugDisconnected).await;
+            return;
+        }
+        let new_client = ButtplugClient::new("WarThunder Haptics GUI");
+        // Поток берем до подключения, чтобы не пропустить ранние DeviceAdded
+        let new_client_event_stream = new_client.event_stream().boxed();
+        let connection_result = match buttplug_connector_kind {
+            ButtplugConnectorKind::InProcess => match new_in_process_connector() {
+                Ok(in_process_connector) => new_client.connect(in_process_connector).await.map_err(|client_error| client_error.to_string()),
+                Err(server_error) => Err(server_error),
+            },
+            ButtplugConnectorKind::WebSocket => new_client.connect(new_json_ws_client_connector(buttplug_server_address)).await.map_err(|client_error| client_error.to_string()),
+        };
+        match connection_result {
+            Ok(_) => {
+                *optional_client = Some(new_client);
+                *optional_event_stream = Some(new_client_event_stream);
+                let _ = to_gui_sender.send(UpdateFromAsyncTasks::ButtplugConnected).await;
+                let _ = to_gui_sender.send(UpdateFromAsyncTasks::LogMessage { category: LogCategory::Buttplug, device_address: None, message: format!("Успешно подключено к Buttplug ({:?}).", buttplug_connector_kind) }).await;
+            }
+            Err(connection_error) => {
+                tracing::error!("Не удалось подключиться к Buttplug ({:?}): {:?}", buttplug_connector_kind, connection_error);
+                let _ = to_gui_sender.send(UpdateFromAsyncTasks::ButtplugError(format!("Ошибка подключения {:?}: {}", buttplug_connector_kind, connection_error))).await;
+                let _ = to_gui_sender.send(UpdateFromAsyncTasks::ButtplugDisconnected).await;
+                *optional_client = None;
+                return;
+            }
+        }
+    }
+
+    if let Some(client_ref) = optional_client.as_ref() {
+        if client_ref.connected() {
+            tracing::info!("Начинаем сканирование устройств Buttplug...");
+            if let Err(scan_error) = client_ref.start_scanning().await {
+                tracing::error!("Ошибка при старте сканирования: {:?}", scan_error);
+                let _ = to_gui_sender.send(UpdateFromAsyncTasks::ButtplugError(format!("Ошибка сканирования: {}", scan_error))).await;
+            } else {
+                if scan_timeout_seconds > 0 {
+                    *scan_deadline = Some(tokio::time::Instant::now() + Duration::from_secs(scan_timeout_seconds));
+                }
+                let _ = to_gui_sender.send(UpdateFromAsyncTasks::LogMessage { category: LogCategory::Buttplug, device_address: None, message: "Сканирование устройств Buttplug запущено.".to_string() }).await;
+            }
+        } else {
+            tracing::warn!("Клиент Buttplug не подключен. Сканирование невозможно.");
+            *optional_client = None;
+            *optional_event_stream = None;
+            let _ = to_gui_sender.send(UpdateFromAsyncTasks::ButtplugDisconnected).await;
+        }
+    }
+}
+
+// Сканирование для --list-devices: короткое, без импульса подключения
+const LIST_DEVICES_SCAN_TIMEOUT_SECONDS: u64 = 5;
+
+// Для --list-devices: обычная задача Buttplug со своими каналами сканирует по настройкам (коннектор, адрес)
+// и возвращает отчет о найденных устройствах в JSON - том же формате, что и экспорт отчета из GUI
+pub async fn scan_and_build_device_report(mut settings: ApplicationSettings) -> Result<String, String> {
+    settings.scan_timeout_seconds = LIST_DEVICES_SCAN_TIMEOUT_SECONDS;
+    settings.connect_pulse.enabled = false;
+    let (update_sender, mut update_receiver) = mpsc::channel::<UpdateFromAsyncTasks>(100);
+    let (command_sender, command_receiver) = mpsc::channel::<CommandToAsyncTasks>(8);
+    let service_task = tokio::spawn(run_buttplug_service_loop(update_sender, command_receiver));
+    let _ = command_sender.send(CommandToAsyncTasks::UpdateApplicationSettings(Box::new(settings))).await;
+    let _ = command_sender.send(CommandToAsyncTasks::ScanForButtplugDevices).await;
+
+    // Запас сверх таймаута сканирования - на подключение к серверу
+    let scan_deadline = tokio::time::Instant::now() + Duration::from_secs(LIST_DEVICES_SCAN_TIMEOUT_SECONDS + 5);
+    let mut found_devices: Vec<Arc<ButtplugClientDevice>> = Vec::new();
+    let scan_result = loop {
+        match tokio::time::timeout_at(scan_deadline, update_receiver.recv()).await {
+            Ok(Some(UpdateFromAsyncTasks::ButtplugDeviceFound(ClonableButtplugClientDevice(device)))) => {
+                if !found_devices.iter().any(|found_device| found_device.index() == device.index()) {
+                    found_devices.push(device);
+                }
+            }
+            Ok(Some(UpdateFromAsyncTasks::ButtplugDeviceLost(ClonableButtplugClientDevice(device)))) => {
+                found_devices.retain(|found_device| found_device.index() != device.index());
+            }
+            Ok(Some(UpdateFromAsyncTasks::ButtplugScanFinished { .. })) => break Ok(()),
+            Ok(Some(UpdateFromAsyncTasks::ButtplugError(message))) => break Err(message),
+            Ok(Some(UpdateFromAsyncTasks::ButtplugDisconnected)) => break Err("Соединение с Buttplug разорвано во время сканирования".to_string()),
+            Ok(Some(_)) => {}
+            Ok(None) => break Err("Задача Buttplug неожиданно завершилась".to_string()),
+            Err(_) => break Err("Сканирование не завершилось вовремя".to_string()),
+        }
+    };
+    let device_report = build_device_report(&found_devices, &mut CapabilitiesCache::default());
+
+    // Закрытие канала команд останавливает задачу и отключает клиента
+    drop(command_sender);
+    drop(update_receiver);
+    let _ = tokio::time::timeout(Duration::from_secs(2), service_task).await;
+    scan_result?;
+    serde_json::to_string_pretty(&device_report).map_err(|e| format!("Ошибка сериализации отчета об устройствах: {}", e))
+}
 
 pub async fn run_buttplug_service_loop(
     to_gui_sender: mpsc::Sender<UpdateFromAsyncTasks>,
@@ -18,100 +965,244 @@ pub async fn run_buttplug_service_loop(
 ) {
     let mut optional_client: Option<ButtplugClient> = None;
     let mut connected_devices: Vec<Arc<ButtplugClientDevice>> = Vec::new();
+    // Поток событий текущего клиента; берется заново при каждом новом подключении
+    let mut optional_event_stream: Option<BoxStream<'static, ButtplugClientEvent>> = None;
+    let mut device_warmup_milliseconds = ApplicationSettings::default().device_warmup_milliseconds;
+    let mut buttplug_connector_kind = ApplicationSettings::default().buttplug_connector_kind;
+    let mut buttplug_server_address = ApplicationSettings::default().buttplug_server_address;
+    // Задачи пересылки показаний датчиков по индексу BP устройства
+    let mut sensor_forwarding_tasks: HashMap<u32, JoinHandle<()>> = HashMap::new();
+    // Последняя отправленная скорость по индексу BP; в GUI уходит периодически и только при изменениях
+    let mut last_commanded_speeds: HashMap<u32, f64> = HashMap::new();
+    let mut are_commanded_speeds_changed = false;
+    let mut device_state_report_interval = tokio::time::interval(tokio::time::Duration::from_millis(250));
+    let mut capabilities_cache = CapabilitiesCache::default();
+    let mut stop_fade_milliseconds = ApplicationSettings::default().stop_fade_milliseconds;
+    let mut intensity_floor = ApplicationSettings::default().intensity_floor;
+    let mut scheduled_device_tasks = ScheduledDeviceTasks::default();
+    let mut is_output_muted = false;
+    let mut is_output_armed = true;
+    let mut connect_pulse = ApplicationSettings::default().connect_pulse;
+    let mut device_filter = ApplicationSettings::default().device_filter;
+    let mut scan_timeout_seconds = ApplicationSettings::default().scan_timeout_seconds;
+    let mut scan_deadline: Option<tokio::time::Instant> = None;
+    let command_latencies: CommandLatencyWindow = Arc::default();
+    let mut device_command_retry_count = ApplicationSettings::default().device_command_retry_count;
+    let mut is_verbose_command_logging_enabled = false;
+    let mut device_minimum_on_times: Vec<DeviceMinimumOnTimeSetting> = Vec::new();
+    let mut explicit_zero_stop_device_addresses: Vec<String> = Vec::new();
+    let mut is_metrics_reporting_enabled = false;
+    let mut metrics_report_interval = tokio::time::interval(METRICS_REPORT_INTERVAL);
+    // Отключение при простое: нет команд устройствам и обработка WT выключена.
+    // После такого отключения следующая команда устройству подключает заново.
+    let mut idle_disconnect_minutes = ApplicationSettings::default().buttplug_idle_disconnect_minutes;
+    let mut last_device_activity_at = tokio::time::Instant::now();
+    let mut is_processing_enabled = false;
+    let mut is_idle_disconnected = false;
+    let mut idle_check_interval = tokio::time::interval(IDLE_CHECK_INTERVAL);
 
     loop {
         tokio::select! {
             biased;
 
-            Some(command_from_gui) = from_gui_receiver.recv() => {
+            optional_command_from_gui = next_command(&mut from_gui_receiver, &mut scheduled_device_tasks) => {
+                let Some(command_from_gui) = optional_command_from_gui else {
+                    tracing::info!("Цикл Buttplug сервиса завершается (канал команд закрыт).");
+                    sensor_forwarding_tasks.drain().for_each(|(_, forwarding_task)| forwarding_task.abort());
+                    scheduled_device_tasks.cancel_all();
+                    if let Some(client_instance) = optional_client.take() {
+                        disconnect_client(&client_instance).await;
+                    }
+                    let _ = tokio::time::timeout(SHUTDOWN_NOTIFICATION_TIMEOUT, to_gui_sender.send(UpdateFromAsyncTasks::ButtplugDisconnected)).await;
+                    break;
+                };
+                if is_device_activity_command(&command_from_gui) {
+                    last_device_activity_at = tokio::time::Instant::now();
+                    if std::mem::take(&mut is_idle_disconnected) && optional_client.is_none()
+                        && !matches!(command_from_gui, CommandToAsyncTasks::ScanForButtplugDevices)
+                    {
+                        let _ = to_gui_sender.send(UpdateFromAsyncTasks::LogMessage { category: LogCategory::Buttplug, device_address: None, message: "Переподключение к Buttplug после простоя...".to_string() }).await;
+                        connect_and_start_scanning(
+                            &mut optional_client,
+                            &mut optional_event_stream,
+                            &mut scan_deadline,
+                            buttplug_connector_kind,
+                            &buttplug_server_address,
+                            scan_timeout_seconds,
+                            &to_gui_sender,
+                        ).await;
+                    }
+                }
                 match command_from_gui {
                     CommandToAsyncTasks::ScanForButtplugDevices => {
-                        if optional_client.is_none() {
-                            tracing::info!("Клиент Buttplug не инициализирован. Попытка создания и подключения (InProcess)...");
-                            let new_client = ButtplugClient::new("WarThunder Haptics GUI");
-                            match new_client.connect(ButtplugInProcessClientConnector::default()).await {
-                                Ok(_) => {
-                                    optional_client = Some(new_client);
-                                    let _ = to_gui_sender.send(UpdateFromAsyncTasks::ButtplugConnected).await;
-                                    let _ = to_gui_sender.send(UpdateFromAsyncTasks::LogMessage("Успешно подключено к Buttplug (InProcess).".to_string())).await;
-                                }
-                                Err(connection_error) => {
-                                    tracing::error!("Не удалось подключиться к InProcess: {:?}", connection_error);
-                                    let _ = to_gui_sender.send(UpdateFromAsyncTasks::ButtplugError(format!("Ошибка подключения InProcess: {}", connection_error))).await;
-                                    let _ = to_gui_sender.send(UpdateFromAsyncTasks::ButtplugDisconnected).await;
-                                    optional_client = None;
-                                    continue;
-                                }
-                            }
-                        }
+                        connect_and_start_scanning(
+                            &mut optional_client,
+                            &mut optional_event_stream,
+                            &mut scan_deadline,
+                            buttplug_connector_kind,
+                            &buttplug_server_address,
+                            scan_timeout_seconds,
+                            &to_gui_sender,
+                        ).await;
+                    }
 
-                        if let Some(client_ref) = optional_client.as_ref() {
-                            if client_ref.connected() {
-                                tracing::info!("Начинаем сканирование устройств Buttplug...");
-                                if let Err(scan_error) = client_ref.start_scanning().await {
-                                    tracing::error!("Ошибка при старте сканирования: {:?}", scan_error);
-                                    let _ = to_gui_sender.send(UpdateFromAsyncTasks::ButtplugError(format!("Ошибка сканирования: {}", scan_error))).await;
-                                } else {
-                                    let _ = to_gui_sender.send(UpdateFromAsyncTasks::LogMessage("Сканирование устройств Buttplug запущено.".to_string())).await;
-                                }
-                            } else {
-                                tracing::warn!("Клиент Buttplug не подключен. Сканирование невозможно.");
-                                optional_client = None;
-                                let _ = to_gui_sender.send(UpdateFromAsyncTasks::ButtplugDisconnected).await;
-                            }
+                    CommandToAsyncTasks::VibrateDevice { device_index, speed, .. } if is_output_muted => {
+                        tracing::debug!("Вывод отключен: вибрация устройства {} со скоростью {} пропущена.", device_index, speed);
+                    }
+                    CommandToAsyncTasks::VibrateGroup { speed, .. } if is_output_muted => {
+                        tracing::debug!("Вывод отключен: вибрация группы со скоростью {} пропущена.", speed);
+                    }
+                    CommandToAsyncTasks::PlayTestSequence { .. } if is_output_muted => {
+                        tracing::debug!("Вывод отключен: тестовая последовательность пропущена.");
+                    }
+                    CommandToAsyncTasks::PlayTestSequence { device_index, steps } => {
+                        if !optional_client.as_ref().is_some_and(|client_ref| client_ref.connected()) {
+                            tracing::warn!("Клиент Buttplug не подключен для PlayTestSequence.");
+                            continue;
+                        }
+                        let Some(device) = find_connected_device(&connected_devices, device_index) else {
+                            tracing::warn!("Устройство с индексом BP {} не найдено для тестовой последовательности.", device_index);
+                            continue;
+                        };
+                        scheduled_device_tasks.cancel(device.index());
+                        let steps = steps.into_iter()
+                            .map(|(step_speed, step_milliseconds)| (lift_to_intensity_floor(step_speed, intensity_floor), step_milliseconds))
+                            .collect();
+                        let steps = apply_minimum_on_time(steps, minimum_on_milliseconds_for(&device_minimum_on_times, &device));
+                        tracing::info!("Тестовая последовательность из {} шагов на '{}'", steps.len(), device.name());
+                        let capabilities = cached_capabilities(&mut capabilities_cache, &device).clone();
+                        let stop_strategy = device_stop_strategy_for(&explicit_zero_stop_device_addresses, &device);
+                        let device_index_bp = device.index();
+                        scheduled_device_tasks.insert(device_index_bp, spawn_test_sequence(device, capabilities, stop_strategy, steps, to_gui_sender.clone()));
+                    }
+                    CommandToAsyncTasks::ExportDeviceReport(report_path) => {
+                        let device_report = build_device_report(&connected_devices, &mut capabilities_cache);
+                        let report_text = serde_json::to_string_pretty(&device_report).unwrap_or_default();
+                        let export_message = match tokio::fs::write(&report_path, report_text).await {
+                            Ok(_) => format!("Отчет об устройствах ({}) сохранен: {}", connected_devices.len(), report_path.display()),
+                            Err(e) => format!("Не удалось сохранить отчет об устройствах в {}: {}", report_path.display(), e),
+                        };
+                        let _ = to_gui_sender.send(UpdateFromAsyncTasks::LogMessage { category: LogCategory::Buttplug, device_address: None, message: export_message }).await;
+                    }
+                    CommandToAsyncTasks::SetOutputMuted(is_muted) => {
+                        is_output_muted = is_muted;
+                    }
+                    CommandToAsyncTasks::SetOutputArmed(is_armed) => {
+                        is_output_armed = is_armed;
+                    }
+                    // Сырая запись может включить мотор в обход всех проверок, поэтому отключение вывода действует и на нее
+                    CommandToAsyncTasks::RawWriteDevice { device_index, .. } if is_output_muted => {
+                        tracing::debug!("Вывод отключен: сырая запись на устройство {} пропущена.", device_index);
+                    }
+                    CommandToAsyncTasks::RawWriteDevice { device_index, endpoint, data, write_with_response } => {
+                        if !optional_client.as_ref().is_some_and(|client_ref| client_ref.connected()) {
+                            tracing::warn!("Клиент Buttplug не подключен для RawWrite.");
+                            continue;
+                        }
+                        let Some(device) = find_connected_device(&connected_devices, device_index) else {
+                            tracing::warn!("Устройство с индексом BP {} не найдено для сырой записи.", device_index);
+                            continue;
+                        };
+                        let raw_command_description = format!(
+                            "RawWrite '{}' -> {:?}: [{}]{}",
+                            device.name(),
+                            endpoint,
+                            format_hex_bytes(&data),
+                            if write_with_response { " (с подтверждением)" } else { "" }
+                        );
+                        tracing::warn!("Сырая команда: {}", raw_command_description);
+                        let log_sender = to_gui_sender.clone();
+                        tokio::spawn(async move {
+                            let message = match device.raw_write(endpoint, &data, write_with_response).await {
+                                Ok(()) => format!("Выполнено: {}", raw_command_description),
+                                Err(e) => format!("Ошибка {}: {}", raw_command_description, e),
+                            };
+                            let _ = log_sender.send(UpdateFromAsyncTasks::LogMessage {
+                                category: LogCategory::Buttplug,
+                                device_address: Some(device_address(&device)),
+                                message,
+                            }).await;
+                        });
+                    }
+                    CommandToAsyncTasks::RawReadDevice { device_index, endpoint, expected_length, timeout_milliseconds } => {
+                        if !optional_client.as_ref().is_some_and(|client_ref| client_ref.connected()) {
+                            tracing::warn!("Клиент Buttplug не подключен для RawRead.");
+                            continue;
                         }
+                        let Some(device) = find_connected_device(&connected_devices, device_index) else {
+                            tracing::warn!("Устройство с индексом BP {} не найдено для сырого чтения.", device_index);
+                            continue;
+                        };
+                        let raw_command_description = format!("RawRead '{}' <- {:?}", device.name(), endpoint);
+                        tracing::info!("Сырая команда: {}, {} байт", raw_command_description, expected_length);
+                        let log_sender = to_gui_sender.clone();
+                        tokio::spawn(async move {
+                            let message = match device.raw_read(endpoint, expected_length, timeout_milliseconds).await {
+                                Ok(read_bytes) => format!("{}: [{}]", raw_command_description, format_hex_bytes(&read_bytes)),
+                                Err(e) => format!("Ошибка {}: {}", raw_command_description, e),
+                            };
+                            let _ = log_sender.send(UpdateFromAsyncTasks::LogMessage {
+                                category: LogCategory::Buttplug,
+                                device_address: Some(device_address(&device)),
+                                message,
+                            }).await;
+                        });
                     }
 
-                    CommandToAsyncTasks::VibrateDevice { device_index, speed } => {
+                    CommandToAsyncTasks::VibrateDevice { device_index, speed, actuator_speeds, duration_milliseconds } => {
+                        let speed = lift_to_intensity_floor(speed, intensity_floor);
+                        let actuator_speeds: Vec<(u32, f64)> = actuator_speeds.into_iter()
+                            .map(|(actuator_index, actuator_speed)| (actuator_index, lift_to_intensity_floor(actuator_speed, intensity_floor)))
+                            .collect();
                         if let Some(ref client_ref) = optional_client {
                             if client_ref.connected() {
-                                if let Some(device) = connected_devices.get(device_index) {
-                                    let device_to_command = device.clone();
+                                if let Some(device_to_command) = find_connected_device(&connected_devices, device_index) {
                                     tracing::info!(
-                                        "Вибрация устройства '{}' (индекс GUI: {}, индекс BP: {}) со скоростью {}",
+                                        "Вибрация устройства '{}' (индекс BP: {}) со скоростью {}",
                                         device_to_command.name(),
                                         device_index,
-                                        device_to_command.index(),
                                         speed
                                     );
 
-                                    if let Some(scalar_features) = device_to_command.message_attributes().scalar_cmd() {
-                                        let mut scalar_subcommands = Vec::new();
-                                        for feature_actuator in scalar_features {
-                                            if *feature_actuator.actuator_type() == ActuatorType::Vibrate {
-                                                scalar_subcommands.push(
-                                                    ScalarSubcommandV3::new(
-                                                        *feature_actuator.index(), // Разыменовываем ссылку
-                                                        speed,
-                                                        ActuatorType::Vibrate
-                                                    )
-                                                );
+                                    scheduled_device_tasks.cancel(device_to_command.index());
+                                    if let Some(duration_milliseconds) = duration_milliseconds {
+                                        let hold_duration = timed_vibration_hold_duration(duration_milliseconds, minimum_on_milliseconds_for(&device_minimum_on_times, &device_to_command));
+                                        scheduled_device_tasks.stop_at(device_to_command.index(), tokio::time::Instant::now() + hold_duration);
+                                    }
+                                    let command_received_at = Instant::now();
+                                    if let Some(assembled_vibration_command) = build_vibration_command(&device_to_command, cached_capabilities(&mut capabilities_cache, &device_to_command), speed, &actuator_speeds) {
+                                        let output_intensity = vibration_output_intensity(cached_capabilities(&mut capabilities_cache, &device_to_command), speed, &actuator_speeds);
+                                        let logged_actuator_speeds = is_verbose_command_logging_enabled
+                                            .then(|| vibrate_actuator_speeds(cached_capabilities(&mut capabilities_cache, &device_to_command), speed, &actuator_speeds));
+                                        last_commanded_speeds.insert(device_to_command.index(), speed);
+                                        are_commanded_speeds_changed = true;
+                                        let target_device_for_vibration = device_to_command.clone();
+                                        let error_sender = to_gui_sender.clone();
+                                        let latency_window = command_latencies.clone();
+                                        let retry_count = device_command_retry_count;
+                                        tokio::spawn(async move {
+                                            let vibration_result = run_device_command_with_retry(target_device_for_vibration.name(), retry_count, || {
+                                                target_device_for_vibration.scalar(&assembled_vibration_command)
+                                            }).await;
+                                            record_command_latency(&latency_window, command_received_at, vibration_result.is_ok());
+                                            if let Some(logged_actuator_speeds) = &logged_actuator_speeds {
+                                                log_device_command_timing(&target_device_for_vibration, "Vibrate", logged_actuator_speeds, command_received_at, vibration_result.is_ok());
                                             }
-                                        }
-
-                                        if !scalar_subcommands.is_empty() {
-                                            let assembled_vibration_command = ScalarCmdV3::new(
-                                                device_to_command.index(),
-                                                scalar_subcommands
-                                            );
-                                            let target_device_for_vibration = device_to_command.clone();
-                                            tokio::spawn(async move {
-                                                if let Err(vibration_error) = target_device_for_vibration.scalar(&assembled_vibration_command).await {
+                                            match vibration_result {
+                                                Ok(()) => report_device_output(&error_sender, target_device_for_vibration.index(), output_intensity),
+                                                Err(vibration_error) => {
                                                     tracing::error!(
                                                         "Ошибка ScalarCmd для {}: {:?}",
                                                         target_device_for_vibration.name(),
                                                         vibration_error
                                                     );
+                                                    report_device_command_error(&error_sender, &target_device_for_vibration, "Vibrate", vibration_error).await;
                                                 }
-                                            });
-                                        } else {
-                                            tracing::warn!("Устройство {} не имеет подходящих вибраторов.", device_to_command.name());
-                                        }
-                                    } else {
-                                        tracing::warn!("Устройство {} не поддерживает ScalarCmd.", device_to_command.name());
+                                            }
+                                        });
                                     }
                                 } else {
-                                    tracing::warn!("Устройство с GUI индексом {} не найдено.", device_index);
+                                    tracing::warn!("Устройство с индексом BP {} не найдено.", device_index);
                                 }
                             } else {
                                 tracing::warn!("Клиент Buttplug не подключен для VibrateDevice.");
@@ -122,21 +1213,47 @@ pub async fn run_buttplug_service_loop(
                     CommandToAsyncTasks::StopDevice(device_index) => {
                         if let Some(ref client_ref) = optional_client {
                             if client_ref.connected() {
-                                if let Some(device) = connected_devices.get(device_index) {
-                                    let device_to_stop = device.clone();
+                                if let Some(device_to_stop) = find_connected_device(&connected_devices, device_index) {
+                                    let start_speed = last_commanded_speeds.insert(device_to_stop.index(), 0.0).unwrap_or(0.0);
+                                    are_commanded_speeds_changed = true;
+                                    let stop_strategy = device_stop_strategy_for(&explicit_zero_stop_device_addresses, &device_to_stop);
                                     tracing::info!(
-                                        "Остановка устройства '{}' (индекс GUI: {}, индекс BP: {})",
+                                        "Остановка устройства '{}' (индекс BP: {}, способ: {})",
                                         device_to_stop.name(),
                                         device_index,
-                                        device_to_stop.index()
+                                        stop_strategy.label()
                                     );
-                                    tokio::spawn(async move {
-                                        if let Err(stop_error) = device_to_stop.stop().await {
-                                            tracing::error!("Ошибка при остановке {}: {:?}", device_to_stop.name(), stop_error);
-                                        }
-                                    });
+                                    scheduled_device_tasks.cancel(device_to_stop.index());
+                                    let error_sender = to_gui_sender.clone();
+                                    let capabilities = cached_capabilities(&mut capabilities_cache, &device_to_stop).clone();
+                                    if stop_fade_milliseconds > 0 && start_speed > 0.0 {
+                                        let device_index_bp = device_to_stop.index();
+                                        let scheduled_task = spawn_fade_out_stop(device_to_stop, capabilities, stop_strategy, start_speed, Duration::from_millis(stop_fade_milliseconds), error_sender);
+                                        scheduled_device_tasks.insert(device_index_bp, scheduled_task);
+                                    } else {
+                                        let latency_window = command_latencies.clone();
+                                        let retry_count = device_command_retry_count;
+                                        let is_command_logged = is_verbose_command_logging_enabled;
+                                        let command_received_at = Instant::now();
+                                        tokio::spawn(async move {
+                                            let stop_result = run_device_command_with_retry(device_to_stop.name(), retry_count, || {
+                                                stop_device_with_strategy(&device_to_stop, &capabilities, stop_strategy)
+                                            }).await;
+                                            record_command_latency(&latency_window, command_received_at, stop_result.is_ok());
+                                            if is_command_logged {
+                                                log_device_command_timing(&device_to_stop, "Stop", &[], command_received_at, stop_result.is_ok());
+                                            }
+                                            match stop_result {
+                                                Ok(()) => report_device_output(&error_sender, device_to_stop.index(), 0.0),
+                                                Err(stop_error) => {
+                                                    tracing::error!("Ошибка при остановке {}: {:?}", device_to_stop.name(), stop_error);
+                                                    report_device_command_error(&error_sender, &device_to_stop, "Stop", stop_error).await;
+                                                }
+                                            }
+                                        });
+                                    }
                                 } else {
-                                    tracing::warn!("Устройство с GUI индексом {} не найдено для StopDevice.", device_index);
+                                    tracing::warn!("Устройство с индексом BP {} не найдено для StopDevice.", device_index);
                                 }
                             } else {
                                 tracing::warn!("Клиент Buttplug не подключен для StopDevice.");
@@ -144,45 +1261,372 @@ pub async fn run_buttplug_service_loop(
                         }
                     }
 
-                    CommandToAsyncTasks::DisconnectButtplug => {
-                        if let Some(client_instance) = optional_client.take() {
-                            if client_instance.connected() {
-                                tracing::info!("Отключение от Buttplug сервера...");
-                                if let Err(disconnect_error) = client_instance.disconnect().await {
-                                    tracing::error!("Ошибка при отключении от Buttplug: {:?}", disconnect_error);
+                    CommandToAsyncTasks::VibrateGroup { member_addresses, speed, actuator_speeds, duration_milliseconds } => {
+                        let speed = lift_to_intensity_floor(speed, intensity_floor);
+                        let actuator_speeds: Vec<(u32, f64)> = actuator_speeds.into_iter()
+                            .map(|(actuator_index, actuator_speed)| (actuator_index, lift_to_intensity_floor(actuator_speed, intensity_floor)))
+                            .collect();
+                        if optional_client.as_ref().is_some_and(|client_ref| client_ref.connected()) {
+                            let command_received_at = Instant::now();
+                            // Приводы каждого участника нужны только для подробного журнала
+                            let group_commands: Vec<GroupMemberCommand> = resolve_group_members(&connected_devices, &member_addresses)
+                                .into_iter()
+                                .filter_map(|device| {
+                                    let capabilities = cached_capabilities(&mut capabilities_cache, &device);
+                                    let output_intensity = vibration_output_intensity(capabilities, speed, &actuator_speeds);
+                                    let logged_actuator_speeds = is_verbose_command_logging_enabled
+                                        .then(|| vibrate_actuator_speeds(capabilities, speed, &actuator_speeds));
+                                    build_vibration_command(&device, capabilities, speed, &actuator_speeds)
+                                        .map(|command| (device, command, output_intensity, logged_actuator_speeds))
+                                })
+                                .collect();
+                            tracing::info!("Вибрация группы из {} устройств со скоростью {}", group_commands.len(), speed);
+                            for (device, _, _, _) in &group_commands {
+                                last_commanded_speeds.insert(device.index(), speed);
+                                scheduled_device_tasks.cancel(device.index());
+                                if let Some(duration_milliseconds) = duration_milliseconds {
+                                    let hold_duration = timed_vibration_hold_duration(duration_milliseconds, minimum_on_milliseconds_for(&device_minimum_on_times, device));
+                                    scheduled_device_tasks.stop_at(device.index(), tokio::time::Instant::now() + hold_duration);
+                                }
+                            }
+                            are_commanded_speeds_changed = true;
+                            // Все участники получают команду одновременно в одной задаче
+                            let error_sender = to_gui_sender.clone();
+                            let latency_window = command_latencies.clone();
+                            let retry_count = device_command_retry_count;
+                            tokio::spawn(async move {
+                                let vibration_results = futures::future::join_all(
+                                    group_commands.iter().map(|(device, command, _, _)| {
+                                        run_device_command_with_retry(device.name(), retry_count, move || device.scalar(command))
+                                    })
+                                ).await;
+                                for ((device, _, output_intensity, logged_actuator_speeds), vibration_result) in group_commands.iter().zip(vibration_results) {
+                                    record_command_latency(&latency_window, command_received_at, vibration_result.is_ok());
+                                    if let Some(logged_actuator_speeds) = logged_actuator_speeds {
+                                        log_device_command_timing(device, "VibrateGroup", logged_actuator_speeds, command_received_at, vibration_result.is_ok());
+                                    }
+                                    match vibration_result {
+                                        Ok(()) => report_device_output(&error_sender, device.index(), *output_intensity),
+                                        Err(vibration_error) => {
+                                            tracing::error!("Ошибка ScalarCmd для {} (группа): {:?}", device.name(), vibration_error);
+                                            report_device_command_error(&error_sender, device, "VibrateGroup", vibration_error).await;
+                                        }
+                                    }
+                                }
+                            });
+                        } else {
+                            tracing::warn!("Клиент Buttplug не подключен для VibrateGroup.");
+                        }
+                    }
+
+                    CommandToAsyncTasks::StopGroup(member_addresses) => {
+                        if optional_client.as_ref().is_some_and(|client_ref| client_ref.connected()) {
+                            let group_devices = resolve_group_members(&connected_devices, &member_addresses);
+                            tracing::info!("Остановка группы из {} устройств", group_devices.len());
+                            let mut instantly_stopped_devices = Vec::new();
+                            for device in group_devices {
+                                let start_speed = last_commanded_speeds.insert(device.index(), 0.0).unwrap_or(0.0);
+                                scheduled_device_tasks.cancel(device.index());
+                                let capabilities = cached_capabilities(&mut capabilities_cache, &device).clone();
+                                let stop_strategy = device_stop_strategy_for(&explicit_zero_stop_device_addresses, &device);
+                                tracing::debug!("Остановка '{}' в группе, способ: {}", device.name(), stop_strategy.label());
+                                if stop_fade_milliseconds > 0 && start_speed > 0.0 {
+                                    let device_index_bp = device.index();
+                                    let scheduled_task = spawn_fade_out_stop(device, capabilities, stop_strategy, start_speed, Duration::from_millis(stop_fade_milliseconds), to_gui_sender.clone());
+                                    scheduled_device_tasks.insert(device_index_bp, scheduled_task);
+                                } else {
+                                    instantly_stopped_devices.push((device, capabilities, stop_strategy));
                                 }
                             }
+                            are_commanded_speeds_changed = true;
+                            let error_sender = to_gui_sender.clone();
+                            let group_devices = instantly_stopped_devices;
+                            let retry_count = device_command_retry_count;
+                            let is_command_logged = is_verbose_command_logging_enabled;
+                            let command_received_at = Instant::now();
+                            tokio::spawn(async move {
+                                let stop_results = futures::future::join_all(group_devices.iter().map(|(device, capabilities, stop_strategy)| {
+                                    run_device_command_with_retry(device.name(), retry_count, move || stop_device_with_strategy(device, capabilities, *stop_strategy))
+                                })).await;
+                                for ((device, _, _), stop_result) in group_devices.iter().zip(stop_results) {
+                                    if is_command_logged {
+                                        log_device_command_timing(device, "StopGroup", &[], command_received_at, stop_result.is_ok());
+                                    }
+                                    match stop_result {
+                                        Ok(()) => report_device_output(&error_sender, device.index(), 0.0),
+                                        Err(stop_error) => {
+                                            tracing::error!("Ошибка при остановке {} (группа): {:?}", device.name(), stop_error);
+                                            report_device_command_error(&error_sender, device, "StopGroup", stop_error).await;
+                                        }
+                                    }
+                                }
+                            });
+                        } else {
+                            tracing::warn!("Клиент Buttplug не подключен для StopGroup.");
+                        }
+                    }
+
+                    CommandToAsyncTasks::StopAllDevices => {
+                        if optional_client.as_ref().is_some_and(|client_ref| client_ref.connected()) {
+                            tracing::info!("Остановка всех устройств ({})", connected_devices.len());
+                            // Экстренная остановка всегда мгновенная
+                            scheduled_device_tasks.cancel_all();
+                            let mut all_devices = Vec::with_capacity(connected_devices.len());
+                            for device in &connected_devices {
+                                last_commanded_speeds.insert(device.index(), 0.0);
+                                let capabilities = cached_capabilities(&mut capabilities_cache, device).clone();
+                                let stop_strategy = device_stop_strategy_for(&explicit_zero_stop_device_addresses, device);
+                                tracing::debug!("Остановка '{}', способ: {}", device.name(), stop_strategy.label());
+                                all_devices.push((device.clone(), capabilities, stop_strategy));
+                            }
+                            are_commanded_speeds_changed = true;
+                            let error_sender = to_gui_sender.clone();
+                            let retry_count = device_command_retry_count;
+                            tokio::spawn(async move {
+                                let stop_results = futures::future::join_all(all_devices.iter().map(|(device, capabilities, stop_strategy)| {
+                                    run_device_command_with_retry(device.name(), retry_count, move || stop_device_with_strategy(device, capabilities, *stop_strategy))
+                                })).await;
+                                for ((device, _, _), stop_result) in all_devices.iter().zip(stop_results) {
+                                    match stop_result {
+                                        Ok(()) => report_device_output(&error_sender, device.index(), 0.0),
+                                        Err(stop_error) => {
+                                            tracing::error!("Ошибка при остановке {} (все устройства): {:?}", device.name(), stop_error);
+                                            report_device_command_error(&error_sender, device, "StopAll", stop_error).await;
+                                        }
+                                    }
+                                }
+                            });
+                        } else {
+                            tracing::warn!("Клиент Buttplug не подключен для StopAllDevices.");
+                        }
+                    }
+
+                    // Восстановление без сканирования: сверяем свой список и список GUI с тем, что знает клиент.
+                    // Помогает, когда после сбоя сервера соединение живо, а устройства "потерялись".
+                    CommandToAsyncTasks::ReacquireButtplugDevices => {
+                        let Some(client_ref) = optional_client.as_ref().filter(|client_ref| client_ref.connected()) else {
+                            let _ = to_gui_sender.send(UpdateFromAsyncTasks::LogMessage { category: LogCategory::Buttplug, device_address: None, message: "Нет подключения к серверу Buttplug: восстанавливать нечего, нужно подключиться заново.".to_string() }).await;
+                            continue;
+                        };
+                        let client_devices = client_ref.devices();
+                        let lost_devices: Vec<Arc<ButtplugClientDevice>> = connected_devices.iter()
+                            .filter(|device| !client_devices.iter().any(|client_device| client_device.index() == device.index()))
+                            .cloned()
+                            .collect();
+                        for lost_device in &lost_devices {
+                            if let Some(forwarding_task) = sensor_forwarding_tasks.remove(&lost_device.index()) {
+                                forwarding_task.abort();
+                            }
+                            scheduled_device_tasks.cancel(lost_device.index());
+                            capabilities_cache.invalidate(lost_device.index());
+                            connected_devices.retain(|device| device.index() != lost_device.index());
+                            let _ = to_gui_sender.send(UpdateFromAsyncTasks::ButtplugDeviceLost(ClonableButtplugClientDevice(lost_device.clone()))).await;
+                        }
+                        let mut reacquired_device_count = 0;
+                        for device_arc in client_devices {
+                            let is_known_device = connected_devices.iter().any(|device| device.index() == device_arc.index());
+                            // Отфильтрованные устройства не принимались при сканировании - не принимаем и здесь
+                            if !is_known_device && device_filter.rejection_reason(&device_address(&device_arc)).is_some() {
+                                continue;
+                            }
+                            // GUI пропускает уже известные ему устройства, так что повтор безопасен
+                            let _ = to_gui_sender.send(UpdateFromAsyncTasks::ButtplugDeviceFound(ClonableButtplugClientDevice(device_arc.clone()))).await;
+                            if is_known_device {
+                                // Устройство уже прогрето - GUI, потерявший его, сразу получает готовность
+                                let _ = to_gui_sender.send(UpdateFromAsyncTasks::ButtplugDeviceReady(device_arc.index())).await;
+                                continue;
+                            }
+                            reacquired_device_count += 1;
+                            connected_devices.push(device_arc.clone());
+                            let device_capabilities = DeviceCapabilities::introspect(&device_arc);
+                            capabilities_cache.insert(device_arc.index(), device_capabilities.clone());
+                            spawn_device_warmup(device_arc, device_capabilities, None, Duration::from_millis(device_warmup_milliseconds), to_gui_sender.clone());
                         }
+                        let _ = to_gui_sender.send(UpdateFromAsyncTasks::LogMessage { category: LogCategory::Buttplug, device_address: None, message: format!(
+                            "Восстановление устройств: у клиента {} устр., возвращено {}, пропало {}.",
+                            connected_devices.len(),
+                            reacquired_device_count,
+                            lost_devices.len()
+                        ) }).await;
+                    }
+                    CommandToAsyncTasks::DisconnectButtplug => {
+                        is_idle_disconnected = false; // Отключили вручную - сами не переподключаемся
+                        if let Some(client_instance) = optional_client.take()
+                            && client_instance.connected() {
+                            tracing::info!("Отключение от Buttplug сервера...");
+                            if let Err(disconnect_error) = client_instance.disconnect().await {
+                                tracing::error!("Ошибка при отключении от Buttplug: {:?}", disconnect_error);
+                            }
+                        }
+                        optional_event_stream = None;
                         connected_devices.clear();
+                        last_commanded_speeds.clear();
+                        capabilities_cache.clear();
+                        sensor_forwarding_tasks.drain().for_each(|(_, forwarding_task)| forwarding_task.abort());
+                        scheduled_device_tasks.cancel_all();
+                        scan_deadline = None;
                         let _ = to_gui_sender.send(UpdateFromAsyncTasks::ButtplugDisconnected).await;
-                        let _ = to_gui_sender.send(UpdateFromAsyncTasks::LogMessage("Отключено от Buttplug сервера по команде.".to_string())).await;
+                        let _ = to_gui_sender.send(UpdateFromAsyncTasks::LogMessage { category: LogCategory::Buttplug, device_address: None, message: "Отключено от Buttplug сервера по команде.".to_string() }).await;
+                    }
+
+                    CommandToAsyncTasks::SetDeviceSensorSubscription { device_index, is_enabled } => {
+                        let Some(device) = find_connected_device(&connected_devices, device_index) else {
+                            tracing::warn!("Устройство с индексом BP {} не найдено для подписки на датчики.", device_index);
+                            continue;
+                        };
+                        if let Some(previous_task) = sensor_forwarding_tasks.remove(&device.index()) {
+                            previous_task.abort();
+                            unsubscribe_device_sensors(&device).await;
+                        }
+                        if is_enabled
+                            && let Some(forwarding_task) = subscribe_device_sensors(device.clone(), to_gui_sender.clone()).await
+                        {
+                            sensor_forwarding_tasks.insert(device.index(), forwarding_task);
+                        }
+                    }
+
+                    CommandToAsyncTasks::TestButtplugServerAddress(server_address) => {
+                        let result_sender = to_gui_sender.clone();
+                        tokio::spawn(async move {
+                            let result = test_server_address(&server_address).await;
+                            let _ = result_sender.send(UpdateFromAsyncTasks::ButtplugServerAddressTestResult { server_address, result }).await;
+                        });
+                    }
+
+                    CommandToAsyncTasks::SetConnectionMetricsEnabled(is_enabled) => {
+                        is_metrics_reporting_enabled = is_enabled;
+                    }
+
+                    CommandToAsyncTasks::UpdateApplicationSettings(settings) => {
+                        device_warmup_milliseconds = settings.device_warmup_milliseconds;
+                        buttplug_connector_kind = settings.buttplug_connector_kind;
+                        stop_fade_milliseconds = settings.stop_fade_milliseconds;
+                        intensity_floor = settings.intensity_floor;
+                        scan_timeout_seconds = settings.scan_timeout_seconds;
+                        device_command_retry_count = settings.device_command_retry_count;
+                        is_verbose_command_logging_enabled = settings.verbose_device_command_logging;
+                        device_minimum_on_times = settings.device_minimum_on_times;
+                        explicit_zero_stop_device_addresses = settings.explicit_zero_stop_device_addresses;
+                        buttplug_server_address = settings.buttplug_server_address;
+                        idle_disconnect_minutes = settings.buttplug_idle_disconnect_minutes;
+                        connect_pulse = settings.connect_pulse;
+                        device_filter = settings.device_filter;
+                    }
+                    CommandToAsyncTasks::StartProcessing => {
+                        is_processing_enabled = true;
+                    }
+                    CommandToAsyncTasks::StopProcessing => {
+                        is_processing_enabled = false;
+                        last_device_activity_at = tokio::time::Instant::now(); // Простой отсчитывается от выключения обработки
                     }
 
                     _ => {}
                 }
             }
 
-            optional_event_from_stream = async {
-                optional_client.as_ref()
-                    .and_then(|client| client.connected().then(|| client.event_stream()))
-                    .and_then(|mut stream| stream.next().now_or_never())
-                    .and_then(|res| res.transpose()) // Решаем проблему Result -> Option
-                    .flatten()
-            } => {
+            _ = device_state_report_interval.tick() => {
+                if are_commanded_speeds_changed {
+                    are_commanded_speeds_changed = false;
+                    let commanded_speeds: Vec<(u32, f64)> = last_commanded_speeds.iter().map(|(index, speed)| (*index, *speed)).collect();
+                    let _ = to_gui_sender.send(UpdateFromAsyncTasks::ButtplugDeviceSpeeds(commanded_speeds)).await;
+                }
+            }
+
+            _ = metrics_report_interval.tick(), if is_metrics_reporting_enabled => {
+                let recent_latencies = command_latencies.lock().map(|mut latency_window| latency_window.recent_samples(Instant::now())).unwrap_or_default();
+                let _ = to_gui_sender.send(UpdateFromAsyncTasks::DeviceCommandMetrics(DeviceCommandMetrics::summarize(&recent_latencies))).await;
+            }
+
+            _ = idle_check_interval.tick(), if idle_disconnect_minutes > 0 && optional_client.is_some() => {
+                let idle_limit = Duration::from_secs(idle_disconnect_minutes * 60);
+                let is_effect_running = scheduled_device_tasks.is_effect_running();
+                let is_any_device_vibrating = last_commanded_speeds.values().any(|speed| *speed > 0.0);
+                if !is_processing_enabled && !is_effect_running && !is_any_device_vibrating && last_device_activity_at.elapsed() >= idle_limit {
+                    tracing::info!("Нет команд устройствам {} мин - отключаемся от Buttplug.", idle_disconnect_minutes);
+                    if let Some(client_instance) = optional_client.take() {
+                        disconnect_client(&client_instance).await;
+                    }
+                    optional_event_stream = None;
+                    connected_devices.clear();
+                    last_commanded_speeds.clear();
+                    sensor_forwarding_tasks.drain().for_each(|(_, forwarding_task)| forwarding_task.abort());
+                    scheduled_device_tasks.cancel_all();
+                    scan_deadline = None;
+                    is_idle_disconnected = true;
+                    let _ = to_gui_sender.send(UpdateFromAsyncTasks::ButtplugDisconnected).await;
+                    let _ = to_gui_sender.send(UpdateFromAsyncTasks::LogMessage { category: LogCategory::Buttplug, device_address: None, message: format!(
+                        "Нет команд устройствам {} мин - отключено от Buttplug для экономии заряда. Следующая команда устройству подключит заново.",
+                        idle_disconnect_minutes
+                    ) }).await;
+                }
+            }
+
+            _ = wait_for_scan_deadline(scan_deadline) => {
+                scan_deadline = None;
+                if let Some(client_ref) = optional_client.as_ref()
+                    && let Err(stop_scan_error) = client_ref.stop_scanning().await
+                {
+                    tracing::warn!("Ошибка остановки сканирования: {:?}", stop_scan_error);
+                }
+                tracing::info!("Сканирование завершено по таймауту, устройств: {}", connected_devices.len());
+                let _ = to_gui_sender.send(UpdateFromAsyncTasks::ButtplugScanFinished { found_device_count: connected_devices.len() }).await;
+            }
+
+            optional_event_from_stream = next_client_event(&mut optional_event_stream) => {
                 match optional_event_from_stream {
                     Some(event) => {
                         match event {
                             ButtplugClientEvent::DeviceAdded(device_arc) => {
                                 tracing::info!("Найдено устр-во: {} (Индекс BP: {})", device_arc.name(), device_arc.index());
-                                if !connected_devices.iter().any(|d| d.index() == device_arc.index()) {
+                                if let Some(rejection_reason) = device_filter.rejection_reason(&device_address(&device_arc)) {
+                                    let skip_message = format!("Устройство '{}' пропущено: {}.", device_arc.name(), rejection_reason);
+                                    tracing::info!("{}", skip_message);
+                                    let _ = to_gui_sender.send(UpdateFromAsyncTasks::LogMessage {
+                                        category: LogCategory::Buttplug,
+                                        device_address: Some(device_address(&device_arc)),
+                                        message: skip_message,
+                                    }).await;
+                                } else if !connected_devices.iter().any(|d| d.index() == device_arc.index()) {
                                     connected_devices.push(device_arc.clone());
-                                    if to_gui_sender.send(UpdateFromAsyncTasks::ButtplugDeviceFound(ClonableButtplugClientDevice(device_arc))).await.is_err() {
+                                    // Переподключенное устройство могло сменить прошивку - перечитываем возможности
+                                    let device_capabilities = DeviceCapabilities::introspect(&device_arc);
+                                    let protocol_log_message = vibration_protocol_log_message(&device_arc, &device_capabilities);
+                                    if device_capabilities.vibration_protocol() == VibrationProtocol::Unsupported {
+                                        tracing::warn!("{}", protocol_log_message);
+                                    } else {
+                                        tracing::info!("{}", protocol_log_message);
+                                    }
+                                    let _ = to_gui_sender.send(UpdateFromAsyncTasks::LogMessage {
+                                        category: LogCategory::Buttplug,
+                                        device_address: Some(device_address(&device_arc)),
+                                        message: protocol_log_message,
+                                    }).await;
+                                    // Импульс подтверждения идет в окне прогрева, пока GUI еще не шлет устройству команды
+                                    let connect_pulse_step = (connect_pulse.enabled && is_output_armed && !is_output_muted).then(|| {
+                                        let minimum_on_milliseconds = minimum_on_milliseconds_for(&device_minimum_on_times, &device_arc);
+                                        (connect_pulse.intensity.clamp(0.0, 1.0), connect_pulse.duration_milliseconds.max(minimum_on_milliseconds))
+                                    });
+                                    let pulse_capabilities = device_capabilities.clone();
+                                    capabilities_cache.insert(device_arc.index(), device_capabilities);
+                                    if to_gui_sender.send(UpdateFromAsyncTasks::ButtplugDeviceFound(ClonableButtplugClientDevice(device_arc.clone()))).await.is_err() {
                                         tracing::warn!("GUI канал (DeviceFound) закрыт");
                                     }
+                                    spawn_device_warmup(
+                                        device_arc,
+                                        pulse_capabilities,
+                                        connect_pulse_step,
+                                        Duration::from_millis(device_warmup_milliseconds),
+                                        to_gui_sender.clone(),
+                                    );
                                 }
                             }
                             ButtplugClientEvent::DeviceRemoved(removed_device_arc) => {
                                 tracing::info!("Устр-во удалено: {} (Индекс BP: {})", removed_device_arc.name(), removed_device_arc.index());
+                                if let Some(forwarding_task) = sensor_forwarding_tasks.remove(&removed_device_arc.index()) {
+                                    forwarding_task.abort();
+                                }
+                                scheduled_device_tasks.cancel(removed_device_arc.index());
+                                capabilities_cache.invalidate(removed_device_arc.index());
                                 let mut device_to_send_as_lost: Option<Arc<ButtplugClientDevice>> = None;
                                 connected_devices.retain(|device_in_list| {
                                     if device_in_list.index() == removed_device_arc.index() {
@@ -192,52 +1636,257 @@ pub async fn run_buttplug_service_loop(
                                         true
                                     }
                                 });
-                                if let Some(lost_device_arc) = device_to_send_as_lost {
-                                    if to_gui_sender.send(UpdateFromAsyncTasks::ButtplugDeviceLost(ClonableButtplugClientDevice(lost_device_arc))).await.is_err() {
-                                        tracing::warn!("GUI канал (DeviceLost) закрыт");
-                                    }
+                                if let Some(lost_device_arc) = device_to_send_as_lost
+                                    && to_gui_sender.send(UpdateFromAsyncTasks::ButtplugDeviceLost(ClonableButtplugClientDevice(lost_device_arc))).await.is_err()
+                                {
+                                    tracing::warn!("GUI канал (DeviceLost) закрыт");
                                 }
                             }
+                            ButtplugClientEvent::ScanningFinished if scan_deadline.take().is_some() => {
+                                let _ = to_gui_sender.send(UpdateFromAsyncTasks::ButtplugScanFinished { found_device_count: connected_devices.len() }).await;
+                            }
                             ButtplugClientEvent::ServerDisconnect => {
                                 tracing::info!("Buttplug сервер отключился.");
                                 optional_client.take();
+                                optional_event_stream = None;
                                 connected_devices.clear();
+                                scheduled_device_tasks.cancel_all();
+                                scan_deadline = None;
                                 let _ = to_gui_sender.send(UpdateFromAsyncTasks::ButtplugDisconnected).await;
-                                let _ = to_gui_sender.send(UpdateFromAsyncTasks::LogMessage("Buttplug сервер отключился.".to_string())).await;
+                                let _ = to_gui_sender.send(UpdateFromAsyncTasks::LogMessage { category: LogCategory::Buttplug, device_address: None, message: "Buttplug сервер отключился.".to_string() }).await;
                             }
                             ButtplugClientEvent::PingTimeout => {
                                 tracing::warn!("Buttplug PING таймаут. Соединение потеряно.");
                                 optional_client.take();
+                                optional_event_stream = None;
                                 connected_devices.clear();
+                                scheduled_device_tasks.cancel_all();
+                                scan_deadline = None;
                                 let _ = to_gui_sender.send(UpdateFromAsyncTasks::ButtplugDisconnected).await;
-                                let _ = to_gui_sender.send(UpdateFromAsyncTasks::LogMessage("Buttplug PING таймаут. Соединение потеряно.".to_string())).await;
+                                let _ = to_gui_sender.send(UpdateFromAsyncTasks::LogMessage { category: LogCategory::Buttplug, device_address: None, message: "Buttplug PING таймаут. Соединение потеряно.".to_string() }).await;
                             }
                             _ => {}
                         }
                     }
                     None => {
-                        if optional_client.is_some() && !optional_client.as_ref().unwrap().connected() {
-                            tracing::info!("Клиент Buttplug отсоединен (поток событий завершен или соединение разорвано).");
-                            optional_client = None;
+                        // Поток событий завершен - клиент больше ничего не сообщит
+                        tracing::info!("Клиент Buttplug отсоединен (поток событий завершен или соединение разорвано).");
+                        optional_event_stream = None;
+                        if optional_client.take().is_some() {
                             connected_devices.clear();
-                            if to_gui_sender.try_send(UpdateFromAsyncTasks::ButtplugDisconnected).is_err() {
-                                tracing::warn!("GUI канал (ButtplugDisconnected) закрыт при обработке конца стрима.");
-                            }
+                            let _ = to_gui_sender.send(UpdateFromAsyncTasks::ButtplugDisconnected).await;
                         }
-                        tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
                     }
                 }
             }
+        }
+    }
+}
 
-            else => {
-                tracing::info!("Цикл Buttplug сервиса завершается (канал команд закрыт или другая причина).");
-                if let Some(client_instance) = optional_client.take() {
-                    if client_instance.connected() {
-                        let _ = client_instance.disconnect().await;
-                    }
-                }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capabilities_with(actuator_types: &[ActuatorType]) -> DeviceCapabilities {
+        DeviceCapabilities {
+            scalar_actuators: actuator_types.iter().enumerate()
+                .map(|(index, actuator_type)| ActuatorCapability { index: index as u32, actuator_type: *actuator_type, step_count: 20 })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn capabilities_cache_reintrospects_reconnected_device() {
+        let mut capabilities_cache = CapabilitiesCache::default();
+        capabilities_cache.insert(0, capabilities_with(&[ActuatorType::Vibrate]));
+        // Повторная команда берет раскладку из кэша и устройство не опрашивает
+        let cached_capabilities = capabilities_cache.get_or_insert_with(0, || panic!("устройство опрошено повторно"));
+        assert_eq!(cached_capabilities.vibration_protocol(), VibrationProtocol::ScalarVibrate);
+
+        // Устройство пропало (DeviceRemoved) и вернулось под тем же индексом с другой раскладкой
+        capabilities_cache.invalidate(0);
+        let reconnected_capabilities = capabilities_cache.get_or_insert_with(0, || capabilities_with(&[ActuatorType::Oscillate]));
+        assert_eq!(reconnected_capabilities.vibration_protocol(), VibrationProtocol::ScalarFallback);
+    }
+
+    #[test]
+    fn capabilities_cache_keeps_devices_with_same_name_apart() {
+        // Два одинаковых устройства: адрес (имя) общий, индексы Buttplug разные
+        let mut capabilities_cache = CapabilitiesCache::default();
+        capabilities_cache.insert(1, capabilities_with(&[ActuatorType::Vibrate]));
+        capabilities_cache.insert(2, capabilities_with(&[ActuatorType::Vibrate, ActuatorType::Vibrate]));
+        capabilities_cache.invalidate(1);
+        let remaining_capabilities = capabilities_cache.get_or_insert_with(2, || panic!("раскладка второго устройства потеряна"));
+        assert_eq!(remaining_capabilities.scalar_actuators.len(), 2);
+        assert!(capabilities_cache.get_or_insert_with(1, DeviceCapabilities::default).scalar_actuators.is_empty());
+    }
+
+    #[test]
+    fn capabilities_cache_clear_forgets_all_devices() {
+        let mut capabilities_cache = CapabilitiesCache::default();
+        capabilities_cache.insert(0, capabilities_with(&[ActuatorType::Vibrate]));
+        capabilities_cache.insert(1, capabilities_with(&[ActuatorType::Vibrate]));
+        capabilities_cache.clear();
+        assert!(capabilities_cache.get_or_insert_with(0, DeviceCapabilities::default).scalar_actuators.is_empty());
+        assert!(capabilities_cache.get_or_insert_with(1, DeviceCapabilities::default).scalar_actuators.is_empty());
+    }
+
+    #[test]
+    fn scheduled_timed_stops_pick_earliest_and_are_cancelled_by_new_commands() {
+        let now = tokio::time::Instant::now();
+        let mut scheduled_device_tasks = ScheduledDeviceTasks::default();
+        assert_eq!(scheduled_device_tasks.next_timed_stop(), None);
+        assert!(!scheduled_device_tasks.is_effect_running());
+
+        scheduled_device_tasks.stop_at(1, now + Duration::from_millis(500));
+        scheduled_device_tasks.stop_at(2, now + Duration::from_millis(200));
+        assert_eq!(scheduled_device_tasks.next_timed_stop(), Some((2, now + Duration::from_millis(200))));
+        assert!(scheduled_device_tasks.is_effect_running());
+
+        // Повторное срабатывание переносит остановку устройства, а не добавляет вторую
+        scheduled_device_tasks.stop_at(2, now + Duration::from_millis(900));
+        assert_eq!(scheduled_device_tasks.next_timed_stop(), Some((1, now + Duration::from_millis(500))));
+
+        // Команда без длительности (непрерывное сопоставление) снимает остановку
+        scheduled_device_tasks.cancel(1);
+        assert_eq!(scheduled_device_tasks.next_timed_stop(), Some((2, now + Duration::from_millis(900))));
+
+        scheduled_device_tasks.cancel_all();
+        assert_eq!(scheduled_device_tasks.next_timed_stop(), None);
+        assert!(!scheduled_device_tasks.is_effect_running());
+    }
+
+    #[tokio::test]
+    async fn next_command_turns_expired_duration_into_stop_device() {
+        let (command_sender, mut from_gui_receiver) = mpsc::channel(4);
+        let mut scheduled_device_tasks = ScheduledDeviceTasks::default();
+        scheduled_device_tasks.stop_at(7, tokio::time::Instant::now() + Duration::from_millis(30));
+
+        // Команды GUI обслуживаются раньше остановки по времени
+        command_sender.send(CommandToAsyncTasks::StartProcessing).await.unwrap();
+        let first_command = next_command(&mut from_gui_receiver, &mut scheduled_device_tasks).await;
+        assert!(matches!(first_command, Some(CommandToAsyncTasks::StartProcessing)));
+        assert!(scheduled_device_tasks.next_timed_stop().is_some());
+
+        let timed_stop = tokio::time::timeout(Duration::from_secs(1), next_command(&mut from_gui_receiver, &mut scheduled_device_tasks))
+            .await
+            .expect("остановка по времени не пришла");
+        assert!(matches!(timed_stop, Some(CommandToAsyncTasks::StopDevice(7))));
+        assert_eq!(scheduled_device_tasks.next_timed_stop(), None);
+
+        drop(command_sender);
+        assert!(next_command(&mut from_gui_receiver, &mut scheduled_device_tasks).await.is_none());
+    }
+
+    #[test]
+    fn timed_vibration_is_held_for_device_minimum_on_time() {
+        assert_eq!(timed_vibration_hold_duration(50, 0), Duration::from_millis(50));
+        assert_eq!(timed_vibration_hold_duration(50, 120), Duration::from_millis(120));
+        assert_eq!(timed_vibration_hold_duration(300, 120), Duration::from_millis(300));
+        // Тот же порог, что и для шагов последовательности
+        assert_eq!(apply_minimum_on_time(vec![(0.5, 50), (0.0, 100)], 120), vec![(0.5, 120), (0.0, 100)]);
+    }
+
+    // Устройство без клиента Buttplug: важны только индекс и имя
+    #[derive(Debug, Clone, PartialEq)]
+    struct FakeDevice {
+        index: u32,
+        name: &'static str,
+    }
+
+    impl ButtplugDeviceIndex for FakeDevice {
+        fn buttplug_index(&self) -> u32 {
+            self.index
+        }
+    }
+
+    #[test]
+    fn device_commands_follow_buttplug_index_when_lists_are_reordered() {
+        let edge = FakeDevice { index: 5, name: "Lovense Edge" };
+        let lush = FakeDevice { index: 2, name: "Lovense Lush" };
+        let max = FakeDevice { index: 9, name: "Lovense Max" };
+        // В GUI выбран Lush (позиция 1); Buttplug задача получила устройства в другом порядке
+        let gui_devices = vec![edge.clone(), lush.clone(), max.clone()];
+        let connected_devices = vec![Arc::new(max.clone()), Arc::new(edge.clone()), Arc::new(lush.clone())];
+
+        let target_index = buttplug_index_at(&gui_devices, Some(1)).unwrap();
+        assert_eq!(target_index, 2);
+        assert_eq!(find_connected_device(&connected_devices, target_index).map(|device| device.name), Some("Lovense Lush"));
+        // По позиции 1 в списке задачи был бы не тот
+        assert_eq!(connected_devices[1].name, "Lovense Edge");
+        assert!(find_connected_device(&connected_devices, 7).is_none());
+    }
+
+    #[test]
+    fn selection_is_kept_by_buttplug_index_after_device_removal() {
+        let mut gui_devices = vec![
+            FakeDevice { index: 5, name: "Lovense Edge" },
+            FakeDevice { index: 2, name: "Lovense Lush" },
+            FakeDevice { index: 9, name: "Lovense Max" },
+        ];
+        let selected_index = buttplug_index_at(&gui_devices, Some(2)).unwrap();
+        gui_devices.retain(|device| device.index != 2);
+        let selected_position = position_of_buttplug_index(&gui_devices, selected_index);
+        assert_eq!(selected_position, Some(1));
+        assert_eq!(buttplug_index_at(&gui_devices, selected_position), Some(9));
+        assert_eq!(buttplug_index_at(&gui_devices, Some(5)), None);
+        assert_eq!(buttplug_index_at(&gui_devices, None), None);
+    }
+
+    #[tokio::test]
+    async fn disconnect_client_disconnects_in_process_client() {
+        let client_instance = ButtplugClient::new("WarThunder Haptics GUI (тест)");
+        client_instance.connect(new_in_process_connector().unwrap()).await.unwrap();
+        assert!(client_instance.connected());
+        disconnect_client(&client_instance).await;
+        assert!(!client_instance.connected());
+        // Повторный вызов для уже отключенного клиента ничего не делает
+        disconnect_client(&client_instance).await;
+    }
+
+    // Все обновления до завершения задачи: канал закрывается, когда задача отпускает отправителя
+    async fn updates_until_task_ends(update_receiver: &mut mpsc::Receiver<UpdateFromAsyncTasks>) -> Vec<UpdateFromAsyncTasks> {
+        let mut updates = Vec::new();
+        while let Some(update) = tokio::time::timeout(Duration::from_secs(5), update_receiver.recv()).await.expect("задача Buttplug не завершилась") {
+            updates.push(update);
+        }
+        updates
+    }
+
+    #[tokio::test]
+    async fn closing_command_channel_disconnects_connected_client() {
+        let (update_sender, mut update_receiver) = mpsc::channel(100);
+        let (command_sender, command_receiver) = mpsc::channel(8);
+        let service_task = tokio::spawn(run_buttplug_service_loop(update_sender, command_receiver));
+        let settings = ApplicationSettings {
+            buttplug_connector_kind: ButtplugConnectorKind::InProcess,
+            scan_timeout_seconds: 0,
+            ..ApplicationSettings::default()
+        };
+        command_sender.send(CommandToAsyncTasks::UpdateApplicationSettings(Box::new(settings))).await.unwrap();
+        command_sender.send(CommandToAsyncTasks::ScanForButtplugDevices).await.unwrap();
+        loop {
+            let update = tokio::time::timeout(Duration::from_secs(5), update_receiver.recv()).await.unwrap().unwrap();
+            if matches!(update, UpdateFromAsyncTasks::ButtplugConnected) {
                 break;
             }
         }
+
+        drop(command_sender);
+        let updates = updates_until_task_ends(&mut update_receiver).await;
+        assert!(matches!(updates.last(), Some(UpdateFromAsyncTasks::ButtplugDisconnected)));
+        service_task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn closing_command_channel_without_client_still_reports_disconnected() {
+        let (update_sender, mut update_receiver) = mpsc::channel(100);
+        let (command_sender, command_receiver) = mpsc::channel(8);
+        let service_task = tokio::spawn(run_buttplug_service_loop(update_sender, command_receiver));
+
+        drop(command_sender);
+        let updates = updates_until_task_ends(&mut update_receiver).await;
+        assert!(matches!(updates.as_slice(), [UpdateFromAsyncTasks::ButtplugDisconnected]));
+        service_task.await.unwrap();
     }
-}
\ No newline at end of file
+}
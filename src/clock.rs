@@ -0,0 +1,167 @@
+// src/clock.rs
+
+// Источник времени для обработки событий, опроса War Thunder и таймеров GUI: кулдауны, затухание сигналов,
+// паузы опроса, временное усиление, мягкий старт и тихие часы берут время отсюда, а не напрямую из Instant::now()/tokio::time::sleep, чтобы логику со временем
+// можно было прогонять на подставных часах, которые двигаются вручную.
+// В работе всегда SystemClock - поведение то же, что при прямых вызовах; в тестах - ManualClock.
+// Таймеры Buttplug задачи завязаны на tokio::time и останавливаются средствами tokio (time::pause).
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+pub type SleepFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+    fn sleep(&self, duration: Duration) -> SleepFuture;
+    // Местное время суток (тихие часы)
+    fn local_time(&self) -> chrono::NaiveTime {
+        chrono::Local::now().time()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> SleepFuture {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+// Подставные часы: время стоит, пока его не сдвинут advance(). Паузы sleep заканчиваются,
+// когда часы дошли до их срока, - так опрос с интервалом проходит шаг за шагом без реального ожидания.
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    state: Arc<Mutex<ManualClockState>>,
+}
+
+#[derive(Debug)]
+struct ManualClockState {
+    now: Instant,
+    local_time: chrono::NaiveTime, // Идет вместе с now, по кругу через полночь
+    pending_sleeps: Vec<(Instant, Waker)>, // Срок паузы и кого разбудить
+}
+
+impl ManualClock {
+    // Местное время начинается с полуночи
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(ManualClockState {
+                now: Instant::now(),
+                local_time: chrono::NaiveTime::MIN,
+                pending_sleeps: Vec::new(),
+            })),
+        }
+    }
+
+    pub fn set_local_time(&self, local_time: chrono::NaiveTime) {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).local_time = local_time;
+    }
+
+    // Сдвигает время вперед и завершает паузы, срок которых наступил
+    pub fn advance(&self, duration: Duration) {
+        let woken_sleeps = {
+            let mut state = self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            state.now += duration;
+            state.local_time = state.local_time.overflowing_add_signed(chrono::TimeDelta::from_std(duration).unwrap_or(chrono::TimeDelta::MAX)).0;
+            let now = state.now;
+            let (woken_sleeps, pending_sleeps) = std::mem::take(&mut state.pending_sleeps)
+                .into_iter()
+                .partition::<Vec<_>, _>(|(wake_at, _)| *wake_at <= now);
+            state.pending_sleeps = pending_sleeps;
+            woken_sleeps
+        };
+        // Будим без блокировки: проснувшаяся задача может сразу снова обратиться к часам
+        for (_, waker) in woken_sleeps {
+            waker.wake();
+        }
+    }
+
+    // Сколько пауз сейчас ждет своего срока (тест может дождаться, пока задача уснет)
+    pub fn pending_sleep_count(&self) -> usize {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).pending_sleeps.len()
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).now
+    }
+
+    fn sleep(&self, duration: Duration) -> SleepFuture {
+        let wake_at = self.now() + duration;
+        Box::pin(ManualSleep { state: self.state.clone(), wake_at })
+    }
+
+    fn local_time(&self) -> chrono::NaiveTime {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).local_time
+    }
+}
+
+struct ManualSleep {
+    state: Arc<Mutex<ManualClockState>>,
+    wake_at: Instant,
+}
+
+impl Future for ManualSleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if state.now >= self.wake_at {
+            return Poll::Ready(());
+        }
+        state.pending_sleeps.push((self.wake_at, context.waker().clone()));
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_clock_moves_only_on_advance() {
+        let clock = ManualClock::new();
+        let started_at = clock.now();
+        assert_eq!(clock.now(), started_at);
+        clock.advance(Duration::from_millis(250));
+        assert_eq!(clock.now().duration_since(started_at), Duration::from_millis(250));
+    }
+
+    #[test]
+    fn manual_clock_local_time_wraps_past_midnight() {
+        let clock = ManualClock::new();
+        clock.set_local_time(chrono::NaiveTime::from_hms_opt(23, 30, 0).unwrap());
+        clock.advance(Duration::from_secs(45 * 60));
+        assert_eq!(clock.local_time(), chrono::NaiveTime::from_hms_opt(0, 15, 0).unwrap());
+    }
+
+    #[tokio::test]
+    async fn manual_sleep_finishes_when_clock_reaches_deadline() {
+        let clock = ManualClock::new();
+        let sleep_task = tokio::spawn(clock.sleep(Duration::from_secs(1)));
+        while clock.pending_sleep_count() == 0 {
+            tokio::task::yield_now().await;
+        }
+        clock.advance(Duration::from_millis(999));
+        tokio::task::yield_now().await;
+        assert!(!sleep_task.is_finished());
+        clock.advance(Duration::from_millis(1));
+        tokio::time::timeout(Duration::from_secs(5), sleep_task).await.unwrap().unwrap();
+    }
+}
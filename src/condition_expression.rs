@@ -0,0 +1,419 @@
+// src/condition_expression.rs
+
+// Мини-язык условий для правил: сравнения полей индикаторов, объединенные AND/OR/NOT и скобками.
+// Пример: "health_percentage < 30 AND (speed > 0 OR NOT rpm_throttle <= 0.1)"
+// Строковые поля (vehicle_type, army) не сравниваются, для них есть проверка "поле CHANGED":
+// истинна на том опросе, где значение отличается от прошлого (например, "vehicle_type CHANGED" - новая техника).
+// Ключевые слова не зависят от регистра. Сравнение с отсутствующим полем дает false.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ComparisonOperator {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+}
+
+impl ComparisonOperator {
+    fn compare(self, left: f64, right: f64) -> bool {
+        match self {
+            ComparisonOperator::Less => left < right,
+            ComparisonOperator::LessOrEqual => left <= right,
+            ComparisonOperator::Greater => left > right,
+            ComparisonOperator::GreaterOrEqual => left >= right,
+            ComparisonOperator::Equal => (left - right).abs() < 1e-6,
+            ComparisonOperator::NotEqual => (left - right).abs() >= 1e-6,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConditionExpression {
+    Comparison {
+        field_name: String,
+        operator: ComparisonOperator,
+        value: f64,
+    },
+    Changed {
+        field_name: String,
+    },
+    And(Box<ConditionExpression>, Box<ConditionExpression>),
+    Or(Box<ConditionExpression>, Box<ConditionExpression>),
+    Not(Box<ConditionExpression>),
+}
+
+impl ConditionExpression {
+    // field_value: значение поля по имени (None - поля нет в текущих данных)
+    // is_field_changed: изменилось ли поле с прошлого опроса (для CHANGED)
+    pub fn evaluate(&self, field_value: &impl Fn(&str) -> Option<f64>, is_field_changed: &impl Fn(&str) -> bool) -> bool {
+        match self {
+            ConditionExpression::Comparison { field_name, operator, value } => {
+                field_value(field_name).is_some_and(|current_value| operator.compare(current_value, *value))
+            }
+            ConditionExpression::Changed { field_name } => is_field_changed(field_name),
+            ConditionExpression::And(left, right) => {
+                left.evaluate(field_value, is_field_changed) && right.evaluate(field_value, is_field_changed)
+            }
+            ConditionExpression::Or(left, right) => {
+                left.evaluate(field_value, is_field_changed) || right.evaluate(field_value, is_field_changed)
+            }
+            ConditionExpression::Not(inner) => !inner.evaluate(field_value, is_field_changed),
+        }
+    }
+
+    // Все имена полей, упомянутые в выражении
+    pub fn field_names(&self) -> Vec<&str> {
+        let mut names = self.comparison_field_names();
+        names.extend(self.changed_field_names());
+        names
+    }
+
+    // Поля в сравнениях (должны быть числовыми) - для проверки при загрузке
+    pub fn comparison_field_names(&self) -> Vec<&str> {
+        self.collect_field_names(false)
+    }
+
+    // Поля в проверках CHANGED (должны быть строковыми) - для проверки при загрузке
+    pub fn changed_field_names(&self) -> Vec<&str> {
+        self.collect_field_names(true)
+    }
+
+    // Пары (поле, значение) из всех сравнений - для проверки значений на допустимый диапазон поля
+    pub fn comparison_values(&self) -> Vec<(&str, f64)> {
+        match self {
+            ConditionExpression::Comparison { field_name, value, .. } => vec![(field_name.as_str(), *value)],
+            ConditionExpression::Changed { .. } => Vec::new(),
+            ConditionExpression::And(left, right) | ConditionExpression::Or(left, right) => {
+                let mut values = left.comparison_values();
+                values.extend(right.comparison_values());
+                values
+            }
+            ConditionExpression::Not(inner) => inner.comparison_values(),
+        }
+    }
+
+    fn collect_field_names(&self, is_changed_check: bool) -> Vec<&str> {
+        match self {
+            ConditionExpression::Comparison { field_name, .. } if !is_changed_check => vec![field_name.as_str()],
+            ConditionExpression::Changed { field_name } if is_changed_check => vec![field_name.as_str()],
+            ConditionExpression::Comparison { .. } | ConditionExpression::Changed { .. } => Vec::new(),
+            ConditionExpression::And(left, right) | ConditionExpression::Or(left, right) => {
+                let mut names = left.collect_field_names(is_changed_check);
+                names.extend(right.collect_field_names(is_changed_check));
+                names
+            }
+            ConditionExpression::Not(inner) => inner.collect_field_names(is_changed_check),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Identifier(String),
+    Number(f64),
+    Operator(ComparisonOperator),
+    And,
+    Or,
+    Not,
+    Changed,
+    OpenParenthesis,
+    CloseParenthesis,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let characters: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut position = 0;
+    while position < characters.len() {
+        let character = characters[position];
+        if character.is_whitespace() {
+            position += 1;
+            continue;
+        }
+        match character {
+            '(' => {
+                tokens.push(Token::OpenParenthesis);
+                position += 1;
+            }
+            ')' => {
+                tokens.push(Token::CloseParenthesis);
+                position += 1;
+            }
+            '<' | '>' | '=' | '!' => {
+                let is_followed_by_equals = characters.get(position + 1) == Some(&'=');
+                let operator = match (character, is_followed_by_equals) {
+                    ('<', true) => ComparisonOperator::LessOrEqual,
+                    ('<', false) => ComparisonOperator::Less,
+                    ('>', true) => ComparisonOperator::GreaterOrEqual,
+                    ('>', false) => ComparisonOperator::Greater,
+                    ('=', _) => ComparisonOperator::Equal,
+                    ('!', true) => ComparisonOperator::NotEqual,
+                    _ => return Err(format!("Неожиданный символ '!' в позиции {}. Для отрицания используйте NOT", position)),
+                };
+                tokens.push(Token::Operator(operator));
+                position += if is_followed_by_equals { 2 } else { 1 };
+            }
+            _ if character.is_ascii_digit() || character == '-' || character == '.' => {
+                let start = position;
+                position += 1;
+                while position < characters.len() && (characters[position].is_ascii_digit() || characters[position] == '.') {
+                    position += 1;
+                }
+                let number_text: String = characters[start..position].iter().collect();
+                let number = number_text.parse::<f64>()
+                    .map_err(|_| format!("Некорректное число '{}' в позиции {}", number_text, start))?;
+                tokens.push(Token::Number(number));
+            }
+            _ if character.is_alphabetic() || character == '_' => {
+                let start = position;
+                while position < characters.len() && (characters[position].is_alphanumeric() || characters[position] == '_' || characters[position] == '.') {
+                    position += 1;
+                }
+                let word: String = characters[start..position].iter().collect();
+                tokens.push(match word.to_uppercase().as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    "CHANGED" => Token::Changed,
+                    _ => Token::Identifier(word),
+                });
+            }
+            _ => return Err(format!("Неожиданный символ '{}' в позиции {}", character, position)),
+        }
+    }
+    Ok(tokens)
+}
+
+// Рекурсивный спуск. Приоритет: NOT > AND > OR.
+struct ExpressionParser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl ExpressionParser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next_token(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn parse_or(&mut self) -> Result<ConditionExpression, String> {
+        let mut expression = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.position += 1;
+            expression = ConditionExpression::Or(Box::new(expression), Box::new(self.parse_and()?));
+        }
+        Ok(expression)
+    }
+
+    fn parse_and(&mut self) -> Result<ConditionExpression, String> {
+        let mut expression = self.parse_not()?;
+        while self.peek() == Some(&Token::And) {
+            self.position += 1;
+            expression = ConditionExpression::And(Box::new(expression), Box::new(self.parse_not()?));
+        }
+        Ok(expression)
+    }
+
+    fn parse_not(&mut self) -> Result<ConditionExpression, String> {
+        if self.peek() == Some(&Token::Not) {
+            self.position += 1;
+            return Ok(ConditionExpression::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<ConditionExpression, String> {
+        match self.next_token() {
+            Some(Token::OpenParenthesis) => {
+                let expression = self.parse_or()?;
+                match self.next_token() {
+                    Some(Token::CloseParenthesis) => Ok(expression),
+                    _ => Err("Ожидалась закрывающая скобка ')'".to_string()),
+                }
+            }
+            Some(Token::Identifier(field_name)) => {
+                let operator = match self.next_token() {
+                    Some(Token::Operator(operator)) => operator,
+                    Some(Token::Changed) => return Ok(ConditionExpression::Changed { field_name }),
+                    _ => return Err(format!("После поля '{}' ожидался оператор сравнения (<, <=, >, >=, ==, !=) или CHANGED", field_name)),
+                };
+                let value = match self.next_token() {
+                    Some(Token::Number(value)) => value,
+                    _ => return Err(format!("После '{} {:?}' ожидалось число", field_name, operator)),
+                };
+                Ok(ConditionExpression::Comparison { field_name, operator, value })
+            }
+            Some(unexpected_token) => Err(format!("Неожиданный элемент {:?}: ожидалось поле или '('", unexpected_token)),
+            None => Err("Выражение неожиданно закончилось".to_string()),
+        }
+    }
+}
+
+pub fn parse_condition_expression(source: &str) -> Result<ConditionExpression, String> {
+    let tokens = tokenize(source)?;
+    if tokens.is_empty() {
+        return Err("Пустое выражение".to_string());
+    }
+    let mut parser = ExpressionParser { tokens, position: 0 };
+    let expression = parser.parse_or()?;
+    if let Some(trailing_token) = parser.peek() {
+        return Err(format!("Лишний элемент в конце выражения: {:?}", trailing_token));
+    }
+    Ok(expression)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn comparison(field_name: &str, operator: ComparisonOperator, value: f64) -> ConditionExpression {
+        ConditionExpression::Comparison { field_name: field_name.to_string(), operator, value }
+    }
+
+    fn parse_error(source: &str) -> String {
+        parse_condition_expression(source).expect_err(source)
+    }
+
+    fn evaluate_with(source: &str, field_values: &[(&str, f64)]) -> bool {
+        parse_condition_expression(source).unwrap().evaluate(
+            &|field_name| field_values.iter().find(|(name, _)| *name == field_name).map(|(_, value)| *value),
+            &|_| false,
+        )
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and_and_and_tighter_than_or() {
+        assert_eq!(
+            parse_condition_expression("speed > 1 OR g_load > 2 AND NOT altitude_10k > 3").unwrap(),
+            ConditionExpression::Or(
+                Box::new(comparison("speed", ComparisonOperator::Greater, 1.0)),
+                Box::new(ConditionExpression::And(
+                    Box::new(comparison("g_load", ComparisonOperator::Greater, 2.0)),
+                    Box::new(ConditionExpression::Not(Box::new(comparison("altitude_10k", ComparisonOperator::Greater, 3.0)))),
+                )),
+            ),
+        );
+    }
+
+    #[test]
+    fn parentheses_override_precedence() {
+        assert_eq!(
+            parse_condition_expression("(speed > 1 OR g_load > 2) AND NOT (altitude_10k > 3)").unwrap(),
+            ConditionExpression::And(
+                Box::new(ConditionExpression::Or(
+                    Box::new(comparison("speed", ComparisonOperator::Greater, 1.0)),
+                    Box::new(comparison("g_load", ComparisonOperator::Greater, 2.0)),
+                )),
+                Box::new(ConditionExpression::Not(Box::new(comparison("altitude_10k", ComparisonOperator::Greater, 3.0)))),
+            ),
+        );
+        assert_eq!(parse_condition_expression("((speed > 1))"), parse_condition_expression("speed > 1"));
+    }
+
+    #[test]
+    fn chained_operators_group_from_the_left() {
+        assert_eq!(
+            parse_condition_expression("speed > 1 AND speed > 2 AND speed > 3").unwrap(),
+            ConditionExpression::And(
+                Box::new(ConditionExpression::And(
+                    Box::new(comparison("speed", ComparisonOperator::Greater, 1.0)),
+                    Box::new(comparison("speed", ComparisonOperator::Greater, 2.0)),
+                )),
+                Box::new(comparison("speed", ComparisonOperator::Greater, 3.0)),
+            ),
+        );
+    }
+
+    #[test]
+    fn each_comparison_operator_parses_and_compares() {
+        let cases = [
+            ("<", ComparisonOperator::Less, [true, false, false]),
+            ("<=", ComparisonOperator::LessOrEqual, [true, true, false]),
+            (">", ComparisonOperator::Greater, [false, false, true]),
+            (">=", ComparisonOperator::GreaterOrEqual, [false, true, true]),
+            ("==", ComparisonOperator::Equal, [false, true, false]),
+            ("=", ComparisonOperator::Equal, [false, true, false]),
+            ("!=", ComparisonOperator::NotEqual, [true, false, true]),
+        ];
+        for (operator_text, operator, expected_results) in cases {
+            let source = format!("speed {} 5", operator_text);
+            assert_eq!(parse_condition_expression(&source), Ok(comparison("speed", operator, 5.0)), "{}", source);
+            for (speed, expected_result) in [4.0, 5.0, 6.0].into_iter().zip(expected_results) {
+                assert_eq!(evaluate_with(&source, &[("speed", speed)]), expected_result, "{} при speed = {}", source, speed);
+            }
+        }
+    }
+
+    #[test]
+    fn numbers_may_be_negative_or_fractional_and_spaces_are_optional() {
+        assert_eq!(parse_condition_expression("g_load>=-1.5"), Ok(comparison("g_load", ComparisonOperator::GreaterOrEqual, -1.5)));
+        assert_eq!(parse_condition_expression("speed < .5"), Ok(comparison("speed", ComparisonOperator::Less, 0.5)));
+    }
+
+    #[test]
+    fn keywords_are_case_insensitive_and_changed_applies_to_field() {
+        assert_eq!(
+            parse_condition_expression("vehicle_type changed and Not army CHANGED").unwrap(),
+            ConditionExpression::And(
+                Box::new(ConditionExpression::Changed { field_name: "vehicle_type".to_string() }),
+                Box::new(ConditionExpression::Not(Box::new(ConditionExpression::Changed { field_name: "army".to_string() }))),
+            ),
+        );
+        let condition = parse_condition_expression("vehicle_type CHANGED OR speed > 1").unwrap();
+        assert_eq!(condition.changed_field_names(), vec!["vehicle_type"]);
+        assert_eq!(condition.comparison_field_names(), vec!["speed"]);
+    }
+
+    #[test]
+    fn raw_field_names_keep_their_dots() {
+        let condition = parse_condition_expression("raw.throttle_1 > 90").unwrap();
+        assert_eq!(condition, comparison("raw.throttle_1", ComparisonOperator::Greater, 90.0));
+        assert!(evaluate_with("raw.throttle_1 > 90", &[("raw.throttle_1", 95.0)]));
+    }
+
+    #[test]
+    fn unknown_field_parses_and_compares_false() {
+        // Имена полей проверяет validate_rule_conditions; сам разбор их не знает
+        let condition = parse_condition_expression("no_such_field > 1").unwrap();
+        assert_eq!(condition.field_names(), vec!["no_such_field"]);
+        assert!(!evaluate_with("no_such_field > 1", &[("speed", 100.0)]));
+        assert!(!evaluate_with("no_such_field <= 1", &[("speed", 100.0)]));
+        assert!(evaluate_with("NOT no_such_field > 1", &[("speed", 100.0)]));
+    }
+
+    #[test]
+    fn trailing_tokens_are_rejected() {
+        assert!(parse_error("speed > 1 speed").contains("Лишний элемент"));
+        assert!(parse_error("speed > 1)").contains("Лишний элемент"));
+        assert!(parse_error("speed > 1 2").contains("Лишний элемент"));
+    }
+
+    #[test]
+    fn malformed_input_reports_what_was_expected() {
+        let cases = [
+            ("", "Пустое выражение"),
+            ("   ", "Пустое выражение"),
+            ("speed >", "ожидалось число"),
+            ("speed > fast", "ожидалось число"),
+            ("speed 5", "ожидался оператор сравнения"),
+            ("speed", "ожидался оператор сравнения"),
+            ("(speed > 1", "закрывающая скобка"),
+            ("speed > 1 AND", "неожиданно закончилось"),
+            ("AND speed > 1", "ожидалось поле или '('"),
+            ("speed ! 5", "используйте NOT"),
+            ("speed > 1.2.3", "Некорректное число '1.2.3'"),
+            ("speed # 1", "Неожиданный символ '#' в позиции 6"),
+        ];
+        for (source, expected_fragment) in cases {
+            let error = parse_error(source);
+            assert!(error.contains(expected_fragment), "'{}': {}", source, error);
+        }
+    }
+}
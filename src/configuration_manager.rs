@@ -1,9 +1,13 @@
 // src/configuration_manager.rs
 
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 use directories::ProjectDirs;
+use crate::war_thunder_api::WarThunderIndicators;
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum DeviceActionType {
@@ -11,6 +15,15 @@ pub enum DeviceActionType {
     Stop,
 }
 
+// Кому адресовано действие: выбранному в GUI устройству, конкретному устройству (по адресу) или группе
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub enum ActionTarget {
+    #[default]
+    SelectedDevice,
+    Device(String),
+    Group(String),
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct DeviceAction {
     pub action_type: DeviceActionType,
@@ -18,84 +31,1618 @@ pub struct DeviceAction {
     pub intensity: f64,
     #[serde(default = "default_duration")]
     pub duration_milliseconds: u64,
+    #[serde(default)]
+    pub target: ActionTarget,
+    // Своя интенсивность для отдельных моторов (например, левый/правый для направления удара).
+    // Моторы без записи получают intensity.
+    #[serde(default)]
+    pub actuator_intensities: Vec<ActuatorIntensity>,
+    // Форма вибрации во времени: точки (доля длительности, интенсивность), между ними - линейно.
+    // Пусто - постоянная intensity. С паттерном действие проигрывается целиком и затем останавливается.
+    #[serde(default)]
+    pub pattern_points: Vec<PatternPoint>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct PatternPoint {
+    pub time_fraction: f64, // 0.0 - начало действия, 1.0 - конец
+    pub intensity: f64,
+}
+
+// Шаг дискретизации паттерна при проигрывании
+const PATTERN_STEP_MILLISECONDS: u64 = 50;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ActuatorIntensity {
+    pub actuator_index: u32,
+    pub intensity: f64,
+}
+
+impl DeviceAction {
+    // Пары (индекс привода, скорость) для команд Buttplug
+    pub fn actuator_speeds(&self) -> Vec<(u32, f64)> {
+        self.actuator_intensities.iter()
+            .map(|actuator_intensity| (actuator_intensity.actuator_index, actuator_intensity.intensity))
+            .collect()
+    }
+
+    // Интенсивность привода: его собственная запись или общая intensity
+    pub fn actuator_intensity(&self, actuator_index: u32) -> f64 {
+        self.actuator_intensities.iter()
+            .find(|actuator_intensity| actuator_intensity.actuator_index == actuator_index)
+            .map_or(self.intensity, |actuator_intensity| actuator_intensity.intensity)
+    }
+
+    // Копия действия с интенсивностями, умноженными на factor (в т.ч. по приводам)
+    pub fn with_scaled_intensity(&self, factor: f64) -> DeviceAction {
+        let mut scaled_action = self.clone();
+        scaled_action.intensity *= factor;
+        for actuator_intensity in &mut scaled_action.actuator_intensities {
+            actuator_intensity.intensity *= factor;
+        }
+        for pattern_point in &mut scaled_action.pattern_points {
+            pattern_point.intensity *= factor;
+        }
+        scaled_action
+    }
+
+    // Точки паттерна по возрастанию времени (в редакторе их можно перетаскивать в любом порядке)
+    pub fn sorted_pattern_points(&self) -> Vec<PatternPoint> {
+        let mut sorted_points = self.pattern_points.clone();
+        sorted_points.sort_by(|left, right| left.time_fraction.total_cmp(&right.time_fraction));
+        sorted_points
+    }
+
+    // Интенсивность паттерна в момент time_fraction: линейная интерполяция, за краями - крайние точки
+    pub fn pattern_intensity_at(&self, time_fraction: f64) -> f64 {
+        let sorted_points = self.sorted_pattern_points();
+        let (Some(first_point), Some(last_point)) = (sorted_points.first(), sorted_points.last()) else {
+            return self.intensity;
+        };
+        if time_fraction <= first_point.time_fraction {
+            return first_point.intensity;
+        }
+        if time_fraction >= last_point.time_fraction {
+            return last_point.intensity;
+        }
+        for segment in sorted_points.windows(2) {
+            let (segment_start, segment_end) = (segment[0], segment[1]);
+            if time_fraction <= segment_end.time_fraction {
+                let segment_length = segment_end.time_fraction - segment_start.time_fraction;
+                if segment_length <= f64::EPSILON {
+                    return segment_end.intensity;
+                }
+                let position = (time_fraction - segment_start.time_fraction) / segment_length;
+                return segment_start.intensity + (segment_end.intensity - segment_start.intensity) * position;
+            }
+        }
+        last_point.intensity
+    }
+
+    // Паттерн как последовательность шагов (интенсивность, длительность мс) для проигрывания на устройстве
+    pub fn pattern_steps(&self) -> Vec<(f64, u64)> {
+        let step_count = self.duration_milliseconds.div_ceil(PATTERN_STEP_MILLISECONDS).max(1);
+        (0..step_count)
+            .map(|step_index| {
+                let time_fraction = (step_index as f64 + 0.5) / step_count as f64;
+                (self.pattern_intensity_at(time_fraction).clamp(0.0, 1.0), PATTERN_STEP_MILLISECONDS)
+            })
+            .collect()
+    }
 }
 
 fn default_intensity() -> f64 { 0.5 }
 fn default_duration() -> u64 { 500 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+// Готовые значения интенсивности/длительности для формы добавления действия.
+// Встроенные "Легкий/Средний/Сильный" можно изменить или дополнить в файле конфигурации.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EffectPreset {
+    pub name: String,
+    #[serde(default = "default_intensity")]
+    pub intensity: f64,
+    #[serde(default = "default_duration")]
+    pub duration_milliseconds: u64,
+}
+
+fn default_effect_presets() -> Vec<EffectPreset> {
+    vec![
+        EffectPreset { name: "Легкий".to_string(), intensity: 0.3, duration_milliseconds: 300 },
+        EffectPreset { name: "Средний".to_string(), intensity: 0.6, duration_milliseconds: 500 },
+        EffectPreset { name: "Сильный".to_string(), intensity: 1.0, duration_milliseconds: 800 },
+    ]
+}
+
+// Именованный набор устройств, которые всегда получают команды вместе (например, парные игрушки).
+// Адрес устройства - см. buttplug_connector::device_address.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DeviceGroupSetting {
+    pub name: String,
+    #[serde(default)]
+    pub member_addresses: Vec<String>,
+}
+
+// Настройки агрегированного сигнала "интенсивность боя" (0.0..=1.0).
+// Сигнал экспоненциально затухает и получает толчки от отслеживаемых событий.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CombatIntensitySettings {
+    #[serde(default = "default_combat_intensity_decay_per_second")]
+    pub decay_per_second: f64, // Доля сигнала, теряемая за секунду
+    #[serde(default = "default_damage_bump_weight")]
+    pub damage_bump_weight: f64, // Толчок на каждый процент потерянного здоровья
+    #[serde(default = "default_fire_bump_weight")]
+    pub fire_bump_weight: f64, // Толчок на каждый выстрел
+}
+
+fn default_combat_intensity_decay_per_second() -> f64 { 0.3 }
+fn default_damage_bump_weight() -> f64 { 0.02 }
+fn default_fire_bump_weight() -> f64 { 0.15 }
+
+impl Default for CombatIntensitySettings {
+    fn default() -> Self {
+        Self {
+            decay_per_second: default_combat_intensity_decay_per_second(),
+            damage_bump_weight: default_damage_bump_weight(),
+            fire_bump_weight: default_fire_bump_weight(),
+        }
+    }
+}
+
+// Временное усиление ("burst"): на время duration_milliseconds интенсивность игровых эффектов
+// умножается на multiplier (меньше 1 - временное ослабление). Включается клавишей hotkey (имя клавиши egui,
+// например "F8"; работает, пока окно приложения в фокусе) или кнопкой в верхней панели.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BurstModifierSettings {
+    #[serde(default = "default_burst_multiplier")]
+    pub multiplier: f64,
+    #[serde(default = "default_burst_duration_milliseconds")]
+    pub duration_milliseconds: u64,
+    #[serde(default = "default_burst_hotkey")]
+    pub hotkey: String,
+}
+
+fn default_burst_multiplier() -> f64 { 1.5 }
+fn default_burst_duration_milliseconds() -> u64 { 5000 }
+fn default_burst_hotkey() -> String { "F8".to_string() }
+
+impl Default for BurstModifierSettings {
+    fn default() -> Self {
+        Self {
+            multiplier: default_burst_multiplier(),
+            duration_milliseconds: default_burst_duration_milliseconds(),
+            hotkey: default_burst_hotkey(),
+        }
+    }
+}
+
+// Короткий слабый импульс на только что подключенное устройство - подтверждение, что оно отвечает.
+// Не отправляется без вибрации и пока вывод после автозапуска ждет подтверждения.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ConnectPulseSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_connect_pulse_intensity")]
+    pub intensity: f64,
+    #[serde(default = "default_connect_pulse_duration_milliseconds")]
+    pub duration_milliseconds: u64,
+    // Тот же импульс при выборе устройства в списке GUI - чтобы найти, какое из устройств выбрано
+    #[serde(default)]
+    pub on_device_select: bool,
+}
+
+fn default_connect_pulse_intensity() -> f64 { 0.2 }
+fn default_connect_pulse_duration_milliseconds() -> u64 { 150 }
+
+impl Default for ConnectPulseSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            intensity: default_connect_pulse_intensity(),
+            duration_milliseconds: default_connect_pulse_duration_milliseconds(),
+            on_device_select: false,
+        }
+    }
+}
+
+// Какие найденные при сканировании устройства принимать (чтобы не управлять чужими BLE-устройствами рядом).
+// Шаблон - подстрока адреса устройства без учета регистра (адрес сейчас равен имени,
+// см. buttplug_connector::device_address). Черный список важнее белого; пустой белый список пропускает все.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct DeviceFilterSettings {
+    #[serde(default)]
+    pub allowed_patterns: Vec<String>,
+    #[serde(default)]
+    pub blocked_patterns: Vec<String>,
+}
+
+impl DeviceFilterSettings {
+    // Причина отказа устройству или None, если его можно принять
+    pub fn rejection_reason(&self, device_address: &str) -> Option<String> {
+        let normalized_address = device_address.to_lowercase();
+        let matching_pattern = |patterns: &[String]| {
+            patterns.iter()
+                .map(|pattern| pattern.trim())
+                .find(|pattern| !pattern.is_empty() && normalized_address.contains(&pattern.to_lowercase()))
+                .map(str::to_string)
+        };
+        if let Some(blocked_pattern) = matching_pattern(&self.blocked_patterns) {
+            return Some(format!("в черном списке (шаблон '{}')", blocked_pattern));
+        }
+        let has_allowed_patterns = self.allowed_patterns.iter().any(|pattern| !pattern.trim().is_empty());
+        if has_allowed_patterns && matching_pattern(&self.allowed_patterns).is_none() {
+            return Some("не подходит ни под один шаблон белого списка".to_string());
+        }
+        None
+    }
+}
+
+// Возвращение War Thunder в бой после отключения или ангара - граница сессии. Состояние сессии
+// (лимиты эффектов, кулдауны, непрерывный вывод) сбрасывается всегда, здесь - только уведомление о готовности.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BattleReturnSettings {
+    #[serde(default = "default_true")]
+    pub announce_ready: bool, // Запись о готовности в лог
+    #[serde(default)]
+    pub confirmation_pulse: bool, // Импульс на устройство по умолчанию (интенсивность и длительность - из connect_pulse)
+}
+
+impl Default for BattleReturnSettings {
+    fn default() -> Self {
+        Self {
+            announce_ready: true,
+            confirmation_pulse: false,
+        }
+    }
+}
+
+// Минимальное время включения мотора для устройства (адрес - см. buttplug_connector::device_address).
+// Слабые моторы не успевают раскрутиться за 50 мс, и короткий сигнал просто не ощущается.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DeviceMinimumOnTimeSetting {
+    pub device_address: String,
+    pub minimum_on_milliseconds: u64,
+}
+
+// Синтетическое событие "жесткий удар" (крушение, жесткая посадка): всплеск перегрузки
+// или потеря всего здоровья у самой земли
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HardImpactSettings {
+    #[serde(default = "default_hard_impact_g_threshold")]
+    pub g_threshold: f64, // Модуль перегрузки, начиная с которого считаем удар
+    #[serde(default = "default_hard_impact_near_ground_altitude")]
+    pub near_ground_altitude: f64, // Ниже этой высоты (altitude_10k) уничтожение считается ударом о землю
+    #[serde(default = "default_hard_impact_cooldown_milliseconds")]
+    pub cooldown_milliseconds: u64, // Одно крушение - один сигнал
+}
+
+fn default_hard_impact_g_threshold() -> f64 { 12.0 }
+fn default_hard_impact_near_ground_altitude() -> f64 { 50.0 }
+fn default_hard_impact_cooldown_milliseconds() -> u64 { 3000 }
+
+impl Default for HardImpactSettings {
+    fn default() -> Self {
+        Self {
+            g_threshold: default_hard_impact_g_threshold(),
+            near_ground_altitude: default_hard_impact_near_ground_altitude(),
+            cooldown_milliseconds: default_hard_impact_cooldown_milliseconds(),
+        }
+    }
+}
+
+// Событие "ремонт завершен": здоровье поднялось через порог снизу вверх.
+// Мгновенные скачки (респаун, полный ремонт/пополнение на аэродроме) отсекаются max_single_step_increase.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RepairCompleteSettings {
+    #[serde(default = "default_repair_health_threshold")]
+    pub health_threshold: f32,
+    #[serde(default = "default_repair_max_single_step_increase")]
+    pub max_single_step_increase: f32,
+}
+
+fn default_repair_health_threshold() -> f32 { 90.0 }
+fn default_repair_max_single_step_increase() -> f32 { 50.0 }
+
+impl Default for RepairCompleteSettings {
+    fn default() -> Self {
+        Self {
+            health_threshold: default_repair_health_threshold(),
+            max_single_step_increase: default_repair_max_single_step_increase(),
+        }
+    }
+}
+
+// Перезарядка: таймера WT не передает, поэтому начало перезарядки - выстрел (уменьшение first_stage_ammo),
+// а длительность задается вручную или оценивается по самому короткому интервалу между выстрелами
+// на текущей технике. Прогресс доступен сопоставлениям как виртуальное поле reload_progress (0.0..=1.0).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ReloadFeedbackSettings {
+    // Длительность перезарядки в секундах; 0 - оценивать по выстрелам
+    #[serde(default)]
+    pub reload_seconds: f64,
+    // Форма нарастания: 1.0 - линейно, больше - интенсивность растет ближе к готовности
+    #[serde(default = "default_reload_curve_exponent")]
+    pub curve_exponent: f64,
+    // Выстрелы чаще этого интервала считаются очередью (автопушки, пулеметы) и оценку не меняют
+    #[serde(default = "default_reload_min_shot_interval_seconds")]
+    pub min_shot_interval_seconds: f64,
+}
+
+fn default_reload_curve_exponent() -> f64 { 2.0 }
+fn default_reload_min_shot_interval_seconds() -> f64 { 1.0 }
+
+impl Default for ReloadFeedbackSettings {
+    fn default() -> Self {
+        Self {
+            reload_seconds: 0.0,
+            curve_exponent: default_reload_curve_exponent(),
+            min_shot_interval_seconds: default_reload_min_shot_interval_seconds(),
+        }
+    }
+}
+
+// Тихие часы: в этом интервале местного времени игровые события не выводятся на устройства
+// (логи и подсветка правил продолжают работать). Время - "ЧЧ:ММ"; интервал может переходить через полночь.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct QuietHoursSettings {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_quiet_hours_start")]
+    pub start: String,
+    #[serde(default = "default_quiet_hours_end")]
+    pub end: String,
+}
+
+fn default_quiet_hours_start() -> String { "23:00".to_string() }
+fn default_quiet_hours_end() -> String { "07:00".to_string() }
+
+impl Default for QuietHoursSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            start: default_quiet_hours_start(),
+            end: default_quiet_hours_end(),
+        }
+    }
+}
+
+impl QuietHoursSettings {
+    pub fn parse_time(time_text: &str) -> Result<chrono::NaiveTime, String> {
+        chrono::NaiveTime::parse_from_str(time_text.trim(), "%H:%M")
+            .map_err(|_| format!("Некорректное время тихих часов '{}' (ожидается ЧЧ:ММ)", time_text))
+    }
+
+    // Попадает ли время в интервал [start, end). Начало позже конца - интервал через полночь.
+    pub fn contains(&self, local_time: chrono::NaiveTime) -> Result<bool, String> {
+        let start_time = Self::parse_time(&self.start)?;
+        let end_time = Self::parse_time(&self.end)?;
+        Ok(if start_time <= end_time {
+            start_time <= local_time && local_time < end_time
+        } else {
+            local_time >= start_time || local_time < end_time
+        })
+    }
+}
+
+// Пороги скоростей для одного класса самолетов. Скорость - поле speed из /indicators
+// (для самолетов это показание указателя приборной скорости, км/ч).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FlightEnvelopeProfile {
+    pub name: String,
+    // Подстрока поля type (без учета регистра); пустая - профиль подходит любому самолету
+    #[serde(default)]
+    pub vehicle_type_pattern: String,
+    pub stall_speed: f32, // Скорость сваливания
+    #[serde(default = "default_stall_warning_margin")]
+    pub stall_warning_margin: f32, // Предупреждение, когда запас до сваливания меньше этого значения
+    pub overspeed_speed: f32, // Предельная скорость по прочности
+    #[serde(default = "default_flight_envelope_hysteresis")]
+    pub hysteresis: f32, // Насколько нужно уйти от порога обратно, чтобы событие сработало снова
+}
+
+fn default_stall_warning_margin() -> f32 { 40.0 }
+fn default_flight_envelope_hysteresis() -> f32 { 15.0 }
+
+// События "близко к сваливанию" и "превышение скорости" для авиации.
+// Профили перебираются по порядку, используется первый подходящий.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FlightEnvelopeSettings {
+    #[serde(default = "default_flight_envelope_min_altitude")]
+    pub min_altitude: f32, // Ниже этой высоты (altitude_10k) сваливание не проверяем: разбег, посадка, рулежка
+    #[serde(default = "default_flight_envelope_profiles")]
+    pub profiles: Vec<FlightEnvelopeProfile>,
+}
+
+fn default_flight_envelope_min_altitude() -> f32 { 50.0 }
+
+fn default_flight_envelope_profiles() -> Vec<FlightEnvelopeProfile> {
+    vec![FlightEnvelopeProfile {
+        name: "Все самолеты".to_string(),
+        vehicle_type_pattern: String::new(),
+        stall_speed: 180.0,
+        stall_warning_margin: default_stall_warning_margin(),
+        overspeed_speed: 800.0,
+        hysteresis: default_flight_envelope_hysteresis(),
+    }]
+}
+
+impl Default for FlightEnvelopeSettings {
+    fn default() -> Self {
+        Self {
+            min_altitude: default_flight_envelope_min_altitude(),
+            profiles: default_flight_envelope_profiles(),
+        }
+    }
+}
+
+impl FlightEnvelopeSettings {
+    pub fn profile_for_vehicle(&self, vehicle_type: &str) -> Option<&FlightEnvelopeProfile> {
+        let vehicle_type = vehicle_type.to_lowercase();
+        self.profiles.iter()
+            .find(|profile| vehicle_type.contains(&profile.vehicle_type_pattern.to_lowercase()))
+    }
+}
+
+// Именованная тестовая последовательность для демонстрации настройки без игры
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TestSequence {
+    pub name: String,
+    #[serde(default)]
+    pub steps: Vec<TestSequenceStep>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TestSequenceStep {
+    pub intensity: f64, // 0.0 - пауза (устройство остановлено)
+    pub duration_milliseconds: u64,
+}
+
+// Слой правил: набор правил, который накладывается поверх основных event_actions, пока слой активен.
+// Правило слоя с тем же именем, что и правило ниже, заменяет его; новые имена добавляются в конец.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RuleLayerSetting {
+    pub name: String,
+    #[serde(default)]
+    pub event_actions: Vec<EventActionSetting>,
+}
+
+// Сглаживание шумного поля индикаторов экспоненциальным средним (EMA):
+// сглаженное = alpha * новое + (1 - alpha) * прошлое сглаженное. alpha = 1.0 - без сглаживания.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IndicatorSmoothingSetting {
+    pub field: String,
+    #[serde(default = "default_smoothing_alpha")]
+    pub alpha: f64,
+}
+
+pub fn default_smoothing_alpha() -> f64 { 0.3 }
+
+// Непрерывное сопоставление: значение поля (в т.ч. виртуального, например "combat_intensity")
+// линейно переводится в интенсивность вибрации.
+// Составное сопоставление: к основному полю (вес 1.0) добавляются additional_terms,
+// нормализованные значения объединяются по combine_mode.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ContinuousMappingSetting {
+    pub name: String,
+    pub enabled: bool,
+    pub source_field: String,
+    #[serde(default)]
+    pub input_min: f64,
+    #[serde(default = "default_mapping_input_max")]
+    pub input_max: f64,
+    #[serde(default = "default_mapping_max_intensity")]
+    pub max_intensity: f64,
+    #[serde(default)]
+    pub additional_terms: Vec<MappingTerm>,
+    #[serde(default)]
+    pub combine_mode: TermCombineMode,
+    // Итоговая интенсивность ниже порога считается нулем (устройство останавливается),
+    // чтобы моторы не "пищали" на почти нулевой скорости
+    #[serde(default)]
+    pub deadzone: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MappingTerm {
+    pub field: String,
+    #[serde(default)]
+    pub input_min: f64,
+    #[serde(default = "default_mapping_input_max")]
+    pub input_max: f64,
+    #[serde(default = "default_term_weight")]
+    pub weight: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum TermCombineMode {
+    #[default]
+    WeightedSum, // Сумма вес*значение, обрезанная до 1.0
+    Max,         // Максимум из вес*значение
+}
+
+fn default_mapping_input_max() -> f64 { 1.0 }
+fn default_mapping_max_intensity() -> f64 { 1.0 }
+fn default_term_weight() -> f64 { 1.0 }
+
+// Способ подключения к серверу Buttplug
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum ButtplugConnectorKind {
+    #[default]
+    InProcess, // Встроенный сервер внутри приложения
+    WebSocket, // Внешний сервер (Intiface Central) по buttplug_server_address
+}
+
+// Дополнительный HTTP заголовок запросов к War Thunder API (например, токен прокси).
+// Значение может быть секретом, поэтому в Debug (а значит, и в логах) оно скрыто.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct HttpHeaderSetting {
+    pub name: String,
+    #[serde(default)]
+    pub value: String,
+}
+
+impl fmt::Debug for HttpHeaderSetting {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.debug_struct("HttpHeaderSetting").field("name", &self.name).field("value", &"***").finish()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct BasicAuthSetting {
+    pub username: String,
+    #[serde(default)]
+    pub password: String,
+}
+
+impl fmt::Debug for BasicAuthSetting {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.debug_struct("BasicAuthSetting").field("username", &self.username).field("password", &"***").finish()
+    }
+}
+
+// Адрес и авторизация War Thunder API. По умолчанию - локальный сервер игры без авторизации;
+// остальное нужно, когда API проксируется или открыто с другого ПК.
+// Секреты хранятся в файле настроек открытым текстом - не делитесь этим файлом.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WarThunderApiSettings {
+    #[serde(default = "default_war_thunder_api_base_url")]
+    pub base_url: String, // Без завершающего "/": к нему добавляются /indicators и /state
+    #[serde(default)]
+    pub request_headers: Vec<HttpHeaderSetting>,
+    #[serde(default)]
+    pub basic_auth: Option<BasicAuthSetting>,
+}
+
+pub fn default_war_thunder_api_base_url() -> String { "http://localhost:8111".to_string() }
+
+impl Default for WarThunderApiSettings {
+    fn default() -> Self {
+        Self {
+            base_url: default_war_thunder_api_base_url(),
+            request_headers: Vec::new(),
+            basic_auth: None,
+        }
+    }
+}
+
+// Где выполняются фоновые задачи (опрос WT, Buttplug, API управления). Читается только при запуске.
+// MultiThread - обычный многопоточный рантайм Tokio: по потоку на ядро, задачи не мешают друг другу.
+// DedicatedThread - однопоточный рантайм в одном отдельном потоке: меньше потоков и памяти на слабых ПК,
+// но все задачи делят один поток - долгая операция в одной (например, блокирующий вызов драйвера
+// Bluetooth во встроенном сервере) задерживает опрос WT и команды устройствам.
+// В обоих случаях GUI работает в своем потоке и с фоновыми задачами общается только через каналы.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum AsyncRuntimeKind {
+    #[default]
+    MultiThread,
+    DedicatedThread,
+}
+
+// Что делать, когда у правила уже запущено максимальное число эффектов
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum ConcurrencyLimitPolicy {
+    #[default]
+    DropNew, // Новое срабатывание игнорируется
+    Restart, // Самый старый эффект вытесняется, новое срабатывание запускается заново
+}
+
+// Что делать, если правило адресовано конкретному устройству, а оно сейчас не подключено
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum MissingDeviceBehavior {
+    SkipSilently,
+    #[default]
+    SkipWithWarning, // Пропустить и записать предупреждение в лог событий
+    FallBackToDefaultDevice, // Отправить на устройство по умолчанию (выбранное в списке или первое)
+}
+
+// Сколько правил из event_actions может сработать на одном обновлении индикаторов
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum RuleEvaluationMode {
+    #[default]
+    AllMatching, // Срабатывают все правила, чьи условия выполнились
+    FirstMatch, // Срабатывает только первое по приоритету; остальные в этом обновлении пропускаются
+}
+
+// Как сводить несколько вибраций одному устройству из разных правил в одном обновлении индикаторов
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum SimultaneousVibrationPolicy {
+    #[default]
+    Max, // Самая сильная из вибраций
+    SumClamped, // Сумма интенсивностей, не больше 1.0
+    Last, // Последняя по порядку правил (как без слияния)
+}
+
+// Класс техники (см. game_event_processor::classify_vehicle)
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VehicleClass {
+    Ground,
+    Air,
+    Naval,
+}
+
+impl VehicleClass {
+    pub const ALL: [VehicleClass; 3] = [VehicleClass::Ground, VehicleClass::Air, VehicleClass::Naval];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            VehicleClass::Ground => "Наземная",
+            VehicleClass::Air => "Авиация",
+            VehicleClass::Naval => "Флот",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct EventActionSetting {
     pub name: String,
     pub enabled: bool,
     pub device_action: DeviceAction,
+    // Сколько эффектов правила может быть активно одновременно (0 - без ограничения).
+    // Нужно для скорострельных событий, чтобы команды не накапливались.
+    #[serde(default)]
+    pub max_concurrent_effects: u32,
+    #[serde(default)]
+    pub concurrency_limit_policy: ConcurrencyLimitPolicy,
+    // Условие срабатывания на мини-языке (см. condition_expression.rs), например
+    // "health_percentage < 30 AND speed > 0". Правило срабатывает, когда условие становится истинным.
+    // None - старая проверка по имени правила.
+    #[serde(default)]
+    pub condition_expression: Option<String>,
+    // Для какой техники действует правило. Пусто - для любой, в том числе неопознанной.
+    #[serde(default)]
+    pub vehicle_classes: Vec<VehicleClass>,
+    // Порядок проверки: правила с большим приоритетом проверяются раньше.
+    // При равном приоритете - порядок в списке (правила слоев - на месте перекрытого правила или в конце).
+    #[serde(default)]
+    pub priority: i32,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ApplicationSettings {
+    // Версия формата файла; старые файлы приводятся к текущей в SettingsStore::migrate
+    pub settings_format_version: u32,
     pub application_name: String,
     pub polling_interval_milliseconds: u64,
+    // Таймаут одного запроса к War Thunder: зависший сервер не должен задерживать опрос
+    #[serde(default = "default_war_thunder_request_timeout_milliseconds")]
+    pub war_thunder_request_timeout_milliseconds: u64,
+    #[serde(default)]
+    pub war_thunder_api: WarThunderApiSettings,
     pub buttplug_server_address: String,
     #[serde(default)]
+    pub buttplug_connector_kind: ButtplugConnectorKind,
+    #[serde(default)]
+    pub async_runtime_kind: AsyncRuntimeKind,
+    #[serde(default)]
     pub event_actions: Vec<EventActionSetting>,
+    #[serde(default)]
+    pub rule_layers: Vec<RuleLayerSetting>,
+    // Имена активных слоев в порядке наложения: более поздний слой перекрывает более ранние
+    #[serde(default)]
+    pub active_rule_layers: Vec<String>,
+    #[serde(default)]
+    pub continuous_mappings: Vec<ContinuousMappingSetting>,
+    #[serde(default)]
+    pub combat_intensity: CombatIntensitySettings,
+    #[serde(default)]
+    pub device_groups: Vec<DeviceGroupSetting>,
+    #[serde(default)]
+    pub missing_device_behavior: MissingDeviceBehavior,
+    #[serde(default)]
+    pub rule_evaluation_mode: RuleEvaluationMode,
+    #[serde(default)]
+    pub simultaneous_vibration_policy: SimultaneousVibrationPolicy,
+    #[serde(default)]
+    pub test_sequences: Vec<TestSequence>,
+    #[serde(default = "default_effect_presets")]
+    pub effect_presets: Vec<EffectPreset>,
+    // Сколько секунд длится сканирование устройств (0 - до отключения)
+    #[serde(default = "default_scan_timeout_seconds")]
+    pub scan_timeout_seconds: u64,
+    // Отключаться от Buttplug, если столько минут не было команд устройствам и обработка выключена (0 - никогда)
+    #[serde(default)]
+    pub buttplug_idle_disconnect_minutes: u64,
+    #[serde(default = "default_true")]
+    pub rule_trigger_flash_enabled: bool, // Подсветка правила в редакторе при срабатывании/тесте
+    #[serde(default)]
+    pub rule_trigger_beep_enabled: bool, // Звуковой сигнал (только со сборкой с feature "sound_feedback")
+    // Ниже этого здоровья (%) правила урона не срабатывают, чтобы не жужжать непрерывно при агонии.
+    // 0 - проверка выключена.
+    #[serde(default)]
+    pub damage_rules_min_health_percentage: f32,
+    // Однократный сигнал при падении здоровья ниже порога выше (None - без сигнала)
+    #[serde(default)]
+    pub critical_health_action: Option<DeviceAction>,
+    // Сигнал на жесткий удар (None - событие выключено)
+    #[serde(default)]
+    pub hard_impact_action: Option<DeviceAction>,
+    #[serde(default)]
+    pub hard_impact: HardImpactSettings,
+    // Сигнал "ремонт завершен" (None - событие выключено)
+    #[serde(default)]
+    pub repair_complete_action: Option<DeviceAction>,
+    #[serde(default)]
+    pub repair_complete: RepairCompleteSettings,
+    // Импульс "снаряд готов" по окончании перезарядки (None - выключено)
+    #[serde(default)]
+    pub reload_complete_action: Option<DeviceAction>,
+    #[serde(default)]
+    pub reload_feedback: ReloadFeedbackSettings,
+    #[serde(default)]
+    pub quiet_hours: QuietHoursSettings,
+    // Мягкий пульс при приближении к сваливанию и сильная вибрация при превышении скорости (None - выключено)
+    #[serde(default)]
+    pub stall_warning_action: Option<DeviceAction>,
+    #[serde(default)]
+    pub overspeed_action: Option<DeviceAction>,
+    // Ник игрока в War Thunder: по нему из общего журнала боя (/hudmsg) выбираются свои уничтожения и гибель.
+    // Пусто - журнал не опрашивается.
+    #[serde(default)]
+    pub player_name: String,
+    // Сигналы "игрок уничтожил технику" и "технику игрока уничтожили" (None - выключено)
+    #[serde(default)]
+    pub own_kill_action: Option<DeviceAction>,
+    #[serde(default)]
+    pub own_death_action: Option<DeviceAction>,
+    #[serde(default)]
+    pub flight_envelope: FlightEnvelopeSettings,
+    // Пауза после подключения устройства (нулевая команда + ожидание), прежде чем считать его готовым
+    #[serde(default = "default_device_warmup_milliseconds")]
+    pub device_warmup_milliseconds: u64,
+    #[serde(default)]
+    pub connect_pulse: ConnectPulseSettings,
+    #[serde(default)]
+    pub device_filter: DeviceFilterSettings,
+    #[serde(default)]
+    pub burst_modifier: BurstModifierSettings,
+    // Останавливать эффекты от игровых событий, когда War Thunder отключился или бой закончился (ручные тесты не трогаем)
+    #[serde(default = "default_true")]
+    pub stop_effects_on_wt_disconnect: bool,
+    #[serde(default)]
+    pub battle_return: BattleReturnSettings,
+    // Останавливать все устройства, когда окно приложения теряет фокус (например, alt-tab)
+    #[serde(default)]
+    pub stop_devices_on_focus_loss: bool,
+    // Останавливать все устройства при сворачивании окна. По умолчанию эффекты продолжаются:
+    // фоновые задачи и обработка событий не зависят от видимости окна.
+    // Сворачивание также снимает фокус, поэтому при включенном stop_devices_on_focus_loss
+    // эффекты остановятся независимо от этой настройки.
+    #[serde(default)]
+    pub stop_devices_on_minimize: bool,
+    // Компактное окно поверх остальных вместо полного интерфейса (запоминается между запусками)
+    #[serde(default)]
+    pub mini_mode_enabled: bool,
+    // Режим разработчика: панель имитации игровых событий для проверки правил без игры
+    #[serde(default)]
+    pub developer_mode_enabled: bool,
+    // Расширенный режим: панель сырых команд устройствам (RawWrite/RawRead). Выключен, чтобы случайно
+    // не отправить устройству байты, которые оно поймет неправильно.
+    #[serde(default)]
+    pub advanced_mode_enabled: bool,
+    // Поля индикаторов, временно исключенные из срабатываний: правила и сопоставления,
+    // которые на них ссылаются, пропускаются (удобно при отладке, вместо выключения правил по одному)
+    #[serde(default)]
+    pub ignored_indicator_fields: Vec<String>,
+    // Сглаживание применяется до проверки условий, детекторов изменений и сопоставлений
+    #[serde(default)]
+    pub indicator_smoothing: Vec<IndicatorSmoothingSetting>,
+    // Плавная остановка: за сколько мс скорость снижается до нуля (0 - мгновенно). Экстренная остановка всех устройств всегда мгновенная.
+    #[serde(default)]
+    pub stop_fade_milliseconds: u64,
+    // Каждая команда устройству пишется в лог (уровень debug) с приводами, скоростями и временем выполнения.
+    // Для разбора жалоб на задержки; в обычной работе выключено.
+    #[serde(default)]
+    pub verbose_device_command_logging: bool,
+    // Любая ненулевая скорость поднимается хотя бы до этого значения, чтобы слабые эффекты трогали мотор;
+    // ноль остается нулем. Применяется после мертвой зоны сопоставлений (она по-прежнему обнуляет слабый сигнал)
+    // и до округления до ступеней привода. Минимальная длительность включения устройства действует независимо.
+    #[serde(default = "default_intensity_floor")]
+    pub intensity_floor: f64,
+    // Короткие включения в паттернах и последовательностях продлеваются до минимума устройства
+    #[serde(default)]
+    pub device_minimum_on_times: Vec<DeviceMinimumOnTimeSetting>,
+    // Адреса устройств, которые stop() останавливает не полностью: вместо него каждому приводу
+    // отправляется явный ноль (см. buttplug_connector::DeviceStopStrategy)
+    #[serde(default)]
+    pub explicit_zero_stop_device_addresses: Vec<String>,
+    // Мягкий старт: после включения обработки интенсивность игровых эффектов растет от 0 до полной
+    // за столько мс (0 - сразу полная). Ручные тесты не масштабируются.
+    #[serde(default)]
+    pub processing_ramp_milliseconds: u64,
+    // Строгая проверка: при загрузке сообщать о неизвестных ключах (опечатках) в файле.
+    // Загрузка при этом не прерывается - неизвестные ключи по-прежнему игнорируются.
+    #[serde(default)]
+    pub strict_config_validation: bool,
+    // Сколько раз повторять команду устройству после временной ошибки связи (сбой BLE). 0 - без повторов.
+    #[serde(default = "default_device_command_retry_count")]
+    pub device_command_retry_count: u32,
+    // Автозапуск при старте приложения. Автоматически запущенная обработка не управляет устройствами,
+    // пока пользователь не разрешит вывод (см. is_output_armed в application.rs).
+    #[serde(default)]
+    pub auto_connect_buttplug: bool,
+    #[serde(default)]
+    pub auto_start_processing: bool,
+    // Локальный HTTP API для внешних инструментов (только со сборкой с feature "control_api").
+    // По умолчанию доступен только с этого компьютера; без токена не запускается.
+    #[serde(default)]
+    pub control_api_enabled: bool,
+    #[serde(default = "default_control_api_bind_address")]
+    pub control_api_bind_address: String,
+    #[serde(default = "default_control_api_port")]
+    pub control_api_port: u16,
+    #[serde(default)]
+    pub control_api_token: String,
+    // Сохранять конфигурацию автоматически вскоре после любого изменения
+    #[serde(default)]
+    pub autosave_on_change: bool,
 }
 
+fn default_device_warmup_milliseconds() -> u64 { 500 }
+
+fn default_true() -> bool { true }
+
+fn default_scan_timeout_seconds() -> u64 { 15 }
+
+fn default_control_api_bind_address() -> String { "127.0.0.1".to_string() }
+fn default_control_api_port() -> u16 { 8765 }
+fn default_war_thunder_request_timeout_milliseconds() -> u64 { 1000 }
+fn default_device_command_retry_count() -> u32 { 2 }
+fn default_intensity_floor() -> f64 { 0.05 }
+
 impl Default for ApplicationSettings {
     fn default() -> Self {
         Self {
+            settings_format_version: SETTINGS_FORMAT_VERSION,
             application_name: "WarThunder Haptics GUI (Default)".to_string(),
             polling_interval_milliseconds: 250,
+            war_thunder_request_timeout_milliseconds: 1000,
+            war_thunder_api: WarThunderApiSettings::default(),
             buttplug_server_address: "ws://127.0.0.1:12345".to_string(),
+            buttplug_connector_kind: ButtplugConnectorKind::InProcess,
+            async_runtime_kind: AsyncRuntimeKind::MultiThread,
             event_actions: vec![
                 EventActionSetting {
                     name: "Пример: Легкая вибрация при старте".to_string(),
-                    enabled: true,
+                    // Выключено: при первом запуске ничего не должно вибрировать, пока пользователь сам не включит правило
+                    enabled: false,
                     device_action: DeviceAction {
                         action_type: DeviceActionType::Vibrate,
                         intensity: 0.3,
                         duration_milliseconds: 1000,
-                    }
+                        target: ActionTarget::SelectedDevice,
+                        actuator_intensities: Vec::new(),
+                        pattern_points: Vec::new(),
+                    },
+                    max_concurrent_effects: 0,
+                    concurrency_limit_policy: ConcurrencyLimitPolicy::DropNew,
+                    condition_expression: None,
+                    vehicle_classes: Vec::new(),
+                    priority: 0,
                 }
             ],
+            rule_layers: Vec::new(),
+            active_rule_layers: Vec::new(),
+            continuous_mappings: Vec::new(),
+            combat_intensity: CombatIntensitySettings::default(),
+            device_groups: Vec::new(),
+            missing_device_behavior: MissingDeviceBehavior::SkipWithWarning,
+            rule_evaluation_mode: RuleEvaluationMode::AllMatching,
+            simultaneous_vibration_policy: SimultaneousVibrationPolicy::Max,
+            scan_timeout_seconds: default_scan_timeout_seconds(),
+            buttplug_idle_disconnect_minutes: 0,
+            test_sequences: vec![TestSequence {
+                name: "Демо: нарастание".to_string(),
+                steps: vec![
+                    TestSequenceStep { intensity: 0.2, duration_milliseconds: 500 },
+                    TestSequenceStep { intensity: 0.5, duration_milliseconds: 500 },
+                    TestSequenceStep { intensity: 1.0, duration_milliseconds: 500 },
+                    TestSequenceStep { intensity: 0.0, duration_milliseconds: 300 },
+                    TestSequenceStep { intensity: 1.0, duration_milliseconds: 200 },
+                ],
+            }],
+            effect_presets: default_effect_presets(),
+            rule_trigger_flash_enabled: true,
+            rule_trigger_beep_enabled: false,
+            damage_rules_min_health_percentage: 0.0,
+            critical_health_action: None,
+            hard_impact_action: None,
+            hard_impact: HardImpactSettings::default(),
+            repair_complete_action: None,
+            repair_complete: RepairCompleteSettings::default(),
+            reload_complete_action: None,
+            reload_feedback: ReloadFeedbackSettings::default(),
+            quiet_hours: QuietHoursSettings::default(),
+            stall_warning_action: None,
+            overspeed_action: None,
+            player_name: String::new(),
+            own_kill_action: None,
+            own_death_action: None,
+            flight_envelope: FlightEnvelopeSettings::default(),
+            device_warmup_milliseconds: default_device_warmup_milliseconds(),
+            connect_pulse: ConnectPulseSettings::default(),
+            device_filter: DeviceFilterSettings::default(),
+            burst_modifier: BurstModifierSettings::default(),
+            stop_effects_on_wt_disconnect: true,
+            battle_return: BattleReturnSettings::default(),
+            autosave_on_change: false,
+            stop_devices_on_focus_loss: false,
+            stop_devices_on_minimize: false,
+            mini_mode_enabled: false,
+            developer_mode_enabled: false,
+            advanced_mode_enabled: false,
+            processing_ramp_milliseconds: 0,
+            strict_config_validation: false,
+            device_command_retry_count: default_device_command_retry_count(),
+            ignored_indicator_fields: Vec::new(),
+            indicator_smoothing: Vec::new(),
+            stop_fade_milliseconds: 0,
+            verbose_device_command_logging: false,
+            intensity_floor: default_intensity_floor(),
+            device_minimum_on_times: Vec::new(),
+            explicit_zero_stop_device_addresses: Vec::new(),
+            auto_connect_buttplug: false,
+            auto_start_processing: false,
+            control_api_enabled: false,
+            control_api_bind_address: default_control_api_bind_address(),
+            control_api_port: default_control_api_port(),
+            control_api_token: String::new(),
+        }
+    }
+}
+
+// Путь из командной строки (--config), задается один раз при старте
+static CONFIG_PATH_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+pub fn set_config_path_override(config_file_path: PathBuf) -> Result<(), String> {
+    CONFIG_PATH_OVERRIDE.set(config_file_path)
+        .map_err(|_| "Путь к файлу конфигурации уже задан.".to_string())
+}
+
+// Путь к активному файлу конфигурации (для отображения в GUI)
+pub fn active_config_path() -> Result<PathBuf, String> {
+    SettingsStore::resolve().map(|settings_store| settings_store.config_file_path().to_path_buf())
+}
+
+// Отчет об устройствах кладем рядом с конфигурацией
+pub fn device_report_path() -> Result<PathBuf, String> {
+    Ok(SettingsStore::resolve()?.sibling_path("device_report.json"))
+}
+
+// Счетчики срабатываний правил живут в отдельном файле рядом с конфигурацией: они растут во время боя,
+// и запись в settings.toml сбивала бы признак несохраненных изменений настроек
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RuleStatistics {
+    #[serde(default)]
+    pub trigger_counts: BTreeMap<String, u64>, // Имя правила -> сколько раз сработало
+}
+
+pub fn rule_statistics_path() -> Result<PathBuf, String> {
+    Ok(SettingsStore::resolve()?.sibling_path("rule_statistics.toml"))
+}
+
+// Файла еще нет - счетчики просто пустые
+pub fn load_rule_statistics() -> Result<RuleStatistics, String> {
+    let statistics_path = rule_statistics_path()?;
+    if !statistics_path.exists() {
+        return Ok(RuleStatistics::default());
+    }
+    let statistics_content = fs::read_to_string(&statistics_path)
+        .map_err(|e| format!("Ошибка чтения файла статистики {:?}: {}", statistics_path, e))?;
+    toml::from_str(&statistics_content)
+        .map_err(|e| format!("Ошибка парсинга файла статистики {:?}: {}", statistics_path, e))
+}
+
+pub fn save_rule_statistics(rule_statistics: &RuleStatistics) -> Result<(), String> {
+    let statistics_path = rule_statistics_path()?;
+    let statistics_content = toml::to_string_pretty(rule_statistics)
+        .map_err(|e| format!("Ошибка сериализации статистики правил: {}", e))?;
+    fs::write(&statistics_path, statistics_content)
+        .map_err(|e| format!("Ошибка записи файла статистики {:?}: {}", statistics_path, e))
+}
+
+// Последние боевые индикаторы (и сырой JSON, если его получали) - чтобы писать условия правил
+// без запущенной игры. Отдельный файл рядом с конфигурацией: это данные, а не настройки.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct IndicatorsCache {
+    #[serde(default)]
+    pub indicators: Option<WarThunderIndicators>,
+    #[serde(default)]
+    pub raw_json: Option<String>,
+}
+
+pub fn indicators_cache_path() -> Result<PathBuf, String> {
+    Ok(SettingsStore::resolve()?.sibling_path("indicators_cache.json"))
+}
+
+// Файла еще нет (игру ни разу не видели) - кэш пустой
+pub fn load_indicators_cache() -> Result<IndicatorsCache, String> {
+    let cache_path = indicators_cache_path()?;
+    if !cache_path.exists() {
+        return Ok(IndicatorsCache::default());
+    }
+    let cache_content = fs::read_to_string(&cache_path)
+        .map_err(|e| format!("Ошибка чтения кэша индикаторов {:?}: {}", cache_path, e))?;
+    serde_json::from_str(&cache_content)
+        .map_err(|e| format!("Ошибка парсинга кэша индикаторов {:?}: {}", cache_path, e))
+}
+
+pub fn save_indicators_cache(indicators_cache: &IndicatorsCache) -> Result<(), String> {
+    let cache_path = indicators_cache_path()?;
+    let cache_content = serde_json::to_string_pretty(indicators_cache)
+        .map_err(|e| format!("Ошибка сериализации кэша индикаторов: {}", e))?;
+    fs::write(&cache_path, cache_content)
+        .map_err(|e| format!("Ошибка записи кэша индикаторов {:?}: {}", cache_path, e))
+}
+
+// Файл профиля для обмена: слой правил вместе с группами устройств, на которые ссылаются
+// его правила, и пресетами эффектов. Версия формата растет при несовместимых изменениях.
+pub const PROFILE_FILE_FORMAT_VERSION: u32 = 1;
+const PROFILE_FILE_EXTENSION: &str = "profile.toml";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProfileFile {
+    pub format_version: u32,
+    pub profile: RuleLayerSetting,
+    #[serde(default)]
+    pub device_groups: Vec<DeviceGroupSetting>,
+    #[serde(default)]
+    pub effect_presets: Vec<EffectPreset>,
+}
+
+// Как поступить, если слой с именем импортируемого профиля уже есть
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProfileImportMode {
+    Overwrite, // Заменить слой; группы и пресеты с теми же именами тоже заменяются
+    Rename(String), // Добавить под новым именем; существующие группы и пресеты не трогаются
+}
+
+// Экспортированные профили лежат в подкаталоге profiles рядом с конфигурацией
+pub fn profiles_directory_path() -> Result<PathBuf, String> {
+    let profiles_directory = SettingsStore::resolve()?.sibling_path("profiles");
+    if !profiles_directory.exists() {
+        fs::create_dir_all(&profiles_directory)
+            .map_err(|e| format!("Не удалось создать каталог профилей {:?}: {}", profiles_directory, e))?;
+    }
+    Ok(profiles_directory)
+}
+
+pub fn build_profile_file(settings: &ApplicationSettings, layer_name: &str) -> Result<ProfileFile, String> {
+    let rule_layer = settings.rule_layers.iter()
+        .find(|rule_layer| rule_layer.name == layer_name)
+        .ok_or_else(|| format!("Слой правил '{}' не найден", layer_name))?;
+    let referenced_group_names: Vec<&str> = rule_layer.event_actions.iter()
+        .filter_map(|event_action| match &event_action.device_action.target {
+            ActionTarget::Group(group_name) => Some(group_name.as_str()),
+            _ => None,
+        })
+        .collect();
+    Ok(ProfileFile {
+        format_version: PROFILE_FILE_FORMAT_VERSION,
+        profile: rule_layer.clone(),
+        device_groups: settings.device_groups.iter()
+            .filter(|device_group| referenced_group_names.contains(&device_group.name.as_str()))
+            .cloned()
+            .collect(),
+        effect_presets: settings.effect_presets.clone(),
+    })
+}
+
+// Имя файла из имени профиля: символы, недопустимые в путях, заменяются на "_"
+pub fn profile_file_name(profile_name: &str) -> String {
+    let safe_name: String = profile_name.chars()
+        .map(|character| if character.is_alphanumeric() || character == '-' || character == ' ' { character } else { '_' })
+        .collect();
+    format!("{}.{}", safe_name.trim(), PROFILE_FILE_EXTENSION)
+}
+
+pub fn export_profile(profile_file: &ProfileFile) -> Result<PathBuf, String> {
+    let profile_path = profiles_directory_path()?.join(profile_file_name(&profile_file.profile.name));
+    let profile_content = toml::to_string_pretty(profile_file)
+        .map_err(|e| format!("Ошибка сериализации профиля: {}", e))?;
+    fs::write(&profile_path, profile_content)
+        .map_err(|e| format!("Ошибка записи файла профиля {:?}: {}", profile_path, e))?;
+    Ok(profile_path)
+}
+
+pub fn load_profile_file(profile_path: &Path) -> Result<ProfileFile, String> {
+    let profile_content = fs::read_to_string(profile_path)
+        .map_err(|e| format!("Ошибка чтения файла профиля {:?}: {}", profile_path, e))?;
+    let profile_file: ProfileFile = toml::from_str(&profile_content)
+        .map_err(|e| format!("Ошибка парсинга файла профиля {:?}: {}", profile_path, e))?;
+    if profile_file.format_version > PROFILE_FILE_FORMAT_VERSION {
+        return Err(format!(
+            "Профиль {:?} сохранен более новой версией программы (формат {}, поддерживается до {})",
+            profile_path, profile_file.format_version, PROFILE_FILE_FORMAT_VERSION
+        ));
+    }
+    if profile_file.profile.name.trim().is_empty() {
+        return Err(format!("В профиле {:?} не указано имя", profile_path));
+    }
+    Ok(profile_file)
+}
+
+// Добавляет профиль в настройки. При Rename ссылки правил на группы не переименовываются:
+// группы с уже занятыми именами остаются свои, и правила профиля будут адресованы им.
+pub fn apply_imported_profile(settings: &mut ApplicationSettings, profile_file: ProfileFile, import_mode: ProfileImportMode) {
+    let mut rule_layer = profile_file.profile;
+    let is_overwrite = import_mode == ProfileImportMode::Overwrite;
+    if let ProfileImportMode::Rename(new_name) = import_mode {
+        rule_layer.name = new_name;
+    }
+    match settings.rule_layers.iter_mut().find(|existing_layer| existing_layer.name == rule_layer.name) {
+        Some(existing_layer) => *existing_layer = rule_layer,
+        None => settings.rule_layers.push(rule_layer),
+    }
+    for device_group in profile_file.device_groups {
+        match settings.device_groups.iter_mut().find(|existing_group| existing_group.name == device_group.name) {
+            Some(existing_group) if is_overwrite => *existing_group = device_group,
+            Some(_) => {}
+            None => settings.device_groups.push(device_group),
+        }
+    }
+    for effect_preset in profile_file.effect_presets {
+        match settings.effect_presets.iter_mut().find(|existing_preset| existing_preset.name == effect_preset.name) {
+            Some(existing_preset) if is_overwrite => *existing_preset = effect_preset,
+            Some(_) => {}
+            None => settings.effect_presets.push(effect_preset),
         }
     }
 }
 
-fn get_config_path() -> Result<PathBuf, String> {
-    if let Some(proj_dirs) = ProjectDirs::from("com", "YourAppName", "WarThunderHapticsGUI") {
-        let config_dir = proj_dirs.config_dir();
-        if !config_dir.exists() {
+// Версия формата файла настроек. При несовместимом изменении формата она растет на единицу,
+// а в SETTINGS_MIGRATIONS добавляется шаг с предыдущей версии.
+pub const SETTINGS_FORMAT_VERSION: u32 = 1;
+const SETTINGS_FORMAT_VERSION_KEY: &str = "settings_format_version";
+
+// Шаг SETTINGS_MIGRATIONS[n] переводит сырое дерево файла с версии n на n + 1
+type SettingsMigration = fn(&mut toml::Table) -> Result<(), String>;
+const SETTINGS_MIGRATIONS: [SettingsMigration; SETTINGS_FORMAT_VERSION as usize] = [migrate_settings_from_unversioned];
+
+// 0 -> 1: файлы до появления номера версии. Поля с тех пор только добавлялись со значениями serde
+// по умолчанию, поэтому дерево не меняется - шаг лишь фиксирует, что такие файлы читаются как есть.
+fn migrate_settings_from_unversioned(_raw_table: &mut toml::Table) -> Result<(), String> {
+    Ok(())
+}
+
+// Хранилище настроек без привязки к GUI: где лежит файл, загрузка, миграция, сохранение и поиск неизвестных ключей.
+// Другие интерфейсы (CLI, веб) работают через него и ведут себя в точности как GUI;
+// load_configuration и остальные функции ниже - обертки над хранилищем активного файла.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SettingsStore {
+    config_file_path: PathBuf,
+}
+
+impl SettingsStore {
+    // Активный файл: путь из --config или settings.toml в каталоге конфигурации пользователя.
+    // Каталог создается, если его еще нет.
+    pub fn resolve() -> Result<Self, String> {
+        let config_file_path = match CONFIG_PATH_OVERRIDE.get() {
+            Some(config_file_path) => config_file_path.clone(),
+            None => ProjectDirs::from("com", "YourAppName", "WarThunderHapticsGUI")
+                .map(|proj_dirs| proj_dirs.config_dir().join("settings.toml"))
+                .ok_or_else(|| "Не удалось определить директорию конфигурации.".to_string())?,
+        };
+        if let Some(config_dir) = config_file_path.parent().filter(|dir| !dir.as_os_str().is_empty())
+            && !config_dir.exists()
+        {
             fs::create_dir_all(config_dir).map_err(|e| format!("Не удалось создать директорию конфигурации: {}", e))?;
         }
-        Ok(config_dir.join("settings.toml"))
-    } else {
-        Err("Не удалось определить директорию конфигурации.".to_string())
+        Ok(Self::at_path(config_file_path))
+    }
+
+    // Произвольный файл; каталог не создается
+    pub fn at_path(config_file_path: impl Into<PathBuf>) -> Self {
+        Self { config_file_path: config_file_path.into() }
+    }
+
+    pub fn config_file_path(&self) -> &Path {
+        &self.config_file_path
+    }
+
+    // Служебные файлы (статистика, кэш индикаторов, отчеты, профили) лежат рядом с конфигурацией
+    pub fn sibling_path(&self, file_name: &str) -> PathBuf {
+        self.config_file_path.with_file_name(file_name)
+    }
+
+    // Если файла нет, он создается с настройками по умолчанию; файл старого формата сначала проходит migrate
+    pub fn load(&self) -> Result<ApplicationSettings, String> {
+        if !self.config_file_path.exists() {
+            tracing::warn!("Файл конфигурации {:?} не найден. Будет создан файл с настройками по умолчанию.", self.config_file_path);
+            let default_settings = ApplicationSettings::default();
+            self.save(&default_settings)?; // Сохраняем дефолтный конфиг при первом запуске
+            return Ok(default_settings);
+        }
+        parse_configuration(&self.read_content()?)
+            .map_err(|e| format!("{} (файл {:?})", e, self.config_file_path))
+    }
+
+    // Приводит сырое дерево файла к SETTINGS_FORMAT_VERSION, применяя шаги по порядку начиная с версии файла
+    // (файл без settings_format_version - версия 0). Файл более новой версии программы не загружается:
+    // незнакомые ей поля пропали бы при следующем сохранении.
+    pub fn migrate(mut raw: toml::Value) -> Result<toml::Value, String> {
+        let raw_table = raw.as_table_mut()
+            .ok_or_else(|| "Файл конфигурации должен быть таблицей TOML".to_string())?;
+        let file_version = match raw_table.get(SETTINGS_FORMAT_VERSION_KEY) {
+            None => 0,
+            Some(version_value) => version_value.as_integer()
+                .and_then(|version| u32::try_from(version).ok())
+                .ok_or_else(|| format!("{} должен быть неотрицательным целым числом, получено: {}", SETTINGS_FORMAT_VERSION_KEY, version_value))?,
+        };
+        if file_version > SETTINGS_FORMAT_VERSION {
+            return Err(format!(
+                "Файл конфигурации версии {} создан более новой версией программы (поддерживается до {})",
+                file_version, SETTINGS_FORMAT_VERSION
+            ));
+        }
+        for (from_version, migration) in SETTINGS_MIGRATIONS.iter().enumerate().skip(file_version as usize) {
+            migration(raw_table).map_err(|e| format!("Ошибка миграции настроек с версии {}: {}", from_version, e))?;
+        }
+        raw_table.insert(SETTINGS_FORMAT_VERSION_KEY.to_string(), toml::Value::Integer(i64::from(SETTINGS_FORMAT_VERSION)));
+        Ok(raw)
+    }
+
+    pub fn save(&self, settings: &ApplicationSettings) -> Result<(), String> {
+        fs::write(&self.config_file_path, serialize_configuration(settings)?)
+            .map_err(|e| format!("Ошибка записи файла конфигурации {:?}: {}", self.config_file_path, e))
+    }
+
+    // Неизвестные ключи в файле (см. find_unknown_configuration_keys)
+    pub fn find_unknown_keys(&self) -> Result<Vec<String>, String> {
+        find_unknown_configuration_keys(&self.read_content()?)
+    }
+
+    fn read_content(&self) -> Result<String, String> {
+        fs::read_to_string(&self.config_file_path)
+            .map_err(|e| format!("Ошибка чтения файла конфигурации {:?}: {}", self.config_file_path, e))
     }
 }
 
-pub fn load_configuration() -> Result<ApplicationSettings, String> {
-    let config_file_path = get_config_path()?;
-    
-    if !config_file_path.exists() {
-        tracing::warn!("Файл конфигурации {:?} не найден. Будет создан файл с настройками по умолчанию.", config_file_path);
-        let default_settings = ApplicationSettings::default();
-        save_configuration(&default_settings)?; // Сохраняем дефолтный конфиг при первом запуске
-        return Ok(default_settings);
+// Ошибки в загруженных настройках по разделам. Настройки с ошибками все равно применяются:
+// неверные правила молчат, а интерфейс показывает эти сообщения пользователю.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SettingsValidation {
+    pub rule_errors: Vec<String>, // Условия и параметры правил, активные слои
+    pub war_thunder_api_errors: Vec<String>, // Адрес, заголовки и авторизация War Thunder API
+}
+
+impl SettingsValidation {
+    pub fn is_valid(&self) -> bool {
+        self.rule_errors.is_empty() && self.war_thunder_api_errors.is_empty()
+    }
+}
+
+pub fn validate_settings(settings: &ApplicationSettings) -> SettingsValidation {
+    SettingsValidation {
+        rule_errors: crate::game_event_processor::validate_rule_conditions(settings),
+        war_thunder_api_errors: crate::war_thunder_api::validate_war_thunder_api_settings(&settings.war_thunder_api),
     }
+}
 
-    let config_content = fs::read_to_string(&config_file_path)
-        .map_err(|e| format!("Ошибка чтения файла конфигурации {:?}: {}", config_file_path, e))?;
-    
-    // Используем toml::from_str (из крейта toml, который ты добавил в Cargo.toml)
-    toml::from_str(&config_content)
-        .map_err(|e| format!("Ошибка парсинга TOML из файла конфигурации {:?}: {}", config_file_path, e))
+// Загрузка из активного файла конфигурации (--config или каталог пользователя)
+pub fn load_configuration() -> Result<ApplicationSettings, String> {
+    load_configuration_from_path(SettingsStore::resolve()?.config_file_path())
 }
 
 pub fn save_configuration(settings: &ApplicationSettings) -> Result<(), String> {
-    let config_file_path = get_config_path()?;
-    // Используем toml::to_string_pretty (из крейта toml)
-    let toml_content = toml::to_string_pretty(settings)
+    save_configuration_to_path(settings, SettingsStore::resolve()?.config_file_path())
+}
+
+// Загрузка из произвольного файла. Если файла нет, он создается с настройками по умолчанию.
+pub fn load_configuration_from_path(config_file_path: &Path) -> Result<ApplicationSettings, String> {
+    SettingsStore::at_path(config_file_path).load()
+}
+
+pub fn save_configuration_to_path(settings: &ApplicationSettings, config_file_path: &Path) -> Result<(), String> {
+    SettingsStore::at_path(config_file_path).save(settings)
+}
+
+// Разбор TOML без обращения к диску: дерево сначала приводится к текущей версии (SettingsStore::migrate),
+// отсутствующие необязательные поля получают значения serde по умолчанию
+pub fn parse_configuration(config_content: &str) -> Result<ApplicationSettings, String> {
+    let raw: toml::Value = toml::from_str(config_content)
+        .map_err(|e| format!("Ошибка парсинга TOML конфигурации: {}", e))?;
+    SettingsStore::migrate(raw)?
+        .try_into()
+        .map_err(|e| format!("Ошибка парсинга TOML конфигурации: {}", e))
+}
+
+// Ключи, которые есть в исходном TOML, но пропали после разбора в настройки: serde их молча игнорирует.
+// Сравниваем исходное дерево с деревом, сериализованным из разобранных настроек (все поля настроек
+// сериализуются, поэтому лишний ключ в исходном дереве - неизвестный). Пути вида "event_actions[0].nmae".
+pub fn find_unknown_configuration_keys(config_content: &str) -> Result<Vec<String>, String> {
+    let original_tree: toml::Value = toml::from_str(config_content)
+        .map_err(|e| format!("Ошибка парсинга TOML конфигурации: {}", e))?;
+    let parsed_settings = parse_configuration(config_content)?;
+    let known_tree = toml::Value::try_from(&parsed_settings)
         .map_err(|e| format!("Ошибка сериализации настроек в TOML: {}", e))?;
-    
-    fs::write(&config_file_path, toml_content)
-        .map_err(|e| format!("Ошибка записи файла конфигурации {:?}: {}", config_file_path, e))
-}
\ No newline at end of file
+    let mut unknown_keys = Vec::new();
+    collect_unknown_keys(&original_tree, &known_tree, "", &mut unknown_keys);
+    Ok(unknown_keys)
+}
+
+fn collect_unknown_keys(original_value: &toml::Value, known_value: &toml::Value, path: &str, unknown_keys: &mut Vec<String>) {
+    match (original_value, known_value) {
+        (toml::Value::Table(original_table), toml::Value::Table(known_table)) => {
+            for (key, original_child) in original_table {
+                let child_path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                match known_table.get(key) {
+                    Some(known_child) => collect_unknown_keys(original_child, known_child, &child_path, unknown_keys),
+                    None => unknown_keys.push(child_path),
+                }
+            }
+        }
+        (toml::Value::Array(original_items), toml::Value::Array(known_items)) => {
+            for (item_index, (original_item, known_item)) in original_items.iter().zip(known_items).enumerate() {
+                collect_unknown_keys(original_item, known_item, &format!("{}[{}]", path, item_index), unknown_keys);
+            }
+        }
+        _ => {}
+    }
+}
+
+// Неизвестные ключи в активном файле конфигурации (для строгой проверки после загрузки)
+pub fn find_unknown_keys_in_active_configuration() -> Result<Vec<String>, String> {
+    SettingsStore::resolve()?.find_unknown_keys()
+}
+
+pub fn serialize_configuration(settings: &ApplicationSettings) -> Result<String, String> {
+    toml::to_string_pretty(settings)
+        .map_err(|e| format!("Ошибка сериализации настроек в TOML: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Отдельный файл на тест и процесс: тесты идут параллельно
+    fn temp_config_path(test_name: &str) -> PathBuf {
+        let config_file_path = std::env::temp_dir().join(format!("wt_haptics_{}_{}.toml", std::process::id(), test_name));
+        let _ = fs::remove_file(&config_file_path);
+        config_file_path
+    }
+
+    const MINIMAL_CONFIGURATION: &str = "application_name = \"Минимум\"\npolling_interval_milliseconds = 250\nbuttplug_server_address = \"ws://127.0.0.1:12345\"\n";
+
+    // Настройки, в которых заполнены вложенные и необязательные части
+    fn non_trivial_settings() -> ApplicationSettings {
+        let mut settings = ApplicationSettings {
+            application_name: "Мои настройки".to_string(),
+            buttplug_connector_kind: ButtplugConnectorKind::WebSocket,
+            rule_evaluation_mode: RuleEvaluationMode::FirstMatch,
+            simultaneous_vibration_policy: SimultaneousVibrationPolicy::SumClamped,
+            ..ApplicationSettings::default()
+        };
+        settings.device_groups.push(DeviceGroupSetting {
+            name: "Пара".to_string(),
+            member_addresses: vec!["Lovense Edge".to_string(), "Lovense Lush".to_string()],
+        });
+        settings.event_actions.push(EventActionSetting {
+            name: "Удар слева".to_string(),
+            enabled: false,
+            device_action: DeviceAction {
+                action_type: DeviceActionType::Vibrate,
+                intensity: 0.75,
+                duration_milliseconds: 120,
+                target: ActionTarget::Group("Пара".to_string()),
+                actuator_intensities: vec![ActuatorIntensity { actuator_index: 1, intensity: 0.25 }],
+                pattern_points: vec![
+                    PatternPoint { time_fraction: 0.0, intensity: 1.0 },
+                    PatternPoint { time_fraction: 1.0, intensity: 0.0 },
+                ],
+            },
+            max_concurrent_effects: 2,
+            concurrency_limit_policy: ConcurrencyLimitPolicy::Restart,
+            condition_expression: Some("health_percentage < 30 AND speed > 0".to_string()),
+            vehicle_classes: vec![VehicleClass::Ground, VehicleClass::Naval],
+            priority: -3,
+        });
+        settings.critical_health_action = Some(DeviceAction {
+            action_type: DeviceActionType::Stop,
+            intensity: 0.0,
+            duration_milliseconds: 0,
+            target: ActionTarget::Device("Lovense Edge".to_string()),
+            actuator_intensities: Vec::new(),
+            pattern_points: Vec::new(),
+        });
+        settings.ignored_indicator_fields = vec!["g_load".to_string()];
+        settings
+    }
+
+    #[test]
+    fn configuration_round_trips_default_settings_through_file() {
+        let config_file_path = temp_config_path("round_trip_default");
+        save_configuration_to_path(&ApplicationSettings::default(), &config_file_path).unwrap();
+        assert_eq!(load_configuration_from_path(&config_file_path), Ok(ApplicationSettings::default()));
+        let _ = fs::remove_file(&config_file_path);
+    }
+
+    #[test]
+    fn configuration_round_trips_non_trivial_settings_through_file() {
+        let config_file_path = temp_config_path("round_trip_non_trivial");
+        let settings = non_trivial_settings();
+        save_configuration_to_path(&settings, &config_file_path).unwrap();
+        assert_eq!(load_configuration_from_path(&config_file_path), Ok(settings));
+        let _ = fs::remove_file(&config_file_path);
+    }
+
+    #[test]
+    fn missing_optional_fields_get_serde_defaults() {
+        let settings = parse_configuration(MINIMAL_CONFIGURATION).unwrap();
+        assert!(settings.event_actions.is_empty());
+        assert!(settings.rule_layers.is_empty());
+        assert_eq!(settings.war_thunder_api, WarThunderApiSettings::default());
+        assert_eq!(settings.war_thunder_request_timeout_milliseconds, default_war_thunder_request_timeout_milliseconds());
+        assert_eq!(settings.simultaneous_vibration_policy, SimultaneousVibrationPolicy::Max);
+        assert_eq!(settings.critical_health_action, None);
+    }
+
+    #[test]
+    fn missing_event_action_fields_get_serde_defaults() {
+        let config_content = format!(
+            "{}\n[[event_actions]]\nname = \"Попадание\"\nenabled = true\n\n[event_actions.device_action]\naction_type = \"Vibrate\"\n",
+            MINIMAL_CONFIGURATION
+        );
+        let settings = parse_configuration(&config_content).unwrap();
+        let event_action = &settings.event_actions[0];
+        assert_eq!(event_action.max_concurrent_effects, 0);
+        assert_eq!(event_action.concurrency_limit_policy, ConcurrencyLimitPolicy::DropNew);
+        assert_eq!(event_action.condition_expression, None);
+        assert!(event_action.vehicle_classes.is_empty());
+        assert_eq!(event_action.priority, 0);
+        assert_eq!(
+            event_action.device_action,
+            DeviceAction {
+                action_type: DeviceActionType::Vibrate,
+                intensity: default_intensity(),
+                duration_milliseconds: default_duration(),
+                target: ActionTarget::SelectedDevice,
+                actuator_intensities: Vec::new(),
+                pattern_points: Vec::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn malformed_configuration_is_an_error() {
+        assert!(parse_configuration("application_name = ").is_err());
+        assert!(parse_configuration("[[event_actions]\nname = 1").is_err());
+        // Обязательное поле отсутствует
+        assert!(parse_configuration("application_name = \"Без интервала\"\n").is_err());
+        // Неверный тип значения
+        assert!(parse_configuration(&MINIMAL_CONFIGURATION.replace("250", "\"часто\"")).is_err());
+    }
+
+    #[test]
+    fn settings_store_creates_default_file_when_missing() {
+        let settings_store = SettingsStore::at_path(temp_config_path("store_missing"));
+        assert_eq!(settings_store.load(), Ok(ApplicationSettings::default()));
+        assert!(settings_store.config_file_path().exists());
+        assert_eq!(settings_store.load(), Ok(ApplicationSettings::default()));
+        let _ = fs::remove_file(settings_store.config_file_path());
+    }
+
+    #[test]
+    fn settings_store_round_trips_saved_settings() {
+        let settings_store = SettingsStore::at_path(temp_config_path("store_round_trip"));
+        let settings = ApplicationSettings {
+            application_name: "Сторонний интерфейс".to_string(),
+            polling_interval_milliseconds: 100,
+            explicit_zero_stop_device_addresses: vec!["Lovense Edge".to_string()],
+            ..ApplicationSettings::default()
+        };
+        settings_store.save(&settings).unwrap();
+        assert_eq!(settings_store.load(), Ok(settings));
+        let _ = fs::remove_file(settings_store.config_file_path());
+    }
+
+    #[test]
+    fn settings_store_reports_unknown_keys() {
+        let settings_store = SettingsStore::at_path(temp_config_path("store_unknown_keys"));
+        fs::write(
+            settings_store.config_file_path(),
+            "application_name = \"Тест\"\npolling_interval_milliseconds = 250\nbuttplug_server_address = \"ws://127.0.0.1:12345\"\npolling_interval_milisecond = 100\n",
+        )
+        .unwrap();
+        assert_eq!(settings_store.find_unknown_keys(), Ok(vec!["polling_interval_milisecond".to_string()]));
+        let _ = fs::remove_file(settings_store.config_file_path());
+    }
+
+    #[test]
+    fn settings_store_load_error_names_the_file() {
+        let settings_store = SettingsStore::at_path(temp_config_path("store_malformed"));
+        fs::write(settings_store.config_file_path(), "application_name = ").unwrap();
+        let load_error = settings_store.load().unwrap_err();
+        assert!(load_error.contains("store_malformed"), "{}", load_error);
+        let _ = fs::remove_file(settings_store.config_file_path());
+    }
+
+    #[test]
+    fn settings_store_sibling_path_is_next_to_config_file() {
+        let settings_store = SettingsStore::at_path(PathBuf::from("configs").join("settings.toml"));
+        assert_eq!(settings_store.sibling_path("rule_statistics.toml"), PathBuf::from("configs").join("rule_statistics.toml"));
+    }
+
+    #[test]
+    fn validate_settings_accepts_default_settings() {
+        assert!(validate_settings(&ApplicationSettings::default()).is_valid());
+    }
+
+    #[test]
+    fn validate_settings_reports_errors_by_section() {
+        let mut settings = ApplicationSettings::default();
+        settings.war_thunder_api.base_url = "не адрес".to_string();
+        settings.war_thunder_api.request_headers.push(HttpHeaderSetting { name: "X Bad".to_string(), value: "1".to_string() });
+        settings.active_rule_layers.push("Нет такого слоя".to_string());
+        let mut broken_rule = settings.event_actions[0].clone();
+        broken_rule.name = "Сломанное".to_string();
+        broken_rule.condition_expression = Some("unknown_field > 1".to_string());
+        settings.event_actions.push(broken_rule);
+
+        let settings_validation = validate_settings(&settings);
+        assert!(!settings_validation.is_valid());
+        assert_eq!(settings_validation.war_thunder_api_errors.len(), 2);
+        assert_eq!(settings_validation.rule_errors.len(), 2);
+        assert!(settings_validation.rule_errors.iter().any(|rule_error| rule_error.contains("Нет такого слоя")));
+        assert!(settings_validation.rule_errors.iter().any(|rule_error| rule_error.starts_with("Правило 'Сломанное'")));
+    }
+
+    fn raw_settings(config_content: &str) -> toml::Value {
+        toml::from_str(config_content).unwrap()
+    }
+
+    #[test]
+    fn migrate_stamps_current_version_on_unversioned_file() {
+        let migrated = SettingsStore::migrate(raw_settings(MINIMAL_CONFIGURATION)).unwrap();
+        let mut expected = raw_settings(MINIMAL_CONFIGURATION);
+        expected.as_table_mut().unwrap().insert("settings_format_version".to_string(), toml::Value::Integer(i64::from(SETTINGS_FORMAT_VERSION)));
+        assert_eq!(migrated, expected);
+    }
+
+    #[test]
+    fn migrate_leaves_current_version_unchanged() {
+        let current_content = format!("settings_format_version = {}\n{}", SETTINGS_FORMAT_VERSION, MINIMAL_CONFIGURATION);
+        assert_eq!(SettingsStore::migrate(raw_settings(&current_content)), Ok(raw_settings(&current_content)));
+    }
+
+    #[test]
+    fn migrate_rejects_newer_and_malformed_versions() {
+        let newer_content = format!("settings_format_version = {}\n{}", SETTINGS_FORMAT_VERSION + 1, MINIMAL_CONFIGURATION);
+        let newer_error = SettingsStore::migrate(raw_settings(&newer_content)).unwrap_err();
+        assert!(newer_error.contains("более новой версией"), "{}", newer_error);
+        for malformed_version in ["-1", "\"1\"", "1.5"] {
+            let malformed_content = format!("settings_format_version = {}\n{}", malformed_version, MINIMAL_CONFIGURATION);
+            assert!(SettingsStore::migrate(raw_settings(&malformed_content)).is_err(), "{}", malformed_version);
+        }
+        assert!(SettingsStore::migrate(toml::Value::Integer(1)).is_err());
+    }
+
+    #[test]
+    fn settings_store_load_migrates_unversioned_file() {
+        let settings_store = SettingsStore::at_path(temp_config_path("store_unversioned"));
+        fs::write(settings_store.config_file_path(), MINIMAL_CONFIGURATION).unwrap();
+        let settings = settings_store.load().unwrap();
+        assert_eq!(settings.settings_format_version, SETTINGS_FORMAT_VERSION);
+        assert_eq!(settings_store.find_unknown_keys(), Ok(Vec::new()));
+        let _ = fs::remove_file(settings_store.config_file_path());
+    }
+}
+
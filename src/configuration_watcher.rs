@@ -0,0 +1,47 @@
+// src/configuration_watcher.rs
+
+// Следит за файлом конфигурации и сообщает GUI, когда его изменили вне приложения
+// (правка в редакторе, синхронизация между ПК). Файл проверяется по времени изменения раз в пару секунд.
+//
+// Владелец рабочих настроек - GUI: задача только читает файл и шлет ApplicationSettingsLoaded,
+// а GUI решает, применить их сразу или спросить пользователя (если есть несохраненные правки).
+// Собственные сохранения GUI тоже меняют файл; такие уведомления GUI узнает по совпадению
+// с последними сохраненными настройками и пропускает.
+
+use crate::configuration_manager;
+use crate::message_passing::{LogCategory, UpdateFromAsyncTasks};
+use std::time::SystemTime;
+use tokio::sync::mpsc;
+use tokio::time::{sleep, Duration};
+
+const CONFIGURATION_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+fn configuration_modified_at() -> Option<SystemTime> {
+    let config_file_path = configuration_manager::active_config_path().ok()?;
+    std::fs::metadata(config_file_path).and_then(|metadata| metadata.modified()).ok()
+}
+
+pub async fn run_configuration_file_watcher(gui_update_sender: mpsc::Sender<UpdateFromAsyncTasks>) {
+    let mut last_seen_modified_at = configuration_modified_at();
+    loop {
+        sleep(CONFIGURATION_CHECK_INTERVAL).await;
+        let modified_at = configuration_modified_at();
+        if modified_at.is_none() || modified_at == last_seen_modified_at {
+            continue;
+        }
+        last_seen_modified_at = modified_at;
+        // Ошибку разбора сообщаем один раз на каждое изменение файла: возможно, его еще дописывают
+        let update = match configuration_manager::load_configuration() {
+            Ok(loaded_settings) => UpdateFromAsyncTasks::ApplicationSettingsLoaded(Box::new(loaded_settings)),
+            Err(load_error) => UpdateFromAsyncTasks::LogMessage {
+                category: LogCategory::General,
+                device_address: None,
+                message: format!("Файл конфигурации изменен, но не загружен: {}", load_error),
+            },
+        };
+        if gui_update_sender.send(update).await.is_err() {
+            tracing::info!("Наблюдение за файлом конфигурации завершено: канал GUI закрыт.");
+            return;
+        }
+    }
+}
@@ -0,0 +1,117 @@
+// src/connection_metrics.rs
+
+// Счетчики для панели диагностики соединений: фоновые задачи копят события за последнюю минуту
+// и периодически (пока панель открыта) отправляют сводку в GUI.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+pub const METRICS_WINDOW: Duration = Duration::from_secs(60);
+pub const METRICS_REPORT_INTERVAL: Duration = Duration::from_secs(2);
+
+// События с временем записи; старше METRICS_WINDOW отбрасываются.
+// Время передает вызывающий (из crate::clock у задачи WT), окно само часы не читает.
+// Default вручную: derive потребовал бы T: Default, а у исходов опроса значения по умолчанию нет
+#[derive(Debug)]
+pub struct RollingWindow<T> {
+    samples: VecDeque<(Instant, T)>,
+}
+
+impl<T> Default for RollingWindow<T> {
+    fn default() -> Self {
+        Self { samples: VecDeque::new() }
+    }
+}
+
+impl<T: Copy> RollingWindow<T> {
+    pub fn record(&mut self, sample: T, now: Instant) {
+        self.samples.push_back((now, sample));
+        self.prune(now);
+    }
+
+    fn prune(&mut self, now: Instant) {
+        while self.samples.front().is_some_and(|(recorded_at, _)| now.duration_since(*recorded_at) > METRICS_WINDOW) {
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn recent_samples(&mut self, now: Instant) -> Vec<T> {
+        self.prune(now);
+        self.samples.iter().map(|(_, sample)| *sample).collect()
+    }
+}
+
+// Исход одного опроса War Thunder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PollOutcome {
+    Success,
+    OutOfBattle, // API ответило, но боя нет - тоже успешный опрос
+    ParseError,
+    Failed, // Нет соединения, таймаут или код ошибки HTTP
+}
+
+// Сводка опросов War Thunder за последнюю минуту
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct WarThunderPollMetrics {
+    pub poll_count: usize,
+    pub successful_poll_count: usize,
+    pub parse_error_count: usize,
+    pub dropped_update_count: usize, // Сообщения WT задачи (индикаторы, сырой JSON, логи), выброшенные из-за переполненного канала в GUI
+}
+
+impl WarThunderPollMetrics {
+    pub fn summarize(poll_outcomes: &[PollOutcome], dropped_update_count: usize) -> Self {
+        Self {
+            poll_count: poll_outcomes.len(),
+            successful_poll_count: poll_outcomes.iter()
+                .filter(|outcome| matches!(outcome, PollOutcome::Success | PollOutcome::OutOfBattle))
+                .count(),
+            parse_error_count: poll_outcomes.iter().filter(|outcome| **outcome == PollOutcome::ParseError).count(),
+            dropped_update_count,
+        }
+    }
+
+    pub fn success_rate(&self) -> Option<f64> {
+        (self.poll_count > 0).then(|| self.successful_poll_count as f64 / self.poll_count as f64)
+    }
+}
+
+// Сводка команд устройствам за последнюю минуту. Задержка - от получения команды задачей Buttplug до ответа сервера.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DeviceCommandMetrics {
+    pub command_count: usize,
+    pub failed_command_count: usize,
+    pub average_latency_milliseconds: Option<f64>,
+    pub max_latency_milliseconds: Option<f64>,
+}
+
+impl DeviceCommandMetrics {
+    // Образец: Some(задержка) - команда выполнена, None - ошибка
+    pub fn summarize(command_latencies: &[Option<Duration>]) -> Self {
+        let successful_latencies: Vec<Duration> = command_latencies.iter().flatten().copied().collect();
+        Self {
+            command_count: command_latencies.len(),
+            failed_command_count: command_latencies.len() - successful_latencies.len(),
+            average_latency_milliseconds: (!successful_latencies.is_empty()).then(|| {
+                successful_latencies.iter().map(|latency| latency.as_secs_f64() * 1000.0).sum::<f64>() / successful_latencies.len() as f64
+            }),
+            max_latency_milliseconds: successful_latencies.iter().max().map(|latency| latency.as_secs_f64() * 1000.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rolling_window_of_poll_outcomes_drops_samples_older_than_window() {
+        let started_at = Instant::now();
+        let mut poll_outcomes: RollingWindow<PollOutcome> = RollingWindow::default();
+        poll_outcomes.record(PollOutcome::Failed, started_at);
+        poll_outcomes.record(PollOutcome::Success, started_at + Duration::from_secs(30));
+
+        assert_eq!(poll_outcomes.recent_samples(started_at + METRICS_WINDOW), vec![PollOutcome::Failed, PollOutcome::Success]);
+        assert_eq!(poll_outcomes.recent_samples(started_at + METRICS_WINDOW + Duration::from_secs(1)), vec![PollOutcome::Success]);
+    }
+}
@@ -0,0 +1,297 @@
+// src/control_api.rs
+
+// Локальный HTTP API для внешних инструментов (алерты стрима, чат-боты, OBS).
+// Собирается только с feature "control_api". По умолчанию слушает только 127.0.0.1;
+// каждый запрос должен содержать токен из настроек: заголовок "Authorization: Bearer <токен>".
+//
+// Запросы (тело - JSON):
+//   POST /vibrate   {"device": "<адрес>", "speed": 0.5, "duration_milliseconds": 500}
+//   POST /stop      {"device": "<адрес>"}
+//   POST /stop_all
+// Адрес устройства - как в настройках групп (см. buttplug_connector::device_address).
+
+use crate::configuration_manager::ApplicationSettings;
+use crate::message_passing::{CommandToAsyncTasks, LogCategory, UpdateFromAsyncTasks};
+use serde::Deserialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::time::{timeout, Duration};
+
+// Ограничения на запрос: API рассчитано на короткие команды
+const MAX_REQUEST_BYTES: usize = 16 * 1024;
+const REQUEST_READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Deserialize)]
+struct VibrateRequest {
+    device: String,
+    speed: f64,
+    #[serde(default)]
+    duration_milliseconds: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct StopRequest {
+    device: String,
+}
+
+struct HttpRequest {
+    method: String,
+    path: String,
+    authorization: Option<String>,
+    body: Vec<u8>,
+}
+
+async fn read_http_request(stream: &mut TcpStream) -> Result<HttpRequest, String> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 2048];
+    let header_end = loop {
+        let read_count = stream.read(&mut chunk).await.map_err(|e| format!("Ошибка чтения запроса: {}", e))?;
+        if read_count == 0 {
+            return Err("Соединение закрыто до конца заголовков".to_string());
+        }
+        buffer.extend_from_slice(&chunk[..read_count]);
+        if let Some(position) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break position + 4;
+        }
+        if buffer.len() > MAX_REQUEST_BYTES {
+            return Err("Слишком большой запрос".to_string());
+        }
+    };
+
+    let header_text = String::from_utf8_lossy(&buffer[..header_end]).to_string();
+    let mut header_lines = header_text.lines();
+    let mut request_line = header_lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let path = request_line.next().unwrap_or_default().to_string();
+
+    let mut content_length = 0usize;
+    let mut authorization = None;
+    for header_line in header_lines {
+        let Some((header_name, header_value)) = header_line.split_once(':') else {
+            continue;
+        };
+        match header_name.trim().to_ascii_lowercase().as_str() {
+            "content-length" => content_length = header_value.trim().parse().map_err(|_| "Некорректный Content-Length".to_string())?,
+            "authorization" => authorization = Some(header_value.trim().to_string()),
+            _ => {}
+        }
+    }
+    if header_end + content_length > MAX_REQUEST_BYTES {
+        return Err("Слишком большой запрос".to_string());
+    }
+
+    let mut body = buffer[header_end..].to_vec();
+    while body.len() < content_length {
+        let read_count = stream.read(&mut chunk).await.map_err(|e| format!("Ошибка чтения тела запроса: {}", e))?;
+        if read_count == 0 {
+            break;
+        }
+        body.extend_from_slice(&chunk[..read_count]);
+    }
+    body.truncate(content_length);
+
+    Ok(HttpRequest { method, path, authorization, body })
+}
+
+async fn write_http_response(stream: &mut TcpStream, status: &str, json_body: &str) {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        json_body.len(),
+        json_body
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+}
+
+fn error_json(message: &str) -> String {
+    serde_json::json!({ "ok": false, "error": message }).to_string()
+}
+
+// Сравнение токена без раннего выхода: время ответа не зависит от того, сколько символов совпало
+fn is_authorization_matching(authorization: Option<&str>, expected_authorization: &str) -> bool {
+    let Some(authorization) = authorization else {
+        return false;
+    };
+    let (provided_bytes, expected_bytes) = (authorization.as_bytes(), expected_authorization.as_bytes());
+    let mut difference = provided_bytes.len() ^ expected_bytes.len();
+    for (byte_index, expected_byte) in expected_bytes.iter().enumerate() {
+        difference |= usize::from(provided_bytes.get(byte_index).copied().unwrap_or(0) ^ expected_byte);
+    }
+    difference == 0
+}
+
+// Передает команду фоновым задачам. Ответ 200 - только если команда действительно принята в очередь:
+// переполненная очередь - 503 (клиент может повторить), закрытый канал - 500 (приложение завершается).
+fn submit_command(command_sender: &mpsc::Sender<CommandToAsyncTasks>, command: CommandToAsyncTasks) -> (&'static str, String) {
+    match command_sender.try_send(command) {
+        Ok(()) => ("200 OK", serde_json::json!({ "ok": true }).to_string()),
+        Err(mpsc::error::TrySendError::Full(_)) => ("503 Service Unavailable", error_json("Очередь команд переполнена, повторите запрос позже")),
+        Err(mpsc::error::TrySendError::Closed(_)) => ("500 Internal Server Error", error_json("Фоновые задачи остановлены")),
+    }
+}
+
+// Переводит запрос в команды фоновым задачам. Возвращает (HTTP статус, JSON ответа).
+fn handle_request(
+    request: &HttpRequest,
+    access_token: &str,
+    command_sender: &mpsc::Sender<CommandToAsyncTasks>,
+) -> (&'static str, String) {
+    let expected_authorization = format!("Bearer {}", access_token);
+    if !is_authorization_matching(request.authorization.as_deref(), &expected_authorization) {
+        return ("401 Unauthorized", error_json("Неверный или отсутствующий токен"));
+    }
+    if request.method != "POST" {
+        return ("405 Method Not Allowed", error_json("Поддерживается только POST"));
+    }
+
+    match request.path.as_str() {
+        "/vibrate" => {
+            let vibrate_request: VibrateRequest = match serde_json::from_slice(&request.body) {
+                Ok(parsed) => parsed,
+                Err(e) => return ("400 Bad Request", error_json(&format!("Некорректный JSON: {}", e))),
+            };
+            // Остановку по duration_milliseconds планирует Buttplug задача: новая команда ее отменит
+            submit_command(command_sender, CommandToAsyncTasks::VibrateGroup {
+                member_addresses: vec![vibrate_request.device],
+                speed: vibrate_request.speed.clamp(0.0, 1.0),
+                actuator_speeds: Vec::new(),
+                duration_milliseconds: vibrate_request.duration_milliseconds,
+            })
+        }
+        "/stop" => {
+            let stop_request: StopRequest = match serde_json::from_slice(&request.body) {
+                Ok(parsed) => parsed,
+                Err(e) => return ("400 Bad Request", error_json(&format!("Некорректный JSON: {}", e))),
+            };
+            submit_command(command_sender, CommandToAsyncTasks::StopGroup(vec![stop_request.device]))
+        }
+        "/stop_all" => {
+            submit_command(command_sender, CommandToAsyncTasks::StopAllDevices)
+        }
+        _ => ("404 Not Found", error_json("Неизвестный путь")),
+    }
+}
+
+async fn serve_connection(
+    mut stream: TcpStream,
+    access_token: String,
+    command_sender: mpsc::Sender<CommandToAsyncTasks>,
+) {
+    let (status, json_body) = match timeout(REQUEST_READ_TIMEOUT, read_http_request(&mut stream)).await {
+        Ok(Ok(request)) => handle_request(&request, &access_token, &command_sender),
+        Ok(Err(read_error)) => ("400 Bad Request", error_json(&read_error)),
+        Err(_) => ("408 Request Timeout", error_json("Таймаут чтения запроса")),
+    };
+    write_http_response(&mut stream, status, &json_body).await;
+}
+
+pub async fn run_control_api_server(
+    settings: ApplicationSettings,
+    command_sender: mpsc::Sender<CommandToAsyncTasks>,
+    gui_update_sender: mpsc::Sender<UpdateFromAsyncTasks>,
+) {
+    if settings.control_api_token.trim().is_empty() {
+        let _ = gui_update_sender.send(UpdateFromAsyncTasks::LogMessage {
+            category: LogCategory::General,
+            device_address: None,
+            message: "API управления не запущено: задайте control_api_token в настройках.".to_string(),
+        }).await;
+        return;
+    }
+
+    let bind_address = format!("{}:{}", settings.control_api_bind_address, settings.control_api_port);
+    let listener = match TcpListener::bind(&bind_address).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            let _ = gui_update_sender.send(UpdateFromAsyncTasks::LogMessage {
+                category: LogCategory::General,
+                device_address: None,
+                message: format!("Не удалось запустить API управления на {}: {}", bind_address, e),
+            }).await;
+            return;
+        }
+    };
+    let _ = gui_update_sender.send(UpdateFromAsyncTasks::LogMessage {
+        category: LogCategory::General,
+        device_address: None,
+        message: format!("API управления слушает http://{}", bind_address),
+    }).await;
+
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer_address)) => {
+                tracing::debug!("Запрос API управления от {}", peer_address);
+                tokio::spawn(serve_connection(stream, settings.control_api_token.clone(), command_sender.clone()));
+            }
+            Err(e) => tracing::warn!("Ошибка приема соединения API управления: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_TOKEN: &str = "секрет-123";
+
+    fn post_request(path: &str, body: &str) -> HttpRequest {
+        HttpRequest {
+            method: "POST".to_string(),
+            path: path.to_string(),
+            authorization: Some(format!("Bearer {}", TEST_TOKEN)),
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn accepted_command_returns_ok() {
+        let (command_sender, mut command_receiver) = mpsc::channel(1);
+        let (status, _) = handle_request(&post_request("/vibrate", r#"{"device": "Lovense Edge", "speed": 1.5, "duration_milliseconds": 500}"#), TEST_TOKEN, &command_sender);
+        assert_eq!(status, "200 OK");
+        match command_receiver.try_recv() {
+            Ok(CommandToAsyncTasks::VibrateGroup { member_addresses, speed, duration_milliseconds, .. }) => {
+                assert_eq!(member_addresses, vec!["Lovense Edge".to_string()]);
+                assert_eq!(speed, 1.0);
+                assert_eq!(duration_milliseconds, Some(500));
+            }
+            _ => panic!("ожидалась команда VibrateGroup"),
+        }
+    }
+
+    #[test]
+    fn full_command_queue_returns_service_unavailable() {
+        let (command_sender, _command_receiver) = mpsc::channel(1);
+        assert_eq!(handle_request(&post_request("/stop_all", ""), TEST_TOKEN, &command_sender).0, "200 OK");
+        let (status, json_body) = handle_request(&post_request("/stop_all", ""), TEST_TOKEN, &command_sender);
+        assert_eq!(status, "503 Service Unavailable");
+        assert!(json_body.contains(r#""ok":false"#), "{}", json_body);
+    }
+
+    #[test]
+    fn closed_command_channel_returns_internal_error() {
+        let (command_sender, command_receiver) = mpsc::channel(1);
+        drop(command_receiver);
+        let (status, _) = handle_request(&post_request("/stop", r#"{"device": "Lovense Edge"}"#), TEST_TOKEN, &command_sender);
+        assert_eq!(status, "500 Internal Server Error");
+    }
+
+    #[test]
+    fn wrong_or_missing_token_is_rejected() {
+        let (command_sender, mut command_receiver) = mpsc::channel(1);
+        for authorization in [None, Some("Bearer".to_string()), Some("Bearer секрет-12".to_string()), Some("Bearer секрет-1234".to_string())] {
+            let request = HttpRequest { authorization, ..post_request("/stop_all", "") };
+            assert_eq!(handle_request(&request, TEST_TOKEN, &command_sender).0, "401 Unauthorized");
+        }
+        assert!(command_receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn authorization_comparison_requires_exact_match() {
+        assert!(is_authorization_matching(Some("Bearer abc"), "Bearer abc"));
+        assert!(!is_authorization_matching(Some("Bearer abd"), "Bearer abc"));
+        assert!(!is_authorization_matching(Some("Bearer ab"), "Bearer abc"));
+        assert!(!is_authorization_matching(Some("Bearer abc\0"), "Bearer abc"));
+        assert!(!is_authorization_matching(None, "Bearer abc"));
+    }
+}
@@ -0,0 +1,129 @@
+// src/device_safety_stop.rs
+
+use crate::message_passing::CommandToAsyncTasks;
+use std::sync::Arc;
+use tokio::sync::{mpsc, watch};
+
+// Сколько ждем, чтобы задача Buttplug успела выполнить аварийную остановку до остановки рантайма
+const SAFETY_STOP_GRACE_PERIOD: std::time::Duration = std::time::Duration::from_millis(300);
+
+// Аварийная остановка устройств при панике в потоке GUI: StopAllDevices уходит прямо в задачу Buttplug
+// (в обход GUI и диспетчера), затем даем ей время выполнить команду. Канал задачи берется из watch:
+// после перезапуска задачи супервизором он новый.
+// Срабатывает из хука паники (он вызывается и при panic = "abort", как в release-сборке) и из Drop guard'а
+// при раскрутке стека - что случится раньше; повторно не выполняется.
+#[derive(Clone)]
+pub struct DeviceSafetyStop {
+    buttplug_command_sender: watch::Receiver<mpsc::Sender<CommandToAsyncTasks>>,
+    runtime_handle: tokio::runtime::Handle,
+    is_triggered: Arc<std::sync::atomic::AtomicBool>,
+}
+
+impl DeviceSafetyStop {
+    pub fn new(buttplug_command_sender: watch::Receiver<mpsc::Sender<CommandToAsyncTasks>>, runtime_handle: tokio::runtime::Handle) -> Self {
+        Self { buttplug_command_sender, runtime_handle, is_triggered: Arc::default() }
+    }
+
+    pub fn stop_all_devices(&self) {
+        if self.is_triggered.swap(true, std::sync::atomic::Ordering::SeqCst) {
+            return;
+        }
+        tracing::error!("Паника в потоке GUI: аварийная остановка всех устройств.");
+        let buttplug_command_sender = self.buttplug_command_sender.borrow().clone();
+        let is_stop_queued = match buttplug_command_sender.try_send(CommandToAsyncTasks::StopAllDevices) {
+            Ok(()) => true,
+            Err(mpsc::error::TrySendError::Closed(_)) => false,
+            // Очередь полна: ждем места ограниченное время. block_on допустим только вне контекста рантайма.
+            Err(mpsc::error::TrySendError::Full(stop_command)) => {
+                tokio::runtime::Handle::try_current().is_err()
+                    && self.runtime_handle.block_on(async {
+                        tokio::time::timeout(SAFETY_STOP_GRACE_PERIOD, buttplug_command_sender.send(stop_command)).await
+                    }).is_ok_and(|send_result| send_result.is_ok())
+            }
+        };
+        if is_stop_queued {
+            std::thread::sleep(SAFETY_STOP_GRACE_PERIOD);
+        } else {
+            tracing::error!("Не удалось отправить аварийную остановку: задача Buttplug недоступна.");
+        }
+    }
+
+    // Хук реагирует только на панику главного потока (там работает GUI): паники задач tokio
+    // перехватывает сам рантайм, и останавливать из-за них устройства не нужно
+    pub fn install_panic_hook(&self) {
+        let device_safety_stop = self.clone();
+        let previous_panic_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |panic_info| {
+            if std::thread::current().name() == Some("main") {
+                device_safety_stop.stop_all_devices();
+            }
+            previous_panic_hook(panic_info);
+        }));
+    }
+}
+
+// Guard должен уничтожаться раньше рантайма фоновых задач, поэтому создается после него.
+// При обычном выходе ничего не делает - там GUI сам останавливает обработку и отключается.
+pub struct DeviceSafetyStopGuard(pub DeviceSafetyStop);
+
+impl Drop for DeviceSafetyStopGuard {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            self.0.stop_all_devices();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn safety_stop_on_channel(channel_capacity: usize) -> (tokio::runtime::Runtime, DeviceSafetyStop, mpsc::Receiver<CommandToAsyncTasks>, watch::Sender<mpsc::Sender<CommandToAsyncTasks>>) {
+        let runtime = tokio::runtime::Runtime::new().expect("рантайм для теста");
+        let (command_sender, command_receiver) = mpsc::channel(channel_capacity);
+        let (command_sender_publisher, command_sender_subscription) = watch::channel(command_sender);
+        let device_safety_stop = DeviceSafetyStop::new(command_sender_subscription, runtime.handle().clone());
+        (runtime, device_safety_stop, command_receiver, command_sender_publisher)
+    }
+
+    fn received_commands(command_receiver: &mut mpsc::Receiver<CommandToAsyncTasks>) -> Vec<CommandToAsyncTasks> {
+        std::iter::from_fn(|| command_receiver.try_recv().ok()).collect()
+    }
+
+    #[test]
+    fn panic_inside_guard_scope_sends_stop_all_devices() {
+        let (_runtime, device_safety_stop, mut command_receiver, _command_sender_publisher) = safety_stop_on_channel(4);
+        let panic_result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _device_safety_stop_guard = DeviceSafetyStopGuard(device_safety_stop.clone());
+            panic!("тестовая паника в области guard'а");
+        }));
+
+        assert!(panic_result.is_err());
+        let commands = received_commands(&mut command_receiver);
+        assert_eq!(commands.len(), 1, "{commands:?}");
+        assert!(matches!(commands[0], CommandToAsyncTasks::StopAllDevices));
+    }
+
+    #[test]
+    fn guard_without_panic_sends_nothing() {
+        let (_runtime, device_safety_stop, mut command_receiver, _command_sender_publisher) = safety_stop_on_channel(4);
+        drop(DeviceSafetyStopGuard(device_safety_stop));
+        assert!(received_commands(&mut command_receiver).is_empty());
+    }
+
+    #[test]
+    fn stop_all_devices_runs_once_and_uses_current_channel() {
+        let (_runtime, device_safety_stop, mut stale_command_receiver, command_sender_publisher) = safety_stop_on_channel(4);
+        // Задача Buttplug перезапущена супервизором: остановка должна уйти в новый канал
+        let (restarted_command_sender, mut restarted_command_receiver) = mpsc::channel(4);
+        command_sender_publisher.send_replace(restarted_command_sender);
+
+        device_safety_stop.stop_all_devices();
+        device_safety_stop.stop_all_devices();
+
+        assert!(received_commands(&mut stale_command_receiver).is_empty());
+        let commands = received_commands(&mut restarted_command_receiver);
+        assert_eq!(commands.len(), 1, "{commands:?}");
+        assert!(matches!(commands[0], CommandToAsyncTasks::StopAllDevices));
+    }
+}
@@ -1,8 +1,72 @@
 // src/game_event_processor.rs
 
-use crate::configuration_manager::{ApplicationSettings, EventActionSetting, DeviceAction, DeviceActionType};
-use crate::war_thunder_connector::WarThunderIndicators;
-use crate::message_passing::CommandToAsyncTasks; // Если мы решим генерировать команды напрямую
+use crate::configuration_manager::{ActionTarget, ActuatorIntensity, ApplicationSettings, ConcurrencyLimitPolicy, ContinuousMappingSetting, EventActionSetting, DeviceAction, DeviceActionType, FlightEnvelopeProfile, RuleEvaluationMode, SimultaneousVibrationPolicy, TermCombineMode, VehicleClass};
+use crate::clock::Clock;
+use crate::condition_expression::{parse_condition_expression, ConditionExpression};
+use crate::telemetry_normalization::RAW_FIELD_PREFIX;
+use crate::war_thunder_api::{own_battle_outcome, HudDamageMessage, OwnBattleOutcome, WarThunderIndicators};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+// Имя виртуального поля с агрегированной интенсивностью боя (для непрерывных сопоставлений)
+pub const COMBAT_INTENSITY_FIELD_NAME: &str = "combat_intensity";
+// Виртуальное поле прогресса перезарядки (см. update_reload_state)
+pub const RELOAD_PROGRESS_FIELD_NAME: &str = "reload_progress";
+// Скорость изменения поля в единицах в секунду: "speed_rate" (со знаком, при падении отрицательна)
+// и "speed_decrease_rate" (только падение, 0 при росте - удобно для сопоставления в интенсивность)
+pub const RATE_FIELD_SUFFIX: &str = "_rate";
+pub const DECREASE_RATE_FIELD_SUFFIX: &str = "_decrease_rate";
+// Опросы чаще этого интервала не обновляют скорость: деление на крошечный интервал дает выбросы
+const MIN_RATE_SAMPLE_INTERVAL: Duration = Duration::from_millis(20);
+// После паузы длиннее этого (подвисание игры, потеря связи) прошлое значение устарело, скорость считается заново
+const MAX_RATE_SAMPLE_INTERVAL: Duration = Duration::from_secs(2);
+
+pub const CRITICAL_HEALTH_RULE_NAME: &str = "Критическое здоровье";
+pub const HARD_IMPACT_RULE_NAME: &str = "Жесткий удар";
+pub const REPAIR_COMPLETE_RULE_NAME: &str = "Ремонт завершен";
+pub const RELOAD_COMPLETE_RULE_NAME: &str = "Перезарядка завершена";
+pub const STALL_WARNING_RULE_NAME: &str = "Близко к сваливанию";
+pub const OVERSPEED_RULE_NAME: &str = "Превышение скорости";
+pub const OWN_KILL_RULE_NAME: &str = "Уничтожил противника";
+pub const OWN_DEATH_RULE_NAME: &str = "Техника уничтожена";
+
+// Правила без условия с такими именами срабатывают на вход в бой и выход из него
+// (имя сравнивается без учета регистра; русские варианты - синонимы)
+pub const COMBAT_STARTED_EVENT_NAME: &str = "CombatStarted";
+pub const COMBAT_ENDED_EVENT_NAME: &str = "CombatEnded";
+const COMBAT_STARTED_EVENT_ALIAS: &str = "Начало боя";
+const COMBAT_ENDED_EVENT_ALIAS: &str = "Конец боя";
+
+// Поля, доступные в выражениях условий и сопоставлениях (см. indicator_field_value)
+pub const INDICATOR_FIELD_NAMES: &[&str] = &[
+    COMBAT_INTENSITY_FIELD_NAME,
+    RELOAD_PROGRESS_FIELD_NAME,
+    "speed",
+    "altitude_10k",
+    "rpm_throttle",
+    "health_percentage",
+    "first_stage_ammo",
+    "g_load",
+];
+
+// Строковые поля: в условиях доступна только проверка "поле CHANGED" (см. string_indicator_field_value)
+pub const STRING_INDICATOR_FIELD_NAMES: &[&str] = &["vehicle_type", "army"];
+
+// Сработавшее правило: имя правила (для обратной связи в GUI) и действие на устройстве
+#[derive(Debug, Clone)]
+pub struct TriggeredEvent {
+    pub rule_name: String,
+    pub device_action: DeviceAction,
+}
+
+impl TriggeredEvent {
+    fn from_rule(event_action_config: &EventActionSetting) -> Self {
+        Self {
+            rule_name: event_action_config.name.clone(),
+            device_action: event_action_config.device_action.clone(),
+        }
+    }
+}
 
 // Эта структура будет хранить предыдущее состояние для сравнения
 #[derive(Default, Clone)]
@@ -10,8 +74,809 @@ pub struct GameStateSnapshot {
     // Добавь сюда поля, которые нужно отслеживать для определения событий "изменение"
     // Например:
     pub last_health_percentage: Option<f32>,
-    // pub last_shells_count: Option<u32>,
+    pub last_ammo_count: Option<f32>,
     // pub was_weapon_active: Option<bool>,
+    pub is_critical_health_signaled: bool, // Сигнал "критическое здоровье" уже отправлен в этой агонии
+    pub combat_intensity: f64, // Агрегированный сигнал 0.0..=1.0, затухает со временем
+    pub last_processed_at: Option<Instant>,
+    // Моменты окончания активных эффектов по имени правила (для ограничения одновременных эффектов)
+    pub active_rule_effects_until: HashMap<String, Vec<Instant>>,
+    // Результат условия-выражения правила на прошлом опросе (срабатываем по переходу false -> true)
+    pub rule_condition_states: HashMap<String, bool>,
+    // Разобранные условия по имени правила вместе с текстом, из которого разобраны (None - ошибка разбора).
+    // Текст разбирается один раз и заново - только после изменения условия в настройках.
+    pub parsed_rule_conditions: HashMap<String, (String, Option<ConditionExpression>)>,
+    pub last_hard_impact_at: Option<Instant>, // Для подавления повторных сигналов одного крушения
+    pub last_vehicle_type: Option<String>, // Смена техники = респаун, а не ремонт
+    pub smoothed_field_values: HashMap<String, f32>, // Текущие значения EMA по имени поля
+    pub is_stall_warning_active: bool, // С гистерезисом, см. update_flight_envelope_state
+    pub is_overspeed_active: bool,
+    pub is_in_combat: bool, // Был ли прошлый опрос в бою - для событий CombatStarted/CombatEnded
+    pub combat_vehicle_class: Option<VehicleClass>, // Класс техники в текущем бою (для правил CombatEnded)
+    // Поле army из /indicators в текущем бою, как его прислал WT. Это род войск техники ("air", "tank"),
+    // а не команда игрока: сторону (союзник/противник) /indicators не передает. None - вне боя или поля нет.
+    pub combat_army: Option<String>,
+    // Свои исходы за текущий бой из журнала /hudmsg (по нику из настроек)
+    pub own_kill_count: u32,
+    pub own_death_count: u32,
+    pub last_shot_at: Option<Instant>, // Начало текущей перезарядки
+    pub is_reloading: bool,
+    pub estimated_reload_duration: Option<Duration>, // Самый короткий интервал между выстрелами на этой технике
+    // Прогресс перезарядки после кривой: 0.0 - заряжено или только выстрелили, растет к 1.0 к готовности.
+    // None - длительность неизвестна или у техники нет счетчика снарядов.
+    pub reload_progress: Option<f64>,
+    // Последние значения строковых полей для проверок CHANGED. Переживают выход из боя:
+    // между техниками WT коротко отдает невалидные данные, а смена техники все равно должна засчитаться.
+    pub last_string_field_values: HashMap<String, String>,
+    // Значение поля и момент опроса, от которых считается скорость изменения (см. update_field_rates)
+    pub rate_samples: HashMap<String, (f64, Instant)>,
+    // Последняя вычисленная скорость изменения по имени поля, единиц в секунду
+    pub field_rates: HashMap<String, f64>,
+}
+
+// Новые записи журнала боя -> сигналы своих исходов. Чужие уничтожения пропускаются:
+// журнал общий для всех участников, свои записи узнаются по settings.player_name.
+pub fn process_hud_damage_messages(
+    hud_messages: &[HudDamageMessage],
+    settings: &ApplicationSettings,
+    previous_state: &mut GameStateSnapshot,
+) -> Vec<TriggeredEvent> {
+    let mut actions_to_perform = Vec::new();
+    for hud_message in hud_messages {
+        let Some(own_outcome) = own_battle_outcome(&hud_message.msg, &settings.player_name) else {
+            continue;
+        };
+        let (rule_name, optional_action) = match own_outcome {
+            OwnBattleOutcome::Kill => {
+                previous_state.own_kill_count += 1;
+                (OWN_KILL_RULE_NAME, &settings.own_kill_action)
+            }
+            OwnBattleOutcome::Death => {
+                previous_state.own_death_count += 1;
+                (OWN_DEATH_RULE_NAME, &settings.own_death_action)
+            }
+        };
+        tracing::info!("Журнал боя: {} ({})", rule_name, hud_message.msg);
+        if let Some(device_action) = optional_action {
+            actions_to_perform.push(TriggeredEvent {
+                rule_name: rule_name.to_string(),
+                device_action: device_action.clone(),
+            });
+        }
+    }
+    actions_to_perform
+}
+
+// Сбрасывает накопленное боевое состояние (вызывается вне боя или при потере связи с WT)
+pub fn reset_combat_state(previous_state: &mut GameStateSnapshot) {
+    let last_string_field_values = std::mem::take(&mut previous_state.last_string_field_values);
+    let parsed_rule_conditions = std::mem::take(&mut previous_state.parsed_rule_conditions);
+    *previous_state = GameStateSnapshot {
+        last_string_field_values,
+        parsed_rule_conditions,
+        ..GameStateSnapshot::default()
+    };
+}
+
+// Кладет в кэш разобранное условие правила, если его там нет или текст условия изменился
+fn cache_rule_condition(
+    parsed_rule_conditions: &mut HashMap<String, (String, Option<ConditionExpression>)>,
+    rule_name: &str,
+    condition_source: &str,
+) {
+    let is_cached = parsed_rule_conditions.get(rule_name)
+        .is_some_and(|(cached_source, _)| cached_source == condition_source);
+    if !is_cached {
+        let parsed_condition = parse_condition_expression(condition_source).ok();
+        parsed_rule_conditions.insert(rule_name.to_string(), (condition_source.to_string(), parsed_condition));
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CombatTransition {
+    Started,
+    Ended,
+}
+
+fn is_combat_event_rule(event_action_config: &EventActionSetting, combat_transition: CombatTransition) -> bool {
+    let (event_name, event_alias) = match combat_transition {
+        CombatTransition::Started => (COMBAT_STARTED_EVENT_NAME, COMBAT_STARTED_EVENT_ALIAS),
+        CombatTransition::Ended => (COMBAT_ENDED_EVENT_NAME, COMBAT_ENDED_EVENT_ALIAS),
+    };
+    let rule_name = event_action_config.name.trim().to_lowercase();
+    event_action_config.condition_expression.is_none()
+        && (rule_name == event_name.to_lowercase() || rule_name == event_alias.to_lowercase())
+}
+
+fn combat_event_rules(
+    settings: &ApplicationSettings,
+    combat_transition: CombatTransition,
+    vehicle_class: Option<VehicleClass>,
+) -> Vec<TriggeredEvent> {
+    effective_event_actions(settings).iter()
+        .filter(|event_action_config| {
+            event_action_config.enabled
+                && rule_applies_to_vehicle(event_action_config, vehicle_class)
+                && is_combat_event_rule(event_action_config, combat_transition)
+        })
+        .map(TriggeredEvent::from_rule)
+        .collect()
+}
+
+// Выход из боя любым путем: невалидные индикаторы, "не в бою" от API или потеря связи с WT.
+// Сбрасывает боевое состояние; правила CombatEnded возвращаются только один раз - если бой действительно шел.
+pub fn end_combat(settings: &ApplicationSettings, previous_state: &mut GameStateSnapshot) -> Vec<TriggeredEvent> {
+    let was_in_combat = previous_state.is_in_combat;
+    let combat_vehicle_class = previous_state.combat_vehicle_class;
+    reset_combat_state(previous_state);
+    if !was_in_combat {
+        return Vec::new();
+    }
+    tracing::info!("Бой закончился");
+    combat_event_rules(settings, CombatTransition::Ended, combat_vehicle_class)
+}
+
+// Индикаторы описывают бой: есть тип техники и WT не пометил данные как невалидные
+pub fn is_battle_data(current_indicators: &WarThunderIndicators) -> bool {
+    current_indicators.vehicle_type.is_some() && current_indicators.valid != Some(false)
+}
+
+// Изменяемое поле структуры индикаторов по имени (виртуальные поля не сглаживаются)
+fn indicator_field_mut<'a>(indicators: &'a mut WarThunderIndicators, field_name: &str) -> Option<&'a mut Option<f32>> {
+    match field_name {
+        "speed" => Some(&mut indicators.speed),
+        "altitude_10k" => Some(&mut indicators.altitude_10k),
+        "rpm_throttle" => Some(&mut indicators.rpm_throttle),
+        "health_percentage" => Some(&mut indicators.health_percentage),
+        "first_stage_ammo" => Some(&mut indicators.first_stage_ammo),
+        "g_load" => Some(&mut indicators.g_load),
+        _ => None,
+    }
+}
+
+// Обновляет EMA сглаживаемых полей новыми значениями. Пропавшее поле сбрасывает свое среднее.
+fn update_smoothed_field_values(
+    current_indicators: &WarThunderIndicators,
+    settings: &ApplicationSettings,
+    previous_state: &mut GameStateSnapshot,
+) {
+    // Сглаживание могли выключить в настройках - его старое среднее больше не подставляется
+    previous_state.smoothed_field_values
+        .retain(|field_name, _| settings.indicator_smoothing.iter().any(|smoothing| smoothing.field == *field_name));
+    for smoothing in &settings.indicator_smoothing {
+        let raw_value = match smoothing.field.as_str() {
+            field_name if is_virtual_indicator_field(field_name) => None, // Виртуальные поля вычисляются процессором и не сглаживаются
+            field_name => indicator_field_value(current_indicators, previous_state, field_name).map(|value| value as f32),
+        };
+        let Some(raw_value) = raw_value else {
+            previous_state.smoothed_field_values.remove(&smoothing.field);
+            continue;
+        };
+        let alpha = smoothing.alpha.clamp(0.01, 1.0) as f32;
+        let smoothed_value = match previous_state.smoothed_field_values.get(&smoothing.field) {
+            Some(&last_smoothed) => alpha * raw_value + (1.0 - alpha) * last_smoothed,
+            None => raw_value,
+        };
+        previous_state.smoothed_field_values.insert(smoothing.field.clone(), smoothed_value);
+    }
+}
+
+// Копия индикаторов, в которой сглаживаемые поля заменены текущими значениями EMA
+fn with_smoothed_fields(current_indicators: &WarThunderIndicators, previous_state: &GameStateSnapshot) -> WarThunderIndicators {
+    let mut smoothed_indicators = current_indicators.clone();
+    for (field_name, smoothed_value) in &previous_state.smoothed_field_values {
+        if let Some(field_value) = indicator_field_mut(&mut smoothed_indicators, field_name) {
+            *field_value = Some(*smoothed_value);
+        }
+    }
+    smoothed_indicators
+}
+
+fn update_combat_intensity(
+    current_indicators: &WarThunderIndicators,
+    settings: &ApplicationSettings,
+    previous_state: &mut GameStateSnapshot,
+    now: Instant,
+) {
+    let combat_settings = &settings.combat_intensity;
+
+    if let Some(last_processed_at) = previous_state.last_processed_at {
+        let elapsed_seconds = now.duration_since(last_processed_at).as_secs_f64();
+        let decay_factor = (1.0 - combat_settings.decay_per_second.clamp(0.0, 1.0)).powf(elapsed_seconds);
+        previous_state.combat_intensity *= decay_factor;
+    }
+
+    if let (Some(current_health), Some(last_health)) = (current_indicators.health_percentage, previous_state.last_health_percentage)
+        && current_health < last_health
+    {
+        previous_state.combat_intensity += (last_health - current_health) as f64 * combat_settings.damage_bump_weight;
+    }
+    if let (Some(current_ammo), Some(last_ammo)) = (current_indicators.first_stage_ammo, previous_state.last_ammo_count)
+        && current_ammo < last_ammo
+    {
+        previous_state.combat_intensity += (last_ammo - current_ammo) as f64 * combat_settings.fire_bump_weight;
+    }
+
+    previous_state.combat_intensity = previous_state.combat_intensity.clamp(0.0, 1.0);
+    previous_state.last_processed_at = Some(now);
+}
+
+// Класс техники по данным индикаторов: сначала поле army, затем префикс модели в type
+// ("tankModels/...", "shipModels/..."). None - класс определить не удалось.
+pub fn classify_vehicle(current_indicators: &WarThunderIndicators) -> Option<VehicleClass> {
+    match current_indicators.army.as_deref().map(str::to_ascii_lowercase).as_deref() {
+        Some("air") => return Some(VehicleClass::Air),
+        Some("tank") => return Some(VehicleClass::Ground),
+        Some("ship") | Some("naval") => return Some(VehicleClass::Naval),
+        _ => {}
+    }
+    let vehicle_type = current_indicators.vehicle_type.as_deref()?.to_ascii_lowercase();
+    if vehicle_type.starts_with("tankmodels/") {
+        Some(VehicleClass::Ground)
+    } else if vehicle_type.starts_with("shipmodels/") {
+        Some(VehicleClass::Naval)
+    } else {
+        None
+    }
+}
+
+// Правило без списка классов действует всегда; со списком - только для опознанной техники из списка
+fn rule_applies_to_vehicle(event_action_config: &EventActionSetting, vehicle_class: Option<VehicleClass>) -> bool {
+    event_action_config.vehicle_classes.is_empty()
+        || vehicle_class.is_some_and(|current_class| event_action_config.vehicle_classes.contains(&current_class))
+}
+
+// Итоговый набор правил: основные event_actions, поверх которых по порядку накладываются активные слои.
+// Совпадение имени заменяет правило на месте, новые имена идут в конец.
+// Затем правила упорядочиваются по убыванию priority; сортировка устойчивая, так что при равном
+// приоритете остается этот порядок.
+pub fn effective_event_actions(settings: &ApplicationSettings) -> Vec<EventActionSetting> {
+    let mut merged_event_actions = settings.event_actions.clone();
+    for active_layer_name in &settings.active_rule_layers {
+        let Some(rule_layer) = settings.rule_layers.iter().find(|rule_layer| rule_layer.name == *active_layer_name) else {
+            continue; // Неизвестные имена сообщаются в validate_rule_conditions
+        };
+        for layer_event_action in &rule_layer.event_actions {
+            match merged_event_actions.iter_mut().find(|event_action| event_action.name == layer_event_action.name) {
+                Some(overridden_event_action) => *overridden_event_action = layer_event_action.clone(),
+                None => merged_event_actions.push(layer_event_action.clone()),
+            }
+        }
+    }
+    merged_event_actions.sort_by_key(|event_action| std::cmp::Reverse(event_action.priority));
+    merged_event_actions
+}
+
+// Проверяет выражения условий всех правил. Возвращает сообщения об ошибках (пусто - все в порядке).
+pub fn validate_rule_conditions(settings: &ApplicationSettings) -> Vec<String> {
+    let mut validation_errors = Vec::new();
+    for active_layer_name in &settings.active_rule_layers {
+        if !settings.rule_layers.iter().any(|rule_layer| rule_layer.name == *active_layer_name) {
+            validation_errors.push(format!("Активный слой правил '{}' не найден в rule_layers", active_layer_name));
+        }
+    }
+    let layer_event_actions = settings.rule_layers.iter().flat_map(|rule_layer| rule_layer.event_actions.iter());
+    for event_action_config in settings.event_actions.iter().chain(layer_event_actions) {
+        for rule_error in validate_event_action(event_action_config) {
+            validation_errors.push(format!("Правило '{}': {}", event_action_config.name, rule_error));
+        }
+    }
+    validation_errors
+}
+
+// Допустимый диапазон числового поля. None - поле не ограничено (например, перегрузка бывает отрицательной).
+pub fn indicator_field_value_range(field_name: &str) -> Option<(f64, f64)> {
+    match field_name {
+        COMBAT_INTENSITY_FIELD_NAME | RELOAD_PROGRESS_FIELD_NAME | "rpm_throttle" => Some((0.0, 1.0)),
+        "health_percentage" => Some((0.0, 100.0)),
+        "speed" | "altitude_10k" | "first_stage_ammo" => Some((0.0, f64::INFINITY)),
+        _ if matches!(rate_field_source(field_name), Some((_, RateFieldKind::Decrease))) => Some((0.0, f64::INFINITY)),
+        _ => None,
+    }
+}
+
+// Ошибки одного правила (без имени правила) - для загрузки конфигурации и для редактора на лету
+pub fn validate_event_action(event_action_config: &EventActionSetting) -> Vec<String> {
+    let mut rule_errors = Vec::new();
+    if let Some(condition_source) = &event_action_config.condition_expression {
+        match parse_condition_expression(condition_source) {
+            Ok(condition) => {
+                for field_name in condition.comparison_field_names() {
+                    if STRING_INDICATOR_FIELD_NAMES.contains(&field_name) {
+                        rule_errors.push(format!(
+                            "строковое поле '{}' нельзя сравнивать, доступна только проверка '{} CHANGED'",
+                            field_name,
+                            field_name
+                        ));
+                    } else if !INDICATOR_FIELD_NAMES.contains(&field_name)
+                        && !field_name.starts_with(RAW_FIELD_PREFIX)
+                        && rate_field_source(field_name).is_none()
+                    {
+                        rule_errors.push(format!(
+                            "неизвестное поле '{}' (доступны: {}, их скорости <поле>{} и <поле>{}, а также сырые поля {}<имя>)",
+                            field_name,
+                            INDICATOR_FIELD_NAMES.join(", "),
+                            RATE_FIELD_SUFFIX,
+                            DECREASE_RATE_FIELD_SUFFIX,
+                            RAW_FIELD_PREFIX
+                        ));
+                    }
+                }
+                for field_name in condition.changed_field_names() {
+                    if !STRING_INDICATOR_FIELD_NAMES.contains(&field_name) {
+                        rule_errors.push(format!(
+                            "CHANGED доступен только для строковых полей ({}), а не для '{}'",
+                            STRING_INDICATOR_FIELD_NAMES.join(", "),
+                            field_name
+                        ));
+                    }
+                }
+                for (field_name, value) in condition.comparison_values() {
+                    if let Some((range_min, range_max)) = indicator_field_value_range(field_name)
+                        && (value < range_min || value > range_max)
+                    {
+                        rule_errors.push(format!(
+                            "значение {} вне диапазона поля '{}' ({}..{}), условие может никогда не выполниться",
+                            value,
+                            field_name,
+                            range_min,
+                            range_max
+                        ));
+                    }
+                }
+            }
+            Err(parse_error) => rule_errors.push(parse_error),
+        }
+    }
+    let device_action = &event_action_config.device_action;
+    if device_action.action_type == DeviceActionType::Vibrate {
+        if !(0.0..=1.0).contains(&device_action.intensity) {
+            rule_errors.push(format!("интенсивность {} вне диапазона 0..1", device_action.intensity));
+        }
+        if device_action.duration_milliseconds == 0 {
+            rule_errors.push("длительность вибрации 0 мс".to_string());
+        }
+        for actuator_intensity in &device_action.actuator_intensities {
+            if !(0.0..=1.0).contains(&actuator_intensity.intensity) {
+                rule_errors.push(format!(
+                    "интенсивность мотора #{} ({}) вне диапазона 0..1",
+                    actuator_intensity.actuator_index,
+                    actuator_intensity.intensity
+                ));
+            }
+        }
+    }
+    rule_errors
+}
+
+// Решает, можно ли запустить еще один эффект правила, и учитывает его как активный.
+// Эффект считается активным в течение duration_milliseconds действия: столько же Buttplug задача
+// держит вибрацию до запланированной остановки, так что учет совпадает с реальным выводом.
+// Restart отправляет команду заново - устройство перезапускает эффект и переносит остановку.
+fn admit_rule_trigger(
+    event_action_config: &EventActionSetting,
+    previous_state: &mut GameStateSnapshot,
+    now: Instant,
+) -> bool {
+    if event_action_config.max_concurrent_effects == 0 {
+        return true;
+    }
+    let effect_ends_at = now + Duration::from_millis(event_action_config.device_action.duration_milliseconds);
+    let active_effects_until = previous_state.active_rule_effects_until
+        .entry(event_action_config.name.clone())
+        .or_default();
+    active_effects_until.retain(|ends_at| *ends_at > now);
+
+    if active_effects_until.len() < event_action_config.max_concurrent_effects as usize {
+        active_effects_until.push(effect_ends_at);
+        return true;
+    }
+    match event_action_config.concurrency_limit_policy {
+        ConcurrencyLimitPolicy::DropNew => false,
+        ConcurrencyLimitPolicy::Restart => {
+            // Вытесняем эффект, который закончится раньше всех
+            if let Some(oldest_position) = active_effects_until.iter().enumerate().min_by_key(|(_, ends_at)| **ends_at).map(|(position, _)| position) {
+                active_effects_until.remove(oldest_position);
+            }
+            active_effects_until.push(effect_ends_at);
+            true
+        }
+    }
+}
+
+// Жесткий удар: всплеск перегрузки выше порога или переход "живой -> уничтожен" у самой земли.
+// Сравнивает с прошлым здоровьем, поэтому вызывается до обновления last_health_percentage.
+fn detect_hard_impact(
+    current_indicators: &WarThunderIndicators,
+    settings: &ApplicationSettings,
+    previous_state: &mut GameStateSnapshot,
+    now: Instant,
+) -> bool {
+    let impact_settings = &settings.hard_impact;
+    let is_g_spike = current_indicators.g_load
+        .is_some_and(|g_load| f64::from(g_load).abs() >= impact_settings.g_threshold);
+    let is_near_ground = current_indicators.altitude_10k
+        .is_some_and(|altitude| f64::from(altitude) <= impact_settings.near_ground_altitude);
+    let is_destroyed_now = matches!(
+        (previous_state.last_health_percentage, current_indicators.health_percentage),
+        (Some(last_health), Some(current_health)) if last_health > 0.0 && current_health <= 0.0
+    );
+    let is_crashed_into_ground = is_destroyed_now && is_near_ground;
+    if !(is_g_spike || is_crashed_into_ground) {
+        return false;
+    }
+
+    let cooldown = Duration::from_millis(impact_settings.cooldown_milliseconds);
+    if previous_state.last_hard_impact_at.is_some_and(|last_impact_at| now.duration_since(last_impact_at) < cooldown) {
+        return false;
+    }
+    previous_state.last_hard_impact_at = Some(now);
+    true
+}
+
+// Ремонт завершен: здоровье пересекло порог снизу вверх постепенным ростом на той же технике.
+// Вызывается до обновления last_health_percentage и last_vehicle_type.
+fn detect_repair_complete(
+    current_indicators: &WarThunderIndicators,
+    settings: &ApplicationSettings,
+    previous_state: &GameStateSnapshot,
+) -> bool {
+    let repair_settings = &settings.repair_complete;
+    let (Some(last_health), Some(current_health)) = (previous_state.last_health_percentage, current_indicators.health_percentage) else {
+        return false;
+    };
+    let is_same_vehicle = previous_state.last_vehicle_type.is_some()
+        && previous_state.last_vehicle_type == current_indicators.vehicle_type;
+    let health_increase = current_health - last_health;
+    is_same_vehicle
+        && last_health < repair_settings.health_threshold
+        && current_health >= repair_settings.health_threshold
+        && health_increase <= repair_settings.max_single_step_increase
+}
+
+// Обновляет прогресс перезарядки. Перезарядка начинается с выстрела (уменьшение first_stage_ammo)
+// и длится reload_seconds или оценку по интервалам между выстрелами. Возвращает true, когда она только что закончилась.
+// Вызывается до обновления last_ammo_count и last_vehicle_type.
+fn update_reload_state(
+    current_indicators: &WarThunderIndicators,
+    settings: &ApplicationSettings,
+    previous_state: &mut GameStateSnapshot,
+    now: Instant,
+) -> bool {
+    let reload_settings = &settings.reload_feedback;
+    // Смена техники (респаун): у новой техники другое орудие
+    if previous_state.last_vehicle_type != current_indicators.vehicle_type {
+        previous_state.estimated_reload_duration = None;
+        previous_state.last_shot_at = None;
+        previous_state.is_reloading = false;
+    }
+    let Some(current_ammo) = current_indicators.first_stage_ammo else {
+        // Без счетчика снарядов перезарядку не определить - поле просто отсутствует
+        previous_state.is_reloading = false;
+        previous_state.reload_progress = None;
+        return false;
+    };
+
+    if previous_state.last_ammo_count.is_some_and(|last_ammo| current_ammo < last_ammo) {
+        if let Some(last_shot_at) = previous_state.last_shot_at {
+            let shot_interval = now.duration_since(last_shot_at);
+            if shot_interval.as_secs_f64() >= reload_settings.min_shot_interval_seconds {
+                previous_state.estimated_reload_duration = Some(previous_state.estimated_reload_duration
+                    .map_or(shot_interval, |estimated_duration| estimated_duration.min(shot_interval)));
+            }
+        }
+        previous_state.last_shot_at = Some(now);
+        previous_state.is_reloading = true;
+    }
+
+    // Файл настроек значение не ограничивает (TOML допускает inf): непредставимая длительность считается незаданной
+    let manual_reload_duration = (reload_settings.reload_seconds > 0.0)
+        .then(|| Duration::try_from_secs_f64(reload_settings.reload_seconds).ok())
+        .flatten();
+    let reload_duration = manual_reload_duration.or(previous_state.estimated_reload_duration);
+    let (Some(reload_duration), Some(last_shot_at)) = (reload_duration, previous_state.last_shot_at) else {
+        previous_state.reload_progress = None;
+        return false;
+    };
+    if !previous_state.is_reloading {
+        previous_state.reload_progress = Some(0.0);
+        return false;
+    }
+    let linear_progress = now.duration_since(last_shot_at).as_secs_f64() / reload_duration.as_secs_f64().max(0.001);
+    if linear_progress >= 1.0 {
+        previous_state.is_reloading = false;
+        previous_state.reload_progress = Some(0.0);
+        return true;
+    }
+    previous_state.reload_progress = Some(linear_progress.powf(reload_settings.curve_exponent.max(0.1)));
+    false
+}
+
+// Профиль скоростей для текущей техники; только для опознанных самолетов
+pub fn flight_envelope_profile<'a>(
+    current_indicators: &WarThunderIndicators,
+    settings: &'a ApplicationSettings,
+) -> Option<&'a FlightEnvelopeProfile> {
+    if classify_vehicle(current_indicators) != Some(VehicleClass::Air) {
+        return None;
+    }
+    settings.flight_envelope.profile_for_vehicle(current_indicators.vehicle_type.as_deref()?)
+}
+
+// Обновляет состояния "близко к сваливанию" и "превышение скорости". Состояние включается при пересечении
+// порога и выключается, только когда скорость ушла от него на profile.hysteresis, - иначе у порога
+// эффект дребезжал бы на каждом опросе. Возвращает (вход в предупреждение о сваливании, вход в превышение).
+fn update_flight_envelope_state(
+    current_indicators: &WarThunderIndicators,
+    profile: &FlightEnvelopeProfile,
+    min_altitude: f32,
+    previous_state: &mut GameStateSnapshot,
+) -> (bool, bool) {
+    let was_stall_warning_active = previous_state.is_stall_warning_active;
+    let was_overspeed_active = previous_state.is_overspeed_active;
+    let Some(speed) = current_indicators.speed else {
+        previous_state.is_stall_warning_active = false;
+        previous_state.is_overspeed_active = false;
+        return (false, false);
+    };
+
+    let stall_warning_speed = profile.stall_speed + profile.stall_warning_margin;
+    let is_below_min_altitude = current_indicators.altitude_10k.is_some_and(|altitude| altitude < min_altitude);
+    previous_state.is_stall_warning_active = if is_below_min_altitude {
+        false
+    } else if was_stall_warning_active {
+        speed < stall_warning_speed + profile.hysteresis
+    } else {
+        speed < stall_warning_speed
+    };
+    previous_state.is_overspeed_active = if was_overspeed_active {
+        speed > profile.overspeed_speed - profile.hysteresis
+    } else {
+        speed > profile.overspeed_speed
+    };
+
+    (
+        !was_stall_warning_active && previous_state.is_stall_warning_active,
+        !was_overspeed_active && previous_state.is_overspeed_active,
+    )
+}
+
+pub fn is_virtual_indicator_field(field_name: &str) -> bool {
+    field_name == COMBAT_INTENSITY_FIELD_NAME || field_name == RELOAD_PROGRESS_FIELD_NAME
+}
+
+// Игнорирование поля распространяется и на его скорости
+pub fn is_indicator_field_ignored(settings: &ApplicationSettings, field_name: &str) -> bool {
+    let source_field_name = rate_field_source(field_name).map_or(field_name, |(source_field_name, _)| source_field_name);
+    settings.ignored_indicator_fields.iter().any(|ignored_field| ignored_field == source_field_name)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateFieldKind {
+    Signed,
+    Decrease,
+}
+
+// Поле скорости -> (исходное поле, вид скорости). Скорость есть только у невиртуальных числовых полей.
+pub fn rate_field_source(field_name: &str) -> Option<(&str, RateFieldKind)> {
+    let (source_field_name, rate_field_kind) = match field_name.strip_suffix(DECREASE_RATE_FIELD_SUFFIX) {
+        Some(source_field_name) => (source_field_name, RateFieldKind::Decrease),
+        None => (field_name.strip_suffix(RATE_FIELD_SUFFIX)?, RateFieldKind::Signed),
+    };
+    let has_rate = INDICATOR_FIELD_NAMES.contains(&source_field_name) && !is_virtual_indicator_field(source_field_name);
+    has_rate.then_some((source_field_name, rate_field_kind))
+}
+
+// Скорость изменения полей по фактическому интервалу между опросами (он не постоянный: WT отвечает
+// с задержками, опрос может пропускаться). Слишком частые опросы копятся до MIN_RATE_SAMPLE_INTERVAL,
+// после паузы дольше MAX_RATE_SAMPLE_INTERVAL скорость неизвестна до следующего опроса.
+fn update_field_rates(current_indicators: &WarThunderIndicators, previous_state: &mut GameStateSnapshot, now: Instant) {
+    for &field_name in INDICATOR_FIELD_NAMES {
+        if is_virtual_indicator_field(field_name) {
+            continue;
+        }
+        let Some(current_value) = indicator_field_value(current_indicators, previous_state, field_name) else {
+            previous_state.rate_samples.remove(field_name);
+            previous_state.field_rates.remove(field_name);
+            continue;
+        };
+        if let Some(&(last_value, sampled_at)) = previous_state.rate_samples.get(field_name) {
+            let elapsed = now.saturating_duration_since(sampled_at);
+            if elapsed < MIN_RATE_SAMPLE_INTERVAL {
+                continue;
+            }
+            if elapsed <= MAX_RATE_SAMPLE_INTERVAL {
+                let field_rate = (current_value - last_value) / elapsed.as_secs_f64();
+                previous_state.field_rates.insert(field_name.to_string(), field_rate);
+            } else {
+                previous_state.field_rates.remove(field_name);
+            }
+        }
+        previous_state.rate_samples.insert(field_name.to_string(), (current_value, now));
+    }
+}
+
+// Значение поля по имени, включая виртуальные поля, вычисляемые процессором
+pub fn indicator_field_value(
+    current_indicators: &WarThunderIndicators,
+    previous_state: &GameStateSnapshot,
+    field_name: &str,
+) -> Option<f64> {
+    match field_name {
+        COMBAT_INTENSITY_FIELD_NAME => Some(previous_state.combat_intensity),
+        RELOAD_PROGRESS_FIELD_NAME => previous_state.reload_progress,
+        "speed" => current_indicators.speed.map(f64::from),
+        "altitude_10k" => current_indicators.altitude_10k.map(f64::from),
+        "rpm_throttle" => current_indicators.rpm_throttle.map(f64::from),
+        "health_percentage" => current_indicators.health_percentage.map(f64::from),
+        "first_stage_ammo" => current_indicators.first_stage_ammo.map(f64::from),
+        "g_load" => current_indicators.g_load.map(f64::from),
+        _ => {
+            if let Some((source_field_name, rate_field_kind)) = rate_field_source(field_name) {
+                let field_rate = previous_state.field_rates.get(source_field_name).copied()?;
+                return Some(match rate_field_kind {
+                    RateFieldKind::Signed => field_rate,
+                    RateFieldKind::Decrease => (-field_rate).max(0.0),
+                });
+            }
+            field_name.strip_prefix(RAW_FIELD_PREFIX)
+                .and_then(|raw_field_name| current_indicators.raw_fields.get(raw_field_name).copied())
+        }
+    }
+}
+
+pub fn string_indicator_field_value<'a>(current_indicators: &'a WarThunderIndicators, field_name: &str) -> Option<&'a str> {
+    match field_name {
+        "vehicle_type" => current_indicators.vehicle_type.as_deref(),
+        "army" => current_indicators.army.as_deref(),
+        _ => None,
+    }
+}
+
+// Строковое поле изменилось с прошлого опроса. Первое появление значения изменением не считается.
+fn is_string_field_changed(current_indicators: &WarThunderIndicators, previous_state: &GameStateSnapshot, field_name: &str) -> bool {
+    match (previous_state.last_string_field_values.get(field_name), string_indicator_field_value(current_indicators, field_name)) {
+        (Some(last_value), Some(current_value)) => last_value != current_value,
+        _ => false,
+    }
+}
+
+// Нормализует значение поля в 0.0..=1.0 по входному диапазону. None - поля нет или диапазон пустой.
+fn normalized_field_value(
+    current_indicators: &WarThunderIndicators,
+    previous_state: &GameStateSnapshot,
+    field_name: &str,
+    input_min: f64,
+    input_max: f64,
+) -> Option<f64> {
+    let value = indicator_field_value(current_indicators, previous_state, field_name)?;
+    let input_range = input_max - input_min;
+    if input_range <= f64::EPSILON {
+        return None;
+    }
+    Some(((value - input_min) / input_range).clamp(0.0, 1.0))
+}
+
+fn evaluate_continuous_mapping(
+    current_indicators: &WarThunderIndicators,
+    settings: &ApplicationSettings,
+    previous_state: &GameStateSnapshot,
+    mapping: &ContinuousMappingSetting,
+) -> Option<f64> {
+    // Игнорируемые поля ведут себя как отсутствующие
+    let primary_term = Some(&mapping.source_field)
+        .filter(|field_name| !is_indicator_field_ignored(settings, field_name))
+        .and_then(|field_name| normalized_field_value(current_indicators, previous_state, field_name, mapping.input_min, mapping.input_max))
+        .map(|normalized| (normalized, 1.0));
+    // Некорректные веса (отрицательные, NaN) не участвуют
+    let additional_terms = mapping.additional_terms.iter()
+        .filter(|term| term.weight.is_finite() && term.weight > 0.0)
+        .filter(|term| !is_indicator_field_ignored(settings, &term.field))
+        .filter_map(|term| {
+            normalized_field_value(current_indicators, previous_state, &term.field, term.input_min, term.input_max)
+                .map(|normalized| (normalized, term.weight))
+        });
+    let weighted_values = primary_term.into_iter().chain(additional_terms).map(|(normalized, weight)| normalized * weight);
+
+    let combined = match mapping.combine_mode {
+        TermCombineMode::WeightedSum => weighted_values.reduce(|sum, value| sum + value),
+        TermCombineMode::Max => weighted_values.reduce(f64::max),
+    }?;
+    let mapped_intensity = combined.clamp(0.0, 1.0) * mapping.max_intensity.clamp(0.0, 1.0);
+    Some(if mapped_intensity < mapping.deadzone { 0.0 } else { mapped_intensity })
+}
+
+// Поле, значение которого можно подставить при подборе кривой (simulate_continuous_mapping)
+pub fn is_simulatable_indicator_field(field_name: &str) -> bool {
+    indicator_field_mut(&mut WarThunderIndicators::default(), field_name).is_some()
+}
+
+// Интенсивность одного сопоставления, если основное поле равно field_value, а остальные - как в base_indicators.
+// Для подбора кривой в GUI без игры: сглаживание и флаг enabled не учитываются.
+// None - поле нельзя подставить (виртуальное, сырое, скорость) или сопоставление не дает значения.
+pub fn simulate_continuous_mapping(
+    base_indicators: &WarThunderIndicators,
+    settings: &ApplicationSettings,
+    previous_state: &GameStateSnapshot,
+    mapping: &ContinuousMappingSetting,
+    field_value: f64,
+) -> Option<f64> {
+    let mut simulated_indicators = base_indicators.clone();
+    *indicator_field_mut(&mut simulated_indicators, &mapping.source_field)? = Some(field_value as f32);
+    evaluate_continuous_mapping(&simulated_indicators, settings, previous_state, mapping)
+}
+
+// Вычисляет итоговую интенсивность всех включенных непрерывных сопоставлений (берется максимум).
+// Возвращает None, если ни одно сопоставление не активно.
+pub fn evaluate_continuous_mappings(
+    current_indicators: &WarThunderIndicators,
+    settings: &ApplicationSettings,
+    previous_state: &GameStateSnapshot,
+) -> Option<f64> {
+    let smoothed_indicators = with_smoothed_fields(current_indicators, previous_state);
+    settings.continuous_mappings.iter()
+        .filter(|mapping| mapping.enabled)
+        .filter_map(|mapping| evaluate_continuous_mapping(&smoothed_indicators, settings, previous_state, mapping))
+        .reduce(f64::max)
+}
+
+// Сводит вибрации, адресованные одной цели в одном обновлении, в одно действие по policy:
+// иначе устройство получает команды подряд и остается с последней. resolve_target_key приводит
+// цели к общему ключу (например, выбранное устройство - к его адресу), сами цели не меняются.
+// Паттерны и остановки проходят без изменений; сводное действие стоит на месте первого из группы.
+// Длительность - наибольшая; поприводные интенсивности - у выбранной вибрации, при сумме складываются по приводам.
+pub fn merge_simultaneous_vibrations(
+    device_actions: Vec<DeviceAction>,
+    policy: SimultaneousVibrationPolicy,
+    resolve_target_key: impl Fn(&ActionTarget) -> ActionTarget,
+) -> Vec<DeviceAction> {
+    let mut merged_actions: Vec<DeviceAction> = Vec::new();
+    let mut merged_position_by_target: Vec<(ActionTarget, usize)> = Vec::new();
+    for device_action in device_actions {
+        if device_action.action_type != DeviceActionType::Vibrate || !device_action.pattern_points.is_empty() {
+            merged_actions.push(device_action);
+            continue;
+        }
+        let target_key = resolve_target_key(&device_action.target);
+        let Some(&(_, merged_position)) = merged_position_by_target.iter().find(|(key, _)| *key == target_key) else {
+            merged_position_by_target.push((target_key, merged_actions.len()));
+            merged_actions.push(device_action);
+            continue;
+        };
+        let merged_action = &mut merged_actions[merged_position];
+        let duration_milliseconds = merged_action.duration_milliseconds.max(device_action.duration_milliseconds);
+        match policy {
+            SimultaneousVibrationPolicy::Max => {
+                if device_action.intensity > merged_action.intensity {
+                    merged_action.intensity = device_action.intensity;
+                    merged_action.actuator_intensities = device_action.actuator_intensities;
+                }
+            }
+            SimultaneousVibrationPolicy::SumClamped => {
+                // Приводы с собственной интенсивностью хотя бы в одном действии складываются по отдельности,
+                // остальные получают сумму общих intensity
+                let mut actuator_indices: Vec<u32> = merged_action.actuator_intensities.iter()
+                    .chain(&device_action.actuator_intensities)
+                    .map(|actuator_intensity| actuator_intensity.actuator_index)
+                    .collect();
+                actuator_indices.sort_unstable();
+                actuator_indices.dedup();
+                merged_action.actuator_intensities = actuator_indices.into_iter()
+                    .map(|actuator_index| ActuatorIntensity {
+                        actuator_index,
+                        intensity: (merged_action.actuator_intensity(actuator_index) + device_action.actuator_intensity(actuator_index)).min(1.0),
+                    })
+                    .collect();
+                merged_action.intensity = (merged_action.intensity + device_action.intensity).min(1.0);
+            }
+            SimultaneousVibrationPolicy::Last => {
+                merged_action.intensity = device_action.intensity;
+                merged_action.actuator_intensities = device_action.actuator_intensities;
+            }
+        }
+        merged_action.duration_milliseconds = duration_milliseconds;
+    }
+    merged_actions
 }
 
 // Эта функция будет вызываться при получении новых данных от War Thunder.
@@ -22,11 +887,143 @@ pub fn process_war_thunder_data(
     current_indicators: &WarThunderIndicators,
     settings: &ApplicationSettings,
     previous_state: &mut GameStateSnapshot, // mutable для обновления состояния
-) -> Vec<DeviceAction> { // Возвращаем список действий, а не команд напрямую
-    let mut actions_to_perform: Vec<DeviceAction> = Vec::new();
+    clock: &dyn Clock, // Время для кулдаунов и затухания (см. crate::clock)
+) -> Vec<TriggeredEvent> { // Возвращаем список сработавших правил, а не команд напрямую
+    let mut actions_to_perform: Vec<TriggeredEvent> = Vec::new();
+
+    // Вне боя накопленное состояние не имеет смысла
+    if !is_battle_data(current_indicators) {
+        return end_combat(settings, previous_state);
+    }
+
+    let vehicle_class = classify_vehicle(current_indicators);
+    previous_state.combat_vehicle_class = vehicle_class;
+    previous_state.combat_army = current_indicators.army.clone();
+    if !previous_state.is_in_combat {
+        previous_state.is_in_combat = true;
+        tracing::info!("Бой начался: {:?}", current_indicators.vehicle_type);
+        actions_to_perform.extend(combat_event_rules(settings, CombatTransition::Started, vehicle_class));
+    }
 
-    for event_action_config in &settings.event_actions {
-        if !event_action_config.enabled {
+    // Дальше все проверки (включая "изменилось ли значение") идут по сглаженным значениям;
+    // прошлые значения в previous_state тоже сохраняются сглаженными
+    update_smoothed_field_values(current_indicators, settings, previous_state);
+    let smoothed_indicators = with_smoothed_fields(current_indicators, previous_state);
+    let current_indicators = &smoothed_indicators;
+
+    let now = clock.now();
+    update_combat_intensity(current_indicators, settings, previous_state, now);
+    update_field_rates(current_indicators, previous_state, now);
+
+    let is_health_ignored = is_indicator_field_ignored(settings, "health_percentage");
+    let is_below_damage_floor = current_indicators.health_percentage
+        .is_some_and(|current_health| current_health < settings.damage_rules_min_health_percentage);
+    if is_health_ignored {
+        // Встроенные правила по здоровью молчат, пока поле игнорируется
+    } else if is_below_damage_floor {
+        if !previous_state.is_critical_health_signaled {
+            if let Some(critical_action) = &settings.critical_health_action {
+                tracing::info!("Здоровье ниже {}%: однократный сигнал критического состояния", settings.damage_rules_min_health_percentage);
+                actions_to_perform.push(TriggeredEvent {
+                    rule_name: CRITICAL_HEALTH_RULE_NAME.to_string(),
+                    device_action: critical_action.clone(),
+                });
+            }
+            previous_state.is_critical_health_signaled = true;
+        }
+    } else {
+        previous_state.is_critical_health_signaled = false;
+    }
+
+    let is_hard_impact_ignored = is_indicator_field_ignored(settings, "g_load") || is_indicator_field_ignored(settings, "altitude_10k");
+    if let Some(hard_impact_action) = settings.hard_impact_action.as_ref().filter(|_| !is_hard_impact_ignored)
+        && detect_hard_impact(current_indicators, settings, previous_state, now)
+    {
+        tracing::info!("Обнаружен жесткий удар (перегрузка: {:?}, высота: {:?})", current_indicators.g_load, current_indicators.altitude_10k);
+        actions_to_perform.push(TriggeredEvent {
+            rule_name: HARD_IMPACT_RULE_NAME.to_string(),
+            device_action: hard_impact_action.clone(),
+        });
+    }
+
+    if let Some(repair_complete_action) = settings.repair_complete_action.as_ref().filter(|_| !is_health_ignored)
+        && detect_repair_complete(current_indicators, settings, previous_state)
+    {
+        tracing::info!("Ремонт завершен: здоровье {:?}%", current_indicators.health_percentage);
+        actions_to_perform.push(TriggeredEvent {
+            rule_name: REPAIR_COMPLETE_RULE_NAME.to_string(),
+            device_action: repair_complete_action.clone(),
+        });
+    }
+
+    let is_reload_complete = update_reload_state(current_indicators, settings, previous_state, now);
+    if let Some(reload_complete_action) = settings.reload_complete_action.as_ref().filter(|_| !is_indicator_field_ignored(settings, "first_stage_ammo"))
+        && is_reload_complete
+    {
+        tracing::info!("Перезарядка завершена");
+        actions_to_perform.push(TriggeredEvent {
+            rule_name: RELOAD_COMPLETE_RULE_NAME.to_string(),
+            device_action: reload_complete_action.clone(),
+        });
+    }
+
+    let flight_envelope_profile = flight_envelope_profile(current_indicators, settings)
+        .filter(|_| !is_indicator_field_ignored(settings, "speed"));
+    if let Some(profile) = flight_envelope_profile {
+        let (is_stall_warning_entered, is_overspeed_entered) =
+            update_flight_envelope_state(current_indicators, profile, settings.flight_envelope.min_altitude, previous_state);
+        if let Some(stall_warning_action) = settings.stall_warning_action.as_ref().filter(|_| is_stall_warning_entered) {
+            tracing::info!("Близко к сваливанию: скорость {:?} (профиль \"{}\")", current_indicators.speed, profile.name);
+            actions_to_perform.push(TriggeredEvent {
+                rule_name: STALL_WARNING_RULE_NAME.to_string(),
+                device_action: stall_warning_action.clone(),
+            });
+        }
+        if let Some(overspeed_action) = settings.overspeed_action.as_ref().filter(|_| is_overspeed_entered) {
+            tracing::info!("Превышение скорости: {:?} (профиль \"{}\")", current_indicators.speed, profile.name);
+            actions_to_perform.push(TriggeredEvent {
+                rule_name: OVERSPEED_RULE_NAME.to_string(),
+                device_action: overspeed_action.clone(),
+            });
+        }
+    } else {
+        previous_state.is_stall_warning_active = false;
+        previous_state.is_overspeed_active = false;
+    }
+
+    // В режиме FirstMatch после первого сработавшего правила остальные не срабатывают,
+    // но условия все равно вычисляются: иначе их состояние устареет и они сработают позже без перехода
+    let is_first_match_mode = settings.rule_evaluation_mode == RuleEvaluationMode::FirstMatch;
+    let mut is_rule_matched = false;
+    for event_action_config in &effective_event_actions(settings) {
+        if !event_action_config.enabled || !rule_applies_to_vehicle(event_action_config, vehicle_class) {
+            continue;
+        }
+        let is_rule_suppressed = is_first_match_mode && is_rule_matched;
+
+        if let Some(condition_source) = &event_action_config.condition_expression {
+            cache_rule_condition(&mut previous_state.parsed_rule_conditions, &event_action_config.name, condition_source);
+            let is_condition_met = {
+                // Ошибки разбора сообщаются при загрузке (validate_rule_conditions), здесь правило просто молчит
+                let Some((_, Some(condition))) = previous_state.parsed_rule_conditions.get(&event_action_config.name) else {
+                    continue;
+                };
+                if condition.field_names().iter().any(|field_name| is_indicator_field_ignored(settings, field_name)) {
+                    continue;
+                }
+                condition.evaluate(
+                    &|field_name| indicator_field_value(current_indicators, previous_state, field_name),
+                    &|field_name| is_string_field_changed(current_indicators, previous_state, field_name),
+                )
+            };
+            let was_condition_met = previous_state.rule_condition_states
+                .insert(event_action_config.name.clone(), is_condition_met)
+                .unwrap_or(false);
+            if is_condition_met && !was_condition_met && !is_rule_suppressed && admit_rule_trigger(event_action_config, previous_state, now) {
+                tracing::info!("Сработало событие (по условию '{}'): {}", condition_source, event_action_config.name);
+                actions_to_perform.push(TriggeredEvent::from_rule(event_action_config));
+                is_rule_matched = true;
+            }
             continue;
         }
 
@@ -37,12 +1034,20 @@ pub fn process_war_thunder_data(
         // (например, сопоставление полей, порогов, типов сравнения)
 
         if event_action_config.name.contains("урона") || event_action_config.name.contains("damage") { // Очень грубая проверка по имени
-            if let Some(current_health) = current_indicators.health_percentage {
-                if let Some(last_health) = previous_state.last_health_percentage {
-                    if current_health < last_health && (last_health - current_health) > 0.01 { // Если здоровье уменьшилось
-                        tracing::info!("Сработало событие (по здоровью): {}", event_action_config.name);
-                        actions_to_perform.push(event_action_config.device_action.clone());
-                    }
+            // Ниже порога урон уже отмечен сигналом критического состояния
+            if is_below_damage_floor || is_health_ignored || is_rule_suppressed {
+                continue;
+            }
+            if let Some(current_health) = current_indicators.health_percentage
+                && let Some(last_health) = previous_state.last_health_percentage
+                && current_health < last_health && (last_health - current_health) > 0.01 // Если здоровье уменьшилось
+            {
+                if admit_rule_trigger(event_action_config, previous_state, now) {
+                    tracing::info!("Сработало событие (по здоровью): {}", event_action_config.name);
+                    actions_to_perform.push(TriggeredEvent::from_rule(event_action_config));
+                    is_rule_matched = true;
+                } else {
+                    tracing::debug!("Событие '{}' пропущено: достигнут лимит одновременных эффектов", event_action_config.name);
                 }
             }
         } else if event_action_config.name.contains("Выстрел") { // Еще один грубый пример
@@ -53,7 +1058,7 @@ pub fn process_war_thunder_data(
             // (это неверно, но для иллюстрации)
             // if current_indicators.weapon_active.unwrap_or(false) {
             //    tracing::info!("Сработало событие (по оружию): {}", event_action_config.name);
-            //    actions_to_perform.push(event_action_config.device_action.clone());
+            //    actions_to_perform.push(TriggeredEvent::from_rule(event_action_config));
             // }
         }
         // Добавь другие проверки для других типов событий из твоего конфига
@@ -61,8 +1066,542 @@ pub fn process_war_thunder_data(
 
     // Обновляем предыдущее состояние
     previous_state.last_health_percentage = current_indicators.health_percentage;
-    // previous_state.last_shells_count = current_indicators.shells_count;
+    previous_state.last_ammo_count = current_indicators.first_stage_ammo;
+    previous_state.last_vehicle_type = current_indicators.vehicle_type.clone();
+    for &field_name in STRING_INDICATOR_FIELD_NAMES {
+        if let Some(field_value) = string_indicator_field_value(current_indicators, field_name) {
+            previous_state.last_string_field_values.insert(field_name.to_string(), field_value.to_string());
+        }
+    }
     // ... и так далее для других отслеживаемых полей
 
     actions_to_perform
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::configuration_manager::{PatternPoint, RuleLayerSetting};
+
+    // Настройки без примеров и встроенных событий: срабатывает только то, что задал тест
+    fn empty_settings() -> ApplicationSettings {
+        ApplicationSettings {
+            event_actions: Vec::new(),
+            critical_health_action: None,
+            hard_impact_action: None,
+            repair_complete_action: None,
+            reload_complete_action: None,
+            stall_warning_action: None,
+            overspeed_action: None,
+            ignored_indicator_fields: Vec::new(),
+            indicator_smoothing: Vec::new(),
+            ..ApplicationSettings::default()
+        }
+    }
+
+    fn battle_indicators() -> WarThunderIndicators {
+        WarThunderIndicators {
+            vehicle_type: Some("tankModels/ussr_t_34_1941".to_string()),
+            valid: Some(true),
+            ..WarThunderIndicators::default()
+        }
+    }
+
+    fn vibrate_action(intensity: f64, duration_milliseconds: u64) -> DeviceAction {
+        DeviceAction {
+            action_type: DeviceActionType::Vibrate,
+            intensity,
+            duration_milliseconds,
+            target: ActionTarget::SelectedDevice,
+            actuator_intensities: Vec::new(),
+            pattern_points: Vec::new(),
+        }
+    }
+
+    fn condition_rule(name: &str, condition_expression: &str) -> EventActionSetting {
+        EventActionSetting {
+            name: name.to_string(),
+            enabled: true,
+            device_action: vibrate_action(0.5, 200),
+            max_concurrent_effects: 0,
+            concurrency_limit_policy: ConcurrencyLimitPolicy::DropNew,
+            condition_expression: Some(condition_expression.to_string()),
+            vehicle_classes: Vec::new(),
+            priority: 0,
+        }
+    }
+
+    fn triggered_rule_names(triggered_events: &[TriggeredEvent]) -> Vec<&str> {
+        triggered_events.iter().map(|triggered_event| triggered_event.rule_name.as_str()).collect()
+    }
+
+    #[test]
+    fn hard_impact_cooldown_follows_clock() {
+        let mut settings = empty_settings();
+        settings.hard_impact_action = Some(vibrate_action(1.0, 300));
+        let clock = ManualClock::new();
+        let mut snapshot = GameStateSnapshot::default();
+        let impact_indicators = WarThunderIndicators { g_load: Some(15.0), ..battle_indicators() };
+
+        let first_events = process_war_thunder_data(&impact_indicators, &settings, &mut snapshot, &clock);
+        assert_eq!(triggered_rule_names(&first_events), vec![HARD_IMPACT_RULE_NAME]);
+        clock.advance(Duration::from_millis(2999));
+        assert!(process_war_thunder_data(&impact_indicators, &settings, &mut snapshot, &clock).is_empty());
+        clock.advance(Duration::from_millis(1));
+        let repeated_events = process_war_thunder_data(&impact_indicators, &settings, &mut snapshot, &clock);
+        assert_eq!(triggered_rule_names(&repeated_events), vec![HARD_IMPACT_RULE_NAME]);
+    }
+
+    #[test]
+    fn combat_intensity_decays_by_elapsed_clock_time() {
+        let settings = empty_settings();
+        let clock = ManualClock::new();
+        let mut snapshot = GameStateSnapshot::default();
+        process_war_thunder_data(&WarThunderIndicators { health_percentage: Some(100.0), ..battle_indicators() }, &settings, &mut snapshot, &clock);
+        let damaged_indicators = WarThunderIndicators { health_percentage: Some(50.0), ..battle_indicators() };
+        process_war_thunder_data(&damaged_indicators, &settings, &mut snapshot, &clock);
+        assert_eq!(snapshot.combat_intensity, 1.0); // 50% урона * 0.02, с ограничением сверху
+
+        clock.advance(Duration::from_secs(1));
+        process_war_thunder_data(&damaged_indicators, &settings, &mut snapshot, &clock);
+        assert!((snapshot.combat_intensity - 0.7).abs() < 1e-9, "{}", snapshot.combat_intensity);
+        clock.advance(Duration::from_secs(2));
+        process_war_thunder_data(&damaged_indicators, &settings, &mut snapshot, &clock);
+        assert!((snapshot.combat_intensity - 0.7 * 0.49).abs() < 1e-9, "{}", snapshot.combat_intensity);
+    }
+
+    const INVALID_INDICATORS_FIXTURE: &str = r#"{"valid": false, "type": "tankModels/ussr_t_34_1941", "speed": 0.0, "H, %": 0.0}"#;
+
+    #[test]
+    fn valid_false_fixture_is_not_battle_data() {
+        let indicators = crate::telemetry_normalization::normalize_indicators(INVALID_INDICATORS_FIXTURE).unwrap();
+        assert_eq!(indicators.valid, Some(false));
+        assert!(!is_battle_data(&indicators));
+        let valid_indicators = crate::telemetry_normalization::normalize_indicators(&INVALID_INDICATORS_FIXTURE.replace("false", "true")).unwrap();
+        assert!(is_battle_data(&valid_indicators));
+        // Без флага valid данные считаются боевыми, без типа техники - нет
+        assert!(is_battle_data(&WarThunderIndicators { valid: None, ..battle_indicators() }));
+        assert!(!is_battle_data(&WarThunderIndicators { vehicle_type: None, ..battle_indicators() }));
+    }
+
+    #[test]
+    fn valid_false_indicators_fire_no_rules_and_end_combat() {
+        let mut settings = empty_settings();
+        settings.event_actions = vec![
+            condition_rule("Стоим", "speed < 1"),
+            condition_rule(COMBAT_ENDED_EVENT_NAME, "speed < 1"),
+        ];
+        settings.event_actions[1].condition_expression = None;
+        let clock = ManualClock::new();
+        let mut snapshot = GameStateSnapshot::default();
+        let invalid_indicators = crate::telemetry_normalization::normalize_indicators(INVALID_INDICATORS_FIXTURE).unwrap();
+
+        // Нулевые данные вне боя не запускают правила, даже если условие на них выполняется
+        assert!(process_war_thunder_data(&invalid_indicators, &settings, &mut snapshot, &clock).is_empty());
+        assert!(!snapshot.is_in_combat);
+
+        let moving_indicators = WarThunderIndicators { speed: Some(20.0), ..battle_indicators() };
+        assert!(process_war_thunder_data(&moving_indicators, &settings, &mut snapshot, &clock).is_empty());
+        assert!(snapshot.is_in_combat);
+        clock.advance(Duration::from_millis(250));
+        let ended_events = process_war_thunder_data(&invalid_indicators, &settings, &mut snapshot, &clock);
+        assert_eq!(triggered_rule_names(&ended_events), vec![COMBAT_ENDED_EVENT_NAME]);
+        assert!(!snapshot.is_in_combat);
+    }
+
+    fn rapid_trigger_rule(concurrency_limit_policy: ConcurrencyLimitPolicy) -> EventActionSetting {
+        EventActionSetting {
+            device_action: vibrate_action(0.5, 1000),
+            max_concurrent_effects: 1,
+            concurrency_limit_policy,
+            ..condition_rule("Стоим", "speed < 1")
+        }
+    }
+
+    // Условие становится истинным каждые 200 мс: остановка, 100 мс движения, снова остановка
+    fn count_rapid_triggers(settings: &ApplicationSettings, snapshot: &mut GameStateSnapshot, clock: &ManualClock, trigger_count: usize) -> usize {
+        let mut triggered_count = 0;
+        for _ in 0..trigger_count {
+            let stopped_indicators = WarThunderIndicators { speed: Some(0.0), ..battle_indicators() };
+            triggered_count += process_war_thunder_data(&stopped_indicators, settings, snapshot, clock).len();
+            clock.advance(Duration::from_millis(100));
+            let moving_indicators = WarThunderIndicators { speed: Some(20.0), ..battle_indicators() };
+            triggered_count += process_war_thunder_data(&moving_indicators, settings, snapshot, clock).len();
+            clock.advance(Duration::from_millis(100));
+        }
+        triggered_count
+    }
+
+    #[test]
+    fn drop_new_ignores_rapid_triggers_until_effect_ends() {
+        let mut settings = empty_settings();
+        settings.event_actions = vec![rapid_trigger_rule(ConcurrencyLimitPolicy::DropNew)];
+        let clock = ManualClock::new();
+        let mut snapshot = GameStateSnapshot::default();
+
+        // Срабатывания на 0, 200, ... 800 мс: эффект длиной 1000 мс еще идет, пропускаются все кроме первого
+        assert_eq!(count_rapid_triggers(&settings, &mut snapshot, &clock, 5), 1);
+        // На 1000 мс первый эффект закончился
+        assert_eq!(count_rapid_triggers(&settings, &mut snapshot, &clock, 1), 1);
+    }
+
+    #[test]
+    fn restart_admits_every_rapid_trigger_and_keeps_one_active_effect() {
+        let mut settings = empty_settings();
+        settings.event_actions = vec![rapid_trigger_rule(ConcurrencyLimitPolicy::Restart)];
+        let clock = ManualClock::new();
+        let mut snapshot = GameStateSnapshot::default();
+        let started_at = clock.now();
+
+        assert_eq!(count_rapid_triggers(&settings, &mut snapshot, &clock, 5), 5);
+        // Активен только последний перезапуск (800 мс) - он и закончится через 1000 мс
+        assert_eq!(snapshot.active_rule_effects_until["Стоим"], vec![started_at + Duration::from_millis(1800)]);
+    }
+
+    #[test]
+    fn concurrency_limit_counts_overlapping_effects() {
+        let mut settings = empty_settings();
+        settings.event_actions = vec![EventActionSetting { max_concurrent_effects: 2, ..rapid_trigger_rule(ConcurrencyLimitPolicy::DropNew) }];
+        let clock = ManualClock::new();
+        let mut snapshot = GameStateSnapshot::default();
+
+        assert_eq!(count_rapid_triggers(&settings, &mut snapshot, &clock, 5), 2);
+        assert_eq!(snapshot.active_rule_effects_until["Стоим"].len(), 2);
+    }
+
+    #[test]
+    fn hud_messages_trigger_only_own_outcomes() {
+        let mut settings = empty_settings();
+        settings.player_name = "Pilot".to_string();
+        settings.own_kill_action = Some(vibrate_action(0.8, 400));
+        let mut snapshot = GameStateSnapshot::default();
+        let hud_message = |id: u64, msg: &str| HudDamageMessage { id, msg: msg.to_string(), time: 0 };
+        let hud_messages = vec![
+            hud_message(1, "=CLAN= Pilot (Bf 109 F-4) shot down Enemy (Spitfire Mk Vc)"),
+            hud_message(2, "Wingman (Bf 109 F-4) shot down Other (Spitfire Mk Vc)"),
+            hud_message(3, "Enemy2 (Yak-1) shot down Pilot (Bf 109 F-4)"),
+        ];
+
+        let triggered_events = process_hud_damage_messages(&hud_messages, &settings, &mut snapshot);
+        // Сигнал гибели выключен, но она все равно засчитана
+        assert_eq!(triggered_rule_names(&triggered_events), vec![OWN_KILL_RULE_NAME]);
+        assert_eq!((snapshot.own_kill_count, snapshot.own_death_count), (1, 1));
+
+        settings.player_name.clear();
+        assert!(process_hud_damage_messages(&hud_messages, &settings, &mut snapshot).is_empty());
+        assert_eq!(snapshot.own_kill_count, 1);
+    }
+
+    fn prioritized_rule(name: &str, priority: i32) -> EventActionSetting {
+        EventActionSetting { priority, ..condition_rule(name, "speed > 10") }
+    }
+
+    fn rule_names(event_actions: &[EventActionSetting]) -> Vec<&str> {
+        event_actions.iter().map(|event_action| event_action.name.as_str()).collect()
+    }
+
+    #[test]
+    fn effective_rules_are_sorted_by_descending_priority_with_stable_ties() {
+        let mut settings = empty_settings();
+        settings.event_actions = vec![
+            prioritized_rule("А", 0),
+            prioritized_rule("Б", 5),
+            prioritized_rule("В", 0),
+            prioritized_rule("Г", 5),
+            prioritized_rule("Д", -1),
+        ];
+        assert_eq!(rule_names(&effective_event_actions(&settings)), vec!["Б", "Г", "А", "В", "Д"]);
+    }
+
+    #[test]
+    fn layer_rules_keep_overridden_position_among_equal_priorities() {
+        let mut settings = empty_settings();
+        settings.event_actions = vec![prioritized_rule("А", 0), prioritized_rule("Б", 0), prioritized_rule("В", 0)];
+        settings.rule_layers = vec![RuleLayerSetting {
+            name: "Танки".to_string(),
+            event_actions: vec![prioritized_rule("Новое", 0), prioritized_rule("Б", 0), prioritized_rule("Срочное", 1)],
+        }];
+        settings.active_rule_layers = vec!["Танки".to_string()];
+        // "Б" заменено на месте, новые правила - в конце, "Срочное" поднято приоритетом
+        assert_eq!(rule_names(&effective_event_actions(&settings)), vec!["Срочное", "А", "Б", "В", "Новое"]);
+    }
+
+    #[test]
+    fn first_match_fires_only_highest_priority_rule() {
+        let mut settings = empty_settings();
+        settings.rule_evaluation_mode = RuleEvaluationMode::FirstMatch;
+        settings.event_actions = vec![
+            EventActionSetting { priority: 0, ..condition_rule("Быстро", "speed > 10") },
+            EventActionSetting { priority: 3, ..condition_rule("Очень быстро", "speed > 50") },
+            EventActionSetting { priority: 3, ..condition_rule("Тоже очень быстро", "speed > 50") },
+        ];
+        let clock = ManualClock::new();
+        let mut snapshot = GameStateSnapshot::default();
+        let fast_indicators = WarThunderIndicators { speed: Some(60.0), ..battle_indicators() };
+
+        let triggered_events = process_war_thunder_data(&fast_indicators, &settings, &mut snapshot, &clock);
+        assert_eq!(triggered_rule_names(&triggered_events), vec!["Очень быстро"]);
+        // Подавленные правила все равно запомнили состояние условия и не сработают без нового перехода
+        assert!(process_war_thunder_data(&fast_indicators, &settings, &mut snapshot, &clock).is_empty());
+
+        settings.rule_evaluation_mode = RuleEvaluationMode::AllMatching;
+        let mut snapshot = GameStateSnapshot::default();
+        let triggered_events = process_war_thunder_data(&fast_indicators, &settings, &mut snapshot, &clock);
+        assert_eq!(triggered_rule_names(&triggered_events), vec!["Очень быстро", "Тоже очень быстро", "Быстро"]);
+    }
+
+    #[test]
+    fn vehicle_type_changed_rule_fires_exactly_once_per_change() {
+        let mut settings = empty_settings();
+        settings.event_actions = vec![condition_rule("Новая техника", "vehicle_type CHANGED")];
+        let clock = ManualClock::new();
+        let mut snapshot = GameStateSnapshot::default();
+        let first_vehicle = battle_indicators();
+        let second_vehicle = WarThunderIndicators { vehicle_type: Some("tankModels/germ_pzkpfw_vi_ausf_h1_tiger".to_string()), ..battle_indicators() };
+        let respawn_screen = WarThunderIndicators { valid: Some(false), ..second_vehicle.clone() };
+
+        // Первое появление техники - не смена; между техниками WT отдает невалидные данные
+        let frames = [&first_vehicle, &first_vehicle, &respawn_screen, &second_vehicle, &second_vehicle, &second_vehicle];
+        let mut triggered_names = Vec::new();
+        for frame in frames {
+            clock.advance(Duration::from_millis(250));
+            triggered_names.extend(process_war_thunder_data(frame, &settings, &mut snapshot, &clock).into_iter().map(|triggered_event| triggered_event.rule_name));
+        }
+        assert_eq!(triggered_names, vec!["Новая техника".to_string()]);
+    }
+
+    fn vibration_on(target: ActionTarget, intensity: f64, duration_milliseconds: u64) -> DeviceAction {
+        DeviceAction { target, ..vibrate_action(intensity, duration_milliseconds) }
+    }
+
+    fn merge_by_target(device_actions: Vec<DeviceAction>, policy: SimultaneousVibrationPolicy) -> Vec<DeviceAction> {
+        merge_simultaneous_vibrations(device_actions, policy, |target| target.clone())
+    }
+
+    fn intensities(device_actions: &[DeviceAction]) -> Vec<f64> {
+        device_actions.iter().map(|device_action| device_action.intensity).collect()
+    }
+
+    #[test]
+    fn max_policy_keeps_strongest_vibration_and_longest_duration() {
+        let mut strong_action = vibration_on(ActionTarget::SelectedDevice, 0.9, 200);
+        strong_action.actuator_intensities = vec![ActuatorIntensity { actuator_index: 1, intensity: 0.3 }];
+        let merged_actions = merge_by_target(vec![
+            vibration_on(ActionTarget::SelectedDevice, 0.4, 800),
+            strong_action.clone(),
+            vibration_on(ActionTarget::SelectedDevice, 0.6, 100),
+        ], SimultaneousVibrationPolicy::Max);
+        assert_eq!(merged_actions.len(), 1);
+        assert_eq!(merged_actions[0].intensity, 0.9);
+        assert_eq!(merged_actions[0].actuator_intensities, strong_action.actuator_intensities);
+        assert_eq!(merged_actions[0].duration_milliseconds, 800);
+    }
+
+    #[test]
+    fn sum_clamped_policy_sums_intensities_per_actuator() {
+        let mut left_action = vibration_on(ActionTarget::SelectedDevice, 0.2, 300);
+        left_action.actuator_intensities = vec![ActuatorIntensity { actuator_index: 0, intensity: 0.7 }];
+        let mut right_action = vibration_on(ActionTarget::SelectedDevice, 0.5, 500);
+        right_action.actuator_intensities = vec![ActuatorIntensity { actuator_index: 1, intensity: 0.6 }];
+        let merged_actions = merge_by_target(vec![left_action, right_action, vibration_on(ActionTarget::SelectedDevice, 0.1, 100)], SimultaneousVibrationPolicy::SumClamped);
+
+        assert_eq!(merged_actions.len(), 1);
+        let merged_action = &merged_actions[0];
+        assert!((merged_action.intensity - 0.8).abs() < 1e-9, "{}", merged_action.intensity);
+        // Привод 0: 0.7 + 0.5 (общая второго) + 0.1 -> 1.0; привод 1: 0.2 + 0.6 + 0.1
+        assert_eq!(merged_action.actuator_intensities.iter().map(|actuator_intensity| actuator_intensity.actuator_index).collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!(merged_action.actuator_intensity(0), 1.0);
+        assert!((merged_action.actuator_intensity(1) - 0.9).abs() < 1e-9, "{}", merged_action.actuator_intensity(1));
+        assert_eq!(merged_action.duration_milliseconds, 500);
+    }
+
+    #[test]
+    fn last_policy_keeps_last_vibration_per_target() {
+        let merged_actions = merge_by_target(vec![
+            vibration_on(ActionTarget::SelectedDevice, 0.9, 100),
+            vibration_on(ActionTarget::Group("Пояс".to_string()), 0.5, 100),
+            vibration_on(ActionTarget::SelectedDevice, 0.2, 400),
+        ], SimultaneousVibrationPolicy::Last);
+        assert_eq!(intensities(&merged_actions), vec![0.2, 0.5]);
+        assert_eq!(merged_actions[0].duration_milliseconds, 400);
+    }
+
+    #[test]
+    fn patterns_and_stops_pass_through_unmerged() {
+        let mut pattern_action = vibration_on(ActionTarget::SelectedDevice, 0.7, 1000);
+        pattern_action.pattern_points = vec![PatternPoint { time_fraction: 0.0, intensity: 0.0 }, PatternPoint { time_fraction: 1.0, intensity: 1.0 }];
+        let stop_action = DeviceAction { action_type: DeviceActionType::Stop, ..vibrate_action(0.0, 0) };
+        let merged_actions = merge_by_target(vec![
+            vibration_on(ActionTarget::SelectedDevice, 0.3, 200),
+            pattern_action.clone(),
+            stop_action.clone(),
+            vibration_on(ActionTarget::SelectedDevice, 0.5, 200),
+        ], SimultaneousVibrationPolicy::SumClamped);
+        assert_eq!(merged_actions.len(), 3);
+        assert!((merged_actions[0].intensity - 0.8).abs() < 1e-9, "{}", merged_actions[0].intensity);
+        assert_eq!(merged_actions[1], pattern_action);
+        assert_eq!(merged_actions[2], stop_action);
+    }
+
+    // Опрос с заданной скоростью через offset_milliseconds от начала; возвращает (speed_rate, speed_decrease_rate)
+    fn sample_speed(snapshot: &mut GameStateSnapshot, started_at: Instant, offset_milliseconds: u64, speed: f32) -> (Option<f64>, Option<f64>) {
+        let indicators = WarThunderIndicators { speed: Some(speed), ..battle_indicators() };
+        update_field_rates(&indicators, snapshot, started_at + Duration::from_millis(offset_milliseconds));
+        (
+            indicator_field_value(&indicators, snapshot, "speed_rate"),
+            indicator_field_value(&indicators, snapshot, "speed_decrease_rate"),
+        )
+    }
+
+    fn assert_rate(actual: Option<f64>, expected: f64) {
+        let actual = actual.expect("скорость должна быть известна");
+        assert!((actual - expected).abs() < 1e-6, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn rates_use_actual_interval_between_irregular_polls() {
+        let started_at = Instant::now();
+        let mut snapshot = GameStateSnapshot::default();
+        assert_eq!(sample_speed(&mut snapshot, started_at, 0, 100.0), (None, None));
+
+        let (speed_rate, speed_decrease_rate) = sample_speed(&mut snapshot, started_at, 100, 110.0);
+        assert_rate(speed_rate, 100.0);
+        assert_rate(speed_decrease_rate, 0.0);
+
+        let (speed_rate, speed_decrease_rate) = sample_speed(&mut snapshot, started_at, 350, 105.0);
+        assert_rate(speed_rate, -20.0);
+        assert_rate(speed_decrease_rate, 20.0);
+
+        let (speed_rate, speed_decrease_rate) = sample_speed(&mut snapshot, started_at, 1350, 125.0);
+        assert_rate(speed_rate, 20.0);
+        assert_rate(speed_decrease_rate, 0.0);
+    }
+
+    #[test]
+    fn polls_closer_than_min_interval_accumulate_into_next_sample() {
+        let started_at = Instant::now();
+        let mut snapshot = GameStateSnapshot::default();
+        sample_speed(&mut snapshot, started_at, 0, 100.0);
+        // 10 мс - слишком рано: скорость еще неизвестна, опорная точка остается прежней
+        assert_eq!(sample_speed(&mut snapshot, started_at, 10, 101.0), (None, None));
+
+        let (speed_rate, _) = sample_speed(&mut snapshot, started_at, 25, 103.0);
+        assert_rate(speed_rate, 3.0 / 0.025);
+
+        // Частый опрос после известной скорости оставляет ее без изменений
+        let (speed_rate, _) = sample_speed(&mut snapshot, started_at, 35, 200.0);
+        assert_rate(speed_rate, 3.0 / 0.025);
+        let (speed_rate, _) = sample_speed(&mut snapshot, started_at, 45, 203.0);
+        assert_rate(speed_rate, 100.0 / 0.02);
+    }
+
+    #[test]
+    fn pause_longer_than_max_interval_resets_rate_until_next_poll() {
+        let started_at = Instant::now();
+        let mut snapshot = GameStateSnapshot::default();
+        sample_speed(&mut snapshot, started_at, 0, 100.0);
+        // Ровно MAX_RATE_SAMPLE_INTERVAL еще считается
+        let (speed_rate, _) = sample_speed(&mut snapshot, started_at, 2000, 110.0);
+        assert_rate(speed_rate, 5.0);
+
+        assert_eq!(sample_speed(&mut snapshot, started_at, 4500, 150.0), (None, None));
+
+        let (speed_rate, speed_decrease_rate) = sample_speed(&mut snapshot, started_at, 4600, 149.0);
+        assert_rate(speed_rate, -10.0);
+        assert_rate(speed_decrease_rate, 10.0);
+    }
+
+    #[test]
+    fn missing_field_clears_its_rate() {
+        let started_at = Instant::now();
+        let mut snapshot = GameStateSnapshot::default();
+        sample_speed(&mut snapshot, started_at, 0, 100.0);
+        sample_speed(&mut snapshot, started_at, 100, 110.0);
+
+        let indicators = WarThunderIndicators { speed: None, ..battle_indicators() };
+        update_field_rates(&indicators, &mut snapshot, started_at + Duration::from_millis(200));
+        assert_eq!(indicator_field_value(&indicators, &snapshot, "speed_rate"), None);
+
+        // После возвращения поля скорость считается заново, без скачка от старого значения
+        assert_eq!(sample_speed(&mut snapshot, started_at, 300, 500.0), (None, None));
+        let (speed_rate, _) = sample_speed(&mut snapshot, started_at, 400, 510.0);
+        assert_rate(speed_rate, 100.0);
+    }
+
+    #[test]
+    fn infinite_reload_seconds_falls_back_to_estimated_duration() {
+        let mut settings = empty_settings();
+        settings.reload_feedback = toml::from_str("reload_seconds = inf").unwrap();
+        assert!(settings.reload_feedback.reload_seconds.is_infinite());
+
+        let started_at = Instant::now();
+        let mut snapshot = GameStateSnapshot::default();
+        let poll_ammo = |snapshot: &mut GameStateSnapshot, offset_milliseconds: u64, ammo: f32| {
+            let indicators = WarThunderIndicators { first_stage_ammo: Some(ammo), ..battle_indicators() };
+            let is_reload_complete = update_reload_state(&indicators, &settings, snapshot, started_at + Duration::from_millis(offset_milliseconds));
+            snapshot.last_ammo_count = indicators.first_stage_ammo;
+            snapshot.last_vehicle_type = indicators.vehicle_type.clone();
+            is_reload_complete
+        };
+        poll_ammo(&mut snapshot, 0, 10.0);
+        poll_ammo(&mut snapshot, 100, 9.0);
+        // Оценки еще нет, а ручная длительность непредставима - прогресса нет
+        assert_eq!(snapshot.reload_progress, None);
+
+        poll_ammo(&mut snapshot, 3100, 8.0);
+        assert_eq!(snapshot.estimated_reload_duration, Some(Duration::from_secs(3)));
+        assert!(!poll_ammo(&mut snapshot, 4600, 8.0));
+        let reload_progress = snapshot.reload_progress.expect("прогресс по оценке");
+        assert!((reload_progress - 0.25).abs() < 1e-9, "{reload_progress}");
+        assert!(poll_ammo(&mut snapshot, 6100, 8.0));
+    }
+
+    #[test]
+    fn rule_condition_is_parsed_once_and_reparsed_after_edit() {
+        let mut settings = empty_settings();
+        settings.event_actions = vec![condition_rule("Быстро", "speed > 100")];
+        let clock = ManualClock::new();
+        let mut snapshot = GameStateSnapshot::default();
+        let fast_indicators = WarThunderIndicators { speed: Some(150.0), ..battle_indicators() };
+
+        let events = process_war_thunder_data(&fast_indicators, &settings, &mut snapshot, &clock);
+        assert_eq!(triggered_rule_names(&events), vec!["Быстро"]);
+        let cached_condition = snapshot.parsed_rule_conditions.get("Быстро").cloned();
+        assert_eq!(cached_condition, Some(("speed > 100".to_string(), parse_condition_expression("speed > 100").ok())));
+
+        // Разбор из кэша переживает сброс боевого состояния
+        reset_combat_state(&mut snapshot);
+        assert_eq!(snapshot.parsed_rule_conditions.get("Быстро").cloned(), cached_condition);
+
+        // Измененный текст условия разбирается заново и сразу действует
+        settings.event_actions[0].condition_expression = Some("speed > 200".to_string());
+        clock.advance(Duration::from_millis(250));
+        assert!(process_war_thunder_data(&fast_indicators, &settings, &mut snapshot, &clock).is_empty());
+        assert_eq!(snapshot.parsed_rule_conditions["Быстро"].0, "speed > 200");
+        clock.advance(Duration::from_millis(250));
+        let faster_indicators = WarThunderIndicators { speed: Some(250.0), ..battle_indicators() };
+        let events = process_war_thunder_data(&faster_indicators, &settings, &mut snapshot, &clock);
+        assert_eq!(triggered_rule_names(&events), vec!["Быстро"]);
+    }
+
+    #[test]
+    fn malformed_rule_condition_is_cached_as_error_and_stays_silent() {
+        let mut settings = empty_settings();
+        settings.event_actions = vec![condition_rule("Сломано", "speed >")];
+        let clock = ManualClock::new();
+        let mut snapshot = GameStateSnapshot::default();
+        let indicators = WarThunderIndicators { speed: Some(150.0), ..battle_indicators() };
+
+        assert!(process_war_thunder_data(&indicators, &settings, &mut snapshot, &clock).is_empty());
+        assert_eq!(snapshot.parsed_rule_conditions.get("Сломано").cloned(), Some(("speed >".to_string(), None)));
+        assert!(validate_event_action(&settings.event_actions[0]).iter().any(|rule_error| rule_error.contains("ожидалось число")));
+    }
+
+    #[test]
+    fn unknown_condition_field_is_reported_by_validation() {
+        let rule_errors = validate_event_action(&condition_rule("Опечатка", "sped > 100"));
+        assert!(rule_errors.iter().any(|rule_error| rule_error.contains("неизвестное поле 'sped'")), "{:?}", rule_errors);
+    }
+}
+
@@ -0,0 +1,12 @@
+// src/lib.rs
+
+// Библиотечная часть приложения: загрузка, сохранение и проверка настроек (SettingsStore,
+// validate_settings), обработка правил и разбор данных War Thunder. Не зависит от GUI и
+// Buttplug - альтернативные интерфейсы (CLI, веб) используют ту же логику, что и окно.
+
+pub mod clock;
+pub mod condition_expression;
+pub mod configuration_manager;
+pub mod game_event_processor;
+pub mod telemetry_normalization;
+pub mod war_thunder_api;
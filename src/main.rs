@@ -1,136 +1,353 @@
 // src/main.rs
 
 mod application;
-mod configuration_manager;
-mod game_event_processor;
+mod configuration_watcher;
 mod message_passing;
 mod war_thunder_connector;
 mod buttplug_connector;
+mod connection_metrics;
+mod device_safety_stop;
+#[cfg(feature = "control_api")]
+mod control_api;
+
+// Настройки, правила и данные War Thunder - из библиотеки (src/lib.rs);
+// импорт в корне сохраняет пути crate::configuration_manager и т.п. в модулях приложения
+use warthunder_haptics_gui::{clock, configuration_manager, game_event_processor, telemetry_normalization, war_thunder_api};
 
 use application::WarThunderHapticsApplication;
-use message_passing::{CommandToAsyncTasks, UpdateFromAsyncTasks};
-use tokio::sync::mpsc;
+use device_safety_stop::{DeviceSafetyStop, DeviceSafetyStopGuard};
+use configuration_manager::AsyncRuntimeKind;
+use message_passing::{BackgroundTaskKind, CommandToAsyncTasks, LogCategory, TriggeredEventNotification, UpdateFromAsyncTasks, TRIGGERED_EVENT_BUS_CAPACITY};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
 
 // Убираем ненужные use, если они не используются в main
 // use configuration_manager::ApplicationSettings; 
 // use buttplug_connector::run_buttplug_service_loop; // Вызываются ниже
 // use war_thunder_connector::run_war_thunder_polling_loop; // Вызываются ниже
 
+// Рантайм фоновых задач (см. AsyncRuntimeKind). Задачи запускаются через handle(),
+// shutdown() вызывается после закрытия окна и отменяет оставшиеся задачи.
+enum AsyncTaskRuntime {
+    MultiThread(tokio::runtime::Runtime),
+    // Однопоточный рантайм выполняет задачи, только пока его поток находится внутри block_on,
+    // поэтому поток ждет в block_on сигнала остановки
+    DedicatedThread {
+        runtime_handle: tokio::runtime::Handle,
+        shutdown_sender: tokio::sync::oneshot::Sender<()>,
+        runtime_thread: std::thread::JoinHandle<()>,
+    },
+}
+
+impl AsyncTaskRuntime {
+    fn start(runtime_kind: AsyncRuntimeKind) -> std::io::Result<Self> {
+        match runtime_kind {
+            AsyncRuntimeKind::MultiThread => Ok(Self::MultiThread(tokio::runtime::Runtime::new()?)),
+            AsyncRuntimeKind::DedicatedThread => {
+                let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+                let runtime_handle = runtime.handle().clone();
+                let (shutdown_sender, shutdown_receiver) = tokio::sync::oneshot::channel::<()>();
+                let runtime_thread = std::thread::Builder::new()
+                    .name("async-tasks".to_string())
+                    .spawn(move || {
+                        // Ошибка приема значит, что отправитель уже уничтожен - это тоже сигнал остановки
+                        let _ = runtime.block_on(shutdown_receiver);
+                        // Уничтожение рантайма здесь отменяет все еще работающие задачи
+                    })?;
+                Ok(Self::DedicatedThread { runtime_handle, shutdown_sender, runtime_thread })
+            }
+        }
+    }
+
+    fn handle(&self) -> &tokio::runtime::Handle {
+        match self {
+            Self::MultiThread(runtime) => runtime.handle(),
+            Self::DedicatedThread { runtime_handle, .. } => runtime_handle,
+        }
+    }
+
+    fn shutdown(self) {
+        match self {
+            Self::MultiThread(runtime) => drop(runtime),
+            Self::DedicatedThread { shutdown_sender, runtime_thread, .. } => {
+                let _ = shutdown_sender.send(());
+                if runtime_thread.join().is_err() {
+                    tracing::error!("Поток фоновых задач завершился с паникой.");
+                }
+            }
+        }
+    }
+}
+
+// Сколько раз перезапускаем упавшую фоновую задачу, прежде чем оставить ее остановленной
+const MAX_BACKGROUND_TASK_RESTARTS: u32 = 3;
+// Как часто диспетчер команд проверяет, живы ли фоновые задачи
+const BACKGROUND_TASK_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+// Фоновая задача под наблюдением диспетчера команд. Задачи работают, пока открыт канал команд,
+// поэтому любое их завершение при живом диспетчере - сбой (паника или выход из цикла).
+// spawn_task запускает задачу заново со свежим каналом команд.
+struct SupervisedTask {
+    kind: BackgroundTaskKind,
+    command_sender: mpsc::Sender<CommandToAsyncTasks>,
+    join_handle: JoinHandle<()>,
+    restart_count: u32,
+    is_abandoned: bool, // Лимит перезапусков исчерпан
+    spawn_task: Box<dyn Fn(mpsc::Receiver<CommandToAsyncTasks>) -> JoinHandle<()> + Send + Sync>,
+    // Публикует новый канал после перезапуска (для аварийной остановки устройств)
+    command_sender_publisher: Option<watch::Sender<mpsc::Sender<CommandToAsyncTasks>>>,
+}
+
+impl SupervisedTask {
+    fn start(
+        kind: BackgroundTaskKind,
+        spawn_task: Box<dyn Fn(mpsc::Receiver<CommandToAsyncTasks>) -> JoinHandle<()> + Send + Sync>,
+    ) -> Self {
+        let (command_sender, command_receiver) = mpsc::channel(100);
+        let join_handle = spawn_task(command_receiver);
+        Self {
+            kind,
+            command_sender,
+            join_handle,
+            restart_count: 0,
+            is_abandoned: false,
+            spawn_task,
+            command_sender_publisher: None,
+        }
+    }
+
+    fn subscribe_command_sender(&mut self) -> watch::Receiver<mpsc::Sender<CommandToAsyncTasks>> {
+        let (command_sender_publisher, command_sender_receiver) = watch::channel(self.command_sender.clone());
+        self.command_sender_publisher = Some(command_sender_publisher);
+        command_sender_receiver
+    }
+
+    async fn send(&self, command: CommandToAsyncTasks) {
+        // Ошибка отправки значит, что задача уже завершилась: ее перезапустит restart_if_finished
+        let _ = self.command_sender.send(command).await;
+    }
+
+    async fn restart_if_finished(&mut self, gui_update_sender: &mpsc::Sender<UpdateFromAsyncTasks>) {
+        if self.is_abandoned || !self.join_handle.is_finished() {
+            return;
+        }
+        let failure_description = match (&mut self.join_handle).await {
+            Err(join_error) if join_error.is_panic() => "упала с паникой",
+            Err(_) => "была отменена",
+            Ok(()) => "неожиданно завершилась",
+        };
+        if self.restart_count >= MAX_BACKGROUND_TASK_RESTARTS {
+            self.is_abandoned = true;
+            tracing::error!("Фоновая задача {} {}; лимит перезапусков ({}) исчерпан.", self.kind.label(), failure_description, MAX_BACKGROUND_TASK_RESTARTS);
+            let _ = gui_update_sender.send(UpdateFromAsyncTasks::LogMessage {
+                category: LogCategory::General,
+                device_address: None,
+                message: format!(
+                    "Фоновая задача {} {} и больше не перезапускается ({} перезапуска уже было). Перезапустите приложение.",
+                    self.kind.label(), failure_description, MAX_BACKGROUND_TASK_RESTARTS
+                ),
+            }).await;
+            return;
+        }
+        self.restart_count += 1;
+        tracing::error!("Фоновая задача {} {}, перезапуск {} из {}.", self.kind.label(), failure_description, self.restart_count, MAX_BACKGROUND_TASK_RESTARTS);
+        let (command_sender, command_receiver) = mpsc::channel(100);
+        self.join_handle = (self.spawn_task)(command_receiver);
+        self.command_sender = command_sender;
+        if let Some(command_sender_publisher) = &self.command_sender_publisher {
+            command_sender_publisher.send_replace(self.command_sender.clone());
+        }
+        let _ = gui_update_sender.send(UpdateFromAsyncTasks::LogMessage {
+            category: LogCategory::General,
+            device_address: None,
+            message: format!("Фоновая задача {} {} и перезапущена ({} из {}).", self.kind.label(), failure_description, self.restart_count, MAX_BACKGROUND_TASK_RESTARTS),
+        }).await;
+        let _ = gui_update_sender.send(UpdateFromAsyncTasks::BackgroundTaskRestarted(self.kind)).await;
+    }
+}
+
+// --list-devices: просканировать устройства, вывести отчет в stdout и выйти без GUI
+fn has_list_devices_argument() -> bool {
+    std::env::args().skip(1).any(|argument| argument == "--list-devices")
+}
+
+// Режим --list-devices. Логи в этом режиме идут в stderr, чтобы stdout содержал только отчет.
+// Возвращает true, если отчет выведен
+fn run_list_devices() -> bool {
+    let settings = configuration_manager::load_configuration().unwrap_or_else(|e| {
+        tracing::warn!("{}. Используются настройки по умолчанию.", e);
+        configuration_manager::ApplicationSettings::default()
+    });
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("Не удалось создать Tokio рантайм: {}", e);
+            return false;
+        }
+    };
+    match runtime.block_on(buttplug_connector::scan_and_build_device_report(settings)) {
+        Ok(device_report) => {
+            println!("{}", device_report);
+            true
+        }
+        Err(e) => {
+            eprintln!("Не удалось получить список устройств: {}", e);
+            false
+        }
+    }
+}
+
+// Путь из аргумента --config <путь> (или --config=<путь>)
+fn parse_config_path_argument() -> Option<PathBuf> {
+    let mut arguments = std::env::args().skip(1);
+    while let Some(argument) = arguments.next() {
+        if argument == "--config" {
+            let config_path = arguments.next().map(PathBuf::from);
+            if config_path.is_none() {
+                tracing::warn!("После --config не указан путь, используется конфигурация по умолчанию.");
+            }
+            return config_path;
+        }
+        if let Some(config_path) = argument.strip_prefix("--config=") {
+            return Some(PathBuf::from(config_path));
+        }
+    }
+    None
+}
+
 fn main() -> Result<(), eframe::Error> { // Возвращаемый тип eframe::Error
+    let is_list_devices_mode = has_list_devices_argument();
+    let log_writer = if is_list_devices_mode {
+        tracing_subscriber::fmt::writer::BoxMakeWriter::new(std::io::stderr)
+    } else {
+        tracing_subscriber::fmt::writer::BoxMakeWriter::new(std::io::stdout)
+    };
     let subscriber = tracing_subscriber::fmt()
         .with_env_filter(tracing_subscriber::EnvFilter::from_default_env().add_directive("warthunder_haptics_gui=info".parse().unwrap()))
         .with_target(true)
+        .with_writer(log_writer)
         .finish();
     tracing::subscriber::set_global_default(subscriber)
         .expect("Не удалось установить глобальный обработчик логов");
 
     tracing::info!("Запуск приложения WarThunder Haptics GUI...");
 
-    let tokio_runtime = tokio::runtime::Runtime::new()
-        .map_err(|e| {
-            tracing::error!("Не удалось создать Tokio рантайм: {}", e);
-            // Создаем кастомную ошибку для eframe, если это возможно, или паникуем.
-            // eframe::Error не имеет простого From<std::io::Error>.
-            // Для простоты примера, паника может быть допустима, если рантайм критичен.
-            // В eframe 0.27 нет очевидного способа вернуть кастомную ошибку Box<dyn std::error::Error>
-            // напрямую в eframe::Error без оборачивания в существующий вариант eframe::Error.
-            // Простейший вариант - паника, или возврат Ok(()) и логирование ошибки, если GUI может работать без рантайма (маловероятно).
-             panic!("Критическая ошибка: Не удалось создать Tokio рантайм: {}", e);
-        })?;
-
-
-    let (command_sender_gui, mut command_receiver_for_wt) = mpsc::channel::<CommandToAsyncTasks>(100);
-    // Для buttplug нужен свой ресивер, если команды будут обрабатываться параллельно и независимо
-    let (command_sender_gui_clone_for_bp, mut command_receiver_for_bp) = mpsc::channel::<CommandToAsyncTasks>(100);
-    // Чтобы Application мог слать в обе задачи, он должен иметь оба sender'а или один sender к диспетчеру.
-    // Пока Application будет иметь один command_sender_gui, который мы клонируем для передачи в Application.
-    // А задачи будут слушать свои ресиверы. Это значит, что Application должен будет как-то
-    // выбирать, в какой sender слать, или мы должны иметь задачу-диспетчер.
-
-    // Упрощение: Application будет иметь один Sender. А в main мы будем форвардить команды.
-    // Это добавляет сложности.
-    // Проще всего, если CommandToAsyncTasks содержит идентификатор целевой задачи,
-    // или задачи сами фильтруют команды.
-
-    // Схема с одним главным command_sender-ом из GUI и отдельными ресиверами для задач:
-    // main.rs создаст ЕДИНЫЙ канал для команд от GUI.
-    // let (gui_command_sender, mut main_command_receiver) = mpsc::channel(100);
-    // И затем main_command_receiver будет использоваться для диспетчеризации команд в нужные задачи.
-    // Это сложно для скелета.
-
-    // ВЕРНЕМСЯ К ПРОСТОЙ СХЕМЕ: Application имеет ОДИН command_sender.
-    // АСИНХРОННЫЕ ЗАДАЧИ ПОЛУЧАЮТ КОПИИ ЭТОГО РЕСИВЕРА (через tokio::sync::broadcast::Receiver если нужно много подписчиков)
-    // ИЛИ КАЖДАЯ ЗАДАЧА ИМЕЕТ СВОЙ MPSC КАНАЛ, И APPLICATION ХРАНИТ НЕСКОЛЬКО SENDER'ОВ.
-    // Для скелета я оставлю первоначальный подход с одним command_sender в Application,
-    // и передам command_receiver_async в задачи, но с оговоркой, что это для mpsc не совсем корректно,
-    // если несколько задач должны слушать ОДИН И ТОТ ЖЕ mpsc::Receiver.
-    // Поэтому сделаем так: Application будет слать команды, а задачи будут их получать через свои каналы.
-    // Main создаст эти каналы.
-
-    let (gui_command_sender, _main_command_receiver_placeholder) = mpsc::channel::<CommandToAsyncTasks>(100); // Этот ресивер не используется напрямую
-    let (update_sender_async, update_receiver_gui) = mpsc::channel::<UpdateFromAsyncTasks>(100);
-
-
-    let initial_settings_for_async = match configuration_manager::load_configuration() {
-        Ok(s) => s,
-        Err(_) => configuration_manager::ApplicationSettings::default(),
-    };
+    if let Some(config_path) = parse_config_path_argument() {
+        tracing::info!("Используется файл конфигурации из командной строки: {:?}", config_path);
+        if let Err(e) = configuration_manager::set_config_path_override(config_path) {
+            tracing::error!("{}", e);
+        }
+    }
 
-    let http_client = reqwest::Client::new();
+    if is_list_devices_mode {
+        if !run_list_devices() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
 
-    // Канал для команд к War Thunder коннектору
-    let (wt_task_command_sender, wt_task_command_receiver) = mpsc::channel(10);
-    // Канал для команд к Buttplug коннектору
-    let (bp_task_command_sender, bp_task_command_receiver) = mpsc::channel(10);
-
-    // Клонируем Sender'ы для Application, чтобы он мог отправлять команды в нужные задачи
-    // Application должен будет решить, в какой из этих Sender'ов отправить команду.
-    // Для этого CommandToAsyncTasks может содержать поле target или Application будет иметь методы типа send_to_wt, send_to_bp.
-    // Пока что Application будет иметь один общий command_sender, и мы должны решить, как он работает.
-    // Давайте упростим: Application будет слать в один агрегирующий Sender, а main будет его слушать и перенаправлять.
-    // Это сложно.
-    // Проще всего, Application будет иметь ОДИН Sender, а в CommandToAsyncTasks будет информация, для кого эта команда.
-    // И каждая задача сама фильтрует. Но это требует от задач слушать один и тот же broadcast канал.
+    // Схема каналов: Application шлет все команды в ОДИН канал, а задача-диспетчер
+    // рассылает каждую команду во все фоновые задачи. Каждая задача сама выбирает нужные ей команды.
+    let (gui_command_sender, mut gui_command_receiver) = mpsc::channel::<CommandToAsyncTasks>(100);
+    let (update_sender_async, update_receiver_gui) = mpsc::channel::<UpdateFromAsyncTasks>(100);
+    // Шина срабатываний: публикует GUI (обработка идет там), подписчики получают приемник через subscribe()
+    let (triggered_event_bus, _) = tokio::sync::broadcast::channel::<TriggeredEventNotification>(TRIGGERED_EVENT_BUS_CAPACITY);
 
-    // --- Окончательная простая схема для скелета с MPSC:
-    // Application получает ОДИН Sender. В main создается ОДИН Receiver для этого Sender'а.
-    // Затем запускается задача-диспетчер, которая читает из этого Receiver'а
-    // и пересылает команды в task-specific каналы (mpsc).
-    // Это чисто, но добавляет еще одну задачу.
 
-    // Для скелета сделаем так: Application имеет один `command_sender`.
-    // Асинхронные задачи создаются с *этим же* `command_sender` (клоном) для *отправки* сообщений
-    // (например, если им нужно послать команду самим себе или другой задаче, что редкость).
-    // А для *получения* команд от GUI у них будут свои `Receiver`'ы.
-    // `Application` должен будет хранить `Sender`'ы к каждой задаче.
+    let initial_settings_for_async = configuration_manager::load_configuration().unwrap_or_default();
 
-    let app_command_sender_to_wt = wt_task_command_sender.clone();
-    let app_command_sender_to_bp = bp_task_command_sender.clone();
+    // Без рантайма приложение бесполезно, поэтому здесь паника, а не ошибка eframe
+    // (eframe::Error не умеет оборачивать std::io::Error)
+    let async_task_runtime = AsyncTaskRuntime::start(initial_settings_for_async.async_runtime_kind)
+        .unwrap_or_else(|e| panic!("Критическая ошибка: Не удалось создать Tokio рантайм: {}", e));
+    tracing::info!("Фоновые задачи: {:?}", initial_settings_for_async.async_runtime_kind);
+    let tokio_runtime = async_task_runtime.handle().clone();
 
+    // Игра отвечает локально: соединение держим одно и переиспользуем, а таймаут самого запроса
+    // задается в коннекторе из настроек (war_thunder_request_timeout_milliseconds)
+    let http_client = reqwest::Client::builder()
+        .connect_timeout(std::time::Duration::from_millis(500))
+        .pool_max_idle_per_host(1)
+        .pool_idle_timeout(std::time::Duration::from_secs(30))
+        .build()
+        .unwrap_or_else(|e| {
+            tracing::warn!("Не удалось настроить HTTP клиент ({}), используются настройки по умолчанию.", e);
+            reqwest::Client::new()
+        });
 
-    // War Thunder Polling Task
+    // War Thunder Polling Task. Перезапущенная задача стартует с начальными настройками, затем GUI присылает текущие.
     let wt_update_sender_clone = update_sender_async.clone();
     let polling_interval = initial_settings_for_async.polling_interval_milliseconds;
-    tokio_runtime.spawn(async move {
-        war_thunder_connector::run_war_thunder_polling_loop(
-            wt_update_sender_clone,
+    let request_timeout = initial_settings_for_async.war_thunder_request_timeout_milliseconds;
+    let wt_runtime_handle = tokio_runtime.clone();
+    let wt_task = SupervisedTask::start(BackgroundTaskKind::WarThunder, Box::new(move |wt_task_command_receiver| {
+        wt_runtime_handle.spawn(war_thunder_connector::run_war_thunder_polling_loop(
+            wt_update_sender_clone.clone(),
             wt_task_command_receiver, // Этот ресивер для команд, специфичных для WT
-            http_client,
+            http_client.clone(),
             polling_interval,
-        ).await;
-    });
+            request_timeout,
+            Arc::new(clock::SystemClock),
+        ))
+    }));
+
+    // Наблюдение за файлом конфигурации: правки вне приложения приходят в GUI как ApplicationSettingsLoaded
+    tokio_runtime.spawn(configuration_watcher::run_configuration_file_watcher(update_sender_async.clone()));
 
     // Buttplug Service Task
     let bp_update_sender_clone = update_sender_async.clone();
-    let buttplug_server_address = initial_settings_for_async.buttplug_server_address.clone();
-    tokio_runtime.spawn(async move {
-        buttplug_connector::run_buttplug_service_loop(
-            bp_update_sender_clone,
+    let bp_runtime_handle = tokio_runtime.clone();
+    let mut bp_task = SupervisedTask::start(BackgroundTaskKind::Buttplug, Box::new(move |bp_task_command_receiver| {
+        bp_runtime_handle.spawn(buttplug_connector::run_buttplug_service_loop(
+            bp_update_sender_clone.clone(),
             bp_task_command_receiver, // Этот ресивер для команд, специфичных для BP
-            buttplug_server_address,
-        ).await;
+        ))
+    }));
+
+    let device_safety_stop = DeviceSafetyStop::new(bp_task.subscribe_command_sender(), tokio_runtime.clone());
+    device_safety_stop.install_panic_hook();
+    let _device_safety_stop_guard = DeviceSafetyStopGuard(device_safety_stop);
+
+    // Диспетчер команд заодно присматривает за фоновыми задачами и перезапускает упавшие.
+    // Buttplug получает команду первым: остановка устройств не ждет места в канале задачи WT.
+    let supervisor_update_sender = update_sender_async.clone();
+    tokio_runtime.spawn(async move {
+        let mut supervised_tasks = [bp_task, wt_task];
+        let mut task_check_interval = tokio::time::interval(BACKGROUND_TASK_CHECK_INTERVAL);
+        loop {
+            tokio::select! {
+                command = gui_command_receiver.recv() => {
+                    let Some(command) = command else {
+                        break;
+                    };
+                    for supervised_task in &supervised_tasks {
+                        supervised_task.send(command.clone()).await;
+                    }
+                }
+                _ = task_check_interval.tick() => {
+                    for supervised_task in &mut supervised_tasks {
+                        supervised_task.restart_if_finished(&supervisor_update_sender).await;
+                    }
+                }
+            }
+        }
+        tracing::info!("Диспетчер команд завершен: канал команд GUI закрыт.");
     });
 
+    // API управления для внешних инструментов: команды идут в тот же канал, что и из GUI
+    #[cfg(feature = "control_api")]
+    {
+        if initial_settings_for_async.control_api_enabled {
+            tokio_runtime.spawn(control_api::run_control_api_server(
+                initial_settings_for_async.clone(),
+                gui_command_sender.clone(),
+                update_sender_async.clone(),
+            ));
+        }
+    }
+
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([800.0, 600.0])
@@ -139,59 +356,21 @@ fn main() -> Result<(), eframe::Error> { // Возвращаемый тип efra
     };
 
     tracing::info!("Запуск основного цикла eframe...");
-    // Передаем в Application Sender'ы для каждой задачи, если он будет напрямую им слать.
-    // Или один общий Sender, если команды будут маршрутизироваться по-другому.
-    // В нашем `application.rs` сейчас один `command_sender`. Это нужно будет согласовать.
-    // Пусть пока Application имеет один `gui_command_sender`, и мы предполагаем,
-    // что команды из него как-то доходят до нужных задач (это слабое место скелета).
-    // Для исправления, Application должен был бы получить `app_command_sender_to_wt` и `app_command_sender_to_bp`.
-    // Я оставлю один `gui_command_sender` в `Application::new`, но тебе нужно будет решить, как он будет использоваться
-    // для отправки команд в *конкретные* асинхронные задачи.
-    // Пока что `CommandToAsyncTasks` не имеет информации о получателе.
-
-    eframe::run_native(
+
+    let eframe_result = eframe::run_native(
         "WarThunder Haptics GUI",
         native_options,
         Box::new(move |creation_context| {
-            // Вот здесь command_sender, который получает Application.
-            // Если команды должны идти в разные задачи, то Application должен иметь доступ
-            // к app_command_sender_to_wt и app_command_sender_to_bp.
-            // Либо этот gui_command_sender идет к диспетчеру.
-            // Сейчас он не подключен ни к одному из task_command_receiver'ов.
-            // Подключим его к `app_command_sender_to_wt` для примера, но это неполное решение.
-            // Правильнее всего, Application должен иметь два sender'а.
-            // В этом скелете, я передам `app_command_sender_to_wt` как основной `command_sender` в `Application`,
-            // это потребует изменений в `Application`, чтобы он знал, что это для WT.
-            // Или, проще, создать новый канал для `Application` и затем в `main` слушать его и перенаправлять.
-            // Но это усложнит `main`.
-
-            // Для текущего скелета, где Application имеет ОДИН command_sender:
-            // пусть он шлет команды, а задачи сами фильтруют. Но для этого нужен broadcast или общий consumer.
-            // Поскольку это mpsc, ОДИН command_sender в Application будет слать команды, которые сможет забрать ОДИН Receiver.
-            // Это все еще проблема.
-
-            // Давай сделаем так: Application получит app_command_sender_to_wt и app_command_sender_to_bp.
-            // Это потребует изменения Application::new и его полей.
-            // Это выходит за рамки простого исправления ошибок, это редизайн.
-
-            // ВЕРНEMСЯ К ТОМУ, ЧТО Application::new ПРИНИМАЕТ ОДИН command_sender,
-            // и это будет sender от основного канала команд GUI.
-            // Этот канал (созданный как gui_command_sender, _main_command_receiver_placeholder)
-            // должен быть прочитан задачей-диспетчером в tokio_runtime, которая будет пересылать команды.
-            // Это наиболее чистое решение. Но для скелета я это не реализовывал.
-
-            // Если мы хотим, чтобы Application отправлял команды, которые будут получены
-            // async задачами, то он должен использовать `app_command_sender_to_wt` или `app_command_sender_to_bp`.
-            // В текущем Application::new передается абстрактный `command_sender`.
-            // Пусть это будет `app_command_sender_to_wt` для примера.
-            // Это значит, что все команды из Application полетят в WT задачу.
-            // Тебе нужно будет это исправить для команд к Buttplug.
-
-            Box::new(WarThunderHapticsApplication::new(
+            Ok(Box::new(WarThunderHapticsApplication::new(
                 creation_context,
-                app_command_sender_to_wt, // <<< ВНИМАНИЕ: это пример, для BP нужен свой!
+                gui_command_sender,
                 update_receiver_gui,
-            ))
+                triggered_event_bus,
+            )))
         }),
-    )
-}
\ No newline at end of file
+    );
+
+    tracing::info!("Окно закрыто, остановка фоновых задач...");
+    async_task_runtime.shutdown();
+    eframe_result
+}
@@ -1,23 +1,94 @@
 // src/message_passing.rs
 
 use crate::configuration_manager::ApplicationSettings;
-use crate::war_thunder_connector::WarThunderIndicators;
+use crate::connection_metrics::{DeviceCommandMetrics, WarThunderPollMetrics};
+use crate::game_event_processor::TriggeredEvent;
+use crate::war_thunder_api::{HudDamageMessage, WarThunderIndicators};
 use buttplug::client::ButtplugClientDevice; // Это Arc<ButtplugDeviceImpl>
+use buttplug::core::message::Endpoint;
+use std::path::PathBuf;
 use std::sync::Arc;
 
 #[derive(Debug, Clone)]
 pub enum CommandToAsyncTasks {
     StartProcessing,
     StopProcessing,
-    UpdateApplicationSettings(ApplicationSettings),
+    SetIndicatorUpdatesEnabled(bool), // false - WT задача не шлет WarThunderIndicatorsUpdate, только статус и события
+    SetRawJsonCaptureEnabled(bool), // Отладка: пересылать в GUI сырые ответы /indicators и /state
+    SetConnectionMetricsEnabled(bool), // Отладка: периодически слать в GUI сводку метрик соединений
+    UpdateApplicationSettings(Box<ApplicationSettings>),
+    // device_index во всех командах ниже - индекс устройства Buttplug (ButtplugClientDevice::index),
+    // а не позиция в списке GUI: порядок списков в GUI и в Buttplug задаче может не совпадать
     VibrateDevice {
-        device_index: usize,
+        device_index: u32,
         speed: f64,
+        actuator_speeds: Vec<(u32, f64)>, // (индекс привода, скорость); остальные приводы получают speed
+        // Some - через столько мс Buttplug задача сама остановит устройство (эффект правила).
+        // None - вибрация до следующей команды (непрерывные сопоставления, ручная проверка).
+        duration_milliseconds: Option<u64>,
+    },
+    StopDevice(u32),
+    // Групповые команды: адреса участников разрешаются в устройства внутри Buttplug задачи
+    VibrateGroup {
+        member_addresses: Vec<String>,
+        speed: f64,
+        actuator_speeds: Vec<(u32, f64)>,
+        duration_milliseconds: Option<u64>, // Как у VibrateDevice, остановка для каждого участника отдельно
+    },
+    StopGroup(Vec<String>),
+    // Остановить все подключенные устройства
+    StopAllDevices,
+    // Проиграть последовательность шагов (интенсивность, длительность мс) на устройстве, затем остановить его.
+    // Используется для тестовых последовательностей и для действий с паттерном.
+    PlayTestSequence {
+        device_index: u32,
+        steps: Vec<(f64, u64)>,
+    },
+    // Записать отчет о подключенных устройствах и их приводах (JSON) в указанный файл
+    ExportDeviceReport(PathBuf),
+    // Временное отключение вывода: пока true, команды вибрации не отправляются на устройства
+    SetOutputMuted(bool),
+    // false - вывод ждет подтверждения после автозапуска: Buttplug задача не шлет собственных
+    // сигналов (импульс при подключении); команды GUI это не затрагивает
+    SetOutputArmed(bool),
+    // Подписка на датчики устройства (давление и т.п.) - включается вручную для каждого устройства
+    SetDeviceSensorSubscription {
+        device_index: u32,
+        is_enabled: bool,
+    },
+    // Расширенный режим: сырые байты в конечную точку устройства, для устройств без поддержки в Buttplug
+    // с известным протоколом. Результат приходит записью лога устройства.
+    RawWriteDevice {
+        device_index: u32,
+        endpoint: Endpoint,
+        data: Vec<u8>,
+        write_with_response: bool,
+    },
+    RawReadDevice {
+        device_index: u32,
+        endpoint: Endpoint,
+        expected_length: u32,
+        timeout_milliseconds: u32,
     },
-    StopDevice(usize),
     ScanForButtplugDevices,
+    // Сверить список устройств с текущим списком клиента Buttplug без нового сканирования
+    ReacquireButtplugDevices,
     DisconnectButtplug,
+    // Проверить адрес WebSocket сервера отдельным разовым подключением (основное подключение не трогается)
+    TestButtplugServerAddress(String),
+}
+// Шина сработавших правил: обработка публикует каждое срабатывание, а подписчики (статистика, внешнее API,
+// уведомления) читают ее независимо друг от друга через subscribe(). Отправка устройствам идет напрямую, мимо шины.
+// Отстающий подписчик теряет самые старые события (RecvError::Lagged), но не задерживает обработку.
+pub const TRIGGERED_EVENT_BUS_CAPACITY: usize = 256;
+pub type TriggeredEventBus = tokio::sync::broadcast::Sender<TriggeredEventNotification>;
+
+#[derive(Debug, Clone)]
+pub struct TriggeredEventNotification {
+    pub triggered_event: TriggeredEvent,
+    pub is_simulated: bool, // Имитация из панели разработчика, а не реальный бой
 }
+
 #[derive(Debug)]
 pub struct ClonableButtplugClientDevice(pub Arc<ButtplugClientDevice>);
 
@@ -27,15 +98,90 @@ impl Clone for ClonableButtplugClientDevice {
     }
 }
 
+// Категория записи лога (для фильтра в GUI)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogCategory {
+    General,
+    WarThunder,
+    Buttplug,
+    Events, // Срабатывания правил и команды устройствам от игровых событий
+}
+
+impl LogCategory {
+    pub const ALL: [LogCategory; 4] = [LogCategory::General, LogCategory::WarThunder, LogCategory::Buttplug, LogCategory::Events];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            LogCategory::General => "Общее",
+            LogCategory::WarThunder => "WT",
+            LogCategory::Buttplug => "BP",
+            LogCategory::Events => "События",
+        }
+    }
+}
+
+// Фоновые задачи, которые main перезапускает после сбоя
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackgroundTaskKind {
+    WarThunder,
+    Buttplug,
+}
+
+impl BackgroundTaskKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            BackgroundTaskKind::WarThunder => "War Thunder",
+            BackgroundTaskKind::Buttplug => "Buttplug",
+        }
+    }
+}
+
 #[derive(Debug, Clone)] 
 pub enum UpdateFromAsyncTasks {
-    LogMessage(String),
+    LogMessage {
+        category: LogCategory,
+        device_address: Option<String>, // Устройство, к которому относится запись (если есть)
+        message: String,
+    },
     WarThunderIndicatorsUpdate(WarThunderIndicators),
+    WarThunderHudDamageMessages(Vec<HudDamageMessage>), // Новые записи журнала боя /hudmsg (опрашивается, если задан ник)
     WarThunderConnectionStatus(bool),
+    WarThunderBattleStatus(bool), // API доступно; true - в бою (данные индикаторов валидны)
+    WarThunderRawJson {
+        source_url: String,
+        pretty_json: String,
+    },
     ButtplugConnected,
     ButtplugDisconnected,
     ButtplugDeviceFound(ClonableButtplugClientDevice), // Используем обертку
     ButtplugDeviceLost(ClonableButtplugClientDevice),  // Используем обертку
+    ButtplugScanFinished { found_device_count: usize }, // Сканирование завершилось (по таймауту или сервером)
+    ButtplugDeviceReady(u32), // Индекс BP устройства, прошедшего прогрев
+    ButtplugDeviceSpeeds(Vec<(u32, f64)>), // Индекс BP -> последняя отправленная скорость
+    // Что устройство фактически получило последней выполненной командой (с учетом приводов, затухания,
+    // шагов паттерна); 0 - устройство остановлено
+    ButtplugDeviceOutput {
+        device_index: u32, // Индекс BP
+        output_intensity: f64,
+    },
+    ButtplugSensorReading {
+        device_index: u32, // Индекс BP
+        sensor_type: String,
+        values: Vec<i32>,
+    },
     ButtplugError(String),
-    ApplicationSettingsLoaded(ApplicationSettings),
+    // Ошибка команды конкретному устройству (адрес - см. buttplug_connector::device_address)
+    ButtplugDeviceError {
+        device_address: String,
+        message: String,
+    },
+    ApplicationSettingsLoaded(Box<ApplicationSettings>), // Файл конфигурации изменен вне приложения (см. configuration_watcher)
+    ButtplugServerAddressTestResult {
+        server_address: String,
+        result: Result<(), String>,
+    },
+    WarThunderPollMetrics(WarThunderPollMetrics),
+    DeviceCommandMetrics(DeviceCommandMetrics),
+    // Задача упала и запущена заново с чистым состоянием: GUI заново отправляет ей свои настройки
+    BackgroundTaskRestarted(BackgroundTaskKind),
 }
\ No newline at end of file
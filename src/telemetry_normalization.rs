@@ -0,0 +1,262 @@
+// src/telemetry_normalization.rs
+
+// Слой между сырым JSON /indicators и процессором событий: ключи в ответе WT зависят от техники
+// (у самолетов одни, у танков другие, а у разных самолетов еще и свои варианты), поэтому поля
+// WarThunderIndicators заполняются по таблице своего класса техники. Правила и интерфейс работают
+// с постоянными именами полей, а все числовые сырые поля доступны как "raw.<ключ>".
+
+use crate::configuration_manager::VehicleClass;
+use crate::game_event_processor::classify_vehicle;
+use crate::war_thunder_api::WarThunderIndicators;
+use serde_json::{Map, Value};
+
+// Префикс сырых полей в условиях и сопоставлениях, например "raw.throttle_1 > 90"
+pub const RAW_FIELD_PREFIX: &str = "raw.";
+
+// Источник нормализованного поля: ключ /indicators и множитель к его значению
+// (например, проценты 0..100 в долю 0..1)
+struct FieldSource {
+    raw_key: &'static str,
+    scale: f32,
+}
+
+const fn source(raw_key: &'static str, scale: f32) -> FieldSource {
+    FieldSource { raw_key, scale }
+}
+
+// Для каждого поля - источники в порядке предпочтения; берется первый присутствующий в ответе
+struct FieldSources {
+    speed: &'static [FieldSource],
+    altitude_10k: &'static [FieldSource],
+    rpm_throttle: &'static [FieldSource],
+    health_percentage: &'static [FieldSource],
+    first_stage_ammo: &'static [FieldSource],
+    g_load: &'static [FieldSource],
+}
+
+// Класс не определен: прежние ключи, под которые писались правила
+const GENERIC_FIELD_SOURCES: FieldSources = FieldSources {
+    speed: &[source("speed", 1.0)],
+    altitude_10k: &[source("altitude_10k", 1.0)],
+    rpm_throttle: &[source("RPM throttle", 1.0)],
+    health_percentage: &[source("H, %", 1.0)],
+    first_stage_ammo: &[source("first_stage_ammo", 1.0)],
+    g_load: &[source("Ny", 1.0)],
+};
+
+const AIR_FIELD_SOURCES: FieldSources = FieldSources {
+    speed: &[source("speed", 1.0), source("IAS, km/h", 1.0), source("TAS, km/h", 1.0)],
+    altitude_10k: &[source("altitude_10k", 1.0), source("altitude_hour", 1.0)],
+    rpm_throttle: &[source("RPM throttle", 1.0), source("throttle 1, %", 0.01), source("throttle", 1.0)],
+    health_percentage: &[source("H, %", 1.0)],
+    first_stage_ammo: &[source("first_stage_ammo", 1.0)],
+    g_load: &[source("Ny", 1.0), source("g_meter", 1.0)],
+};
+
+const GROUND_FIELD_SOURCES: FieldSources = FieldSources {
+    speed: &[source("speed", 1.0)],
+    altitude_10k: &[],
+    rpm_throttle: &[source("RPM throttle", 1.0), source("throttle", 1.0)],
+    health_percentage: &[source("H, %", 1.0)],
+    first_stage_ammo: &[source("first_stage_ammo", 1.0)],
+    g_load: &[],
+};
+
+const NAVAL_FIELD_SOURCES: FieldSources = FieldSources {
+    speed: &[source("speed", 1.0)],
+    altitude_10k: &[],
+    rpm_throttle: &[source("RPM throttle", 1.0), source("throttle", 1.0)],
+    health_percentage: &[source("H, %", 1.0)],
+    first_stage_ammo: &[source("first_stage_ammo", 1.0)],
+    g_load: &[],
+};
+
+fn field_sources_for_class(vehicle_class: Option<VehicleClass>) -> &'static FieldSources {
+    match vehicle_class {
+        Some(VehicleClass::Air) => &AIR_FIELD_SOURCES,
+        Some(VehicleClass::Ground) => &GROUND_FIELD_SOURCES,
+        Some(VehicleClass::Naval) => &NAVAL_FIELD_SOURCES,
+        None => &GENERIC_FIELD_SOURCES,
+    }
+}
+
+fn first_source_value(raw_fields: &Map<String, Value>, field_sources: &[FieldSource]) -> Option<f32> {
+    field_sources.iter().find_map(|field_source| {
+        raw_fields.get(field_source.raw_key)
+            .and_then(Value::as_f64)
+            .map(|raw_value| raw_value as f32 * field_source.scale)
+    })
+}
+
+// Ключ WT как имя поля для условий: "throttle 1, %" -> "throttle_1"
+pub fn raw_field_name(raw_key: &str) -> String {
+    let mut field_name = String::new();
+    for character in raw_key.chars() {
+        if character.is_ascii_alphanumeric() {
+            field_name.push(character.to_ascii_lowercase());
+        } else if !field_name.is_empty() && !field_name.ends_with('_') {
+            field_name.push('_');
+        }
+    }
+    field_name.trim_end_matches('_').to_string()
+}
+
+// Разбор тела /indicators: строковые поля и valid - как есть, числовые - по таблице класса техники
+pub fn normalize_indicators(raw_body: &str) -> Result<WarThunderIndicators, String> {
+    let raw_fields: Map<String, Value> = serde_json::from_str(raw_body)
+        .map_err(|e| format!("Ответ /indicators не является JSON-объектом: {}", e))?;
+    let mut indicators = WarThunderIndicators {
+        vehicle_type: raw_fields.get("type").and_then(Value::as_str).map(str::to_string),
+        army: raw_fields.get("army").and_then(Value::as_str).map(str::to_string),
+        valid: raw_fields.get("valid").and_then(Value::as_bool),
+        ..Default::default()
+    };
+    let field_sources = field_sources_for_class(classify_vehicle(&indicators));
+    indicators.speed = first_source_value(&raw_fields, field_sources.speed);
+    indicators.altitude_10k = first_source_value(&raw_fields, field_sources.altitude_10k);
+    indicators.rpm_throttle = first_source_value(&raw_fields, field_sources.rpm_throttle);
+    indicators.health_percentage = first_source_value(&raw_fields, field_sources.health_percentage);
+    indicators.first_stage_ammo = first_source_value(&raw_fields, field_sources.first_stage_ammo);
+    indicators.g_load = first_source_value(&raw_fields, field_sources.g_load);
+    // Разные ключи могут дать одно имя ("throttle 1, %" и "throttle_1"): ключ, совпадающий с именем,
+    // главнее, в остальных случаях остается первый по порядку ключей
+    for (raw_key, raw_value) in &raw_fields {
+        let Some(raw_value) = raw_value.as_f64() else { continue };
+        let field_name = raw_field_name(raw_key);
+        if field_name.is_empty() {
+            continue;
+        }
+        if *raw_key == field_name {
+            indicators.raw_fields.insert(field_name, raw_value);
+        } else {
+            indicators.raw_fields.entry(field_name).or_insert(raw_value);
+        }
+    }
+    Ok(indicators)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: Option<f32>, expected: f32) {
+        let actual = actual.expect("поле должно быть заполнено");
+        assert!((actual - expected).abs() < 1e-4, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn raw_field_name_lowercases_and_joins_words() {
+        assert_eq!(raw_field_name("throttle 1, %"), "throttle_1");
+        assert_eq!(raw_field_name("IAS, km/h"), "ias_km_h");
+        assert_eq!(raw_field_name("H, %"), "h");
+        assert_eq!(raw_field_name("  Ny"), "ny");
+        assert_eq!(raw_field_name("RPM throttle"), "rpm_throttle");
+        assert_eq!(raw_field_name("already_normalized"), "already_normalized");
+        assert_eq!(raw_field_name(", %"), "");
+    }
+
+    #[test]
+    fn air_fields_use_fallback_keys_and_scale_throttle_percent() {
+        let indicators = normalize_indicators(r#"{
+            "valid": true, "army": "air", "type": "f_16a",
+            "IAS, km/h": 540, "TAS, km/h": 610, "altitude_hour": 3.2,
+            "throttle 1, %": 85, "H, %": 100, "g_meter": 4.5
+        }"#).unwrap();
+
+        assert_eq!(indicators.vehicle_type.as_deref(), Some("f_16a"));
+        assert_eq!(indicators.valid, Some(true));
+        assert_close(indicators.speed, 540.0);
+        assert_close(indicators.altitude_10k, 3.2);
+        assert_close(indicators.rpm_throttle, 0.85);
+        assert_close(indicators.health_percentage, 100.0);
+        assert_close(indicators.g_load, 4.5);
+        assert_eq!(indicators.first_stage_ammo, None);
+        assert_eq!(indicators.raw_fields.get("throttle_1"), Some(&85.0));
+        assert_eq!(indicators.raw_fields.get("tas_km_h"), Some(&610.0));
+    }
+
+    #[test]
+    fn air_fields_prefer_primary_keys_over_fallbacks() {
+        let indicators = normalize_indicators(r#"{
+            "army": "air", "speed": 300, "IAS, km/h": 540,
+            "RPM throttle": 0.4, "throttle 1, %": 85, "Ny": 2.0, "g_meter": 4.5
+        }"#).unwrap();
+
+        assert_close(indicators.speed, 300.0);
+        assert_close(indicators.rpm_throttle, 0.4);
+        assert_close(indicators.g_load, 2.0);
+    }
+
+    #[test]
+    fn ground_fields_ignore_air_only_keys() {
+        let indicators = normalize_indicators(r#"{
+            "army": "tank", "type": "tankmodels/ussr_t_72a",
+            "speed": 42, "throttle": 0.7, "first_stage_ammo": 12,
+            "altitude_10k": 120, "Ny": 1.1, "IAS, km/h": 99
+        }"#).unwrap();
+
+        assert_close(indicators.speed, 42.0);
+        assert_close(indicators.rpm_throttle, 0.7);
+        assert_close(indicators.first_stage_ammo, 12.0);
+        assert_eq!(indicators.altitude_10k, None);
+        assert_eq!(indicators.g_load, None);
+        // Сырые значения все равно доступны правилам
+        assert_eq!(indicators.raw_fields.get("altitude_10k"), Some(&120.0));
+        assert_eq!(indicators.raw_fields.get("ny"), Some(&1.1));
+    }
+
+    #[test]
+    fn naval_class_comes_from_vehicle_type_without_army() {
+        let indicators = normalize_indicators(r#"{
+            "type": "shipmodels/us_destroyer_fletcher",
+            "speed": 30, "throttle": 0.5, "H, %": 64, "Ny": 1.0
+        }"#).unwrap();
+
+        assert_eq!(indicators.army, None);
+        assert_close(indicators.speed, 30.0);
+        assert_close(indicators.rpm_throttle, 0.5);
+        assert_close(indicators.health_percentage, 64.0);
+        assert_eq!(indicators.g_load, None);
+    }
+
+    #[test]
+    fn generic_fields_use_only_legacy_keys() {
+        let indicators = normalize_indicators(r#"{
+            "type": "dummy_plane", "IAS, km/h": 540, "throttle 1, %": 85,
+            "RPM throttle": 0.3, "H, %": 90, "Ny": 3.0, "first_stage_ammo": 7
+        }"#).unwrap();
+
+        assert_eq!(indicators.speed, None);
+        assert_close(indicators.rpm_throttle, 0.3);
+        assert_close(indicators.health_percentage, 90.0);
+        assert_close(indicators.g_load, 3.0);
+        assert_close(indicators.first_stage_ammo, 7.0);
+    }
+
+    #[test]
+    fn raw_fields_keep_only_numbers_and_resolve_name_collisions() {
+        let indicators = normalize_indicators(r#"{
+            "army": "air", "type": "f_16a", "valid": true,
+            "throttle 1, %": 85, "throttle_1": 0.5,
+            "IAS, km/h": 540, "ias km/h": 10,
+            ", %": 1
+        }"#).unwrap();
+
+        // Ключ, совпадающий с именем, главнее вариантов с пробелами и знаками
+        assert_eq!(indicators.raw_fields.get("throttle_1"), Some(&0.5));
+        // Без точного совпадения остается первый ключ по порядку
+        assert_eq!(indicators.raw_fields.get("ias_km_h"), Some(&540.0));
+        // Нормализованное поле по-прежнему берется из своего ключа
+        assert_close(indicators.rpm_throttle, 0.85);
+        assert!(!indicators.raw_fields.contains_key("type"));
+        assert!(!indicators.raw_fields.contains_key("army"));
+        assert!(!indicators.raw_fields.contains_key("valid"));
+        assert!(!indicators.raw_fields.contains_key(""));
+    }
+
+    #[test]
+    fn non_object_body_is_an_error() {
+        assert!(normalize_indicators("[1, 2]").is_err());
+        assert!(normalize_indicators("не json").is_err());
+    }
+}
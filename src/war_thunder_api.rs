@@ -0,0 +1,154 @@
+// src/war_thunder_api.rs
+
+// Формат данных War Thunder API без сетевого опроса: индикаторы, журнал боя /hudmsg и проверка настроек API.
+// Модуль не зависит от GUI и Buttplug, поэтому входит в библиотеку (см. src/lib.rs).
+
+use serde::{Deserialize, Serialize};
+use reqwest::header::{HeaderName, HeaderValue};
+use crate::configuration_manager::{HttpHeaderSetting, WarThunderApiSettings};
+use std::collections::BTreeMap;
+
+// Индикаторы с постоянными именами полей. Из ответа /indicators заполняются через
+// telemetry_normalization::normalize_indicators (ключи WT зависят от класса техники);
+// serde-имена ниже - формат кэша индикаторов и прежние ключи WT.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct WarThunderIndicators {
+    #[serde(rename = "type")]
+    pub vehicle_type: Option<String>,
+    pub speed: Option<f32>,
+    pub altitude_10k: Option<f32>, // Пример, если есть такое поле
+    #[serde(rename = "RPM throttle")] // Пример с переименованием
+    pub rpm_throttle: Option<f32>,
+    #[serde(rename = "H, %")]
+    pub health_percentage: Option<f32>, // Здоровье в процентах
+    pub first_stage_ammo: Option<f32>, // Снаряды в боеукладке первой очереди (уменьшение = выстрел)
+    pub valid: Option<bool>, // false - данные не относятся к бою (нули/устаревшие значения)
+    pub army: Option<String>, // Род войск текущей техники ("air", "tank", "ship"), если WT его передает
+    #[serde(rename = "Ny")]
+    pub g_load: Option<f32>, // Перегрузка (G) для самолетов; у наземной техники отсутствует
+    // Все числовые поля ответа как есть, по имени из telemetry_normalization::raw_field_name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub raw_fields: BTreeMap<String, f64>,
+    // ... добавь сюда все интересующие тебя поля из /indicators
+    // Например:
+    // pub Gx: Option<f32>,
+    // pub Gy: Option<f32>,
+    // pub Gz: Option<f32>,
+    // pub weapon_active: Option<bool>, // Если есть флаг активного оружия
+    // pub shells_count: Option<u32>, // Количество снарядов
+}
+
+// Запись журнала боя из раздела damage ответа /hudmsg: уничтожения, поджоги, критические попадания.
+// Текст вида "=ТЕГ= Игрок (Техника) destroyed Противник (Техника)"; сторон WT не передает.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct HudDamageMessage {
+    pub id: u64,
+    pub msg: String,
+    #[serde(default)]
+    pub time: u64, // Секунды от начала боя
+}
+
+#[derive(Deserialize)]
+struct HudMessagesResponse {
+    #[serde(default)]
+    damage: Vec<HudDamageMessage>,
+}
+
+// Путь запроса журнала: WT возвращает записи с id больше last_damage_id
+pub fn hud_messages_path(last_damage_id: u64) -> String {
+    format!("/hudmsg?lastEvt=0&lastDmg={}", last_damage_id)
+}
+
+pub fn parse_hud_damage_messages(raw_body: &str) -> Result<Vec<HudDamageMessage>, String> {
+    serde_json::from_str::<HudMessagesResponse>(raw_body)
+        .map(|hud_messages| hud_messages.damage)
+        .map_err(|e| format!("Некорректный ответ /hudmsg: {}", e))
+}
+
+// Исход для самого игрока. Журнал общий для всех участников боя, поэтому свое отличаем по нику из настроек.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OwnBattleOutcome {
+    Kill,  // Игрок уничтожил технику другого участника
+    Death, // Технику игрока уничтожили
+}
+
+// Глаголы уничтожения в журнале (английский и русский клиент)
+const DESTRUCTION_VERBS: [&str; 4] = [" destroyed ", " shot down ", " уничтожил ", " сбил "];
+
+// Ник участника из "=ТЕГ= Ник (Техника)": тег полка и техника отбрасываются, в нике пробелов нет
+fn hud_participant_name(participant_text: &str) -> &str {
+    let without_vehicle = participant_text.find(" (").map_or(participant_text, |vehicle_start| &participant_text[..vehicle_start]);
+    without_vehicle.split_whitespace().last().unwrap_or_default()
+}
+
+// None - запись не про уничтожение, не про игрока или ник не задан
+pub fn own_battle_outcome(message: &str, player_name: &str) -> Option<OwnBattleOutcome> {
+    let player_name = player_name.trim();
+    if player_name.is_empty() {
+        return None;
+    }
+    let (attacker_text, victim_text) = DESTRUCTION_VERBS.iter()
+        .find_map(|verb| message.split_once(verb))?;
+    if hud_participant_name(victim_text) == player_name {
+        Some(OwnBattleOutcome::Death)
+    } else if hud_participant_name(attacker_text) == player_name {
+        Some(OwnBattleOutcome::Kill)
+    } else {
+        None
+    }
+}
+
+pub fn parse_request_header(header_setting: &HttpHeaderSetting) -> Result<(HeaderName, HeaderValue), String> {
+    let header_name = HeaderName::from_bytes(header_setting.name.trim().as_bytes())
+        .map_err(|_| format!("Недопустимое имя заголовка '{}'", header_setting.name))?;
+    let mut header_value = HeaderValue::from_str(&header_setting.value)
+        .map_err(|_| format!("Недопустимое значение заголовка '{}' (переводы строк и управляющие символы запрещены)", header_setting.name))?;
+    header_value.set_sensitive(true); // reqwest не покажет значение в отладочном выводе
+    Ok((header_name, header_value))
+}
+
+// Ошибки в адресе и заголовках War Thunder API - для отчета при загрузке настроек
+pub fn validate_war_thunder_api_settings(api_settings: &WarThunderApiSettings) -> Vec<String> {
+    let mut validation_errors = Vec::new();
+    if let Err(e) = reqwest::Url::parse(&api_settings.base_url) {
+        validation_errors.push(format!("Недопустимый адрес War Thunder API '{}': {}", api_settings.base_url, e));
+    }
+    validation_errors.extend(api_settings.request_headers.iter().filter_map(|header_setting| parse_request_header(header_setting).err()));
+    validation_errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HUD_MESSAGES_FIXTURE: &str = r#"{"events": [], "damage": [
+        {"id": 41, "msg": "=CLAN= Pilot (Bf 109 F-4) shot down Enemy (Spitfire Mk Vc)", "sender": "", "enemy": false, "mode": "", "time": 65},
+        {"id": 42, "msg": "Enemy (Spitfire Mk Vc) set afire Pilot (Bf 109 F-4)", "sender": "", "enemy": false, "mode": "", "time": 70}
+    ]}"#;
+
+    #[test]
+    fn hud_damage_messages_are_parsed() {
+        let messages = parse_hud_damage_messages(HUD_MESSAGES_FIXTURE).unwrap();
+        assert_eq!(messages.iter().map(|message| message.id).collect::<Vec<_>>(), vec![41, 42]);
+        assert_eq!(messages[0].time, 65);
+        assert!(parse_hud_damage_messages(r#"{"events": []}"#).unwrap().is_empty());
+        assert!(parse_hud_damage_messages("<html>").is_err());
+        assert_eq!(hud_messages_path(42), "/hudmsg?lastEvt=0&lastDmg=42");
+    }
+
+    #[test]
+    fn own_outcomes_are_matched_by_player_name() {
+        let kill_message = "=CLAN= Pilot (Bf 109 F-4) shot down Enemy (Spitfire Mk Vc)";
+        assert_eq!(own_battle_outcome(kill_message, "Pilot"), Some(OwnBattleOutcome::Kill));
+        assert_eq!(own_battle_outcome(kill_message, "Enemy"), Some(OwnBattleOutcome::Death));
+        // Чужие исходы, частичное совпадение ника и пустой ник не засчитываются
+        assert_eq!(own_battle_outcome(kill_message, "Someone"), None);
+        assert_eq!(own_battle_outcome(kill_message, "Pilo"), None);
+        assert_eq!(own_battle_outcome(kill_message, "  "), None);
+        assert_eq!(own_battle_outcome("Tanker (Т-34) уничтожил Pilot (Tiger H1)", "Pilot"), Some(OwnBattleOutcome::Death));
+        assert_eq!(own_battle_outcome("Pilot (Bf 109 F-4 (trop)) destroyed Enemy (Yak-1)", "Pilot"), Some(OwnBattleOutcome::Kill));
+        // Поджог - не уничтожение
+        assert_eq!(own_battle_outcome("Enemy (Spitfire Mk Vc) set afire Pilot (Bf 109 F-4)", "Pilot"), None);
+    }
+}
+
@@ -1,115 +1,559 @@
 // src/war_thunder_connector.rs
 
-use serde::Deserialize;
 use tokio::sync::mpsc;
-use tokio::time::{sleep, Duration};
-use reqwest::Client;
-use crate::message_passing::{UpdateFromAsyncTasks, CommandToAsyncTasks}; // CommandToAsyncTasks может понадобиться для сигнала остановки или изменения интервала опроса
-
-// Пример структуры для данных из /indicators. Тебе нужно будет ее дополнить на основе реального JSON.
-// Используй https://app.quicktype.io/ чтобы сгенерировать структуры из примера JSON.
-#[derive(Deserialize, Debug, Clone, Default)]
-pub struct WarThunderIndicators {
-    #[serde(rename = "type")]
-    pub vehicle_type: Option<String>,
-    pub speed: Option<f32>,
-    pub altitude_10k: Option<f32>, // Пример, если есть такое поле
-    #[serde(rename = "RPM throttle")] // Пример с переименованием
-    pub rpm_throttle: Option<f32>,
-    #[serde(rename = "H, %")]
-    pub health_percentage: Option<f32>, // Здоровье в процентах
-    // ... добавь сюда все интересующие тебя поля из /indicators
-    // Например:
-    // pub Gx: Option<f32>,
-    // pub Gy: Option<f32>,
-    // pub Gz: Option<f32>,
-    // pub weapon_active: Option<bool>, // Если есть флаг активного оружия
-    // pub shells_count: Option<u32>, // Количество снарядов
+use tokio::time::Duration;
+use reqwest::{Client, RequestBuilder};
+use reqwest::header::HeaderMap;
+use crate::configuration_manager::{HttpHeaderSetting, WarThunderApiSettings};
+use crate::message_passing::{UpdateFromAsyncTasks, CommandToAsyncTasks, LogCategory}; // CommandToAsyncTasks может понадобиться для сигнала остановки или изменения интервала опроса
+use crate::connection_metrics::{PollOutcome, RollingWindow, WarThunderPollMetrics, METRICS_REPORT_INTERVAL};
+use crate::clock::Clock;
+use crate::telemetry_normalization::normalize_indicators;
+use crate::war_thunder_api::{hud_messages_path, parse_hud_damage_messages, parse_request_header, HudDamageMessage};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Instant;
+
+const WAR_THUNDER_STATE_PATH: &str = "/state";
+const WAR_THUNDER_INDICATORS_PATH: &str = "/indicators";
+
+// Ошибочные заголовки не отправляются: о них уже сообщила validate_war_thunder_api_settings
+fn build_request_headers(header_settings: &[HttpHeaderSetting]) -> HeaderMap {
+    header_settings.iter()
+        .filter_map(|header_setting| parse_request_header(header_setting).ok())
+        .collect()
+}
+
+fn war_thunder_url(api_settings: &WarThunderApiSettings, path: &str) -> String {
+    format!("{}{}", api_settings.base_url.trim_end_matches('/'), path)
+}
+
+fn war_thunder_request(
+    http_client: &Client,
+    api_settings: &WarThunderApiSettings,
+    request_headers: &HeaderMap,
+    path: &str,
+    request_timeout: Duration,
+) -> RequestBuilder {
+    let request_builder = http_client.get(war_thunder_url(api_settings, path)).timeout(request_timeout).headers(request_headers.clone());
+    match &api_settings.basic_auth {
+        Some(basic_auth) => request_builder.basic_auth(&basic_auth.username, Some(&basic_auth.password)),
+        None => request_builder,
+    }
+}
+
+// Вне боя WT может ответить 200 с пустым телом, `null` или {"valid": false} - это не ошибка парсинга,
+// а просто "не в бою".
+fn is_out_of_battle_body(raw_body: &str) -> bool {
+    let trimmed_body = raw_body.trim();
+    if trimmed_body.is_empty() {
+        return true;
+    }
+    match serde_json::from_str::<serde_json::Value>(trimmed_body) {
+        Ok(serde_json::Value::Null) => true,
+        Ok(json_value) => json_value.get("valid").and_then(serde_json::Value::as_bool) == Some(false),
+        Err(_) => false,
+    }
+}
+
+// Форматирует сырой ответ WT для отладочной панели; невалидный JSON возвращается как есть
+fn prettify_raw_json(raw_body: &str) -> String {
+    serde_json::from_str::<serde_json::Value>(raw_body)
+        .and_then(|value| serde_json::to_string_pretty(&value))
+        .unwrap_or_else(|_| raw_body.to_string())
+}
+
+// Не чаще этого предупреждаем в логе о выброшенных сообщениях, и только если их набралось заметное число
+const DROPPED_UPDATES_WARNING_INTERVAL: Duration = Duration::from_secs(10);
+const DROPPED_UPDATES_WARNING_THRESHOLD: usize = 10;
+
+// Отправка в GUI, которая не блокирует опрос: если GUI завис и канал полон, опрос не должен вставать вместе с ним.
+// Обычные сообщения (индикаторы, сырой JSON, логи, метрики) при переполнении выбрасываются и считаются.
+// Статусы соединения и боя отправляются только при изменении и с ожиданием места в канале:
+// они редкие, а потерянный переход "отключено" оставил бы эффекты включенными.
+struct GuiUpdateSender {
+    sender: mpsc::Sender<UpdateFromAsyncTasks>,
+    clock: Arc<dyn Clock>,
+    dropped_updates: RollingWindow<()>,
+    dropped_since_last_warning: usize,
+    last_drop_warning_at: Instant,
+    last_connection_status: Option<bool>,
+    last_battle_status: Option<bool>,
+}
+
+// GUI закрыл канал - опрос больше никому не нужен
+struct GuiChannelClosed;
+
+impl GuiUpdateSender {
+    fn new(sender: mpsc::Sender<UpdateFromAsyncTasks>, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            sender,
+            dropped_updates: RollingWindow::default(),
+            dropped_since_last_warning: 0,
+            last_drop_warning_at: clock.now(),
+            clock,
+            last_connection_status: None,
+            last_battle_status: None,
+        }
+    }
+
+    fn send_lossy(&mut self, update: UpdateFromAsyncTasks) -> Result<(), GuiChannelClosed> {
+        match self.sender.try_send(update) {
+            Ok(()) => Ok(()),
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                self.dropped_updates.record((), self.clock.now());
+                self.dropped_since_last_warning += 1;
+                Ok(())
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => Err(GuiChannelClosed),
+        }
+    }
+
+    fn send_log_lossy(&mut self, message: String) -> Result<(), GuiChannelClosed> {
+        self.send_lossy(UpdateFromAsyncTasks::LogMessage { category: LogCategory::WarThunder, device_address: None, message })
+    }
+
+    async fn send_connection_status(&mut self, is_connected: bool) -> Result<(), GuiChannelClosed> {
+        if self.last_connection_status == Some(is_connected) {
+            return Ok(());
+        }
+        self.sender.send(UpdateFromAsyncTasks::WarThunderConnectionStatus(is_connected)).await.map_err(|_| GuiChannelClosed)?;
+        self.last_connection_status = Some(is_connected);
+        if !is_connected {
+            self.last_battle_status = None; // GUI при отключении сам считает, что боя нет
+        }
+        Ok(())
+    }
+
+    // События боя ждут места в канале, в отличие от индикаторов: пропущенное уничтожение не повторится
+    async fn send_event(&self, update: UpdateFromAsyncTasks) -> Result<(), GuiChannelClosed> {
+        self.sender.send(update).await.map_err(|_| GuiChannelClosed)
+    }
+
+    async fn send_battle_status(&mut self, is_in_battle: bool) -> Result<(), GuiChannelClosed> {
+        if self.last_battle_status == Some(is_in_battle) {
+            return Ok(());
+        }
+        self.sender.send(UpdateFromAsyncTasks::WarThunderBattleStatus(is_in_battle)).await.map_err(|_| GuiChannelClosed)?;
+        self.last_battle_status = Some(is_in_battle);
+        Ok(())
+    }
+
+    fn warn_about_dropped_updates(&mut self, now: Instant) -> Result<(), GuiChannelClosed> {
+        if now.duration_since(self.last_drop_warning_at) < DROPPED_UPDATES_WARNING_INTERVAL {
+            return Ok(());
+        }
+        self.last_drop_warning_at = now;
+        let dropped_count = std::mem::take(&mut self.dropped_since_last_warning);
+        if dropped_count < DROPPED_UPDATES_WARNING_THRESHOLD {
+            return Ok(());
+        }
+        tracing::warn!("GUI не успевает разбирать обновления WT: выброшено {} сообщений за {} с", dropped_count, DROPPED_UPDATES_WARNING_INTERVAL.as_secs());
+        self.send_log_lossy(format!("Интерфейс не успевает за данными War Thunder: пропущено {} обновлений.", dropped_count))
+    }
+}
+
+// Первый ответ журнала в бою содержит всю его историю (например, приложение запущено посреди боя):
+// ее только запоминаем, дальше отдаем записи, появившиеся после. None - журнал в этом бою еще не читали.
+fn take_new_hud_messages(last_hud_damage_id: &mut Option<u64>, hud_messages: Vec<HudDamageMessage>) -> Vec<HudDamageMessage> {
+    let newest_id = hud_messages.iter().map(|hud_message| hud_message.id).max();
+    let Some(last_id) = *last_hud_damage_id else {
+        *last_hud_damage_id = Some(newest_id.unwrap_or(0));
+        return Vec::new();
+    };
+    *last_hud_damage_id = Some(newest_id.map_or(last_id, |newest_id| newest_id.max(last_id)));
+    hud_messages.into_iter().filter(|hud_message| hud_message.id > last_id).collect()
 }
 
-const WAR_THUNDER_STATE_URL: &str = "http://localhost:8111/state";
-const WAR_THUNDER_INDICATORS_URL: &str = "http://localhost:8111/indicators";
+// Ждет future (запрос к WT, паузу опроса) и все это время принимает команды в pending_commands:
+// диспетчер не ждет места в канале задачи, пока WT медленно отвечает, и не задерживает команды Buttplug.
+// None - канал команд закрыт.
+async fn await_accepting_commands<T>(
+    future: impl Future<Output = T>,
+    command_receiver: &mut mpsc::Receiver<CommandToAsyncTasks>,
+    pending_commands: &mut VecDeque<CommandToAsyncTasks>,
+) -> Option<T> {
+    tokio::pin!(future);
+    loop {
+        tokio::select! {
+            output = &mut future => return Some(output),
+            command = command_receiver.recv() => match command {
+                Some(command) => pending_commands.push_back(command),
+                None => return None,
+            },
+        }
+    }
+}
 
 pub async fn run_war_thunder_polling_loop(
     gui_update_sender: mpsc::Sender<UpdateFromAsyncTasks>,
     mut command_receiver: mpsc::Receiver<CommandToAsyncTasks>, // Пока не используется, но для будущего
     http_client: Client,
     mut polling_interval_milliseconds: u64,
+    mut request_timeout_milliseconds: u64,
+    clock: Arc<dyn Clock>,
 ) {
     let mut last_known_health: Option<f32> = None; // Пример для отслеживания изменений
+    let mut are_indicator_updates_enabled = true;
+    let mut is_raw_json_capture_enabled = false;
+    let mut is_metrics_reporting_enabled = false;
+    let mut poll_outcomes: RollingWindow<PollOutcome> = RollingWindow::default();
+    let mut gui_updates = GuiUpdateSender::new(gui_update_sender, clock.clone());
+    let mut last_metrics_report_at = clock.now();
+    let mut api_settings = WarThunderApiSettings::default();
+    let mut request_headers = HeaderMap::new();
+    // StopProcessing только приостанавливает опрос: задача запускается один раз в main,
+    // и после StartProcessing опрос должен продолжиться в ней же
+    let mut is_polling_paused = false;
+    // Команды, принятые во время запроса или паузы; разбираются в начале следующего шага
+    let mut pending_commands: VecDeque<CommandToAsyncTasks> = VecDeque::new();
+    // Журнал боя нужен только для своих исходов, поэтому опрашивается, когда в настройках задан ник
+    let mut is_hud_polling_enabled = false;
+    let mut last_hud_damage_id: Option<u64> = None;
 
     loop {
-        // Проверяем, не пришла ли команда на изменение интервала или остановку
-        // Это пример, как можно было бы обрабатывать команды
-        match command_receiver.try_recv() {
-            Ok(CommandToAsyncTasks::UpdateApplicationSettings(settings)) => {
-                polling_interval_milliseconds = settings.polling_interval_milliseconds;
-                let _ = gui_update_sender.send(UpdateFromAsyncTasks::LogMessage(format!("Интервал опроса War Thunder изменен на {} мс", polling_interval_milliseconds))).await;
-            }
-            Ok(CommandToAsyncTasks::StopProcessing) => {
-                 let _ = gui_update_sender.send(UpdateFromAsyncTasks::LogMessage("Остановлен опрос War Thunder.".to_string())).await;
-                 let _ = gui_update_sender.send(UpdateFromAsyncTasks::WarThunderConnectionStatus(false)).await;
-                break;
-            }
-            Err(mpsc::error::TryRecvError::Empty) => { /* нет команд, продолжаем */ }
-            Err(mpsc::error::TryRecvError::Disconnected) => {
-                tracing::warn!("Канал команд для War Thunder коннектора закрыт.");
-                break;
+        // Проверяем, не пришли ли команды на изменение интервала или остановку.
+        // Разбираем все накопившиеся команды, чтобы не задерживать диспетчер команд.
+        // На паузе не опрашиваем, а ждем следующую команду.
+        loop {
+            let next_command = if let Some(pending_command) = pending_commands.pop_front() {
+                Ok(pending_command)
+            } else if is_polling_paused {
+                command_receiver.recv().await.ok_or(mpsc::error::TryRecvError::Disconnected)
+            } else {
+                command_receiver.try_recv()
+            };
+            match next_command {
+                Ok(CommandToAsyncTasks::UpdateApplicationSettings(settings)) => {
+                    polling_interval_milliseconds = settings.polling_interval_milliseconds;
+                    request_timeout_milliseconds = settings.war_thunder_request_timeout_milliseconds;
+                    request_headers = build_request_headers(&settings.war_thunder_api.request_headers);
+                    is_hud_polling_enabled = !settings.player_name.trim().is_empty();
+                    api_settings = settings.war_thunder_api;
+                    let _ = gui_updates.send_log_lossy(format!("Интервал опроса War Thunder изменен на {} мс", polling_interval_milliseconds));
+                }
+                Ok(CommandToAsyncTasks::SetIndicatorUpdatesEnabled(is_enabled)) => {
+                    are_indicator_updates_enabled = is_enabled;
+                    tracing::debug!("Отправка индикаторов WT в GUI: {}", if is_enabled { "включена" } else { "выключена" });
+                }
+                Ok(CommandToAsyncTasks::SetRawJsonCaptureEnabled(is_enabled)) => {
+                    is_raw_json_capture_enabled = is_enabled;
+                }
+                Ok(CommandToAsyncTasks::SetConnectionMetricsEnabled(is_enabled)) => {
+                    is_metrics_reporting_enabled = is_enabled;
+                }
+                Ok(CommandToAsyncTasks::StopProcessing) if !is_polling_paused => {
+                    is_polling_paused = true;
+                    last_known_health = None;
+                    let _ = gui_updates.send_log_lossy("Опрос War Thunder приостановлен.".to_string());
+                    if gui_updates.send_connection_status(false).await.is_err() {
+                        return;
+                    }
+                }
+                Ok(CommandToAsyncTasks::StartProcessing) if is_polling_paused => {
+                    is_polling_paused = false;
+                    let _ = gui_updates.send_log_lossy("Опрос War Thunder возобновлен.".to_string());
+                }
+                Err(mpsc::error::TryRecvError::Empty) => break, // нет команд, продолжаем опрос
+                Err(mpsc::error::TryRecvError::Disconnected) => {
+                    tracing::warn!("Канал команд для War Thunder коннектора закрыт.");
+                    return;
+                }
+                _ => { /* другие команды пока игнорируем */ }
             }
-            _ => { /* другие команды пока игнорируем */ }
         }
 
 
-        match http_client.get(WAR_THUNDER_INDICATORS_URL).send().await {
-            Ok(response) => {
-                if response.status().is_success() {
-                    match response.json::<WarThunderIndicators>().await {
+        let now = clock.now();
+        if is_metrics_reporting_enabled && now.duration_since(last_metrics_report_at) >= METRICS_REPORT_INTERVAL {
+            last_metrics_report_at = now;
+            let poll_metrics = WarThunderPollMetrics::summarize(&poll_outcomes.recent_samples(now), gui_updates.dropped_updates.recent_samples(now).len());
+            let _ = gui_updates.send_lossy(UpdateFromAsyncTasks::WarThunderPollMetrics(poll_metrics));
+        }
+        if gui_updates.warn_about_dropped_updates(now).is_err() {
+            break; // Канал закрыт
+        }
+
+        let request_timeout = Duration::from_millis(request_timeout_milliseconds.max(1));
+        let indicators_request = async {
+            let response = war_thunder_request(&http_client, &api_settings, &request_headers, WAR_THUNDER_INDICATORS_PATH, request_timeout).send().await?;
+            let status = response.status();
+            let raw_body = if status.is_success() { response.text().await.unwrap_or_default() } else { String::new() };
+            Ok::<_, reqwest::Error>((status, raw_body))
+        };
+        let Some(indicators_result) = await_accepting_commands(indicators_request, &mut command_receiver, &mut pending_commands).await else {
+            tracing::warn!("Канал команд для War Thunder коннектора закрыт.");
+            return;
+        };
+        match indicators_result {
+            Ok((status, raw_body)) => {
+                if status.is_success() {
+                    if is_raw_json_capture_enabled {
+                        let _ = gui_updates.send_lossy(UpdateFromAsyncTasks::WarThunderRawJson {
+                            source_url: war_thunder_url(&api_settings, WAR_THUNDER_INDICATORS_PATH),
+                            pretty_json: prettify_raw_json(&raw_body),
+                        });
+                    }
+                    if is_out_of_battle_body(&raw_body) {
+                        poll_outcomes.record(PollOutcome::OutOfBattle, clock.now());
+                        last_hud_damage_id = None; // Следующий бой начнется с чтения истории
+                        // API отвечает, но боя нет: индикаторы не отправляем, чтобы правила не срабатывали на нулях
+                        if gui_updates.send_connection_status(true).await.is_err() || gui_updates.send_battle_status(false).await.is_err() {
+                            break; // Канал закрыт
+                        }
+                        if await_accepting_commands(clock.sleep(Duration::from_millis(polling_interval_milliseconds)), &mut command_receiver, &mut pending_commands).await.is_none() {
+                            break; // Канал команд закрыт
+                        }
+                        continue;
+                    }
+                    match normalize_indicators(&raw_body) {
                         Ok(indicators) => {
+                            poll_outcomes.record(PollOutcome::Success, clock.now());
                             // Пример простой логики: если здоровье изменилось
                             if let Some(current_health) = indicators.health_percentage {
-                                if let Some(last_health) = last_known_health {
-                                    if (current_health - last_health).abs() > 0.01 && current_health < last_health { // Небольшой порог, и здоровье уменьшилось
-                                        let _ = gui_update_sender.send(UpdateFromAsyncTasks::LogMessage(format!("Обнаружен урон! Здоровье: {:.2}%", current_health))).await;
-                                        // Здесь можно было бы генерировать более специфичное событие,
-                                        // но пока просто отправляем все индикаторы
-                                    }
+                                if let Some(last_health) = last_known_health
+                                    && (current_health - last_health).abs() > 0.01 && current_health < last_health { // Небольшой порог, и здоровье уменьшилось
+                                    let _ = gui_updates.send_log_lossy(format!("Обнаружен урон! Здоровье: {:.2}%", current_health));
+                                    // Здесь можно было бы генерировать более специфичное событие,
+                                    // но пока просто отправляем все индикаторы
                                 }
                                 last_known_health = Some(current_health);
                             }
 
-                            // Отправляем полные данные в GUI для отображения или дальнейшей обработки
-                            if gui_update_sender.send(UpdateFromAsyncTasks::WarThunderIndicatorsUpdate(indicators)).await.is_err() {
+                            // Отправляем полные данные в GUI для отображения или дальнейшей обработки.
+                            // Если GUI не успевает разбирать канал, обновление выбрасываем: ждать ради устаревших данных незачем.
+                            if are_indicator_updates_enabled && gui_updates.send_lossy(UpdateFromAsyncTasks::WarThunderIndicatorsUpdate(indicators)).is_err() {
                                 tracing::error!("Не удалось отправить обновление индикаторов WT в GUI: канал закрыт.");
                                 break;
                             }
-                             if gui_update_sender.send(UpdateFromAsyncTasks::WarThunderConnectionStatus(true)).await.is_err() {
+                            if gui_updates.send_connection_status(true).await.is_err() || gui_updates.send_battle_status(true).await.is_err() {
                                 break; // Канал закрыт
                             }
                         }
                         Err(parse_error) => {
+                            poll_outcomes.record(PollOutcome::ParseError, clock.now());
                             tracing::error!("Ошибка парсинга JSON от War Thunder Indicators: {}", parse_error);
-                            let _ = gui_update_sender.send(UpdateFromAsyncTasks::LogMessage(format!("Ошибка парсинга JSON от WT: {}", parse_error))).await;
-                             if gui_update_sender.send(UpdateFromAsyncTasks::WarThunderConnectionStatus(false)).await.is_err() {
+                            let _ = gui_updates.send_log_lossy(format!("Ошибка парсинга JSON от WT: {}", parse_error));
+                            if gui_updates.send_connection_status(false).await.is_err() {
                                 break;
                             }
                         }
                     }
                 } else {
                     // War Thunder API может возвращать 404 или 503 если не в ангаре/бою или API выключено
-                    // tracing::warn!("War Thunder API (Indicators) вернул статус: {}", response.status());
-                    if gui_update_sender.send(UpdateFromAsyncTasks::WarThunderConnectionStatus(false)).await.is_err() {
+                    poll_outcomes.record(PollOutcome::Failed, clock.now());
+                    // tracing::warn!("War Thunder API (Indicators) вернул статус: {}", status);
+                    if gui_updates.send_connection_status(false).await.is_err() {
                         break; // Канал закрыт
                     }
                 }
             }
             Err(request_error) => {
-                // Это обычно означает, что игра не запущена или API выключено
+                poll_outcomes.record(PollOutcome::Failed, clock.now());
+                // Это обычно означает, что игра не запущена или API выключено.
+                // Таймаут (игра зависла/загружается) считаем тем же отключением, без долгого ожидания.
+                if request_error.is_timeout() {
+                    tracing::debug!("Таймаут запроса к War Thunder ({} мс)", request_timeout_milliseconds);
+                }
                 // tracing::debug!("Ошибка подключения к War Thunder Indicators API: {}. Возможно, игра не запущена.", request_error);
-                 if gui_update_sender.send(UpdateFromAsyncTasks::WarThunderConnectionStatus(false)).await.is_err() {
+                if gui_updates.send_connection_status(false).await.is_err() {
                     break; // Канал закрыт
                 }
             }
         }
-        sleep(Duration::from_millis(polling_interval_milliseconds)).await;
+
+        if !gui_updates.last_battle_status.unwrap_or(false) {
+            last_hud_damage_id = None;
+        } else if is_hud_polling_enabled {
+            let hud_messages_request = async {
+                war_thunder_request(&http_client, &api_settings, &request_headers, &hud_messages_path(last_hud_damage_id.unwrap_or(0)), request_timeout)
+                    .send().await?.text().await
+            };
+            match await_accepting_commands(hud_messages_request, &mut command_receiver, &mut pending_commands).await {
+                Some(Ok(raw_body)) => match parse_hud_damage_messages(&raw_body) {
+                    Ok(hud_messages) => {
+                        let new_hud_messages = take_new_hud_messages(&mut last_hud_damage_id, hud_messages);
+                        if !new_hud_messages.is_empty() && gui_updates.send_event(UpdateFromAsyncTasks::WarThunderHudDamageMessages(new_hud_messages)).await.is_err() {
+                            break; // Канал закрыт
+                        }
+                    }
+                    Err(parse_error) => tracing::debug!("{}", parse_error),
+                },
+                Some(Err(_)) => {} // Журнал не влияет на статус соединения: его определяет /indicators
+                None => break, // Канал команд закрыт
+            }
+        }
+
+        if is_raw_json_capture_enabled {
+            let state_request = async {
+                war_thunder_request(&http_client, &api_settings, &request_headers, WAR_THUNDER_STATE_PATH, request_timeout).send().await?.text().await
+            };
+            match await_accepting_commands(state_request, &mut command_receiver, &mut pending_commands).await {
+                Some(Ok(raw_body)) => {
+                    let _ = gui_updates.send_lossy(UpdateFromAsyncTasks::WarThunderRawJson {
+                        source_url: war_thunder_url(&api_settings, WAR_THUNDER_STATE_PATH),
+                        pretty_json: prettify_raw_json(&raw_body),
+                    });
+                }
+                Some(Err(_)) => {}
+                None => break, // Канал команд закрыт
+            }
+        }
+
+        if await_accepting_commands(clock.sleep(Duration::from_millis(polling_interval_milliseconds)), &mut command_receiver, &mut pending_commands).await.is_none() {
+            break; // Канал команд закрыт
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::SystemClock;
+    use crate::configuration_manager::ApplicationSettings;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    // Локальный сервер вместо игры: на любой запрос отвечает "не в бою" через response_delay.
+    // Возвращает базовый адрес и счетчик принятых запросов.
+    async fn spawn_mock_war_thunder_server(response_delay: Duration) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let request_count = Arc::new(AtomicUsize::new(0));
+        let server_request_count = request_count.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let connection_request_count = server_request_count.clone();
+                tokio::spawn(async move {
+                    let mut request_bytes = Vec::new();
+                    let mut chunk = [0u8; 1024];
+                    while !request_bytes.windows(4).any(|window| window == b"\r\n\r\n") {
+                        match stream.read(&mut chunk).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(read_count) => request_bytes.extend_from_slice(&chunk[..read_count]),
+                        }
+                    }
+                    connection_request_count.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(response_delay).await;
+                    let body = r#"{"valid": false}"#;
+                    let response = format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body);
+                    let _ = stream.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        (base_url, request_count)
     }
-}
\ No newline at end of file
+
+    fn mock_server_settings(base_url: &str, polling_interval_milliseconds: u64, request_timeout_milliseconds: u64) -> ApplicationSettings {
+        ApplicationSettings {
+            polling_interval_milliseconds,
+            war_thunder_request_timeout_milliseconds: request_timeout_milliseconds,
+            war_thunder_api: WarThunderApiSettings { base_url: base_url.to_string(), ..WarThunderApiSettings::default() },
+            ..ApplicationSettings::default()
+        }
+    }
+
+    async fn next_connection_status(update_receiver: &mut mpsc::Receiver<UpdateFromAsyncTasks>) -> bool {
+        loop {
+            let update = tokio::time::timeout(Duration::from_secs(5), update_receiver.recv())
+                .await
+                .expect("нет статуса соединения WT")
+                .expect("канал обновлений закрыт");
+            if let UpdateFromAsyncTasks::WarThunderConnectionStatus(is_connected) = update {
+                return is_connected;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn slow_server_is_reported_disconnected_after_request_timeout() {
+        let (base_url, request_count) = spawn_mock_war_thunder_server(Duration::from_secs(10)).await;
+        let (update_sender, mut update_receiver) = mpsc::channel(64);
+        let (command_sender, command_receiver) = mpsc::channel(8);
+        command_sender.send(CommandToAsyncTasks::UpdateApplicationSettings(Box::new(mock_server_settings(&base_url, 5000, 200)))).await.unwrap();
+
+        let started_at = Instant::now();
+        let polling_task = tokio::spawn(run_war_thunder_polling_loop(update_sender, command_receiver, Client::new(), 5000, 1000, Arc::new(SystemClock)));
+        assert!(!next_connection_status(&mut update_receiver).await);
+        let elapsed = started_at.elapsed();
+        // Отключение видно через заданный таймаут, а не через 10 с, пока сервер ответит
+        assert!(elapsed >= Duration::from_millis(200), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(1500), "{:?}", elapsed);
+        assert_eq!(request_count.load(Ordering::SeqCst), 1);
+
+        drop(command_sender);
+        tokio::time::timeout(Duration::from_secs(1), polling_task).await.expect("опрос не завершился").unwrap();
+    }
+
+    #[tokio::test]
+    async fn stop_processing_pauses_polling_until_start_processing() {
+        let (base_url, request_count) = spawn_mock_war_thunder_server(Duration::ZERO).await;
+        let (update_sender, mut update_receiver) = mpsc::channel(64);
+        let (command_sender, command_receiver) = mpsc::channel(8);
+        command_sender.send(CommandToAsyncTasks::UpdateApplicationSettings(Box::new(mock_server_settings(&base_url, 20, 500)))).await.unwrap();
+        let polling_task = tokio::spawn(run_war_thunder_polling_loop(update_sender, command_receiver, Client::new(), 20, 500, Arc::new(SystemClock)));
+        assert!(next_connection_status(&mut update_receiver).await);
+
+        command_sender.send(CommandToAsyncTasks::StopProcessing).await.unwrap();
+        assert!(!next_connection_status(&mut update_receiver).await);
+        let paused_request_count = request_count.load(Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(200)).await; // Около 10 интервалов опроса
+        assert_eq!(request_count.load(Ordering::SeqCst), paused_request_count);
+
+        command_sender.send(CommandToAsyncTasks::StartProcessing).await.unwrap();
+        assert!(next_connection_status(&mut update_receiver).await);
+        assert!(request_count.load(Ordering::SeqCst) > paused_request_count);
+
+        drop(command_sender);
+        tokio::time::timeout(Duration::from_secs(1), polling_task).await.expect("опрос не завершился").unwrap();
+    }
+
+    #[test]
+    fn first_hud_response_in_battle_only_remembers_history() {
+        let hud_message = |id: u64| HudDamageMessage { id, msg: format!("запись {}", id), time: 0 };
+        let mut last_hud_damage_id = None;
+        assert!(take_new_hud_messages(&mut last_hud_damage_id, vec![hud_message(3), hud_message(5)]).is_empty());
+        assert_eq!(last_hud_damage_id, Some(5));
+
+        let new_hud_messages = take_new_hud_messages(&mut last_hud_damage_id, vec![hud_message(5), hud_message(6), hud_message(7)]);
+        assert_eq!(new_hud_messages.iter().map(|hud_message| hud_message.id).collect::<Vec<_>>(), vec![6, 7]);
+        assert_eq!(last_hud_damage_id, Some(7));
+        assert!(take_new_hud_messages(&mut last_hud_damage_id, Vec::new()).is_empty());
+        assert_eq!(last_hud_damage_id, Some(7));
+
+        // Пустой журнал в начале боя: все последующие записи новые
+        let mut last_hud_damage_id = None;
+        assert!(take_new_hud_messages(&mut last_hud_damage_id, Vec::new()).is_empty());
+        assert_eq!(take_new_hud_messages(&mut last_hud_damage_id, vec![hud_message(1)]).len(), 1);
+    }
+
+    #[test]
+    fn empty_body_means_out_of_battle() {
+        assert!(is_out_of_battle_body(""));
+        assert!(is_out_of_battle_body(" \r\n\t"));
+    }
+
+    #[test]
+    fn null_body_means_out_of_battle() {
+        assert!(is_out_of_battle_body("null"));
+        assert!(is_out_of_battle_body(" null\n"));
+    }
+
+    #[test]
+    fn invalid_indicators_mean_out_of_battle() {
+        assert!(is_out_of_battle_body(r#"{"valid": false}"#));
+        assert!(is_out_of_battle_body(r#"{"valid": false, "type": "tankModels/ussr_t_34_1941", "speed": 0.0}"#));
+    }
+
+    #[test]
+    fn battle_and_malformed_bodies_are_not_out_of_battle() {
+        assert!(!is_out_of_battle_body(r#"{"valid": true, "type": "tankModels/ussr_t_34_1941", "speed": 12.5}"#));
+        // Без флага valid данные считаются боевыми
+        assert!(!is_out_of_battle_body(r#"{"type": "f_16a"}"#));
+        // Испорченный JSON - ошибка парсинга, а не "не в бою"
+        assert!(!is_out_of_battle_body(r#"{"valid": fal"#));
+        assert!(!is_out_of_battle_body("<html>503</html>"));
+    }
+}